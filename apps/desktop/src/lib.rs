@@ -0,0 +1,426 @@
+#![no_std]
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+use rui_lib::{measure_text, subscribe_theme, theme_changed, Widget, Icon, Button, BlitMode, Image, Rgb, Rgba, RenderCommandBuffer, StackLayer, TextAlign, Theme, Window};
+use common::{ipc::Endpoint, theme::DesktopTheme, IpcMessage, IpcStr, kernel, Pid, Rect, WindowId};
+use core::ffi::CStr;
+
+/// 任务栏左边的标题
+const TASKBAR_TITLE: &str = "RUI Desktop";
+
+// 随桌面一起发布的图片（运行时解码）
+const WALLPAPER: &[u8] = include_bytes!("../assets/wallpaper.qoi");
+const CALC_ICON: &[u8] = include_bytes!("../assets/calc.bmp");
+const EDITOR_ICON: &[u8] = include_bytes!("../assets/editor.ppm");
+const TERMINAL_ICON: &[u8] = include_bytes!("../assets/terminal.qoi");
+
+// ==========================
+// 数据结构定义
+// ==========================
+/// 桌面壁纸（缩放铺满整个屏幕）
+struct Wallpaper {
+    image: Option<Image>,
+    width: u16,  // 屏幕大小
+    height: u16,
+    background: Rgb, // 壁纸解码失败时的背景色（来自主题）
+}
+
+/// 任务栏（顶部）
+struct Taskbar {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+    title_width: u16,            // 左边标题的宽度（窗口按钮排在它后面）
+    window_buttons: Vec<(WindowId, Button)>, // 已打开窗口的按钮（附带对应窗口ID）
+    time_text: &'static str,     // 时间文本（简化：静态）
+    theme: DesktopTheme,         // 颜色、字体和间距
+}
+
+/// Dock栏（底部）：放在顶层的面板上，最大化的窗口也盖不住它
+struct Dock {
+    x: u16,          // 面板在屏幕上的位置（底部居中，动态计算）
+    y: u16,
+    width: u16,
+    height: u16,
+    icons: Vec<Icon>, // 常用应用图标（坐标相对面板左上角）
+    background: Rgba, // 半透明背景（来自主题）
+    panel: Option<Window>, // Dock所在的面板（WM没有回复时为None，不画）
+}
+
+/// 桌面全局状态
+struct Desktop {
+    screen_width: u16,
+    screen_height: u16,
+    wm_pid: Pid,     // 窗口管理器PID
+    cmds: RenderCommandBuffer, // 发给渲染服务的绘图命令
+    taskbar: Taskbar,
+    dock: Dock,
+    wallpaper: Wallpaper,
+    endpoint: Endpoint, // 收消息端点（查询渲染服务时暂存其他消息）
+}
+
+// ==========================
+// 实现：壁纸
+// ==========================
+impl Wallpaper {
+    fn new(screen_width: u16, screen_height: u16, theme: &DesktopTheme) -> Self {
+        Wallpaper { image: Image::decode(WALLPAPER).ok(), width: screen_width, height: screen_height, background: theme.background }
+    }
+
+    /// 重画屏幕上area这块背景（没有壁纸时用纯色）
+    fn draw(&self, cmds: &mut RenderCommandBuffer, area: Rect) {
+        cmds.push_clip(area.x, area.y, area.w, area.h);
+        match &self.image {
+            Some(image) => cmds.blit(image, 0, 0, self.width, self.height, BlitMode::Opaque),
+            None => cmds.rect(area.x, area.y, area.w, area.h, self.background),
+        };
+        cmds.pop_clip();
+    }
+}
+
+// ==========================
+// 实现：任务栏
+// ==========================
+impl Taskbar {
+    /// 创建任务栏（顶部，高度按主题；标题宽度向渲染服务量）
+    fn new(screen_width: u16, theme: &DesktopTheme, endpoint: &mut Endpoint) -> Self {
+        let mut taskbar = Taskbar {
+            x: 0,
+            y: 0,
+            width: screen_width,
+            height: theme.taskbar_height,
+            title_width: 0,
+            window_buttons: Vec::new(),
+            time_text: "12:00", // 静态时间，后续替换为实时时间
+            theme: *theme,
+        };
+        taskbar.measure_title(endpoint);
+        taskbar
+    }
+
+    /// 量左边标题的宽度（字体变了要重新量）
+    fn measure_title(&mut self, endpoint: &mut Endpoint) {
+        let font = self.theme.taskbar_font;
+        self.title_width = measure_text(endpoint, TASKBAR_TITLE, font, 0).map_or(100, |(width, _)| width);
+    }
+
+    /// 绘制任务栏
+    fn draw(&self, cmds: &mut RenderCommandBuffer) {
+        let (theme, font) = (&self.theme, self.theme.taskbar_font);
+        // 1. 绘制任务栏背景
+        cmds.rect(self.x, self.y, self.width, self.height, theme.taskbar_background);
+
+        // 2. 绘制左侧标题（竖直居中）
+        let spacing = theme.taskbar_spacing;
+        cmds.text_box(spacing, self.y, 0, self.height, TASKBAR_TITLE, font, TextAlign::Left, theme.taskbar_text);
+
+        // 3. 绘制右侧时间（右边对齐到离屏幕右边taskbar_spacing处）
+        let right = self.width.saturating_sub(spacing);
+        cmds.text_box(right, self.y, 0, self.height, self.time_text, font, TextAlign::Right, theme.taskbar_text);
+
+        // 4. 绘制窗口按钮（中间区域）
+        for (_, btn) in &self.window_buttons {
+            btn.draw(cmds); // 桌面直接画在背景表面上（屏幕坐标）
+        }
+    }
+
+    /// 添加窗口按钮（窗口打开时调用）：排在标题和已有按钮后面
+    fn add_window_button(&mut self, title: &str, window_id: WindowId, endpoint: &mut Endpoint) {
+        let button = Button::new(0, 0, 0, 0, title, self.theme.task_button, || {}); // 大小和位置由place_button定
+        self.window_buttons.push((window_id, button));
+        self.place_button(self.window_buttons.len() - 1, endpoint);
+    }
+
+    /// 按主题重建第index个窗口按钮：宽度按标题量，紧跟在前一个按钮（或左边标题）后面
+    fn place_button(&mut self, index: usize, endpoint: &mut Endpoint) {
+        let theme = &self.theme;
+        let first_x = theme.taskbar_spacing.saturating_mul(2).saturating_add(self.title_width);
+        let x = self.window_buttons[..index].iter().fold(first_x, |x, (_, btn)| x + btn.width() + 5);
+        let (_, button) = &mut self.window_buttons[index];
+        let title = IpcStr::new(button.text());
+        let text_width = measure_text(endpoint, title.as_str(), theme.taskbar_font, 0).map_or(0, |(width, _)| width);
+        // 最小宽度比最大宽度还大时按最小宽度（clamp会panic）
+        let width = text_width.saturating_add(theme.taskbar_spacing.saturating_mul(2)).min(theme.task_button_max_width).max(theme.task_button_min_width);
+        *button = Button::new(
+            x, 2,
+            width, self.height.saturating_sub(4), // 上下各留2像素
+            title.as_str(),
+            theme.task_button, // 按钮背景
+            || {}, // 点击由handle_click统一处理（需要知道对应的窗口ID）
+        );
+        button.set_text_style(theme.taskbar_font);
+    }
+
+    /// 换主题：重新量标题，按新字体和间距重排窗口按钮
+    fn set_theme(&mut self, theme: &Theme, endpoint: &mut Endpoint) {
+        self.theme = theme.desktop;
+        self.height = theme.desktop.taskbar_height;
+        self.measure_title(endpoint);
+        for index in 0..self.window_buttons.len() {
+            self.place_button(index, endpoint);
+            self.window_buttons[index].1.set_theme(theme);
+        }
+    }
+
+    /// 处理点击：点中窗口按钮则通知WM切换焦点到该窗口
+    fn handle_click(&self, x: u16, y: u16, wm_pid: Pid) {
+        for (window_id, btn) in &self.window_buttons {
+            if btn.contains(x, y) {
+                kernel::send(wm_pid, &IpcMessage::WmFocusWindow { window_id: *window_id });
+            }
+        }
+    }
+}
+
+// ==========================
+// 实现：Dock栏
+// ==========================
+impl Dock {
+    /// 创建Dock栏（底部，高度48px）和它的面板
+    fn new(screen_width: u16, screen_height: u16, theme: &DesktopTheme, endpoint: &mut Endpoint) -> Self {
+        // 初始化3个常用应用图标
+        let mut icons = vec![
+            // 计算器图标
+            Icon::new(
+                0, 0, // x/y在面板里依次排开
+                32, 32, // 图标尺寸
+                "calc", // 图标名称
+                || unsafe { kernel::spawn_process(b"rui-app-calculator\0".as_ptr()); }, // 启动计算器
+            ),
+            // 文本编辑器图标
+            Icon::new(
+                0, 0,
+                32, 32,
+                "editor",
+                || unsafe { kernel::spawn_process(b"rui-app-text-editor\0".as_ptr()); },
+            ),
+            // 终端图标
+            Icon::new(
+                0, 0,
+                32, 32,
+                "terminal",
+                || unsafe { kernel::spawn_process(b"rui-app-terminal\0".as_ptr()); },
+            ),
+        ];
+        // 图标的位图（解码失败的画占位方块）
+        for (icon, file) in icons.iter_mut().zip([CALC_ICON, EDITOR_ICON, TERMINAL_ICON]) {
+            icon.image = Image::decode(file).ok();
+        }
+
+        // 宽度为图标宽×3 + 间距×2，左右各留10px边距
+        let mut icon_x = 10;
+        for icon in &mut icons {
+            icon.x = icon_x;
+            icon.y = 8; // 垂直居中
+            icon_x += 32 + 10; // 图标宽+间距
+        }
+        let (width, height) = (32 * 3 + 10 * 4, 48);
+        let (x, y) = Self::position(screen_width, screen_height, width, height);
+        let panel = Window::new_panel(endpoint, x, y, width, height, StackLayer::Top).ok();
+        Dock { x, y, width, height, icons, background: theme.dock_background, panel }
+    }
+
+    /// 屏幕上底部居中的位置
+    fn position(screen_width: u16, screen_height: u16, width: u16, height: u16) -> (u16, u16) {
+        (screen_width.saturating_sub(width) / 2, screen_height.saturating_sub(height))
+    }
+
+    /// 按屏幕大小重新定位（底部居中），让WM把面板挪过去
+    fn place(&mut self, screen_width: u16, screen_height: u16) {
+        (self.x, self.y) = Self::position(screen_width, screen_height, self.width, self.height);
+        if let Some(panel) = &self.panel {
+            panel.move_to(self.x, self.y);
+        }
+    }
+
+    /// 绘制Dock栏：画在面板上，画完切回桌面背景（壁纸和任务栏画在背景上）
+    fn draw(&self, cmds: &mut RenderCommandBuffer) {
+        let Some(panel) = &self.panel else {
+            return;
+        };
+        // 1. 绘制Dock背景（半透明灰）；半透明会和面板上原来的像素混合，先清成透明
+        cmds.target(panel.surface());
+        cmds.clear(0, 0, self.width, self.height);
+        cmds.rect(0, 0, self.width, self.height, self.background);
+
+        // 2. 绘制图标
+        for icon in &self.icons {
+            icon.draw(cmds);
+        }
+        cmds.target(0);
+    }
+
+    /// 屏幕上的点(x, y)换成面板上的坐标（在面板左边、上边时为None）
+    fn to_local(&self, x: u16, y: u16) -> Option<(u16, u16)> {
+        Some((x.checked_sub(self.x)?, y.checked_sub(self.y)?))
+    }
+
+    /// 处理点击：点中图标就启动对应的应用
+    fn handle_click(&mut self, x: u16, y: u16) {
+        let Some((x, y)) = self.to_local(x, y) else {
+            return;
+        };
+        for icon in &mut self.icons {
+            icon.on_click(x, y);
+        }
+    }
+
+    /// 处理鼠标悬停（简化：图标放大）
+    fn handle_hover(&mut self, x: u16, y: u16) {
+        // 在面板左边、上边的点不会落在图标上
+        let (x, y) = self.to_local(x, y).unwrap_or((u16::MAX, u16::MAX));
+        for icon in &mut self.icons {
+            // 检查鼠标是否在图标范围内
+            let in_icon = x >= icon.x && x < icon.x + icon.width
+                && y >= icon.y && y < icon.y + icon.height;
+
+            if in_icon {
+                icon.width = 40;
+                icon.height = 40;
+            } else {
+                icon.width = 32;
+                icon.height = 32;
+            }
+        }
+    }
+}
+
+// ==========================
+// 桌面主逻辑
+// ==========================
+impl Desktop {
+    /// 初始化桌面
+    fn init() -> Self {
+        unsafe {
+            // 0. 注册成"desktop"服务（渲染服务只让这个进程画桌面背景）
+            let _ = kernel::kernel_register_service(c"desktop".as_ptr() as *const u8);
+
+            // 1. 获取依赖服务PID
+            let wm_pid = kernel::get_service_pid(CStr::from_bytes_with_nul(b"wm\0").unwrap().as_ptr() as *const u8);
+            let render_pid = kernel::get_service_pid(CStr::from_bytes_with_nul(b"render\0").unwrap().as_ptr() as *const u8);
+
+            // 2. 向渲染服务查询屏幕分辨率（同时订阅分辨率变化）；查询失败按800x600
+            let mut endpoint = Endpoint::new();
+            let (screen_width, screen_height) = match endpoint.call(render_pid, &IpcMessage::RenderSubscribeScreen) {
+                Ok(IpcMessage::RenderScreenInfo { width, height }) if width != 0 && height != 0 => (width, height),
+                _ => (800, 600),
+            };
+            // 绘图命令缓冲区（分配不到共享内存就画不了，停在这里）
+            let Some(cmds) = RenderCommandBuffer::new(render_pid) else {
+                loop { kernel::idle(); }
+            };
+
+            // 3. 向WM订阅主题，按主题初始化任务栏和Dock
+            let theme = subscribe_theme(&mut endpoint);
+            let taskbar = Taskbar::new(screen_width, &theme.desktop, &mut endpoint);
+            let mut dock = Dock::new(screen_width, screen_height, &theme.desktop, &mut endpoint);
+            for icon in &mut dock.icons {
+                icon.set_theme(&theme);
+            }
+            let wallpaper = Wallpaper::new(screen_width, screen_height, &theme.desktop);
+
+            Desktop {
+                screen_width,
+                screen_height,
+                wm_pid,
+                cmds,
+                taskbar,
+                dock,
+                wallpaper,
+                endpoint,
+            }
+        }
+    }
+
+    /// 屏幕分辨率变化：重新布局任务栏和Dock
+    fn layout(&mut self, screen_width: u16, screen_height: u16) {
+        self.screen_width = screen_width;
+        self.screen_height = screen_height;
+        self.taskbar.width = screen_width;
+        self.dock.place(screen_width, screen_height);
+        self.wallpaper.width = screen_width;
+        self.wallpaper.height = screen_height;
+    }
+
+    /// 换主题：各部分换上新的颜色、字体和间距（之后调用draw重画）
+    fn set_theme(&mut self, theme: &Theme) {
+        self.taskbar.set_theme(theme, &mut self.endpoint);
+        self.dock.background = theme.desktop.dock_background;
+        for icon in &mut self.dock.icons {
+            icon.set_theme(theme);
+        }
+        self.wallpaper.background = theme.desktop.background;
+    }
+
+    /// 绘制整个桌面
+    fn draw(&mut self) {
+        // 1. 绘制壁纸
+        self.wallpaper.draw(&mut self.cmds, Rect::new(0, 0, self.screen_width, self.screen_height));
+
+        // 2. 绘制任务栏和Dock
+        self.taskbar.draw(&mut self.cmds);
+        self.dock.draw(&mut self.cmds);
+
+        // 3. 呈现（桌面画在背景表面上，Dock的面板和窗口由渲染服务合成在上面）
+        self.cmds.submit();
+    }
+}
+
+// ==========================
+// 入口函数与事件循环
+// ==========================
+/// 桌面主函数（裸机入口main.rs和宿主模拟器都从这里启动）
+pub fn run() -> ! {
+    let mut desktop = Desktop::init();
+    desktop.draw(); // 初始绘制
+
+    // 测试：添加一个默认窗口按钮到任务栏
+    desktop.taskbar.add_window_button(
+        "测试窗口",
+        1, // 假设窗口ID为1
+        &mut desktop.endpoint,
+    );
+    desktop.taskbar.draw(&mut desktop.cmds); // 重绘任务栏
+    desktop.cmds.submit();
+
+    // 事件循环：处理鼠标事件和窗口状态更新
+    loop {
+        let incoming = desktop.endpoint.recv();
+        match incoming.msg {
+            // 处理鼠标点击
+            IpcMessage::MouseClick { x, y } => {
+                // 检查是否点击Dock图标
+                desktop.dock.handle_click(x, y);
+                // 检查是否点击任务栏窗口按钮
+                desktop.taskbar.handle_click(x, y, desktop.wm_pid);
+            }
+            // 处理鼠标移动（Dock图标放大）
+            IpcMessage::MouseMove { x, y } => {
+                desktop.dock.handle_hover(x, y);
+                desktop.dock.draw(&mut desktop.cmds); // 重绘Dock
+                desktop.cmds.submit();
+            }
+            // 处理窗口打开事件（来自WM）
+            IpcMessage::WmWindowOpened { title, window_id } => {
+                desktop.taskbar.add_window_button(title.as_str(), window_id, &mut desktop.endpoint);
+                desktop.taskbar.draw(&mut desktop.cmds); // 重绘任务栏
+                desktop.cmds.submit();
+            }
+            // 主题换了（来自WM）：换上新主题并重绘
+            IpcMessage::ThemeChanged { .. } => {
+                if let Some(theme) = theme_changed(&incoming) {
+                    desktop.set_theme(&theme);
+                    desktop.draw();
+                }
+            }
+            // 屏幕分辨率变了（来自渲染服务）：重新布局并重绘
+            IpcMessage::ScreenModeChanged { width, height } => {
+                desktop.layout(width, height);
+                desktop.draw();
+            }
+            _ => {}
+        }
+        kernel::idle();
+    }
+}
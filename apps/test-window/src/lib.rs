@@ -0,0 +1,94 @@
+#![no_std]
+
+use rui_lib::{Window, Widget, Button, Label, common::{ipc::Endpoint, Rect, Rgb, kernel}, RenderCommandBuffer, TextStyle};
+
+/// 控件离内容区左边的距离，渐变和说明文字的最大宽度
+const MARGIN: u16 = 50;
+const STRIP_WIDTH: u16 = 256;
+const LABEL_WIDTH: u16 = 200;
+/// 内容区的最小大小（WmSetSizeLimits）
+const MIN_WIDTH: u16 = 160;
+const MIN_HEIGHT: u16 = 200;
+
+// 按钮点击回调：暂时只做占位
+fn on_button_click() {
+    // 后续可扩展：发送消息给窗口管理器修改窗口
+}
+
+/// 应用主函数（裸机入口main.rs和宿主模拟器都从这里启动）
+pub fn run() -> ! {
+    // 创建窗口（WM没有回复时无事可做，直接停下）
+    let mut endpoint = Endpoint::new();
+    let window = match Window::new(&mut endpoint, "测试窗口", 100, 100, 400, 300) {
+        Ok(window) => window,
+        Err(_) => loop { kernel::idle(); },
+    };
+
+    // 内容区至少要放得下按钮和渐变
+    window.set_size_limits(MIN_WIDTH, MIN_HEIGHT, 0, 0);
+
+    // 订阅主题（控件按主题画，换主题时重画）
+    let theme = rui_lib::subscribe_theme(&mut endpoint);
+
+    // 创建按钮
+    let mut button = Button::new(
+        50, 50, 100, 30, "点击我",
+        Rgb { r: 0, g: 120, b: 215 },  // 蓝色按钮
+        on_button_click
+    );
+    button.set_theme(&theme);
+
+    // 渐变下面一段会折行的说明文字（中英文混排）
+    let mut label = Label::new(
+        MARGIN, 150, LABEL_WIDTH,
+        "Labels wrap at the box width, 测试窗口 included.",
+        TextStyle::sans(14),
+        theme.widget.label_text,
+    )
+    .expect("说明文字不超过IPC_STR_CAPACITY字节");
+
+    let render_pid = unsafe {
+        let render_name = core::ffi::CStr::from_bytes_with_nul(b"render\0").unwrap();
+        common::kernel::get_service_pid(render_name.as_ptr() as *const u8)
+    };
+    let Some(mut cmds) = RenderCommandBuffer::new(render_pid) else {
+        loop { kernel::idle(); }
+    };
+    let mut strip_width = STRIP_WIDTH;
+    draw(&window, &button, &label, strip_width, &mut cmds, &mut endpoint);
+
+    // 事件循环：主题换了、窗口大小变了就重画（WM已经清掉了内容区）
+    loop {
+        let incoming = endpoint.recv();
+        if let Some(theme) = rui_lib::theme_changed(&incoming) {
+            button.set_theme(&theme);
+            label.set_color(theme.widget.label_text);
+            draw(&window, &button, &label, strip_width, &mut cmds, &mut endpoint);
+        }
+        // 渐变和说明文字跟着窗口变窄，最宽到原来的大小
+        if let Some((width, _)) = window.resized(&incoming.msg) {
+            let available = width.saturating_sub(2 * MARGIN);
+            strip_width = available.min(STRIP_WIDTH);
+            label.set_width(available.min(LABEL_WIDTH));
+            draw(&window, &button, &label, strip_width, &mut cmds, &mut endpoint);
+        }
+        kernel::idle();
+    }
+}
+
+/// 画窗口内容（渐变strip_width宽）
+fn draw(window: &Window, button: &Button, label: &Label, strip_width: u16, cmds: &mut RenderCommandBuffer, endpoint: &mut Endpoint) {
+    window.draw(button, cmds); // 坐标相对窗口内容区左上角
+    window.draw(label, cmds);
+    cmds.submit();
+
+    // 直接写像素：按钮下面画一条渐变（演示共享内存表面）
+    if let Ok(mut buffer) = window.buffer(endpoint) {
+        let strip = Rect::new(MARGIN, 100, strip_width, 40);
+        for x in 0..strip.w {
+            let level = x as u8;
+            buffer.fill_rect(strip.x + x, strip.y, 1, strip.h, Rgb { r: level, g: 255 - level, b: 128 });
+        }
+        buffer.commit(&[strip]);
+    }
+}
@@ -0,0 +1,465 @@
+// ==========================
+// 批量绘图命令：RenderCommandBuffer
+// ==========================
+// 客户端把一批绘图命令录进和渲染服务共享的环形缓冲区，最后发一条RenderSubmit，
+// 渲染服务一次读完执行，整批只做一次脏区域合并和呈现（读到Commit命令才呈现）。
+//
+// 共享内存布局：[RingHeader][数据区capacity字节]。
+// 位置都是只增不减的字节计数（按u32回绕），对capacity取模得到数据区里的位置
+// （capacity是2的幂，回绕前后取模的结果仍然连续）。
+// 渲染服务读到哪里记在头部的head里；客户端写到哪里（end）随RenderSubmit一起发过去，
+// 渲染服务只执行到end为止——这样命令和其他消息的先后顺序与发送顺序一致
+// （比如桌面画完背景再通知WM画窗口，WM的命令不会跑到背景前面去）。
+// 每条命令的编码见wire::encode_command。
+
+use crate::{image::{BlitMode, Image}, kernel, wire, IpcMessage, IpcStr, Pid, Point, PointList, Rgba, ShmHandle, SurfaceId, TextAlign, TextStyle};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// 一条绘图命令（坐标相对当前目标表面的左上角；窗口所属的应用画自己的窗口时相对内容区左上角）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderCommand {
+    Pixel { x: u16, y: u16, color: Rgba },
+    Rect { x: u16, y: u16, w: u16, h: u16, color: Rgba },
+    Text { x: u16, y: u16, text: IpcStr, style: TextStyle, color: Rgba }, // (x, y)是这行文字的左上角
+    Commit, // 一批命令结束：呈现到屏幕上
+    Target { surface: SurfaceId }, // 之后的命令画到哪个表面（0是桌面背景，每个命令缓冲区一开始都是0）
+    PushClip { x: u16, y: u16, w: u16, h: u16 }, // 之后只画在这个矩形里（和外层裁剪区取交集）
+    PopClip, // 恢复到上一层PushClip之前的裁剪区
+    // 矢量图形：thickness是线宽（从边界向内画），0表示填充
+    Line { x0: u16, y0: u16, x1: u16, y1: u16, color: Rgba }, // 1像素宽的线段（含两个端点）
+    StrokeRect { x: u16, y: u16, w: u16, h: u16, thickness: u16, color: Rgba },
+    Ellipse { cx: u16, cy: u16, rx: u16, ry: u16, thickness: u16, color: Rgba }, // 圆心和两个半轴
+    RoundRect { x: u16, y: u16, w: u16, h: u16, radius: u16, thickness: u16, color: Rgba },
+    Polygon { points: PointList, color: Rgba }, // 填充（奇偶规则，自相交的部分交替镂空）
+    // 把共享内存image里width×height的位图缩放（取最近的像素）画到(x, y, w, h)
+    Blit { image: ShmHandle, width: u16, height: u16, x: u16, y: u16, w: u16, h: u16, mode: BlitMode },
+    // 多行文字：按w折行、每行按align对齐；h不为0时在框里竖直居中（排版规则见RenderMeasureText）
+    TextBox { x: u16, y: u16, w: u16, h: u16, text: IpcStr, style: TextStyle, align: TextAlign, color: Rgba },
+    Clear { x: u16, y: u16, w: u16, h: u16 }, // 把矩形里的像素换成全透明（不混合），重画半透明的内容前用；背景表面上清出来是黑色
+}
+
+/// 单条命令编码后的最大长度
+pub const MAX_COMMAND_SIZE: usize = 128;
+/// 默认的环形缓冲区大小（不含头部）
+pub const DEFAULT_RING_CAPACITY: usize = 16 * 1024;
+/// 缓冲区满时最多等渲染服务腾出空间多久（毫秒）
+pub const PUSH_TIMEOUT_MS: u64 = 1000;
+
+/// 环形缓冲区头部（放在共享内存开头）
+#[repr(C)]
+struct RingHeader {
+    head: AtomicU32,  // 渲染服务读到哪里
+    capacity: u32,    // 数据区字节数
+}
+
+const HEADER_SIZE: usize = core::mem::size_of::<RingHeader>();
+
+/// 映射好的环形缓冲区（客户端和渲染服务共用）
+pub struct CommandRing {
+    header: *mut RingHeader,
+    data: *mut u8,
+    capacity: u32,
+}
+
+impl CommandRing {
+    /// 共享内存里需要多少字节才能放下capacity字节的数据区
+    pub const fn shm_size(capacity: usize) -> usize {
+        HEADER_SIZE + capacity
+    }
+
+    /// 在刚分配的共享内存上建立空的环形缓冲区
+    ///
+    /// # Safety
+    /// ptr指向至少shm_size(capacity)字节、按4字节对齐的可写内存；capacity是2的幂。
+    pub unsafe fn create(ptr: *mut u8, capacity: usize) -> Self {
+        let header = ptr as *mut RingHeader;
+        header.write(RingHeader {
+            head: AtomicU32::new(0),
+            capacity: capacity as u32,
+        });
+        CommandRing { header, data: ptr.add(HEADER_SIZE), capacity: capacity as u32 }
+    }
+
+    /// 打开别的进程建立的环形缓冲区；头部记录的大小和size对不上时返回None
+    ///
+    /// # Safety
+    /// ptr指向size字节的共享内存，对方已经用create初始化过。
+    pub unsafe fn open(ptr: *mut u8, size: usize) -> Option<Self> {
+        if ptr.is_null() || size <= HEADER_SIZE {
+            return None;
+        }
+        let header = ptr as *mut RingHeader;
+        let capacity = (*header).capacity;
+        if !capacity.is_power_of_two() || capacity as usize > size - HEADER_SIZE {
+            return None;
+        }
+        Some(CommandRing { header, data: ptr.add(HEADER_SIZE), capacity })
+    }
+
+    fn header(&self) -> &RingHeader {
+        unsafe { &*self.header }
+    }
+
+    /// 把data写到position开始的位置（跨过数据区末尾时回到开头）
+    unsafe fn write_at(&self, position: u32, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            let index = position.wrapping_add(i as u32) % self.capacity;
+            self.data.add(index as usize).write_volatile(*byte);
+        }
+    }
+
+    /// 从position开始读满buf
+    unsafe fn read_at(&self, position: u32, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let index = position.wrapping_add(i as u32) % self.capacity;
+            *byte = self.data.add(index as usize).read_volatile();
+        }
+    }
+
+    /// 渲染服务：依次取出到end为止的命令交给execute；
+    /// 遇到畸形记录时丢掉剩下的全部内容（客户端有bug，不能让它卡住渲染服务）
+    pub fn drain(&self, end: u32, mut execute: impl FnMut(RenderCommand)) {
+        let header = self.header();
+        let mut head = header.head.load(Ordering::Relaxed);
+        if end.wrapping_sub(head) > self.capacity {
+            return; // end在已读位置之前（重复或过期的通知）或超出缓冲区
+        }
+        core::sync::atomic::fence(Ordering::Acquire); // 和客户端publish里的fence配对
+        let mut record = [0u8; MAX_COMMAND_SIZE];
+        while head != end {
+            let available = end.wrapping_sub(head) as usize;
+            let mut len = [0u8; 2];
+            unsafe { self.read_at(head, &mut len) };
+            let len = u16::from_le_bytes(len) as usize;
+            if !(3..=MAX_COMMAND_SIZE).contains(&len) || len > available {
+                head = end;
+                break;
+            }
+            unsafe { self.read_at(head, &mut record[..len]) };
+            head = head.wrapping_add(len as u32);
+            match wire::decode_command(&record[..len]) {
+                Ok(command) => execute(command),
+                Err(_) => continue, // 不认识的命令：跳过这一条
+            }
+        }
+        header.head.store(head, Ordering::Release);
+    }
+}
+
+/// 客户端的绘图命令缓冲区
+pub struct RenderCommandBuffer {
+    render_pid: Pid,
+    shm: ShmHandle,
+    size: usize,
+    ring: CommandRing,
+    cursor: u32,    // 写到哪里
+    published: u32, // 已经通知渲染服务执行到哪里
+    stalled: Option<u32>, // 上次等空间超时的时候渲染服务读到哪里（它一直没动就不再等）
+}
+
+impl RenderCommandBuffer {
+    /// 分配一块默认大小的共享内存作为命令缓冲区，内存不足时返回None
+    pub fn new(render_pid: Pid) -> Option<Self> {
+        Self::with_capacity(render_pid, DEFAULT_RING_CAPACITY)
+    }
+
+    /// 数据区至少capacity字节（向上取到2的幂）
+    pub fn with_capacity(render_pid: Pid, capacity: usize) -> Option<Self> {
+        let size = CommandRing::shm_size(capacity.max(MAX_COMMAND_SIZE).next_power_of_two());
+        let memory = kernel::SharedMemory::alloc(size)?;
+        let ring = unsafe { CommandRing::create(memory.as_ptr(), size - HEADER_SIZE) };
+        Some(RenderCommandBuffer { render_pid, shm: memory.handle(), size, ring, cursor: 0, published: 0, stalled: None })
+    }
+
+    /// 录入一条命令；缓冲区满时先把已录的交给渲染服务，等它腾出空间。
+    /// 等了PUSH_TIMEOUT_MS还没有空间（渲染服务卡住了，或者没接受这个缓冲区）时丢掉这条命令，返回false；
+    /// 之后渲染服务一直没读时不再等，只把提交通知重发一遍就返回false
+    pub fn push(&mut self, command: &RenderCommand) -> bool {
+        let mut record = [0u8; MAX_COMMAND_SIZE];
+        let Ok(len) = wire::encode_command(command, &mut record) else {
+            return false; // 编码失败说明调用方有bug，直接丢弃
+        };
+        if self.free() < len as u32 {
+            if self.stalled == Some(self.head()) {
+                self.notify();
+                return false;
+            }
+            // 没呈现的部分先执行掉（渲染服务读到Commit才呈现，所以这一批仍然是一起上屏的）
+            self.publish();
+            let deadline = unsafe { kernel::kernel_uptime_ms() }.saturating_add(PUSH_TIMEOUT_MS);
+            while self.free() < len as u32 {
+                if unsafe { kernel::kernel_uptime_ms() } >= deadline {
+                    self.stalled = Some(self.head());
+                    return false;
+                }
+                kernel::idle();
+            }
+        }
+        self.stalled = None;
+        unsafe { self.ring.write_at(self.cursor, &record[..len]) };
+        self.cursor = self.cursor.wrapping_add(len as u32);
+        true
+    }
+
+    /// 渲染服务读到哪里
+    fn head(&self) -> u32 {
+        self.ring.header().head.load(Ordering::Acquire)
+    }
+
+    /// 还能写多少字节
+    fn free(&self) -> u32 {
+        self.ring.capacity - self.cursor.wrapping_sub(self.head())
+    }
+
+    // 下面的方法各录入一条命令，返回值和push一样（false：这条命令丢掉了）。
+    // 颜色可以是Rgb（不透明）或Rgba（和底下的像素按alpha混合）
+
+    pub fn pixel(&mut self, x: u16, y: u16, color: impl Into<Rgba>) -> bool {
+        self.push(&RenderCommand::Pixel { x, y, color: color.into() })
+    }
+
+    pub fn rect(&mut self, x: u16, y: u16, w: u16, h: u16, color: impl Into<Rgba>) -> bool {
+        self.push(&RenderCommand::Rect { x, y, w, h, color: color.into() })
+    }
+
+    // 文字命令里的text最多IPC_STR_CAPACITY字节（UTF-8），更长时什么都不录，返回false
+    pub fn text(&mut self, x: u16, y: u16, text: &str, color: impl Into<Rgba>) -> bool {
+        self.styled_text(x, y, text, TextStyle::DEFAULT, color)
+    }
+
+    // 指定字体、大小和字重的文字
+    pub fn styled_text(&mut self, x: u16, y: u16, text: &str, style: TextStyle, color: impl Into<Rgba>) -> bool {
+        let Some(text) = IpcStr::try_new(text) else {
+            return false;
+        };
+        self.push(&RenderCommand::Text { x, y, text, style, color: color.into() })
+    }
+
+    // 在(x, y, w, h)里排一段文字：超过w的折行（w为0时不折行，x是对齐的位置），每行按align对齐；
+    // h不为0时整段在框里竖直居中（按钮标签），为0时从y往下排
+    #[allow(clippy::too_many_arguments)]
+    pub fn text_box(&mut self, x: u16, y: u16, w: u16, h: u16, text: &str, style: TextStyle, align: TextAlign, color: impl Into<Rgba>) -> bool {
+        let Some(text) = IpcStr::try_new(text) else {
+            return false;
+        };
+        self.push(&RenderCommand::TextBox { x, y, w, h, text, style, align, color: color.into() })
+    }
+
+    /// 把矩形清成全透明（其他命令都是混合上去的，半透明的背景重画前要先清掉）
+    pub fn clear(&mut self, x: u16, y: u16, w: u16, h: u16) -> bool {
+        self.push(&RenderCommand::Clear { x, y, w, h })
+    }
+
+    pub fn line(&mut self, x0: u16, y0: u16, x1: u16, y1: u16, color: impl Into<Rgba>) -> bool {
+        self.push(&RenderCommand::Line { x0, y0, x1, y1, color: color.into() })
+    }
+
+    /// 矩形边框（线宽thickness，画在矩形里面）
+    pub fn stroke_rect(&mut self, x: u16, y: u16, w: u16, h: u16, thickness: u16, color: impl Into<Rgba>) -> bool {
+        self.push(&RenderCommand::StrokeRect { x, y, w, h, thickness, color: color.into() })
+    }
+
+    /// 椭圆（thickness为0时填充）
+    pub fn ellipse(&mut self, cx: u16, cy: u16, rx: u16, ry: u16, thickness: u16, color: impl Into<Rgba>) -> bool {
+        self.push(&RenderCommand::Ellipse { cx, cy, rx, ry, thickness, color: color.into() })
+    }
+
+    pub fn circle(&mut self, cx: u16, cy: u16, radius: u16, thickness: u16, color: impl Into<Rgba>) -> bool {
+        self.ellipse(cx, cy, radius, radius, thickness, color)
+    }
+
+    /// 圆角矩形（thickness为0时填充）
+    #[allow(clippy::too_many_arguments)]
+    pub fn round_rect(&mut self, x: u16, y: u16, w: u16, h: u16, radius: u16, thickness: u16, color: impl Into<Rgba>) -> bool {
+        self.push(&RenderCommand::RoundRect { x, y, w, h, radius, thickness, color: color.into() })
+    }
+
+    /// 填充多边形（最多POLYGON_CAPACITY个顶点，多出来的忽略）
+    pub fn polygon(&mut self, points: &[Point], color: impl Into<Rgba>) -> bool {
+        self.push(&RenderCommand::Polygon { points: PointList::from_slice(points), color: color.into() })
+    }
+
+    /// 把位图缩放画到(x, y, w, h)（w、h和位图一样大时不缩放）；
+    /// 渲染服务执行时才读像素，在那之前不要改image
+    pub fn blit(&mut self, image: &Image, x: u16, y: u16, w: u16, h: u16, mode: BlitMode) -> bool {
+        let (width, height) = (image.width(), image.height());
+        self.push(&RenderCommand::Blit { image: image.handle(), width, height, x, y, w, h, mode })
+    }
+
+    /// 切换绘图目标（窗口的表面或0：桌面背景），同时清空裁剪栈
+    pub fn target(&mut self, surface: SurfaceId) -> bool {
+        self.push(&RenderCommand::Target { surface })
+    }
+
+    /// 压入裁剪矩形：到对应的pop_clip为止，只画在它和外层裁剪区的交集里
+    pub fn push_clip(&mut self, x: u16, y: u16, w: u16, h: u16) -> bool {
+        self.push(&RenderCommand::PushClip { x, y, w, h })
+    }
+
+    pub fn pop_clip(&mut self) -> bool {
+        self.push(&RenderCommand::PopClip)
+    }
+
+    /// 把已录的命令交给渲染服务执行，但不呈现（后面还有别人要接着画）
+    pub fn flush(&mut self) {
+        self.publish();
+    }
+
+    /// 结束这一批：渲染服务执行完后呈现到屏幕上（Commit没录进去时返回false，已录的照样执行）
+    pub fn submit(&mut self) -> bool {
+        let committed = self.push(&RenderCommand::Commit);
+        self.publish();
+        committed
+    }
+
+    /// 通知渲染服务执行已录的命令（没有新命令时不发消息）
+    fn publish(&mut self) {
+        if self.published == self.cursor {
+            return;
+        }
+        // 命令字节要先于通知对渲染服务可见
+        core::sync::atomic::fence(Ordering::Release);
+        self.published = self.cursor;
+        self.notify();
+    }
+
+    /// 告诉渲染服务执行到published为止
+    fn notify(&self) {
+        let submit = IpcMessage::RenderSubmit { ring: self.shm, size: self.size as u32, end: self.published };
+        kernel::send(self.render_pid, &submit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host;
+    use alloc::vec::Vec;
+
+    const RENDER: Pid = 7;
+
+    /// 执行完已经提交的命令（模拟渲染服务）
+    fn drain(buffer: &RenderCommandBuffer) -> usize {
+        let mut count = 0;
+        buffer.ring.drain(buffer.published, |_| count += 1);
+        count
+    }
+
+    /// 执行到end为止（模拟渲染服务分几次读），返回读到的矩形的x
+    fn drain_to(buffer: &RenderCommandBuffer, end: u32) -> Vec<u16> {
+        let mut xs = Vec::new();
+        buffer.ring.drain(end, |command| match command {
+            RenderCommand::Rect { x, .. } => xs.push(x),
+            other => panic!("意外的命令：{other:?}"),
+        });
+        xs
+    }
+
+    fn rect(x: u16) -> RenderCommand {
+        RenderCommand::Rect { x, y: 0, w: 1, h: 1, color: Rgba::new(1, 2, 3, 4) }
+    }
+
+    /// 一条rect命令编码后的字节数
+    fn rect_len() -> u32 {
+        wire::encode_command(&rect(0), &mut [0; MAX_COMMAND_SIZE]).unwrap() as u32
+    }
+
+    /// 一直录到缓冲区满，返回录进去了几条
+    fn fill(buffer: &mut RenderCommandBuffer) -> usize {
+        let mut count = 0;
+        while buffer.rect(0, 0, 1, 1, Rgba::new(1, 2, 3, 4)) {
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn full_ring_times_out() {
+        let mut buffer = RenderCommandBuffer::with_capacity(RENDER, 256).unwrap();
+        let start = host::now();
+        let recorded = fill(&mut buffer);
+        assert!(recorded > 0);
+        // 满了先把已录的交给渲染服务，等PUSH_TIMEOUT_MS之后放弃
+        assert!(host::now() - start >= PUSH_TIMEOUT_MS);
+        let end = buffer.cursor;
+        assert_eq!(host::sent(), [(RENDER, IpcMessage::RenderSubmit { ring: buffer.shm, size: buffer.size as u32, end })]);
+        // 渲染服务一直没读：不再等，只重发通知
+        let start = host::now();
+        assert!(!buffer.submit());
+        assert!(host::now() - start < PUSH_TIMEOUT_MS);
+        assert_eq!(host::sent(), [(RENDER, IpcMessage::RenderSubmit { ring: buffer.shm, size: buffer.size as u32, end })]);
+        // 读完之后又能录了
+        assert_eq!(drain(&buffer), recorded);
+        assert!(buffer.submit());
+        assert_eq!(drain(&buffer), 1);
+    }
+
+    #[test]
+    fn free_space_follows_partial_drains() {
+        let mut buffer = RenderCommandBuffer::with_capacity(RENDER, 200).unwrap();
+        assert_eq!(buffer.ring.capacity, 256);
+        assert_eq!(buffer.free(), 256);
+        let len = rect_len();
+        for x in 0..4 {
+            assert!(buffer.push(&rect(x)));
+        }
+        assert_eq!(buffer.free(), 256 - 4 * len);
+        buffer.flush();
+        // 渲染服务先只读了一条
+        assert_eq!(drain_to(&buffer, len), [0]);
+        assert_eq!(buffer.free(), 256 - 3 * len);
+        // 过期的通知（end在已读位置之前）什么都不做
+        assert_eq!(drain_to(&buffer, 0), []);
+        assert_eq!(drain_to(&buffer, buffer.published), [1, 2, 3]);
+        assert_eq!(buffer.free(), 256);
+        assert_eq!(host::sent().len(), 1);
+    }
+
+    #[test]
+    fn records_wrap_around_end_of_ring() {
+        let mut buffer = RenderCommandBuffer::with_capacity(RENDER, 256).unwrap();
+        let len = rect_len();
+        let per_ring = (256 / len) as u16;
+        // 先写满一圈再读完，下一圈的记录会跨过数据区末尾
+        for x in 0..per_ring {
+            assert!(buffer.push(&rect(x)));
+        }
+        buffer.flush();
+        assert_eq!(drain_to(&buffer, buffer.published).len(), per_ring as usize);
+        assert_ne!(buffer.cursor % 256, 0);
+        let start = buffer.cursor;
+        for x in 100..100 + per_ring {
+            assert!(buffer.push(&rect(x)));
+        }
+        assert_eq!(buffer.free(), 256 - per_ring as u32 * len);
+        buffer.flush();
+        // 分三次读
+        let mut xs = drain_to(&buffer, start + 5 * len);
+        xs.extend(drain_to(&buffer, start + 11 * len));
+        xs.extend(drain_to(&buffer, buffer.published));
+        assert_eq!(xs, (100..100 + per_ring).collect::<Vec<_>>());
+        assert_eq!(buffer.free(), 256);
+        assert_eq!(host::sent().len(), 2);
+    }
+
+    #[test]
+    fn positions_wrap_around_u32() {
+        let mut buffer = RenderCommandBuffer::with_capacity(RENDER, 256).unwrap();
+        let len = rect_len();
+        // 位置快到u32::MAX：录几条之后回绕到0附近
+        let start = 0u32.wrapping_sub(2 * len + 3);
+        buffer.ring.header().head.store(start, Ordering::Relaxed);
+        (buffer.cursor, buffer.published) = (start, start);
+        assert_eq!(buffer.free(), 256);
+        for x in 0..5 {
+            assert!(buffer.push(&rect(x)));
+        }
+        assert!(buffer.cursor < start);
+        assert_eq!(buffer.free(), 256 - 5 * len);
+        buffer.flush();
+        assert_eq!(drain_to(&buffer, start.wrapping_add(3 * len)), [0, 1, 2]);
+        assert_eq!(drain_to(&buffer, buffer.published), [3, 4]);
+        assert_eq!(buffer.free(), 256);
+        assert_eq!(host::sent().len(), 1);
+    }
+}
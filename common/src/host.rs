@@ -0,0 +1,110 @@
+// ==========================
+// 宿主上的单元测试用的内核接口
+// ==========================
+// 测试时没有内核：共享内存用堆内存代替（句柄就是内存的地址，记下是哪个进程分配的，run_as切换），
+// 时钟每读一次走1毫秒，
+// 发出去的消息记在本线程的列表里（sent取出来检查），收消息从本线程的信箱里取（deliver放进去）。
+
+extern crate std;
+
+use crate::{wire::{self, Correlation}, IpcMessage, Pid};
+use core::cell::{Cell, RefCell};
+use std::{
+    alloc::{alloc_zeroed, Layout},
+    collections::VecDeque,
+    vec::Vec,
+};
+
+std::thread_local! {
+    static NOW: Cell<u64> = const { Cell::new(0) };
+    static SENT: RefCell<Vec<(Pid, Vec<u8>)>> = const { RefCell::new(Vec::new()) };
+    static INBOX: RefCell<VecDeque<(Pid, Vec<u8>)>> = const { RefCell::new(VecDeque::new()) };
+    static PID: Cell<Pid> = const { Cell::new(1) };
+    static SHM: RefCell<Vec<(u64, usize, Pid)>> = const { RefCell::new(Vec::new()) }; // 地址、大小、分配者
+}
+
+/// 之后本线程当作pid这个进程分配共享内存
+pub(crate) fn run_as(pid: Pid) {
+    PID.with(|current| current.set(pid));
+}
+
+/// 往本线程的信箱里放一条sender发来的消息
+pub(crate) fn deliver(sender: Pid, msg: &IpcMessage, correlation: Correlation) {
+    let mut buf = [0u8; wire::MAX_MESSAGE_SIZE];
+    let len = wire::encode_frame(msg, correlation, &mut buf).unwrap();
+    INBOX.with(|inbox| inbox.borrow_mut().push_back((sender, buf[..len].to_vec())));
+}
+
+/// 取出本线程到现在为止发出的消息
+pub(crate) fn sent() -> Vec<(Pid, IpcMessage)> {
+    let sent = SENT.with(|sent| sent.take());
+    sent.into_iter().map(|(pid, bytes)| (pid, wire::decode(&bytes).unwrap())).collect()
+}
+
+/// 本线程的时钟（毫秒）
+pub(crate) fn now() -> u64 {
+    NOW.with(Cell::get)
+}
+
+#[no_mangle]
+extern "C" fn shm_alloc(size: usize) -> u64 {
+    let handle = match Layout::from_size_align(size, 4096) {
+        Ok(layout) if size > 0 => unsafe { alloc_zeroed(layout) as u64 },
+        _ => return 0,
+    };
+    SHM.with(|shm| shm.borrow_mut().push((handle, size, PID.with(Cell::get))));
+    handle
+}
+
+#[no_mangle]
+extern "C" fn shm_owner(handle: u64, size: usize) -> Pid {
+    let inside = |&&(base, len, _): &&(u64, usize, Pid)| handle >= base && handle.saturating_add(size as u64) <= base + len as u64;
+    SHM.with(|shm| shm.borrow().iter().find(inside).map_or(0, |&(_, _, owner)| owner))
+}
+
+#[no_mangle]
+extern "C" fn map_phys(phys_addr: u64, _size: usize) -> *mut u8 {
+    phys_addr as *mut u8
+}
+
+#[no_mangle]
+unsafe extern "C" fn ipc_send_bytes(pid: Pid, buf: *const u8, len: usize) {
+    let bytes = std::slice::from_raw_parts(buf, len).to_vec();
+    SENT.with(|sent| sent.borrow_mut().push((pid, bytes)));
+}
+
+#[no_mangle]
+extern "C" fn kernel_uptime_ms() -> u64 {
+    NOW.with(|now| {
+        now.set(now.get() + 1);
+        now.get()
+    })
+}
+
+/// 从信箱里取一条消息写给调用方，返回长度（信箱空了返回0）
+unsafe fn receive(sender_pid: *mut Pid, buf: *mut u8, cap: usize) -> usize {
+    let Some((sender, bytes)) = INBOX.with(|inbox| inbox.borrow_mut().pop_front()) else {
+        return 0;
+    };
+    let len = bytes.len().min(cap);
+    core::ptr::copy_nonoverlapping(bytes.as_ptr(), buf, len);
+    *sender_pid = sender;
+    len
+}
+
+#[no_mangle]
+unsafe extern "C" fn ipc_recv_bytes(sender_pid: *mut Pid, buf: *mut u8, cap: usize) -> usize {
+    match receive(sender_pid, buf, cap) {
+        0 => panic!("信箱是空的，ipc_recv_bytes会一直等下去"),
+        len => len,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn ipc_recv_bytes_timeout(sender_pid: *mut Pid, buf: *mut u8, cap: usize, timeout_ms: u64) -> usize {
+    let len = receive(sender_pid, buf, cap);
+    if len == 0 {
+        NOW.with(|now| now.set(now.get().saturating_add(timeout_ms))); // 等到超时
+    }
+    len
+}
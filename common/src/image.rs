@@ -0,0 +1,579 @@
+// ==========================
+// 位图与图片解码
+// ==========================
+// 图标、壁纸等图片作为文件随程序一起发布（比如include_bytes!），运行时解码成和渲染服务的表面相同的像素：
+// 预乘alpha的0xAARRGGBB，每行width个，从上到下。解码结果放在共享内存里（Image），
+// 用RenderCommandBuffer::blit让渲染服务画到表面上（可以缩放、按颜色键或alpha透明）。
+//
+// 支持的格式（按文件开头的魔数识别）：
+//   BMP：未压缩的1/4/8位调色板和24位；32位的BI_RGB当作不透明，BI_BITFIELDS按掩码取通道（可以带alpha）
+//   PPM：P3（文本）和P6（二进制），最大值不超过255
+//   QOI：全部（RGB和RGBA）
+
+use crate::{kernel::SharedMemory, Pixel, Rgb, Rgba, ShmHandle};
+
+/// 位图画到表面上的方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlitMode {
+    Opaque,        // 直接覆盖（半透明的像素按原来的颜色画成不透明）
+    ColorKey(Rgb), // 颜色和key相同的像素不画，其余直接覆盖
+    Alpha,         // 按像素的alpha和底下混合
+}
+
+/// 图片文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Bmp,
+    Ppm,
+    Qoi,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    Unsupported,    // 不认识的格式或不支持的变体（比如RLE压缩的BMP、16位PPM）
+    Malformed,      // 文件损坏或被截断
+    TooLarge,       // 宽或高超过u16
+    BufferTooSmall, // decode_into的输出缓冲区放不下
+    OutOfMemory,    // 分配不到共享内存
+}
+
+/// 按魔数识别格式
+pub fn format(data: &[u8]) -> Option<ImageFormat> {
+    match data {
+        [b'B', b'M', ..] => Some(ImageFormat::Bmp),
+        [b'P', b'3' | b'6', ..] => Some(ImageFormat::Ppm),
+        [b'q', b'o', b'i', b'f', ..] => Some(ImageFormat::Qoi),
+        _ => None,
+    }
+}
+
+/// 只读文件头，返回(宽, 高)
+pub fn dimensions(data: &[u8]) -> Result<(u16, u16), ImageError> {
+    match format(data) {
+        Some(ImageFormat::Bmp) => Ok(bmp_header(data)?.size()),
+        Some(ImageFormat::Ppm) => Ok(ppm_header(data)?.size()),
+        Some(ImageFormat::Qoi) => qoi_header(data),
+        None => Err(ImageError::Unsupported),
+    }
+}
+
+/// 解码到out（至少宽×高个像素，按行从上到下），返回(宽, 高)
+pub fn decode_into(data: &[u8], out: &mut [Pixel]) -> Result<(u16, u16), ImageError> {
+    let (width, height) = dimensions(data)?;
+    let out = out.get_mut(..width as usize * height as usize).ok_or(ImageError::BufferTooSmall)?;
+    match format(data) {
+        Some(ImageFormat::Bmp) => decode_bmp(data, out)?,
+        Some(ImageFormat::Ppm) => decode_ppm(data, out)?,
+        Some(ImageFormat::Qoi) => decode_qoi(data, out)?,
+        None => return Err(ImageError::Unsupported),
+    }
+    Ok((width, height))
+}
+
+// ==========================
+// 位图（放在共享内存里，渲染服务可以直接读）
+// ==========================
+/// 解码好的位图
+pub struct Image {
+    memory: SharedMemory,
+    width: u16,
+    height: u16,
+}
+
+impl Image {
+    /// width×height的全透明位图，内存不足时返回None
+    pub fn new(width: u16, height: u16) -> Option<Image> {
+        let memory = SharedMemory::alloc(width as usize * height as usize * 4)?;
+        let mut image = Image { memory, width, height };
+        image.pixels_mut().fill(0);
+        Some(image)
+    }
+
+    /// 解码图片文件
+    pub fn decode(data: &[u8]) -> Result<Image, ImageError> {
+        let (width, height) = dimensions(data)?;
+        let mut image = Image::new(width, height).ok_or(ImageError::OutOfMemory)?;
+        decode_into(data, image.pixels_mut())?;
+        Ok(image)
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// 像素所在的共享内存（blit命令里带的就是它）
+    pub fn handle(&self) -> ShmHandle {
+        self.memory.handle()
+    }
+
+    /// 预乘的0xAARRGGBB像素，每行width个；改的时候注意渲染服务可能还没执行完之前的blit
+    pub fn pixels_mut(&mut self) -> &mut [Pixel] {
+        let len = self.width as usize * self.height as usize;
+        unsafe { core::slice::from_raw_parts_mut(self.memory.as_ptr() as *mut Pixel, len) }
+    }
+}
+
+/// 宽高都要能放进u16
+fn checked_size(width: u32, height: u32) -> Result<(u16, u16), ImageError> {
+    if width == 0 || height == 0 {
+        return Err(ImageError::Malformed);
+    }
+    match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => Err(ImageError::TooLarge),
+    }
+}
+
+fn premultiplied(r: u8, g: u8, b: u8, a: u8) -> Pixel {
+    Rgba::new(r, g, b, a).to_premultiplied()
+}
+
+// ==========================
+// BMP
+// ==========================
+// [文件头14字节][信息头（至少40字节的BITMAPINFOHEADER）][调色板或通道掩码][像素]
+// 每行补齐到4字节；高为正数时从最下面一行开始存。
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+struct BmpHeader {
+    width: u16,
+    height: u16,
+    bottom_up: bool,
+    bpp: u16,
+    pixels: usize,       // 像素数据的偏移
+    palette: usize,      // 调色板的偏移（每项BGRx四字节）
+    colors: usize,       // 调色板项数
+    masks: Option<[u32; 4]>, // BI_BITFIELDS的红绿蓝alpha掩码
+}
+
+impl BmpHeader {
+    fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn stride(&self) -> usize {
+        (self.width as usize * self.bpp as usize).div_ceil(32) * 4
+    }
+}
+
+fn le_u16(data: &[u8], offset: usize) -> Result<u16, ImageError> {
+    let bytes = data.get(offset..offset + 2).ok_or(ImageError::Malformed)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn le_u32(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    let bytes = data.get(offset..offset + 4).ok_or(ImageError::Malformed)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn bmp_header(data: &[u8]) -> Result<BmpHeader, ImageError> {
+    let pixels = le_u32(data, 10)? as usize;
+    let info_size = le_u32(data, 14)? as usize;
+    if info_size < 40 {
+        return Err(ImageError::Unsupported); // OS/2的12字节信息头
+    }
+    let width = le_u32(data, 18)? as i32;
+    let height = le_u32(data, 22)? as i32;
+    let bpp = le_u16(data, 28)?;
+    let compression = le_u32(data, 30)?;
+    let colors_used = le_u32(data, 46)? as usize;
+    if width <= 0 || height == 0 {
+        return Err(ImageError::Malformed);
+    }
+    let bottom_up = height > 0;
+    let (width, height) = checked_size(width as u32, height.unsigned_abs())?;
+    let masks = match (compression, bpp) {
+        (BI_RGB, 1 | 4 | 8 | 24 | 32) => None,
+        // 掩码在信息头里（V3以上），或者紧跟在40字节的信息头后面
+        (BI_BITFIELDS, 32) => {
+            let alpha = if info_size >= 56 { le_u32(data, 66)? } else { 0 };
+            Some([le_u32(data, 54)?, le_u32(data, 58)?, le_u32(data, 62)?, alpha])
+        }
+        _ => return Err(ImageError::Unsupported),
+    };
+    let colors = match bpp {
+        1 | 4 | 8 if colors_used == 0 => 1 << bpp,
+        1 | 4 | 8 => colors_used.min(1 << bpp),
+        _ => 0,
+    };
+    let header = BmpHeader {
+        width,
+        height,
+        bottom_up,
+        bpp,
+        pixels,
+        palette: 14 + info_size,
+        colors,
+        masks,
+    };
+    if header.palette + header.colors * 4 > data.len() || header.pixels + header.stride() * height as usize > data.len() {
+        return Err(ImageError::Malformed);
+    }
+    Ok(header)
+}
+
+/// 按掩码取出一个通道并换算到8位（掩码为空时返回default）
+fn bmp_channel(value: u32, mask: u32, default: u8) -> u8 {
+    if mask == 0 {
+        return default;
+    }
+    let shift = mask.trailing_zeros();
+    let bits = (mask >> shift).count_ones();
+    let v = (value & mask) >> shift;
+    match bits {
+        8.. => (v >> (bits - 8)) as u8,
+        _ => (v * 255 / ((1 << bits) - 1)) as u8,
+    }
+}
+
+fn decode_bmp(data: &[u8], out: &mut [Pixel]) -> Result<(), ImageError> {
+    let header = bmp_header(data)?;
+    let (width, height) = (header.width as usize, header.height as usize);
+    let stride = header.stride();
+    for (y, out_row) in out.chunks_exact_mut(width).enumerate() {
+        let stored = if header.bottom_up { height - 1 - y } else { y };
+        let row = &data[header.pixels + stored * stride..][..stride];
+        for (x, pixel) in out_row.iter_mut().enumerate() {
+            *pixel = match header.bpp {
+                24 => premultiplied(row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255),
+                32 => {
+                    let v = u32::from_le_bytes([row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]]);
+                    match header.masks {
+                        Some([r, g, b, a]) => premultiplied(
+                            bmp_channel(v, r, 0),
+                            bmp_channel(v, g, 0),
+                            bmp_channel(v, b, 0),
+                            bmp_channel(v, a, 255),
+                        ),
+                        None => premultiplied((v >> 16) as u8, (v >> 8) as u8, v as u8, 255),
+                    }
+                }
+                bpp => {
+                    // 调色板：每字节从高位起放8/bpp个索引
+                    let bit = x * bpp as usize;
+                    let index = (row[bit / 8] >> (8 - bpp as usize - bit % 8)) as usize & ((1 << bpp) - 1);
+                    if index >= header.colors {
+                        return Err(ImageError::Malformed);
+                    }
+                    let entry = &data[header.palette + index * 4..][..3];
+                    premultiplied(entry[2], entry[1], entry[0], 255)
+                }
+            };
+        }
+    }
+    Ok(())
+}
+
+// ==========================
+// PPM
+// ==========================
+// "P6 宽 高 最大值"加一个空白字符后是二进制的RGB；P3的像素也是十进制文本。
+// 头部的数字之间可以有任意空白和#开头的注释。
+struct PpmHeader {
+    binary: bool,
+    width: u16,
+    height: u16,
+    max: u32,
+    pixels: usize, // 像素数据的偏移
+}
+
+impl PpmHeader {
+    fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+}
+
+/// 从pos开始跳过空白和注释读一个十进制数，返回(数, 数后面的位置)
+fn ppm_number(data: &[u8], mut pos: usize) -> Result<(u32, usize), ImageError> {
+    loop {
+        match data.get(pos) {
+            Some(b'#') => {
+                while data.get(pos).is_some_and(|c| *c != b'\n') {
+                    pos += 1;
+                }
+            }
+            Some(c) if c.is_ascii_whitespace() => pos += 1,
+            Some(c) if c.is_ascii_digit() => break,
+            _ => return Err(ImageError::Malformed),
+        }
+    }
+    let mut value: u32 = 0;
+    while let Some(c) = data.get(pos).filter(|c| c.is_ascii_digit()) {
+        value = value.checked_mul(10).and_then(|v| v.checked_add((c - b'0') as u32)).ok_or(ImageError::Malformed)?;
+        pos += 1;
+    }
+    Ok((value, pos))
+}
+
+fn ppm_header(data: &[u8]) -> Result<PpmHeader, ImageError> {
+    let binary = data.get(1) == Some(&b'6');
+    let (width, pos) = ppm_number(data, 2)?;
+    let (height, pos) = ppm_number(data, pos)?;
+    let (max, pos) = ppm_number(data, pos)?;
+    let (width, height) = checked_size(width, height)?;
+    match max {
+        0 => return Err(ImageError::Malformed),
+        256.. => return Err(ImageError::Unsupported), // 每个分量两字节
+        _ => {}
+    }
+    // 最大值后面正好一个空白字符
+    if !data.get(pos).is_some_and(|c| c.is_ascii_whitespace()) {
+        return Err(ImageError::Malformed);
+    }
+    let header = PpmHeader { binary, width, height, max, pixels: pos + 1 };
+    if binary && header.pixels + width as usize * height as usize * 3 > data.len() {
+        return Err(ImageError::Malformed);
+    }
+    Ok(header)
+}
+
+fn decode_ppm(data: &[u8], out: &mut [Pixel]) -> Result<(), ImageError> {
+    let header = ppm_header(data)?;
+    let scale = |v: u32| -> Result<u8, ImageError> {
+        if v > header.max {
+            return Err(ImageError::Malformed);
+        }
+        Ok((v * 255 / header.max) as u8)
+    };
+    let mut pos = header.pixels;
+    for pixel in out.iter_mut() {
+        let mut rgb = [0u8; 3];
+        for channel in &mut rgb {
+            let v = if header.binary {
+                pos += 1;
+                data[pos - 1] as u32
+            } else {
+                let (v, next) = ppm_number(data, pos)?;
+                pos = next;
+                v
+            };
+            *channel = scale(v)?;
+        }
+        *pixel = premultiplied(rgb[0], rgb[1], rgb[2], 255);
+    }
+    Ok(())
+}
+
+// ==========================
+// QOI（Quite OK Image，https://qoiformat.org）
+// ==========================
+// [qoif][宽u32][高u32][通道数u8][色彩空间u8]（大端），之后是一串操作码，最后8字节结束标记。
+const QOI_HEADER_SIZE: usize = 14;
+const QOI_OP_RGB: u8 = 0xFE;
+const QOI_OP_RGBA: u8 = 0xFF;
+const QOI_OP_INDEX: u8 = 0x00; // 高两位
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80; // 0xC0是QOI_OP_RUN
+
+fn qoi_header(data: &[u8]) -> Result<(u16, u16), ImageError> {
+    let header = data.get(..QOI_HEADER_SIZE).ok_or(ImageError::Malformed)?;
+    let width = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let height = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    if !matches!(header[12], 3 | 4) || header[13] > 1 {
+        return Err(ImageError::Malformed);
+    }
+    checked_size(width, height)
+}
+
+fn decode_qoi(data: &[u8], out: &mut [Pixel]) -> Result<(), ImageError> {
+    let mut seen = [Rgba::new(0, 0, 0, 0); 64];
+    let mut px = Rgba::new(0, 0, 0, 255);
+    let mut pos = QOI_HEADER_SIZE;
+    let mut next = || -> Result<u8, ImageError> {
+        pos += 1;
+        data.get(pos - 1).copied().ok_or(ImageError::Malformed)
+    };
+    let mut run = 0;
+    for pixel in out.iter_mut() {
+        if run > 0 {
+            run -= 1;
+        } else {
+            let op = next()?;
+            match op {
+                QOI_OP_RGB => px = Rgba::new(next()?, next()?, next()?, px.a),
+                QOI_OP_RGBA => px = Rgba::new(next()?, next()?, next()?, next()?),
+                _ => match op & 0xC0 {
+                    QOI_OP_INDEX => px = seen[op as usize],
+                    QOI_OP_DIFF => {
+                        px.r = px.r.wrapping_add((op >> 4) & 3).wrapping_sub(2);
+                        px.g = px.g.wrapping_add((op >> 2) & 3).wrapping_sub(2);
+                        px.b = px.b.wrapping_add(op & 3).wrapping_sub(2);
+                    }
+                    QOI_OP_LUMA => {
+                        let dg = (op & 0x3F).wrapping_sub(32);
+                        let second = next()?;
+                        px.r = px.r.wrapping_add(dg.wrapping_sub(8).wrapping_add(second >> 4));
+                        px.g = px.g.wrapping_add(dg);
+                        px.b = px.b.wrapping_add(dg.wrapping_sub(8).wrapping_add(second & 0x0F));
+                    }
+                    _ => run = op & 0x3F, // RUN：这个像素和后面run个都和上一个一样
+                },
+            }
+            let hash = (px.r as usize * 3 + px.g as usize * 5 + px.b as usize * 7 + px.a as usize * 11) % 64;
+            seen[hash] = px;
+        }
+        *pixel = px.to_premultiplied();
+    }
+    // 最后一个RUN不能超出图片
+    if run > 0 {
+        return Err(ImageError::Malformed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    const RED: Pixel = 0xFFFF_0000;
+    const GREEN: Pixel = 0xFF00_FF00;
+    const BLUE: Pixel = 0xFF00_00FF;
+    const WHITE: Pixel = 0xFFFF_FFFF;
+
+    fn decode(data: &[u8]) -> Result<(u16, u16, Vec<Pixel>), ImageError> {
+        let mut out = [0; 16];
+        let (width, height) = decode_into(data, &mut out)?;
+        Ok((width, height, out[..width as usize * height as usize].to_vec()))
+    }
+
+    /// BMP文件：40字节信息头，rows按文件里的顺序（每行已经补齐到4字节）
+    fn bmp(bpp: u16, width: i32, height: i32, colors_used: u32, palette: &[[u8; 4]], rows: &[&[u8]]) -> Vec<u8> {
+        let pixels = 14 + 40 + palette.len() * 4;
+        let mut data = Vec::new();
+        data.extend_from_slice(b"BM");
+        data.extend_from_slice(&0u32.to_le_bytes()); // 文件大小（不检查）
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(pixels as u32).to_le_bytes());
+        data.extend_from_slice(&40u32.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&bpp.to_le_bytes());
+        data.extend_from_slice(&BI_RGB.to_le_bytes());
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&colors_used.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for entry in palette {
+            data.extend_from_slice(entry);
+        }
+        for row in rows {
+            data.extend_from_slice(row);
+        }
+        data
+    }
+
+    #[test]
+    fn bmp_24bit_bottom_up() {
+        // 最下面一行先存：蓝、白；上面一行：红、绿（BGR，每行补2字节）
+        let data = bmp(24, 2, 2, 0, &[], &[&[255, 0, 0, 255, 255, 255, 0, 0], &[0, 0, 255, 0, 255, 0, 0, 0]]);
+        assert_eq!(dimensions(&data), Ok((2, 2)));
+        assert_eq!(decode(&data), Ok((2, 2, [RED, GREEN, BLUE, WHITE].to_vec())));
+    }
+
+    #[test]
+    fn bmp_palette() {
+        // 4位调色板，从上往下存（高为负数）：一行3个像素，索引1、0、1
+        let palette = [[0, 0, 255, 0], [255, 0, 0, 0]];
+        let data = bmp(4, 3, -1, 2, &palette, &[&[0x10, 0x10, 0, 0]]);
+        assert_eq!(decode(&data), Ok((3, 1, [BLUE, RED, BLUE].to_vec())));
+        // 索引超出调色板
+        let data = bmp(4, 3, -1, 2, &palette, &[&[0x12, 0x10, 0, 0]]);
+        assert_eq!(decode(&data), Err(ImageError::Malformed));
+    }
+
+    #[test]
+    fn bmp_rejects_truncated_and_unsupported() {
+        let data = bmp(24, 2, 2, 0, &[], &[&[0; 8], &[0; 8]]);
+        for len in [2, 20, 53, data.len() - 1] {
+            assert_eq!(decode(&data[..len]), Err(ImageError::Malformed), "{len}");
+        }
+        // 调色板被截断
+        let data = bmp(8, 1, 1, 4, &[[0; 4]; 2], &[]);
+        assert_eq!(decode(&data), Err(ImageError::Malformed));
+        assert_eq!(decode(&bmp(16, 1, 1, 0, &[], &[&[0; 4]])), Err(ImageError::Unsupported));
+        assert_eq!(decode(&bmp(24, 0, 1, 0, &[], &[])), Err(ImageError::Malformed));
+        assert_eq!(decode(&bmp(24, 70000, 1, 0, &[], &[])), Err(ImageError::TooLarge));
+    }
+
+    #[test]
+    fn ppm_binary_and_text() {
+        let mut data = b"P6\n# comment\n2 1\n255\n".to_vec();
+        data.extend_from_slice(&[255, 0, 0, 0, 255, 0]);
+        assert_eq!(decode(&data), Ok((2, 1, [RED, GREEN].to_vec())));
+        // 最大值15时按比例放大到255
+        assert_eq!(decode(b"P3 2 1 15\n15 15 15  0 0 15\n"), Ok((2, 1, [WHITE, BLUE].to_vec())));
+    }
+
+    #[test]
+    fn ppm_rejects_bad_data() {
+        assert_eq!(decode(b"P6 2 1 255\n\xFF\x00\x00\x00\xFF"), Err(ImageError::Malformed));
+        assert_eq!(decode(b"P3 2 1 255\n255 0 0 0 255"), Err(ImageError::Malformed));
+        assert_eq!(decode(b"P3 1 1 0\n0 0 0"), Err(ImageError::Malformed));
+        assert_eq!(decode(b"P3 1 1 256\n0 0 0"), Err(ImageError::Unsupported));
+        assert_eq!(decode(b"P3 1 1 65535\n0 0 0"), Err(ImageError::Unsupported));
+        // 分量超过最大值
+        assert_eq!(decode(b"P3 1 1 15\n16 0 0"), Err(ImageError::Malformed));
+        assert_eq!(decode(b"P3 1 1 255\n99999999999 0 0"), Err(ImageError::Malformed));
+        assert_eq!(decode(b"P6 1 1"), Err(ImageError::Malformed));
+    }
+
+    /// QOI文件：头部 + 操作码 + 结束标记
+    fn qoi(width: u32, height: u32, ops: &[u8]) -> Vec<u8> {
+        let mut data = b"qoif".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[4, 0]);
+        data.extend_from_slice(ops);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        data
+    }
+
+    #[test]
+    fn qoi_ops() {
+        let data = qoi(
+            6,
+            1,
+            &[
+                QOI_OP_RGB, 255, 0, 0,           // 红
+                QOI_OP_RGBA, 0, 0, 255, 128,     // 半透明蓝
+                0xC0 | 1,                        // RUN：再来2个
+                QOI_OP_LUMA | 32, 0x88,          // 不变（dg=0, dr-dg=0, db-dg=0）
+                QOI_OP_INDEX | 50,               // INDEX：红色的哈希是(255×3 + 255×11) % 64 = 50
+            ],
+        );
+        let half_blue = Rgba::new(0, 0, 255, 128).to_premultiplied();
+        assert_eq!(decode(&data), Ok((6, 1, [RED, half_blue, half_blue, half_blue, half_blue, RED].to_vec())));
+        let data = qoi(2, 1, &[QOI_OP_RGB, 10, 20, 30, QOI_OP_DIFF | 0b11_10_01]);
+        assert_eq!(decode(&data), Ok((2, 1, [0xFF0A_141E, 0xFF0B_141D].to_vec())));
+    }
+
+    #[test]
+    fn qoi_rejects_bad_data() {
+        let data = qoi(2, 1, &[QOI_OP_RGB, 1, 2, 3, QOI_OP_RGB, 4, 5, 6]);
+        assert_eq!(decode(&data[..QOI_HEADER_SIZE + 6]), Err(ImageError::Malformed));
+        assert_eq!(decode(&data[..10]), Err(ImageError::Malformed));
+        // RUN超出图片（2个像素，RUN一次就是3个）
+        assert_eq!(decode(&qoi(2, 1, &[QOI_OP_RGB, 1, 2, 3, 0xC0 | 1])), Err(ImageError::Malformed));
+        assert_eq!(decode(&qoi(2, 1, &[0xC0 | 61])), Err(ImageError::Malformed));
+        let mut data = qoi(1, 1, &[QOI_OP_RGB, 1, 2, 3]);
+        data[12] = 5; // 通道数
+        assert_eq!(decode(&data), Err(ImageError::Malformed));
+        assert_eq!(decode(&qoi(0, 1, &[])), Err(ImageError::Malformed));
+        assert_eq!(decode(&qoi(1, 1 << 16, &[])), Err(ImageError::TooLarge));
+    }
+
+    #[test]
+    fn decode_into_checks_buffer() {
+        let data = qoi(4, 4, &[0xC0 | 15]);
+        assert_eq!(decode_into(&data, &mut [0; 15]), Err(ImageError::BufferTooSmall));
+        assert_eq!(decode(b"GIF89a"), Err(ImageError::Unsupported));
+    }
+}
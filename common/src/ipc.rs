@@ -0,0 +1,239 @@
+// ==========================
+// 请求-回复式IPC
+// ==========================
+// kernel::send/recv只管收发，收到什么算什么。Endpoint在此之上加了：
+// - call()：给请求分配关联ID，只接受对应的回复，超时返回错误；
+// - 等回复期间收到的无关消息先暂存（队列按需变长，一条都不丢），之后recv()按到达顺序取出；
+// - 超时之后才到的过期回复直接丢弃。
+// 每个进程（服务/应用）应该只用一个Endpoint收消息，否则暂存的消息会丢。
+
+use crate::{
+    kernel,
+    wire::{self, Correlation, WireError},
+    IpcMessage, Pid,
+};
+use alloc::collections::VecDeque;
+
+/// call()的默认超时（毫秒）
+pub const DEFAULT_CALL_TIMEOUT_MS: u64 = 1000;
+
+/// 请求失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcError {
+    NoService,         // 目标PID为0（服务不存在）
+    Encode(WireError), // 请求编码失败
+    Timeout,           // 规定时间内没收到回复
+    UnexpectedReply,   // 收到了回复，但不是期望的消息类型
+    Refused,           // 对方回复了，但拒绝了请求（比如无权访问）
+}
+
+/// 收到的一条消息（带发送者和关联信息）
+#[derive(Debug, Clone, Copy)]
+pub struct Incoming {
+    pub sender: Pid,
+    pub request_id: u32, // 非0表示发送者在等回复，用reply()回复它
+    pub msg: IpcMessage,
+}
+
+/// 进程的IPC端点：分配请求ID并暂存无关消息
+pub struct Endpoint {
+    next_request_id: u32,
+    pending: VecDeque<Incoming>, // 等回复时收到的无关消息（按到达顺序）
+}
+
+impl Endpoint {
+    pub const fn new() -> Self {
+        Endpoint {
+            next_request_id: 1,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// 发送请求并等待对应的回复（默认超时）
+    pub fn call(&mut self, pid: Pid, request: &IpcMessage) -> Result<IpcMessage, IpcError> {
+        self.call_timeout(pid, request, DEFAULT_CALL_TIMEOUT_MS)
+    }
+
+    /// 发送请求并最多等待timeout_ms毫秒；期间的无关消息暂存起来
+    pub fn call_timeout(&mut self, pid: Pid, request: &IpcMessage, timeout_ms: u64) -> Result<IpcMessage, IpcError> {
+        if pid == 0 {
+            return Err(IpcError::NoService);
+        }
+        let request_id = self.next_request_id;
+        // 跳过0（0表示"不是请求"）
+        self.next_request_id = self.next_request_id.wrapping_add(1).max(1);
+        send_frame(pid, request, Correlation { request_id, reply_to: 0 })?;
+
+        let deadline = unsafe { kernel::kernel_uptime_ms() }.saturating_add(timeout_ms); // 调用方可能传很大的超时（比如u64::MAX）
+        loop {
+            let now = unsafe { kernel::kernel_uptime_ms() };
+            if now >= deadline {
+                return Err(IpcError::Timeout);
+            }
+            let Some((correlation, incoming)) = recv_frame(deadline - now) else {
+                return Err(IpcError::Timeout);
+            };
+            if correlation.reply_to == request_id && incoming.sender == pid {
+                return Ok(incoming.msg);
+            }
+            if correlation.reply_to == 0 {
+                self.pending.push_back(incoming);
+            }
+            // 其他请求的过期回复：丢弃
+        }
+    }
+
+    /// 接收下一条消息：先取暂存的，再阻塞等内核
+    pub fn recv(&mut self) -> Incoming {
+        if let Some(incoming) = self.pending.pop_front() {
+            return incoming;
+        }
+        loop {
+            // 没有call()在等的时候收到的回复都是过期的
+            if let Some((correlation, incoming)) = recv_frame(u64::MAX) {
+                if correlation.reply_to == 0 {
+                    return incoming;
+                }
+            }
+        }
+    }
+
+    /// 最多等待timeout_ms毫秒接收下一条消息（先取暂存的），超时返回None
+    pub fn recv_timeout(&mut self, timeout_ms: u64) -> Option<Incoming> {
+        if let Some(incoming) = self.pending.pop_front() {
+            return Some(incoming);
+        }
+        let deadline = unsafe { kernel::kernel_uptime_ms() }.saturating_add(timeout_ms);
+        loop {
+            let now = unsafe { kernel::kernel_uptime_ms() };
+            let (correlation, incoming) = recv_frame(deadline.checked_sub(now).filter(|left| *left > 0)?)?;
+            if correlation.reply_to == 0 {
+                return Some(incoming);
+            }
+        }
+    }
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Endpoint::new()
+    }
+}
+
+/// 回复一条请求（对方没在等回复时照常发送，对方会当普通消息处理）
+pub fn reply(to: &Incoming, msg: &IpcMessage) {
+    let _ = send_frame(to.sender, msg, Correlation { request_id: 0, reply_to: to.request_id });
+}
+
+fn send_frame(pid: Pid, msg: &IpcMessage, correlation: Correlation) -> Result<(), IpcError> {
+    let mut buf = [0u8; wire::MAX_MESSAGE_SIZE];
+    let len = wire::encode_frame(msg, correlation, &mut buf).map_err(IpcError::Encode)?;
+    unsafe { kernel::ipc_send_bytes(pid, buf.as_ptr(), len); }
+    Ok(())
+}
+
+/// 最多等待timeout_ms毫秒接收一帧（u64::MAX表示一直等）；超时返回None，畸形消息跳过
+fn recv_frame(timeout_ms: u64) -> Option<(Correlation, Incoming)> {
+    let mut buf = [0u8; wire::MAX_MESSAGE_SIZE];
+    loop {
+        let mut sender: Pid = 0;
+        let len = unsafe {
+            if timeout_ms == u64::MAX {
+                kernel::ipc_recv_bytes(&mut sender, buf.as_mut_ptr(), buf.len())
+            } else {
+                kernel::ipc_recv_bytes_timeout(&mut sender, buf.as_mut_ptr(), buf.len(), timeout_ms)
+            }
+        };
+        if len == 0 {
+            return None;
+        }
+        if let Ok((correlation, msg)) = wire::decode_frame(&buf[..len.min(buf.len())]) {
+            let incoming = Incoming { sender, request_id: correlation.request_id, msg };
+            return Some((correlation, incoming));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{host, WindowId};
+    use std::vec::Vec;
+
+    const SERVICE: Pid = 3;
+    const OTHER: Pid = 4;
+
+    /// 不是回复的消息
+    fn event(window_id: WindowId) -> IpcMessage {
+        IpcMessage::WmFocusWindow { window_id }
+    }
+
+    fn plain() -> Correlation {
+        Correlation { request_id: 0, reply_to: 0 }
+    }
+
+    fn reply_to(request_id: u32) -> Correlation {
+        Correlation { request_id: 0, reply_to: request_id }
+    }
+
+    #[test]
+    fn call_matches_request_id_and_sender() {
+        let mut endpoint = Endpoint::new();
+        let answer = IpcMessage::RenderScreenInfo { width: 640, height: 480 };
+        host::deliver(SERVICE, &IpcMessage::RenderScreenInfo { width: 1, height: 1 }, reply_to(2));
+        host::deliver(OTHER, &IpcMessage::RenderScreenInfo { width: 2, height: 2 }, reply_to(1));
+        host::deliver(SERVICE, &answer, reply_to(1));
+        assert_eq!(endpoint.call(SERVICE, &IpcMessage::RenderSubscribeScreen), Ok(answer));
+        assert_eq!(host::sent(), [(SERVICE, IpcMessage::RenderSubscribeScreen)]);
+        // 下一个请求用下一个ID
+        host::deliver(SERVICE, &answer, reply_to(2));
+        assert_eq!(endpoint.call(SERVICE, &IpcMessage::RenderSubscribeScreen), Ok(answer));
+    }
+
+    #[test]
+    fn drops_stale_replies() {
+        let mut endpoint = Endpoint::new();
+        host::deliver(SERVICE, &IpcMessage::RenderScreenInfo { width: 1, height: 1 }, reply_to(9));
+        host::deliver(OTHER, &event(1), plain());
+        let incoming = endpoint.recv();
+        assert_eq!((incoming.sender, incoming.msg), (OTHER, event(1)));
+        host::deliver(SERVICE, &IpcMessage::RenderScreenInfo { width: 1, height: 1 }, reply_to(9));
+        assert!(endpoint.recv_timeout(10).is_none());
+    }
+
+    #[test]
+    fn call_times_out() {
+        let mut endpoint = Endpoint::new();
+        let start = host::now();
+        assert_eq!(endpoint.call_timeout(SERVICE, &IpcMessage::RenderSubscribeScreen, 50), Err(IpcError::Timeout));
+        assert!(host::now() - start >= 50);
+        // 超时之后才到的回复丢掉
+        host::deliver(SERVICE, &IpcMessage::RenderScreenInfo { width: 1, height: 1 }, reply_to(1));
+        assert!(endpoint.recv_timeout(10).is_none());
+        assert_eq!(endpoint.call(0, &IpcMessage::RenderSubscribeScreen), Err(IpcError::NoService));
+        // 超时特别长也不会溢出
+        host::deliver(SERVICE, &IpcMessage::RenderScreenInfo { width: 1, height: 1 }, reply_to(2));
+        assert!(endpoint.call_timeout(SERVICE, &IpcMessage::RenderSubscribeScreen, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn keeps_deferred_messages_in_order() {
+        let mut endpoint = Endpoint::new();
+        // 等回复期间来了很多无关消息（比如拖动时的鼠标事件），一条都不能丢
+        for window_id in 0..200 {
+            host::deliver(OTHER, &event(window_id), plain());
+        }
+        host::deliver(SERVICE, &IpcMessage::RenderScreenInfo { width: 1, height: 1 }, reply_to(1));
+        host::deliver(OTHER, &event(200), Correlation { request_id: 5, reply_to: 0 });
+        assert!(endpoint.call(SERVICE, &IpcMessage::RenderSubscribeScreen).is_ok());
+        let received: Vec<_> = (0..=200).map(|_| endpoint.recv()).collect();
+        for (window_id, incoming) in received.iter().enumerate() {
+            assert_eq!((incoming.sender, incoming.msg), (OTHER, event(window_id as WindowId)));
+        }
+        // 对方在等回复的请求带着它的ID
+        assert_eq!(received[200].request_id, 5);
+        assert!(endpoint.recv_timeout(10).is_none());
+    }
+}
@@ -1,9 +1,22 @@
 #![no_std]
+extern crate alloc;
+
+pub mod wire; // IPC消息的线上编码（跨进程传递用）
+pub mod ipc;  // 请求-回复式IPC（关联ID、超时、暂存无关消息）
+pub mod command; // 批量绘图命令（RenderCommandBuffer，经共享内存环形缓冲区提交）
+pub mod image;   // 位图和图片解码（BMP/PPM/QOI）
+pub mod theme;   // 主题（INI格式的颜色、尺寸、字体，运行时可以换）
+#[cfg(test)]
+mod host;
 
 // 基础类型：进程ID、窗口ID、像素值
 pub type Pid = u64;
 pub type WindowId = u32;
-pub type Pixel = u32; // 24位色：0xRRGGBB
+// 渲染服务里的离屏表面ID（0是桌面背景，也就是整个屏幕的底层）
+pub type SurfaceId = u32;
+pub type Pixel = u32; // 24位色：0xRRGGBB（帧缓冲区里的实际布局见PixelFormat）
+// 共享内存句柄（目前就是物理地址，接收方用kernel::map_phys映射；0表示无效）
+pub type ShmHandle = u64;
 
 // RGB颜色结构体（安全的颜色操作）
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -20,15 +33,716 @@ impl Rgb {
     }
 }
 
+// 带透明度的颜色（a=255不透明，0全透明；r/g/b没有预乘）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+impl Rgba {
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Rgba { r, g, b, a }
+    }
+
+    pub const fn is_opaque(&self) -> bool {
+        self.a == 255
+    }
+
+    // 转换为表面里存的预乘像素值（0xAARRGGBB，r/g/b已乘过a/255）
+    pub fn to_premultiplied(&self) -> Pixel {
+        let a = self.a as u32;
+        (a << 24) | (mul_div255(self.r as u32, a) << 16) | (mul_div255(self.g as u32, a) << 8) | mul_div255(self.b as u32, a)
+    }
+}
+impl From<Rgb> for Rgba {
+    fn from(c: Rgb) -> Self {
+        Rgba { r: c.r, g: c.g, b: c.b, a: 255 }
+    }
+}
+
+// x×a/255（四舍五入，x和a都不超过255）
+const fn mul_div255(x: u32, a: u32) -> u32 {
+    let t = x * a + 128;
+    (t + (t >> 8)) >> 8
+}
+
+// 预乘像素的source-over混合：src盖在dst上
+// 像素可能来自客户端的共享内存，不一定真是预乘过的：src的颜色通道先截到alpha，每个通道的和才不会超过255
+pub fn blend(dst: Pixel, src: Pixel) -> Pixel {
+    let alpha = src >> 24;
+    if alpha == 255 {
+        return src;
+    }
+    if alpha == 0 {
+        return dst;
+    }
+    let src = clamp_premultiplied(src);
+    // 两个通道一组（0x00AA00GG和0x00RR00BB）同时乘以(255-alpha)/255
+    let keep = 255 - alpha;
+    let scale = |lanes: u32| {
+        let t = lanes * keep + 0x0080_0080;
+        ((t + ((t >> 8) & 0x00FF_00FF)) >> 8) & 0x00FF_00FF
+    };
+    src + (scale((dst >> 8) & 0x00FF_00FF) << 8) + scale(dst & 0x00FF_00FF)
+}
+
+// 把颜色通道截到不超过alpha（合法的预乘像素不变）
+pub fn clamp_premultiplied(pixel: Pixel) -> Pixel {
+    let alpha = pixel >> 24;
+    let channel = |shift: u32| ((pixel >> shift) & 0xFF).min(alpha) << shift;
+    (alpha << 24) | channel(16) | channel(8) | channel(0)
+}
+
+// 预乘像素还原成不透明的颜色（颜色通道除以alpha，alpha当作255；全透明的像素是黑色）
+pub fn unpremultiply(pixel: Pixel) -> Pixel {
+    let alpha = pixel >> 24;
+    if alpha == 255 {
+        return pixel;
+    }
+    let channel = |shift: u32| match alpha {
+        0 => 0,
+        _ => ((((pixel >> shift) & 0xFF).min(alpha) * 255 + alpha / 2) / alpha) << shift,
+    };
+    0xFF00_0000 | channel(16) | channel(8) | channel(0)
+}
+
+// 预乘像素乘以coverage/255（抗锯齿的边缘像素只盖住一部分）
+pub fn fade(src: Pixel, coverage: u8) -> Pixel {
+    let c = coverage as u32;
+    (mul_div255(src >> 24, c) << 24)
+        | (mul_div255((src >> 16) & 0xFF, c) << 16)
+        | (mul_div255((src >> 8) & 0xFF, c) << 8)
+        | mul_div255(src & 0xFF, c)
+}
+
+// ==========================
+// 文字样式
+// ==========================
+/// 字体族
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FontFamily {
+    Bitmap = 0, // 点阵字体（PSF2/BDF，一行16像素；size是它的整数倍时放大）
+    Sans = 1,   // TrueType无衬线字体，任意像素大小，抗锯齿
+}
+
+/// 字重
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FontWeight {
+    Regular = 0,
+    Bold = 1,
+}
+
+/// 多行文字里每一行的水平对齐
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TextAlign {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// 文字用哪个字体、多大、多粗（size是像素：点阵字体是行高，TrueType是em大小）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct TextStyle {
+    pub family: FontFamily,
+    pub weight: FontWeight,
+    pub size: u16,
+}
+
+impl TextStyle {
+    /// 默认样式：16像素的点阵字体
+    pub const DEFAULT: TextStyle = TextStyle::new(FontFamily::Bitmap, 16, FontWeight::Regular);
+
+    pub const fn new(family: FontFamily, size: u16, weight: FontWeight) -> Self {
+        TextStyle { family, weight, size }
+    }
+
+    /// size像素的无衬线字体
+    pub const fn sans(size: u16) -> Self {
+        TextStyle::new(FontFamily::Sans, size, FontWeight::Regular)
+    }
+
+    /// 同样的字体换成粗体
+    pub const fn bold(self) -> Self {
+        TextStyle { weight: FontWeight::Bold, ..self }
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle::DEFAULT
+    }
+}
+
+// ==========================
+// 帧缓冲区像素格式
+// ==========================
+/// 一个颜色通道在像素值里的位置（从最低位数起的偏移 + 位数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Channel {
+    pub shift: u8,
+    pub size: u8,
+}
+
+impl Channel {
+    const fn new(shift: u8, size: u8) -> Self {
+        Channel { shift, size }
+    }
+
+    fn max(&self) -> u32 {
+        (1u32 << self.size) - 1
+    }
+
+    /// 8位分量 → 本通道的位（四舍五入缩放）
+    fn encode(&self, c: u8) -> u32 {
+        ((c as u32 * self.max() + 127) / 255) << self.shift
+    }
+
+    /// 本通道的位 → 8位分量
+    fn decode(&self, value: u32) -> u8 {
+        let max = self.max();
+        if max == 0 {
+            return 0;
+        }
+        (((value >> self.shift) & max) * 255 / max) as u8
+    }
+}
+
+/// 帧缓冲区像素格式：每像素位数、各通道位置、每行字节数
+///
+/// 像素值按小端存放，占 bytes_per_pixel() 个字节；每行从 y×pitch 开始，
+/// 行尾可能有填充字节。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PixelFormat {
+    pub bpp: u8,    // 每像素位数：15/16/24/32
+    pub pitch: u32, // 每行字节数（≥ 宽×每像素字节数）
+    pub red: Channel,
+    pub green: Channel,
+    pub blue: Channel,
+}
+
+impl PixelFormat {
+    /// 32位0x00RRGGBB，无行填充（和Rgb::to_pixel一致；渲染服务的表面也是这个布局，最高字节放预乘的alpha）
+    pub const fn xrgb8888(width: u16) -> Self {
+        PixelFormat {
+            bpp: 32,
+            pitch: width as u32 * 4,
+            red: Channel::new(16, 8),
+            green: Channel::new(8, 8),
+            blue: Channel::new(0, 8),
+        }
+    }
+
+    /// 按每像素位数取常见的RGB布局（VBE没有给出通道信息时用）；不支持的位数返回None
+    pub const fn from_bpp(bpp: u8, pitch: u32) -> Option<Self> {
+        let (red, green, blue) = match bpp {
+            15 => (Channel::new(10, 5), Channel::new(5, 5), Channel::new(0, 5)),
+            16 => (Channel::new(11, 5), Channel::new(5, 6), Channel::new(0, 5)),
+            24 | 32 => (Channel::new(16, 8), Channel::new(8, 8), Channel::new(0, 8)),
+            _ => return None,
+        };
+        Some(PixelFormat { bpp, pitch, red, green, blue })
+    }
+
+    /// 每像素占的字节数（15bpp也占2字节）
+    pub const fn bytes_per_pixel(&self) -> usize {
+        (self.bpp as usize).div_ceil(8)
+    }
+
+    /// 格式是否可用：位数受支持，通道不超出像素，一行放得下width个像素
+    pub fn is_valid(&self, width: u16) -> bool {
+        let bits = self.bytes_per_pixel() as u32 * 8;
+        let channel_ok = |c: &Channel| c.size >= 1 && c.size <= 16 && c.shift as u32 + c.size as u32 <= bits;
+        matches!(self.bpp, 15 | 16 | 24 | 32)
+            && channel_ok(&self.red)
+            && channel_ok(&self.green)
+            && channel_ok(&self.blue)
+            && self.pitch as usize >= width as usize * self.bytes_per_pixel()
+    }
+
+    /// 整个帧缓冲区的字节数
+    pub const fn buffer_size(&self, height: u16) -> usize {
+        self.pitch as usize * height as usize
+    }
+
+    /// 像素(x, y)相对帧缓冲区起点的字节偏移
+    pub const fn offset(&self, x: u16, y: u16) -> usize {
+        y as usize * self.pitch as usize + x as usize * self.bytes_per_pixel()
+    }
+
+    /// 颜色 → 本格式的像素值
+    pub fn encode(&self, color: Rgb) -> u32 {
+        self.red.encode(color.r) | self.green.encode(color.g) | self.blue.encode(color.b)
+    }
+
+    /// 两个格式的像素编码是否相同（每行字节数可以不同），相同时可以整行复制
+    pub fn same_pixels(&self, other: &PixelFormat) -> bool {
+        self.bpp == other.bpp && self.red == other.red && self.green == other.green && self.blue == other.blue
+    }
+
+    /// 本格式的像素值 → 颜色
+    pub fn decode(&self, value: u32) -> Rgb {
+        Rgb { r: self.red.decode(value), g: self.green.decode(value), b: self.blue.decode(value) }
+    }
+
+    /// 把像素值写到ptr（小端，bytes_per_pixel个字节）
+    ///
+    /// # Safety
+    /// ptr开始的bytes_per_pixel()个字节必须可写。
+    pub unsafe fn store(&self, ptr: *mut u8, value: u32) {
+        let bytes = value.to_le_bytes();
+        core::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, self.bytes_per_pixel());
+    }
+
+    /// 从ptr读出像素值
+    ///
+    /// # Safety
+    /// ptr开始的bytes_per_pixel()个字节必须可读。
+    pub unsafe fn load(&self, ptr: *const u8) -> u32 {
+        let mut bytes = [0u8; 4];
+        core::ptr::copy_nonoverlapping(ptr, bytes.as_mut_ptr(), self.bytes_per_pixel());
+        u32::from_le_bytes(bytes)
+    }
+}
+
+// ==========================
+// 显示模式
+// ==========================
+/// 一个显示模式（分辨率 + 每像素位数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct VideoMode {
+    pub width: u16,
+    pub height: u16,
+    pub bpp: u8,
+}
+
+/// 一条消息里最多带多少个模式
+pub const MODE_LIST_CAPACITY: usize = 32;
+
+/// 内联在消息里的定长模式列表（GpuListModes的回复）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeList {
+    modes: [VideoMode; MODE_LIST_CAPACITY],
+    len: u8,
+}
+
+impl ModeList {
+    pub const fn new() -> Self {
+        ModeList { modes: [VideoMode { width: 0, height: 0, bpp: 0 }; MODE_LIST_CAPACITY], len: 0 }
+    }
+
+    /// 追加一个模式；已满或已存在时忽略，返回是否加入
+    pub fn push(&mut self, mode: VideoMode) -> bool {
+        if self.len as usize == MODE_LIST_CAPACITY || self.as_slice().contains(&mode) {
+            return false;
+        }
+        self.modes[self.len as usize] = mode;
+        self.len += 1;
+        true
+    }
+
+    pub fn as_slice(&self) -> &[VideoMode] {
+        &self.modes[..self.len as usize]
+    }
+}
+
+impl Default for ModeList {
+    fn default() -> Self {
+        ModeList::new()
+    }
+}
+
+// ==========================
+// 矩形与脏区域
+// ==========================
+/// 屏幕上的矩形区域（像素）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+}
+
+impl Rect {
+    pub const fn new(x: u16, y: u16, w: u16, h: u16) -> Self {
+        Rect { x, y, w, h }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
+    }
+
+    /// 右边界（不含），用u32避免溢出
+    pub const fn right(&self) -> u32 {
+        self.x as u32 + self.w as u32
+    }
+
+    /// 下边界（不含）
+    pub const fn bottom(&self) -> u32 {
+        self.y as u32 + self.h as u32
+    }
+
+    pub const fn area(&self) -> u32 {
+        self.w as u32 * self.h as u32
+    }
+
+    /// 两个矩形相交或紧挨着（合并后不会多出多余的面积）
+    pub fn touches(&self, other: &Rect) -> bool {
+        self.x as u32 <= other.right()
+            && other.x as u32 <= self.right()
+            && self.y as u32 <= other.bottom()
+            && other.y as u32 <= self.bottom()
+    }
+
+    /// 包住两个矩形的最小矩形（空矩形不参与）
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right()).min(u16::MAX as u32);
+        let bottom = self.bottom().max(other.bottom()).min(u16::MAX as u32);
+        Rect { x, y, w: (right - x as u32) as u16, h: (bottom - y as u32) as u16 }
+    }
+
+    /// 点(x, y)是否在矩形内
+    pub const fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && y >= self.y && (x as u32) < self.right() && (y as u32) < self.bottom()
+    }
+
+    /// 两个矩形的重叠部分，不重叠时返回None
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if x as u32 >= right || y as u32 >= bottom {
+            return None;
+        }
+        Some(Rect { x, y, w: (right - x as u32) as u16, h: (bottom - y as u32) as u16 })
+    }
+
+    /// 裁剪到width×height的屏幕内，完全在外面时返回None
+    pub fn clip(&self, width: u16, height: u16) -> Option<Rect> {
+        self.intersect(&Rect::new(0, 0, width, height))
+    }
+
+    /// 平移(dx, dy)，超出u16范围的部分截掉
+    pub fn offset(&self, dx: u16, dy: u16) -> Rect {
+        let x = self.x.saturating_add(dx);
+        let y = self.y.saturating_add(dy);
+        Rect { x, y, w: self.w.min(u16::MAX - x), h: self.h.min(u16::MAX - y) }
+    }
+}
+
+/// 一条消息里最多带多少个脏矩形
+pub const DAMAGE_CAPACITY: usize = 16;
+
+/// 内联在消息里的定长脏矩形列表（GpuFlush用）
+///
+/// 加入时和相交或相邻的矩形合并；满了就并到扩大面积最少的那个矩形里，所以列表里的矩形互不重叠。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamageList {
+    rects: [Rect; DAMAGE_CAPACITY],
+    len: u8,
+}
+
+impl DamageList {
+    pub const fn new() -> Self {
+        DamageList { rects: [Rect::new(0, 0, 0, 0); DAMAGE_CAPACITY], len: 0 }
+    }
+
+    /// 只有一个矩形的列表
+    pub fn from_rect(rect: Rect) -> Self {
+        let mut list = DamageList::new();
+        list.add(rect);
+        list
+    }
+
+    /// 加入一个脏矩形（空矩形忽略）
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        // 合并后的矩形可能又碰到别的矩形，一直合并到没有相交的为止
+        let mut rect = rect;
+        while let Some(i) = self.as_slice().iter().position(|r| r.touches(&rect)) {
+            rect = rect.union(&self.rects[i]);
+            self.remove(i);
+        }
+        if (self.len as usize) < DAMAGE_CAPACITY {
+            self.rects[self.len as usize] = rect;
+            self.len += 1;
+            return;
+        }
+        // 满了：并到扩大面积最少的矩形里（再重新加入，处理合并后的相交）
+        let growth = |r: &Rect| r.union(&rect).area() - r.area();
+        let best = (0..self.len as usize).min_by_key(|&i| growth(&self.rects[i])).unwrap_or(0);
+        let merged = self.rects[best].union(&rect);
+        self.remove(best);
+        self.add(merged);
+    }
+
+    /// 原样放进一个矩形，不合并（解码用）；列表满了、矩形为空或者碰到已有的矩形
+    /// （add不会留下这样的列表）时返回false
+    pub(crate) fn push(&mut self, rect: Rect) -> bool {
+        if self.len as usize == DAMAGE_CAPACITY || rect.is_empty() || self.as_slice().iter().any(|r| r.touches(&rect)) {
+            return false;
+        }
+        self.rects[self.len as usize] = rect;
+        self.len += 1;
+        true
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.len -= 1;
+        self.rects[index] = self.rects[self.len as usize];
+    }
+
+    /// 包住所有脏矩形的最小矩形
+    pub fn bounds(&self) -> Rect {
+        self.as_slice().iter().fold(Rect::default(), |acc, r| acc.union(r))
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[Rect] {
+        &self.rects[..self.len as usize]
+    }
+}
+
+impl Default for DamageList {
+    fn default() -> Self {
+        DamageList::new()
+    }
+}
+
+// ==========================
+// 合成场景
+// ==========================
+/// 场景里的一层：把表面画在屏幕的(x, y)处
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct Layer {
+    pub surface: SurfaceId,
+    pub x: u16,
+    pub y: u16,
+}
+
+/// 一条消息里最多带多少层
+pub const SCENE_CAPACITY: usize = 24;
+
+/// 内联在消息里的定长层列表（从下到上，RenderSetScene用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerList {
+    layers: [Layer; SCENE_CAPACITY],
+    len: u8,
+}
+
+impl LayerList {
+    pub const fn new() -> Self {
+        LayerList { layers: [Layer { surface: 0, x: 0, y: 0 }; SCENE_CAPACITY], len: 0 }
+    }
+
+    /// 在最上面加一层；已满时忽略，返回是否加入
+    pub fn push(&mut self, layer: Layer) -> bool {
+        if self.len as usize == SCENE_CAPACITY {
+            return false;
+        }
+        self.layers[self.len as usize] = layer;
+        self.len += 1;
+        true
+    }
+
+    pub fn as_slice(&self) -> &[Layer] {
+        &self.layers[..self.len as usize]
+    }
+}
+
+impl Default for LayerList {
+    fn default() -> Self {
+        LayerList::new()
+    }
+}
+
+// ==========================
+// 多边形
+// ==========================
+/// 一个顶点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct Point {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Point {
+    pub const fn new(x: u16, y: u16) -> Self {
+        Point { x, y }
+    }
+}
+
+/// 一个多边形最多几个顶点
+pub const POLYGON_CAPACITY: usize = 16;
+
+/// 内联在消息里的定长顶点列表（按顺序首尾相连）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointList {
+    points: [Point; POLYGON_CAPACITY],
+    len: u8,
+}
+
+impl PointList {
+    pub const fn new() -> Self {
+        PointList { points: [Point::new(0, 0); POLYGON_CAPACITY], len: 0 }
+    }
+
+    /// 取前POLYGON_CAPACITY个顶点（多出来的忽略）
+    pub fn from_slice(points: &[Point]) -> Self {
+        let mut list = PointList::new();
+        for point in points {
+            list.push(*point);
+        }
+        list
+    }
+
+    /// 追加一个顶点；已满时忽略，返回是否加入
+    pub fn push(&mut self, point: Point) -> bool {
+        if self.len as usize == POLYGON_CAPACITY {
+            return false;
+        }
+        self.points[self.len as usize] = point;
+        self.len += 1;
+        true
+    }
+
+    pub fn as_slice(&self) -> &[Point] {
+        &self.points[..self.len as usize]
+    }
+}
+
+impl Default for PointList {
+    fn default() -> Self {
+        PointList::new()
+    }
+}
+
+/// 帧缓冲区导出格式（GpuDump用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DumpFormat {
+    Ppm = 0, // 二进制PPM（P6），最简单，方便脚本比对
+    Png = 1, // PNG（未压缩的deflate块），图片查看器都能打开
+}
+
+/// IPC字符串的最大字节数（UTF-8编码后）
+pub const IPC_STR_CAPACITY: usize = 64;
+
+/// 内联在消息里的定长字符串（不含指针，消息拷贝到别的进程后依然有效）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct IpcStr {
+    len: u8,
+    bytes: [u8; IPC_STR_CAPACITY],
+}
+
+impl IpcStr {
+    /// 从&str创建，超出容量的部分在字符边界处截断
+    pub fn new(s: &str) -> Self {
+        let mut len = s.len().min(IPC_STR_CAPACITY);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; IPC_STR_CAPACITY];
+        bytes[..len].copy_from_slice(&s.as_bytes()[..len]);
+        IpcStr { len: len as u8, bytes }
+    }
+
+    /// 从&str创建，超出容量时返回None（不截断）
+    pub fn try_new(s: &str) -> Option<Self> {
+        (s.len() <= IPC_STR_CAPACITY).then(|| IpcStr::new(s))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // new()只接受合法UTF-8并按字符边界截断，这里不会失败
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or("")
+    }
+}
+
+impl From<&str> for IpcStr {
+    fn from(s: &str) -> Self {
+        IpcStr::new(s)
+    }
+}
+
+impl core::fmt::Debug for IpcStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// 鼠标按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MouseButton {
+    Left = 0,
+    Right = 1,
+    Middle = 2,
+}
+
+/// 鼠标指针的形状（WM按指针下面是什么告诉鼠标驱动该画哪种）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CursorShape {
+    Arrow = 0,
+    Move = 1,       // 拖着标题栏移动窗口
+    ResizeNS = 2,   // 上下边框：竖直方向改大小
+    ResizeEW = 3,   // 左右边框：水平方向改大小
+    ResizeNWSE = 4, // 左上角、右下角
+    ResizeNESW = 5, // 右上角、左下角
+}
+
+/// 窗口叠放的层：同一层里按次序叠放，Bottom层的窗口总在Normal层下面，Top层的总在上面
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum StackLayer {
+    Bottom = 0, // 比如桌面上的小组件
+    Normal = 1,
+    Top = 2,    // 比如Dock
+}
+
 // IPC消息类型：所有服务都用这套消息通信
-#[derive(Debug)]
+// 消息里不能有指针（用IpcStr/ShmHandle代替），通过wire模块编码后由内核按字节拷贝
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(C)]
 pub enum IpcMessage {
     // GPU驱动相关消息（渲染服务→GPU驱动）
-    GpuInit { width: u16, height: u16 }, // 设置分辨率
+    GpuInit { width: u16, height: u16 }, // 初始化并选择最接近的分辨率（0x0表示沿用当前/默认模式）
     GpuGetFb,                            // 请求帧缓冲区地址
-    GpuFlush,                            // 刷新屏幕
-    GpuFbReply { fb: *mut Pixel, width: u16, height: u16 }, // GPU驱动的回复
+    GpuFlush { rects: DamageList },      // 把这些区域刷新到屏幕上（空列表表示整个屏幕）
+    // GPU驱动的回复：fb为帧缓冲区物理地址，里面连续放着pages页（每页format.buffer_size(height)字节），
+    // pages≥2时可以用GpuFlip翻页，当前显示第0页
+    GpuFbReply { fb: ShmHandle, width: u16, height: u16, format: PixelFormat, pages: u8 },
 
     // 渲染服务相关消息（窗口管理器→渲染服务）
     RenderDrawPixel { x: u16, y: u16, color: Rgb },
@@ -39,7 +753,7 @@ pub enum IpcMessage {
         y: u16,
         w: u16,
         h: u16,
-        title: &'static str, // 窗口标题
+        title: IpcStr, // 窗口标题
     },
 
     // 鼠标事件消息（鼠标驱动→WM）
@@ -49,8 +763,8 @@ pub enum IpcMessage {
     },
 
     // 窗口管理器→应用：窗口已打开通知
-     WmWindowOpened {
-        title: &'static str,
+    WmWindowOpened {
+        title: IpcStr,
         window_id: WindowId,
     },
 
@@ -69,19 +783,261 @@ pub enum IpcMessage {
     RenderDrawText {
         x: u16,
         y: u16,
-        text: &'static str,
+        text: IpcStr,
+        style: TextStyle,
         color: Rgb,
     },
+
+    // 渲染服务：矢量图形（画在桌面背景上；命令缓冲区里有对应的命令，见command模块）
+    // thickness是线宽（向内画），0表示填充
+    RenderDrawLine { x0: u16, y0: u16, x1: u16, y1: u16, color: Rgba },
+    RenderStrokeRect { x: u16, y: u16, w: u16, h: u16, thickness: u16, color: Rgba },
+    RenderDrawEllipse { cx: u16, cy: u16, rx: u16, ry: u16, thickness: u16, color: Rgba },
+    RenderDrawRoundRect { x: u16, y: u16, w: u16, h: u16, radius: u16, thickness: u16, color: Rgba },
+    RenderFillPolygon { points: PointList, color: Rgba },
+    // 渲染服务：把位图（image是发送方分配的共享内存里width×height个预乘像素，见image模块）缩放画到(x, y, w, h)
+    RenderBlit { image: ShmHandle, width: u16, height: u16, x: u16, y: u16, w: u16, h: u16, mode: image::BlitMode },
+
+    // 窗口管理器→应用：WmCreateWindow的回复，带新窗口的ID和窗口内容所在的表面
+    // （应用把绘图命令的目标设成这个表面，坐标相对窗口左上角）
+    WmWindowCreated {
+        window_id: WindowId,
+        surface: SurfaceId,
+    },
+
+    // 调试/测试→GPU驱动：把当前帧缓冲区写到文件（golden图片测试用）；只有无头后端支持，
+    // name是文件名（字母、数字、'.'、'-'、'_'，不带目录），写到GPU驱动的dumps/目录里
+    GpuDump {
+        format: DumpFormat,
+        name: IpcStr,
+    },
+
+    // GPU驱动→请求方：GpuDump的回复
+    GpuDumpDone {
+        ok: bool,
+    },
+
+    // 任意进程→GPU驱动：查询支持的显示模式，回复GpuModeList
+    GpuListModes,
+    GpuModeList {
+        modes: ModeList,
+    },
+
+    // 渲染服务→GPU驱动：运行时切换到最接近的模式，回复GpuFbReply（切换失败时fb为0）
+    // 只有发过GpuInit的进程能切换，别的进程通过RenderSetMode请渲染服务切换
+    GpuSetMode {
+        width: u16,
+        height: u16,
+    },
+
+    // 任意进程→渲染服务：切换到最接近的模式，回复RenderScreenInfo（实际的分辨率，切换失败时为0x0）；
+    // 切换后订阅者收到ScreenModeChanged
+    RenderSetMode {
+        width: u16,
+        height: u16,
+    },
+
+    // 应用/WM/桌面→渲染服务：查询屏幕大小并订阅之后的变化，回复RenderScreenInfo
+    RenderSubscribeScreen,
+    RenderScreenInfo {
+        width: u16,
+        height: u16,
+    },
+
+    // 渲染服务→订阅者：屏幕分辨率变了，需要重新布局并重绘
+    ScreenModeChanged {
+        width: u16,
+        height: u16,
+    },
+
+    // 应用/WM/桌面→渲染服务：这一批绘制画完了，把后台缓冲区呈现到屏幕上
+    RenderCommit,
+
+    // 渲染服务→GPU驱动：显示第page页（GpuFbReply的pages≥2时可用），翻完回复GpuFlipped
+    GpuFlip {
+        page: u8,
+    },
+    GpuFlipped {
+        page: u8,
+    },
+
+    // 窗口管理器→渲染服务：创建width×height的离屏表面，owner（窗口所属的应用）也可以往里画，
+    // 但只能画在content（内容区，不含标题栏）里，坐标以content左上角为原点；
+    // 回复RenderSurfaceCreated（失败时surface为0）
+    RenderCreateSurface {
+        width: u16,
+        height: u16,
+        owner: Pid,
+        content: Rect,
+    },
+    RenderSurfaceCreated {
+        surface: SurfaceId,
+        shm: ShmHandle, // 表面像素所在的共享内存（预乘alpha的0xAARRGGBB，每行width×4字节）
+    },
+
+    // 窗口管理器→渲染服务：整个合成场景（桌面背景之上的各层，从下到上），换完立即重新合成并呈现；
+    // 只接受注册为"wm"的服务发来的
+    RenderSetScene {
+        layers: LayerList,
+    },
+
+    // 应用/WM/桌面→渲染服务：命令缓冲区（发送方分配的共享内存ring，共size字节）里写到end为止的命令可以执行了，见command模块
+    RenderSubmit {
+        ring: ShmHandle,
+        size: u32,
+        end: u32,
+    },
+
+    // 应用→渲染服务：要直接写自己窗口表面的像素，查询它的共享内存；
+    // 回复RenderSurfaceMapped（表面不存在或无权访问时shm为0）
+    RenderMapSurface {
+        surface: SurfaceId,
+    },
+    RenderSurfaceMapped {
+        surface: SurfaceId,
+        shm: ShmHandle, // 预乘alpha的0xAARRGGBB，每行width×4字节
+        width: u16,
+        height: u16,
+        area: Rect, // 请求方能写的区域（应用是内容区），它的左上角是请求方坐标的原点
+    },
+
+    // 应用→渲染服务：直接写完表面像素，rects是改过的区域（坐标相对area左上角，空列表表示整个area）；
+    // 渲染服务合成这些区域并呈现
+    RenderDamageSurface {
+        surface: SurfaceId,
+        rects: DamageList,
+    },
+
+    // 任意进程→渲染服务：量文字画出来有多大（和TextBox命令的排版一样，按max_width折行，0表示不折行）；
+    // 回复RenderTextMeasured：width是最宽一行的宽度，height是行数×行高
+    RenderMeasureText {
+        text: IpcStr,
+        style: TextStyle,
+        max_width: u16,
+    },
+    RenderTextMeasured {
+        width: u16,
+        height: u16,
+    },
+
+    // 任意进程→窗口管理器：换主题，text是发送方自己分配的共享内存里len字节的主题文本（UTF-8，写法见theme模块）；
+    // 回复WmThemeSet：ok为false时主题不变，line是主题文本出错的行号（0表示读不了：映射失败或不是UTF-8）
+    WmSetTheme {
+        text: ShmHandle,
+        len: u32,
+    },
+    WmThemeSet {
+        ok: bool,
+        line: u16,
+    },
+
+    // 应用/桌面→窗口管理器：订阅主题变化，回复当前主题的ThemeChanged
+    WmSubscribeTheme,
+
+    // 窗口管理器→订阅者：主题换了（text是WM分配的共享内存，为0表示默认主题），重新解析主题文本并重画
+    ThemeChanged {
+        text: ShmHandle,
+        len: u32,
+    },
+
+    // 鼠标驱动→WM/桌面：按键按下、松开（按下和松开之间的MouseMove就是拖动）
+    MouseDown {
+        x: u16,
+        y: u16,
+        button: MouseButton,
+    },
+    MouseUp {
+        x: u16,
+        y: u16,
+        button: MouseButton,
+    },
+
+    // 窗口管理器→渲染服务：把表面改成width×height（只有创建者能改），content是新的内容区；
+    // 表面ID不变，像素清成全透明，需要重画。回复RenderSurfaceResized（失败时shm为0，表面保持原样）
+    RenderResizeSurface {
+        surface: SurfaceId,
+        width: u16,
+        height: u16,
+        content: Rect,
+    },
+    RenderSurfaceResized {
+        surface: SurfaceId,
+        shm: ShmHandle, // 共享内存可能换了一块，直接写像素的应用要重新RenderMapSurface
+    },
+
+    // 窗口管理器→应用：窗口大小变了（拖边框、最大化），width×height是新的内容区大小；
+    // 应用重新布局、重画（映射过的表面像素要重新映射）
+    WmWindowResized {
+        window_id: WindowId,
+        width: u16,
+        height: u16,
+    },
+
+    // 应用→窗口管理器：限制窗口内容区的大小（拖边框改大小、最大化时都不会超出），max为0表示不限制
+    WmSetSizeLimits {
+        window_id: WindowId,
+        min_width: u16,
+        min_height: u16,
+        max_width: u16,
+        max_height: u16,
+    },
+
+    // 窗口管理器→鼠标驱动（发鼠标事件的进程）：指针该换成什么形状（只在变化时发）
+    WmSetCursor {
+        shape: CursorShape,
+    },
+
+    // 任意进程→窗口管理器：把窗口提到它那一层的最上面、降到最下面（子窗口总在父窗口上面）；
+    // 获得焦点的窗口也会提到最上面
+    WmRaiseWindow {
+        window_id: WindowId,
+    },
+    WmLowerWindow {
+        window_id: WindowId,
+    },
+
+    // 应用→窗口管理器：窗口换到layer层（子窗口跟着父窗口，不单独换层）
+    WmSetStackLayer {
+        window_id: WindowId,
+        layer: StackLayer,
+    },
+
+    // 应用→窗口管理器：窗口是parent的子窗口（对话框等），总叠在parent上面，跟着parent一起提升；
+    // parent为0表示不再是子窗口，parent是它自己的子孙时忽略
+    WmSetTransientFor {
+        window_id: WindowId,
+        parent: WindowId,
+    },
+
+    // 应用/桌面→窗口管理器：创建面板（没有标题栏和边框的窗口，不获得焦点，不能拖动、改大小），
+    // 放在layer层，整个表面都是内容区；回复WmWindowCreated
+    WmCreatePanel {
+        x: u16,
+        y: u16,
+        w: u16,
+        h: u16,
+        layer: StackLayer,
+    },
+
+    // 应用→窗口管理器：把自己的窗口挪到(x, y)（比如分辨率变了之后重新摆放面板）
+    WmMoveWindow {
+        window_id: WindowId,
+        x: u16,
+        y: u16,
+    },
+
+    // 窗口管理器→渲染服务：销毁表面（只有创建者能销毁，窗口关闭时发），表面从场景里拿掉，编号作废；
+    // 共享内存内核没有释放接口，进程退出时才回收
+    RenderDestroySurface {
+        surface: SurfaceId,
+    },
 }
 
 // 内核提供的基础API（通过FFI调用，Rust包装）
 pub mod kernel {
     use super::*;
+    // 内核原有的接口
     extern "C" {
-        // 发送IPC消息：目标PID + 消息
-        pub fn ipc_send(pid: Pid, msg: *const IpcMessage);
-        // 接收IPC消息：返回发送者PID，消息通过指针传递
-        pub fn ipc_recv(sender_pid: *mut Pid, msg: *mut IpcMessage);
         // 获取指定服务的PID（比如"gpu-driver"→对应的进程ID）
         pub fn get_service_pid(name: *const u8) -> Pid;
         // 映射物理地址到用户态（用于帧缓冲区）
@@ -89,4 +1045,247 @@ pub mod kernel {
         // 注册服务到内核（让其他服务能通过名称找到本服务）
         pub fn kernel_register_service(name: *const u8) -> bool;
     }
-}
\ No newline at end of file
+
+    // 新增的系统调用：内核要实现这些才能链接（说明见README_BUILD.md的Kernel ABI一节）。
+    // 原有的ipc_send/ipc_recv按IpcMessage结构体原样拷贝，跨进程时结构体里的指针就失效了，
+    // 所以收发改成拷贝编码后的字节（见wire模块）。换了名字是故意的：旧内核上会链接失败，
+    // 而不是按错误的参数去调旧的ipc_send/ipc_recv。
+    extern "C" {
+        // 发送IPC消息：目标PID + 编码后的消息字节（内核只负责拷贝）
+        pub fn ipc_send_bytes(pid: Pid, buf: *const u8, len: usize);
+        // 接收IPC消息：阻塞到有消息，写入发送者PID和消息字节，返回消息长度
+        pub fn ipc_recv_bytes(sender_pid: *mut Pid, buf: *mut u8, cap: usize) -> usize;
+        // 带超时的接收：最多等待timeout_ms毫秒，超时返回0
+        pub fn ipc_recv_bytes_timeout(sender_pid: *mut Pid, buf: *mut u8, cap: usize, timeout_ms: u64) -> usize;
+        // 系统启动以来的毫秒数（用于计算超时）
+        pub fn kernel_uptime_ms() -> u64;
+        // 分配一段可在进程间共享的物理内存，返回句柄（用map_phys映射），失败返回0
+        pub fn shm_alloc(size: usize) -> ShmHandle;
+        // 查询共享内存是谁分配的：[handle, handle+size)落在同一块shm_alloc分配的内存里时返回分配它的进程PID，否则返回0
+        pub fn shm_owner(handle: ShmHandle, size: usize) -> Pid;
+        // 写文件：append为false时先清空文件，成功返回true
+        pub fn kernel_write_file(path: *const u8, data: *const u8, len: usize, append: bool) -> bool;
+        // 按名称启动应用进程（比如"test-window"），返回新进程PID，失败返回0
+        pub fn spawn_process(name: *const u8) -> Pid;
+    }
+
+    /// 编码并发送消息（消息编码失败说明调用方有bug，直接丢弃）
+    pub fn send(pid: Pid, msg: &IpcMessage) {
+        let mut buf = [0u8; wire::MAX_MESSAGE_SIZE];
+        if let Ok(len) = wire::encode(msg, &mut buf) {
+            unsafe { ipc_send_bytes(pid, buf.as_ptr(), len); }
+        }
+    }
+
+    /// 阻塞接收下一条合法消息（解码失败的畸形消息直接丢弃）
+    pub fn recv() -> (Pid, IpcMessage) {
+        let mut buf = [0u8; wire::MAX_MESSAGE_SIZE];
+        loop {
+            let mut sender_pid: Pid = 0;
+            let len = unsafe { ipc_recv_bytes(&mut sender_pid, buf.as_mut_ptr(), buf.len()) };
+            if let Ok(msg) = wire::decode(&buf[..len.min(buf.len())]) {
+                return (sender_pid, msg);
+            }
+        }
+    }
+
+    /// 空闲等待：裸机上用hlt停机到下一次中断；
+    /// 宿主模拟器里ipc_recv_bytes本身会阻塞，这里只给CPU一个自旋提示
+    pub fn idle() {
+        #[cfg(target_os = "none")]
+        x86_64::instructions::hlt();
+        #[cfg(not(target_os = "none"))]
+        core::hint::spin_loop();
+    }
+
+    /// 映射到本进程的一段共享内存（把handle发给别的进程，对方用map映射同一段内存）
+    pub struct SharedMemory {
+        handle: ShmHandle,
+        ptr: *mut u8,
+        size: usize,
+    }
+
+    impl SharedMemory {
+        /// 分配并映射size字节，内存不足时返回None
+        pub fn alloc(size: usize) -> Option<Self> {
+            if size == 0 {
+                return None;
+            }
+            unsafe {
+                let handle = shm_alloc(size);
+                Self::map(handle, size)
+            }
+        }
+
+        /// 映射owner分配的共享内存：句柄是别的进程发来的，内核确认这段内存确实是owner用shm_alloc
+        /// 分配的才映射（否则句柄可能指向任意物理内存），不是时返回None
+        pub fn map_from(owner: Pid, handle: ShmHandle, size: usize) -> Option<Self> {
+            if owner == 0 || handle == 0 || size == 0 || unsafe { shm_owner(handle, size) } != owner {
+                return None;
+            }
+            unsafe { Self::map(handle, size) }
+        }
+
+        /// handle必须是shm_alloc分配的、至少size字节的共享内存
+        unsafe fn map(handle: ShmHandle, size: usize) -> Option<Self> {
+            if handle == 0 || size == 0 {
+                return None;
+            }
+            let ptr = map_phys(handle, size);
+            if ptr.is_null() {
+                return None;
+            }
+            Some(SharedMemory { handle, ptr, size })
+        }
+
+        pub fn handle(&self) -> ShmHandle {
+            self.handle
+        }
+
+        pub fn as_ptr(&self) -> *mut u8 {
+            self.ptr
+        }
+
+        pub fn size(&self) -> usize {
+            self.size
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blend_premultiplied() {
+        // 半透明白色盖在不透明黑色上得到中灰
+        assert_eq!(blend(0xFF00_0000, Rgba::new(255, 255, 255, 128).to_premultiplied()), 0xFF80_8080);
+        assert_eq!(blend(0xFF12_3456, 0xFFAB_CDEF), 0xFFAB_CDEF);
+        assert_eq!(blend(0xFF12_3456, 0x0000_0000), 0xFF12_3456);
+    }
+
+    #[test]
+    fn blend_clamps_invalid_pixels() {
+        // 没有预乘的像素（颜色通道大于alpha）不能让通道溢出
+        assert_eq!(blend(0xFFFF_FFFF, 0x80FF_FFFF), 0xFFFF_FFFF);
+        assert_eq!(blend(0x0000_0000, 0x80FF_FFFF), 0x8080_8080);
+        assert_eq!(clamp_premultiplied(0x4010_FF40), 0x4010_4040);
+    }
+
+    #[test]
+    fn unpremultiply_restores_color() {
+        for color in [Rgba::new(255, 0, 0, 128), Rgba::new(10, 200, 99, 77), Rgba::new(1, 2, 3, 255)] {
+            let opaque = Rgba { a: 255, ..color }.to_premultiplied();
+            let restored = unpremultiply(color.to_premultiplied());
+            // 预乘时舍入过，还原后每个通道最多差255/(2a)
+            for shift in [16, 8, 0] {
+                let diff = ((restored >> shift) & 0xFF).abs_diff((opaque >> shift) & 0xFF);
+                assert!(diff <= 255 / (2 * color.a as u32), "{color:?}");
+            }
+            assert_eq!(restored >> 24, 0xFF);
+        }
+        assert_eq!(unpremultiply(0x8080_4000), 0xFFFF_8000);
+        assert_eq!(unpremultiply(0x0012_3456), 0xFF00_0000);
+        // 颜色通道比alpha大的像素不会溢出
+        assert_eq!(unpremultiply(0x40FF_0000), 0xFFFF_0000);
+    }
+
+    #[test]
+    fn pixel_formats_round_trip() {
+        let color = Rgb { r: 128, g: 64, b: 32 };
+        for (bpp, white, value, decoded) in [
+            (15, 0x7FFF, 0x4104, Rgb { r: 131, g: 65, b: 32 }),
+            (16, 0xFFFF, 0x8204, Rgb { r: 131, g: 64, b: 32 }),
+            (24, 0xFF_FFFF, 0x80_4020, color),
+            (32, 0xFF_FFFF, 0x80_4020, color),
+        ] {
+            let format = PixelFormat::from_bpp(bpp, 64).unwrap();
+            assert_eq!(format.encode(Rgb { r: 255, g: 255, b: 255 }), white, "{bpp}bpp");
+            assert_eq!(format.encode(color), value, "{bpp}bpp");
+            assert_eq!(format.decode(value), decoded, "{bpp}bpp");
+            assert_eq!(format.decode(white), Rgb { r: 255, g: 255, b: 255 }, "{bpp}bpp");
+        }
+        assert!(PixelFormat::from_bpp(8, 64).is_none());
+    }
+
+    #[test]
+    fn bgr_channel_order() {
+        // 蓝色在高位：通道交换位置，编码解码仍然对得上
+        let mut format = PixelFormat::from_bpp(32, 64).unwrap();
+        (format.red, format.blue) = (format.blue, format.red);
+        assert_eq!(format.encode(Rgb { r: 0x12, g: 0x34, b: 0x56 }), 0x56_3412);
+        assert_eq!(format.decode(0x56_3412), Rgb { r: 0x12, g: 0x34, b: 0x56 });
+        assert!(!format.same_pixels(&PixelFormat::xrgb8888(16)));
+        let mut format = PixelFormat::from_bpp(16, 64).unwrap();
+        (format.red, format.blue) = (Channel::new(0, 5), Channel::new(11, 5));
+        assert_eq!(format.encode(Rgb { r: 255, g: 0, b: 0 }), 0x001F);
+        assert_eq!(format.encode(Rgb { r: 0, g: 0, b: 255 }), 0xF800);
+        assert!(format.is_valid(16));
+    }
+
+    #[test]
+    fn padded_pitch() {
+        // 2×2的24bpp，每行6字节像素加2字节填充
+        let format = PixelFormat::from_bpp(24, 8).unwrap();
+        assert!(format.is_valid(2));
+        assert!(!format.is_valid(3));
+        assert_eq!(format.buffer_size(2), 16);
+        assert_eq!(format.offset(1, 1), 11);
+        let mut buffer = [0xAAu8; 16];
+        unsafe {
+            format.store(buffer.as_mut_ptr().add(format.offset(1, 1)), 0x12_3456);
+            assert_eq!(format.load(buffer.as_ptr().add(11)), 0x12_3456);
+        }
+        // 只写3个字节，填充不动
+        assert_eq!(buffer[8..], [0xAA, 0xAA, 0xAA, 0x56, 0x34, 0x12, 0xAA, 0xAA]);
+    }
+
+    #[test]
+    fn damage_merges_touching_rects() {
+        let mut damage = DamageList::new();
+        damage.add(Rect::new(0, 0, 10, 10));
+        damage.add(Rect::new(50, 50, 10, 10));
+        damage.add(Rect::new(0, 0, 0, 5)); // 空矩形忽略
+        assert_eq!(damage.as_slice().len(), 2);
+        // 挨着第一个的并进去
+        damage.add(Rect::new(10, 0, 5, 5));
+        assert_eq!(damage.as_slice().len(), 2);
+        assert!(damage.as_slice().contains(&Rect::new(0, 0, 15, 10)));
+        // 合并后碰到第二个，三个并成一个
+        damage.add(Rect::new(12, 8, 40, 45));
+        assert_eq!(damage.as_slice(), [Rect::new(0, 0, 60, 60)]);
+        damage.clear();
+        assert!(damage.is_empty());
+    }
+
+    #[test]
+    fn damage_overflow_merges_into_bounds() {
+        let mut damage = DamageList::new();
+        for i in 0..DAMAGE_CAPACITY as u16 + 4 {
+            damage.add(Rect::new(i * 20, i * 20, 5, 5));
+        }
+        assert!(damage.as_slice().len() <= DAMAGE_CAPACITY);
+        assert_eq!(damage.bounds(), Rect::new(0, 0, (DAMAGE_CAPACITY as u16 + 3) * 20 + 5, (DAMAGE_CAPACITY as u16 + 3) * 20 + 5));
+        // 合并之后也互不接触
+        let rects = damage.as_slice();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(rects[i + 1..].iter().all(|b| !a.touches(b)));
+        }
+    }
+
+    #[test]
+    fn maps_only_memory_of_owner() {
+        use kernel::SharedMemory;
+        host::run_as(5);
+        let handle = SharedMemory::alloc(100).unwrap().handle();
+        host::run_as(1);
+        assert!(SharedMemory::map_from(5, handle, 100).is_some());
+        assert!(SharedMemory::map_from(5, handle + 16, 84).is_some());
+        // 别的进程、超出分配的大小、不是shm_alloc分配的地址都不映射
+        assert!(SharedMemory::map_from(1, handle, 100).is_none());
+        assert!(SharedMemory::map_from(5, handle, 200).is_none());
+        assert!(SharedMemory::map_from(5, handle + 200, 4).is_none());
+        assert!(SharedMemory::map_from(5, 0xB_8000, 4).is_none());
+        assert!(SharedMemory::map_from(0, handle, 100).is_none());
+    }
+}
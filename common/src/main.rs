@@ -2,7 +2,7 @@
 #![no_main]
 
 // 引入common自身的类型和内核API
-use common::{Rgb, IpcMessage, kernel, Pid, WindowId};
+use common::{Rgb, IpcMessage, IpcStr, kernel, Pid, WindowId};
 // no_std环境下需要核心库的调试功能
 use core::fmt::Write;
 
@@ -48,7 +48,7 @@ pub extern "C" fn main() -> ! {
     // 测试窗口创建消息
     let create_win = IpcMessage::WmCreateWindow {
         x: 50, y: 50, w: 800, h: 600,
-        title: "测试窗口"
+        title: IpcStr::new("测试窗口")
     };
     print(&format!("✅ 创建窗口消息：{:?}\n", create_win));
 
@@ -60,7 +60,7 @@ pub extern "C" fn main() -> ! {
     unsafe {
         // 发送一个鼠标点击消息
         let mouse_click = IpcMessage::MouseClick { x: 150, y: 250 };
-        kernel::ipc_send(dummy_pid, &mouse_click as *const _);
+        kernel::send(dummy_pid, &mouse_click);
         print("✅ 内核ipc_send调用语法正确\n");
 
         // 模拟获取服务PID（比如获取GPU驱动的PID）
@@ -0,0 +1,324 @@
+// ==========================
+// 主题（颜色、尺寸、字体、间距）
+// ==========================
+// 主题是一个INI格式的文本文件（写法见themes/default.theme）：
+//   [分组]
+//   键 = 值   # 注释
+// 解析从默认主题开始，文件里写了的键覆盖默认值，所以自定义主题只需要写改动的部分。
+// 默认主题随程序发布（include_str!），每个组件启动时自己解析一遍。
+//
+// 运行时换主题：任意进程把主题文本放进自己分配的共享内存，发WmSetTheme给窗口管理器；WM把文本
+// 复制到自己的共享内存里再解析，成功后用ThemeChanged把副本广播给订阅者（WmSubscribeTheme），
+// 各组件重新解析并重画。共享内存只认发送方自己分配的（SharedMemory::map_from）。
+
+use crate::{kernel::SharedMemory, FontFamily, FontWeight, Pid, Rgb, Rgba, ShmHandle, TextStyle};
+
+/// 默认主题的文本
+pub const DEFAULT_THEME: &str = include_str!("../../themes/default.theme");
+/// 尺寸最大多少像素（再大就当写错了，也免得窗口边框的计算溢出）
+pub const MAX_SIZE: u16 = 256;
+
+/// 窗口装饰（WM画的边框、标题栏和按钮）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowTheme {
+    pub titlebar_height: u16,
+    pub border_width: u16,
+    pub button_size: u16,
+    pub button_margin: u16,
+    pub title_font: TextStyle,
+    pub title_offset: u16,
+    pub focused_titlebar: Rgb,
+    pub unfocused_titlebar: Rgb,
+    pub focused_title_text: Rgb,
+    pub unfocused_title_text: Rgb,
+    pub focused_border: Rgb,
+    pub unfocused_border: Rgb,
+    pub background: Rgb,
+    pub close_button: Rgb,
+    pub close_button_hover: Rgb,
+    pub maximize_button: Rgb,
+    pub maximize_button_hover: Rgb,
+    pub minimize_button: Rgb,
+    pub minimize_button_hover: Rgb,
+    pub button_symbol: Rgb,
+}
+
+/// 桌面（背景、任务栏、Dock）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DesktopTheme {
+    pub background: Rgb,
+    pub taskbar_height: u16,
+    pub taskbar_background: Rgb,
+    pub taskbar_text: Rgb,
+    pub taskbar_font: TextStyle,
+    pub taskbar_spacing: u16,
+    pub task_button: Rgb,
+    pub task_button_min_width: u16,
+    pub task_button_max_width: u16,
+    pub dock_background: Rgba,
+}
+
+/// rui_lib的控件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WidgetTheme {
+    pub button_font: TextStyle,
+    pub button_light_text: Rgb,
+    pub button_dark_text: Rgb,
+    pub label_text: Rgb,
+    pub icon_placeholder: Rgb,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub window: WindowTheme,
+    pub desktop: DesktopTheme,
+    pub widget: WidgetTheme,
+}
+
+/// 解析失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeErrorKind {
+    Syntax,         // 既不是[分组]也不是"键 = 值"
+    UnknownSection, // 不认识的分组
+    UnknownKey,     // 分组里没有这个键（或者键写在了第一个分组前面）
+    BadValue,       // 值的写法不对
+    Unreadable,     // 共享内存映射不了，或者文本不是UTF-8（行号为0）
+}
+
+/// 解析失败：line是出错的行号（从1开始）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThemeError {
+    pub line: u16,
+    pub kind: ThemeErrorKind,
+}
+
+impl ThemeError {
+    /// 主题文本读不了
+    pub const UNREADABLE: ThemeError = ThemeError { line: 0, kind: ThemeErrorKind::Unreadable };
+}
+
+const BLACK: Rgb = Rgb { r: 0, g: 0, b: 0 };
+
+impl Theme {
+    /// 全是0的主题（静态变量的初始值，用之前先换成解析出来的主题）
+    pub const EMPTY: Theme = Theme {
+        window: WindowTheme {
+            titlebar_height: 0,
+            border_width: 0,
+            button_size: 0,
+            button_margin: 0,
+            title_font: TextStyle::DEFAULT,
+            title_offset: 0,
+            focused_titlebar: BLACK,
+            unfocused_titlebar: BLACK,
+            focused_title_text: BLACK,
+            unfocused_title_text: BLACK,
+            focused_border: BLACK,
+            unfocused_border: BLACK,
+            background: BLACK,
+            close_button: BLACK,
+            close_button_hover: BLACK,
+            maximize_button: BLACK,
+            maximize_button_hover: BLACK,
+            minimize_button: BLACK,
+            minimize_button_hover: BLACK,
+            button_symbol: BLACK,
+        },
+        desktop: DesktopTheme {
+            background: BLACK,
+            taskbar_height: 0,
+            taskbar_background: BLACK,
+            taskbar_text: BLACK,
+            taskbar_font: TextStyle::DEFAULT,
+            taskbar_spacing: 0,
+            task_button: BLACK,
+            task_button_min_width: 0,
+            task_button_max_width: 0,
+            dock_background: Rgba { r: 0, g: 0, b: 0, a: 0 },
+        },
+        widget: WidgetTheme {
+            button_font: TextStyle::DEFAULT,
+            button_light_text: BLACK,
+            button_dark_text: BLACK,
+            label_text: BLACK,
+            icon_placeholder: BLACK,
+        },
+    };
+
+    /// 解析主题文本（没写的键用默认主题的值）
+    pub fn parse(text: &str) -> Result<Theme, ThemeError> {
+        let mut theme = Theme::default();
+        theme.apply(text)?;
+        Ok(theme)
+    }
+
+    /// 解析owner发来的WmSetTheme/ThemeChanged带的主题文本（text为0表示默认主题），
+    /// 文本必须在owner分配的共享内存里；每次都重新映射（内核没有取消映射的接口，换主题又很少，不缓存）
+    pub fn from_shared(owner: Pid, text: ShmHandle, len: u32) -> Result<Theme, ThemeError> {
+        if text == 0 {
+            return Ok(Theme::default());
+        }
+        let memory = SharedMemory::map_from(owner, text, len as usize).ok_or(ThemeError::UNREADABLE)?;
+        Theme::from_memory(&memory)
+    }
+
+    /// 解析一整段共享内存里的主题文本
+    pub fn from_memory(memory: &SharedMemory) -> Result<Theme, ThemeError> {
+        let bytes = unsafe { core::slice::from_raw_parts(memory.as_ptr(), memory.size()) };
+        Theme::parse(core::str::from_utf8(bytes).map_err(|_| ThemeError::UNREADABLE)?)
+    }
+
+    /// 把主题文本里写了的键改到self上；出错时self可能已经改了一部分
+    pub fn apply(&mut self, text: &str) -> Result<(), ThemeError> {
+        let mut section = "";
+        for (index, line) in text.lines().enumerate() {
+            let error = |kind| ThemeError { line: (index + 1).min(u16::MAX as usize) as u16, kind };
+            let line = strip_comment(line);
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = name.strip_suffix(']').ok_or(error(ThemeErrorKind::Syntax))?.trim();
+                if !matches!(section, "window" | "desktop" | "widget") {
+                    return Err(error(ThemeErrorKind::UnknownSection));
+                }
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or(error(ThemeErrorKind::Syntax))?;
+            self.set(section, key.trim(), value.trim()).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    /// 改一个键
+    fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), ThemeErrorKind> {
+        let (w, d, g) = (&mut self.window, &mut self.desktop, &mut self.widget);
+        match (section, key) {
+            ("window", "titlebar_height") => w.titlebar_height = size(value)?,
+            ("window", "border_width") => w.border_width = size(value)?,
+            ("window", "button_size") => w.button_size = size(value)?,
+            ("window", "button_margin") => w.button_margin = size(value)?,
+            ("window", "title_font") => w.title_font = font(value)?,
+            ("window", "title_offset") => w.title_offset = size(value)?,
+            ("window", "focused_titlebar") => w.focused_titlebar = rgb(value)?,
+            ("window", "unfocused_titlebar") => w.unfocused_titlebar = rgb(value)?,
+            ("window", "focused_title_text") => w.focused_title_text = rgb(value)?,
+            ("window", "unfocused_title_text") => w.unfocused_title_text = rgb(value)?,
+            ("window", "focused_border") => w.focused_border = rgb(value)?,
+            ("window", "unfocused_border") => w.unfocused_border = rgb(value)?,
+            ("window", "background") => w.background = rgb(value)?,
+            ("window", "close_button") => w.close_button = rgb(value)?,
+            ("window", "close_button_hover") => w.close_button_hover = rgb(value)?,
+            ("window", "maximize_button") => w.maximize_button = rgb(value)?,
+            ("window", "maximize_button_hover") => w.maximize_button_hover = rgb(value)?,
+            ("window", "minimize_button") => w.minimize_button = rgb(value)?,
+            ("window", "minimize_button_hover") => w.minimize_button_hover = rgb(value)?,
+            ("window", "button_symbol") => w.button_symbol = rgb(value)?,
+            ("desktop", "background") => d.background = rgb(value)?,
+            ("desktop", "taskbar_height") => d.taskbar_height = size(value)?,
+            ("desktop", "taskbar_background") => d.taskbar_background = rgb(value)?,
+            ("desktop", "taskbar_text") => d.taskbar_text = rgb(value)?,
+            ("desktop", "taskbar_font") => d.taskbar_font = font(value)?,
+            ("desktop", "taskbar_spacing") => d.taskbar_spacing = size(value)?,
+            ("desktop", "task_button") => d.task_button = rgb(value)?,
+            ("desktop", "task_button_min_width") => d.task_button_min_width = size(value)?,
+            ("desktop", "task_button_max_width") => d.task_button_max_width = size(value)?,
+            ("desktop", "dock_background") => d.dock_background = rgba(value)?,
+            ("widget", "button_font") => g.button_font = font(value)?,
+            ("widget", "button_light_text") => g.button_light_text = rgb(value)?,
+            ("widget", "button_dark_text") => g.button_dark_text = rgb(value)?,
+            ("widget", "label_text") => g.label_text = rgb(value)?,
+            ("widget", "icon_placeholder") => g.icon_placeholder = rgb(value)?,
+            _ => return Err(ThemeErrorKind::UnknownKey),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Theme {
+    /// 默认主题（随程序发布的themes/default.theme）
+    fn default() -> Self {
+        let mut theme = Theme::EMPTY;
+        // 默认主题是仓库里的文件，解析不会失败
+        let _ = theme.apply(DEFAULT_THEME);
+        theme
+    }
+}
+
+/// 去掉注释和两边的空白（颜色也以#开头，所以行内注释的#前面必须有空白）
+fn strip_comment(line: &str) -> &str {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return "";
+    }
+    let comment = line.char_indices().find(|&(i, c)| {
+        let before = &line[..i];
+        c == '#' && before.ends_with([' ', '\t']) && !before.trim_end().ends_with('=')
+    });
+    match comment {
+        Some((i, _)) => line[..i].trim_end(),
+        None => line,
+    }
+}
+
+/// 像素数（0..=MAX_SIZE）
+fn size(value: &str) -> Result<u16, ThemeErrorKind> {
+    value.parse().ok().filter(|size| *size <= MAX_SIZE).ok_or(ThemeErrorKind::BadValue)
+}
+
+/// #rrggbb或#rrggbbaa
+fn rgba(value: &str) -> Result<Rgba, ThemeErrorKind> {
+    let hex = value.strip_prefix('#').filter(|hex| hex.len() == 6 || hex.len() == 8).ok_or(ThemeErrorKind::BadValue)?;
+    let channel = |i: usize| hex.get(i..i + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok()).ok_or(ThemeErrorKind::BadValue);
+    let a = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Rgba::new(channel(0)?, channel(2)?, channel(4)?, a))
+}
+
+/// 不透明的颜色（#rrggbb）
+fn rgb(value: &str) -> Result<Rgb, ThemeErrorKind> {
+    if value.len() != 7 {
+        return Err(ThemeErrorKind::BadValue);
+    }
+    let Rgba { r, g, b, .. } = rgba(value)?;
+    Ok(Rgb { r, g, b })
+}
+
+/// "字体族 大小 [bold]"
+fn font(value: &str) -> Result<TextStyle, ThemeErrorKind> {
+    let mut words = value.split_whitespace();
+    let family = match words.next() {
+        Some("bitmap") => FontFamily::Bitmap,
+        Some("sans") => FontFamily::Sans,
+        _ => return Err(ThemeErrorKind::BadValue),
+    };
+    let size = size(words.next().ok_or(ThemeErrorKind::BadValue)?)?;
+    let weight = match words.next() {
+        None => FontWeight::Regular,
+        Some("bold") => FontWeight::Bold,
+        Some(_) => return Err(ThemeErrorKind::BadValue),
+    };
+    if words.next().is_some() {
+        return Err(ThemeErrorKind::BadValue);
+    }
+    Ok(TextStyle::new(family, size, weight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_bounded() {
+        let mut theme = Theme::default();
+        assert_eq!(theme.apply("[window]\nborder_width = 256\ntitle_font = sans 256 bold"), Ok(()));
+        assert_eq!(theme.window.border_width, MAX_SIZE);
+        for (text, line) in [
+            ("[window]\nborder_width = 257", 2),
+            ("[window]\ntitlebar_height = 65535", 2),
+            ("[desktop]\n\ntaskbar_height = -1", 3),
+            ("[window]\ntitle_font = sans 300", 2),
+        ] {
+            assert_eq!(theme.apply(text), Err(ThemeError { line, kind: ThemeErrorKind::BadValue }), "{text}");
+        }
+    }
+}
@@ -0,0 +1,1844 @@
+// ==========================
+// IPC消息的线上编码（定长布局、带版本号、不含指针）
+// ==========================
+// 帧格式（全部小端）：
+//   [版本号 u8][消息类型 u8][负载长度 u16][请求ID u32][回复的请求ID u32][负载...]
+// 请求ID/回复ID用于请求-回复配对（见ipc模块），0表示没有。
+// 负载按字段顺序依次写入：u16/u32/u64按小端，Rgb为3个字节，Rgba为4个字节，
+// 字符串为"长度u8 + UTF-8字节"，PixelFormat为"bpp u8 + pitch u32 + 红绿蓝各(偏移u8, 位数u8)"（要能通过is_valid），
+// 矩形为"x, y, 宽, 高各u16"，模式列表为"个数u8 + 每个(宽u16, 高u16, bpp u8)"，脏矩形列表为"个数u8 + 每个矩形"，
+// 层列表为"个数u8 + 每个(表面ID u32, x u16, y u16)"，顶点列表为"个数u8 + 每个(x u16, y u16)"，
+// 位图的画法为"0不透明 / 1颜色键(后跟Rgb) / 2按alpha混合"，文字样式为"字体族u8 + 字重u8 + 大小u16"，
+// 对齐方式为"0左 / 1居中 / 2右"，鼠标按键为"0左 / 1右 / 2中"，
+// 指针形状为"0箭头 / 1移动 / 2上下 / 3左右 / 4左上-右下 / 5右上-左下"，
+// 叠放的层为"0底层 / 1普通 / 2顶层"。解码时严格检查长度和内容，畸形消息返回错误。
+
+use crate::{
+    command::RenderCommand, image::BlitMode, Channel, DamageList, DumpFormat, FontFamily, FontWeight, IpcMessage, IpcStr, Layer,
+    CursorShape, LayerList, ModeList, MouseButton, PixelFormat, Point, PointList, Rect, Rgb, Rgba, StackLayer, TextAlign, TextStyle, VideoMode, DAMAGE_CAPACITY, IPC_STR_CAPACITY,
+    MODE_LIST_CAPACITY, POLYGON_CAPACITY, SCENE_CAPACITY,
+};
+
+/// 当前编码版本（布局不兼容的修改必须加1）
+pub const WIRE_VERSION: u8 = 1;
+/// 帧头长度
+pub const HEADER_SIZE: usize = 12;
+/// 单条消息编码后的最大长度（内核和接收方按这个大小准备缓冲区）
+pub const MAX_MESSAGE_SIZE: usize = 256;
+
+// 消息类型编号（只能追加，不能改已有编号）
+const TAG_GPU_INIT: u8 = 0;
+const TAG_GPU_GET_FB: u8 = 1;
+const TAG_GPU_FLUSH: u8 = 2;
+const TAG_GPU_FB_REPLY: u8 = 3;
+const TAG_RENDER_DRAW_PIXEL: u8 = 4;
+const TAG_RENDER_DRAW_RECT: u8 = 5;
+const TAG_WM_CREATE_WINDOW: u8 = 6;
+const TAG_MOUSE_CLICK: u8 = 7;
+const TAG_WM_WINDOW_OPENED: u8 = 8;
+const TAG_WM_FOCUS_WINDOW: u8 = 9;
+const TAG_MOUSE_MOVE: u8 = 10;
+const TAG_RENDER_DRAW_TEXT: u8 = 11;
+const TAG_WM_WINDOW_CREATED: u8 = 12;
+const TAG_GPU_DUMP: u8 = 13;
+const TAG_GPU_DUMP_DONE: u8 = 14;
+const TAG_GPU_LIST_MODES: u8 = 15;
+const TAG_GPU_MODE_LIST: u8 = 16;
+const TAG_GPU_SET_MODE: u8 = 17;
+const TAG_RENDER_SET_MODE: u8 = 18;
+const TAG_RENDER_SUBSCRIBE_SCREEN: u8 = 19;
+const TAG_RENDER_SCREEN_INFO: u8 = 20;
+const TAG_SCREEN_MODE_CHANGED: u8 = 21;
+const TAG_RENDER_COMMIT: u8 = 22;
+const TAG_GPU_FLIP: u8 = 23;
+const TAG_GPU_FLIPPED: u8 = 24;
+const TAG_RENDER_SUBMIT: u8 = 25;
+const TAG_RENDER_CREATE_SURFACE: u8 = 26;
+const TAG_RENDER_SURFACE_CREATED: u8 = 27;
+const TAG_RENDER_SET_SCENE: u8 = 28;
+const TAG_RENDER_MAP_SURFACE: u8 = 29;
+const TAG_RENDER_SURFACE_MAPPED: u8 = 30;
+const TAG_RENDER_DAMAGE_SURFACE: u8 = 31;
+const TAG_RENDER_DRAW_LINE: u8 = 32;
+const TAG_RENDER_STROKE_RECT: u8 = 33;
+const TAG_RENDER_DRAW_ELLIPSE: u8 = 34;
+const TAG_RENDER_DRAW_ROUND_RECT: u8 = 35;
+const TAG_RENDER_FILL_POLYGON: u8 = 36;
+const TAG_RENDER_BLIT: u8 = 37;
+const TAG_RENDER_MEASURE_TEXT: u8 = 38;
+const TAG_RENDER_TEXT_MEASURED: u8 = 39;
+const TAG_WM_SET_THEME: u8 = 40;
+const TAG_WM_THEME_SET: u8 = 41;
+const TAG_WM_SUBSCRIBE_THEME: u8 = 42;
+const TAG_THEME_CHANGED: u8 = 43;
+const TAG_MOUSE_DOWN: u8 = 44;
+const TAG_MOUSE_UP: u8 = 45;
+const TAG_RENDER_RESIZE_SURFACE: u8 = 46;
+const TAG_RENDER_SURFACE_RESIZED: u8 = 47;
+const TAG_WM_WINDOW_RESIZED: u8 = 48;
+const TAG_WM_SET_SIZE_LIMITS: u8 = 49;
+const TAG_WM_SET_CURSOR: u8 = 50;
+const TAG_WM_RAISE_WINDOW: u8 = 51;
+const TAG_WM_LOWER_WINDOW: u8 = 52;
+const TAG_WM_SET_STACK_LAYER: u8 = 53;
+const TAG_WM_SET_TRANSIENT_FOR: u8 = 54;
+const TAG_WM_CREATE_PANEL: u8 = 55;
+const TAG_WM_MOVE_WINDOW: u8 = 56;
+const TAG_RENDER_DESTROY_SURFACE: u8 = 57;
+
+// 绘图命令类型编号（RenderCommandBuffer里的记录，同样只能追加）
+const CMD_PIXEL: u8 = 0;
+const CMD_RECT: u8 = 1;
+const CMD_TEXT: u8 = 2;
+const CMD_COMMIT: u8 = 3;
+const CMD_TARGET: u8 = 4;
+const CMD_PUSH_CLIP: u8 = 5;
+const CMD_POP_CLIP: u8 = 6;
+const CMD_LINE: u8 = 7;
+const CMD_STROKE_RECT: u8 = 8;
+const CMD_ELLIPSE: u8 = 9;
+const CMD_ROUND_RECT: u8 = 10;
+const CMD_POLYGON: u8 = 11;
+const CMD_BLIT: u8 = 12;
+const CMD_TEXT_BOX: u8 = 13;
+const CMD_CLEAR: u8 = 14;
+
+/// 请求-回复关联信息（0表示没有）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Correlation {
+    pub request_id: u32, // 发送方给这条请求分配的ID（需要回复时非0）
+    pub reply_to: u32,   // 这条消息回复的是哪个请求ID（不是回复时为0）
+}
+
+/// 编解码错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    BufferTooSmall,       // 编码：输出缓冲区放不下
+    Truncated,            // 解码：数据比声明的短
+    BadVersion(u8),       // 解码：版本号不认识
+    UnknownTag(u8),       // 解码：消息类型不认识
+    BadLength,            // 解码：负载长度和消息类型不符
+    StringTooLong,        // 解码：字符串超过IPC_STR_CAPACITY
+    InvalidUtf8,          // 解码：字符串不是合法UTF-8
+    BadValue,             // 解码：枚举/布尔字段的取值非法
+}
+
+// ==========================
+// 编码
+// ==========================
+/// 顺序写入器
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn bytes(&mut self, data: &[u8]) -> Result<(), WireError> {
+        let end = self.pos + data.len();
+        if end > self.buf.len() {
+            return Err(WireError::BufferTooSmall);
+        }
+        self.buf[self.pos..end].copy_from_slice(data);
+        self.pos = end;
+        Ok(())
+    }
+
+    fn u8(&mut self, v: u8) -> Result<(), WireError> {
+        self.bytes(&[v])
+    }
+
+    fn u16(&mut self, v: u16) -> Result<(), WireError> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn u32(&mut self, v: u32) -> Result<(), WireError> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn u64(&mut self, v: u64) -> Result<(), WireError> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn rgb(&mut self, c: Rgb) -> Result<(), WireError> {
+        self.bytes(&[c.r, c.g, c.b])
+    }
+
+    fn rgba(&mut self, c: Rgba) -> Result<(), WireError> {
+        self.bytes(&[c.r, c.g, c.b, c.a])
+    }
+
+    fn bool(&mut self, v: bool) -> Result<(), WireError> {
+        self.u8(v as u8)
+    }
+
+    fn pixel_format(&mut self, f: &PixelFormat) -> Result<(), WireError> {
+        self.u8(f.bpp)?;
+        self.u32(f.pitch)?;
+        for c in [f.red, f.green, f.blue] {
+            self.u8(c.shift)?;
+            self.u8(c.size)?;
+        }
+        Ok(())
+    }
+
+    fn mode_list(&mut self, list: &ModeList) -> Result<(), WireError> {
+        let modes = list.as_slice();
+        self.u8(modes.len() as u8)?;
+        for mode in modes {
+            self.u16(mode.width)?;
+            self.u16(mode.height)?;
+            self.u8(mode.bpp)?;
+        }
+        Ok(())
+    }
+
+    fn rect(&mut self, rect: &Rect) -> Result<(), WireError> {
+        self.u16(rect.x)?;
+        self.u16(rect.y)?;
+        self.u16(rect.w)?;
+        self.u16(rect.h)
+    }
+
+    fn damage_list(&mut self, list: &DamageList) -> Result<(), WireError> {
+        let rects = list.as_slice();
+        self.u8(rects.len() as u8)?;
+        for rect in rects {
+            self.rect(rect)?;
+        }
+        Ok(())
+    }
+
+    fn layer_list(&mut self, list: &LayerList) -> Result<(), WireError> {
+        let layers = list.as_slice();
+        self.u8(layers.len() as u8)?;
+        for layer in layers {
+            self.u32(layer.surface)?;
+            self.u16(layer.x)?;
+            self.u16(layer.y)?;
+        }
+        Ok(())
+    }
+
+    fn blit_mode(&mut self, mode: BlitMode) -> Result<(), WireError> {
+        match mode {
+            BlitMode::Opaque => self.u8(0),
+            BlitMode::ColorKey(key) => {
+                self.u8(1)?;
+                self.rgb(key)
+            }
+            BlitMode::Alpha => self.u8(2),
+        }
+    }
+
+    fn text_style(&mut self, style: TextStyle) -> Result<(), WireError> {
+        self.u8(style.family as u8)?;
+        self.u8(style.weight as u8)?;
+        self.u16(style.size)
+    }
+
+    fn text_align(&mut self, align: TextAlign) -> Result<(), WireError> {
+        self.u8(align as u8)
+    }
+
+    fn mouse_button(&mut self, button: MouseButton) -> Result<(), WireError> {
+        self.u8(button as u8)
+    }
+
+    fn cursor_shape(&mut self, shape: CursorShape) -> Result<(), WireError> {
+        self.u8(shape as u8)
+    }
+
+    fn stack_layer(&mut self, layer: StackLayer) -> Result<(), WireError> {
+        self.u8(layer as u8)
+    }
+
+    fn point_list(&mut self, list: &PointList) -> Result<(), WireError> {
+        let points = list.as_slice();
+        self.u8(points.len() as u8)?;
+        for point in points {
+            self.u16(point.x)?;
+            self.u16(point.y)?;
+        }
+        Ok(())
+    }
+
+    fn str(&mut self, s: &IpcStr) -> Result<(), WireError> {
+        let s = s.as_str().as_bytes();
+        self.u8(s.len() as u8)?;
+        self.bytes(s)
+    }
+}
+
+/// 把消息编码到buf（不带关联信息），返回写入的字节数
+pub fn encode(msg: &IpcMessage, buf: &mut [u8]) -> Result<usize, WireError> {
+    encode_frame(msg, Correlation::default(), buf)
+}
+
+/// 把消息连同关联信息编码到buf，返回写入的字节数
+pub fn encode_frame(msg: &IpcMessage, correlation: Correlation, buf: &mut [u8]) -> Result<usize, WireError> {
+    if buf.len() < HEADER_SIZE {
+        return Err(WireError::BufferTooSmall);
+    }
+    let mut w = Writer { buf, pos: HEADER_SIZE };
+    let tag = match *msg {
+        IpcMessage::GpuInit { width, height } => {
+            w.u16(width)?;
+            w.u16(height)?;
+            TAG_GPU_INIT
+        }
+        IpcMessage::GpuGetFb => TAG_GPU_GET_FB,
+        IpcMessage::GpuFlush { ref rects } => {
+            w.damage_list(rects)?;
+            TAG_GPU_FLUSH
+        }
+        IpcMessage::GpuFbReply { fb, width, height, format, pages } => {
+            w.u64(fb)?;
+            w.u16(width)?;
+            w.u16(height)?;
+            w.pixel_format(&format)?;
+            w.u8(pages)?;
+            TAG_GPU_FB_REPLY
+        }
+        IpcMessage::RenderDrawPixel { x, y, color } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.rgb(color)?;
+            TAG_RENDER_DRAW_PIXEL
+        }
+        IpcMessage::RenderDrawRect { x, y, w: width, h, color } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.u16(width)?;
+            w.u16(h)?;
+            w.rgb(color)?;
+            TAG_RENDER_DRAW_RECT
+        }
+        IpcMessage::WmCreateWindow { x, y, w: width, h, ref title } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.u16(width)?;
+            w.u16(h)?;
+            w.str(title)?;
+            TAG_WM_CREATE_WINDOW
+        }
+        IpcMessage::MouseClick { x, y } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            TAG_MOUSE_CLICK
+        }
+        IpcMessage::WmWindowOpened { ref title, window_id } => {
+            w.str(title)?;
+            w.u32(window_id)?;
+            TAG_WM_WINDOW_OPENED
+        }
+        IpcMessage::WmFocusWindow { window_id } => {
+            w.u32(window_id)?;
+            TAG_WM_FOCUS_WINDOW
+        }
+        IpcMessage::MouseMove { x, y } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            TAG_MOUSE_MOVE
+        }
+        IpcMessage::RenderDrawText { x, y, ref text, style, color } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.str(text)?;
+            w.text_style(style)?;
+            w.rgb(color)?;
+            TAG_RENDER_DRAW_TEXT
+        }
+        IpcMessage::WmWindowCreated { window_id, surface } => {
+            w.u32(window_id)?;
+            w.u32(surface)?;
+            TAG_WM_WINDOW_CREATED
+        }
+        IpcMessage::GpuDump { format, ref name } => {
+            w.u8(format as u8)?;
+            w.str(name)?;
+            TAG_GPU_DUMP
+        }
+        IpcMessage::GpuDumpDone { ok } => {
+            w.bool(ok)?;
+            TAG_GPU_DUMP_DONE
+        }
+        IpcMessage::GpuListModes => TAG_GPU_LIST_MODES,
+        IpcMessage::GpuModeList { ref modes } => {
+            w.mode_list(modes)?;
+            TAG_GPU_MODE_LIST
+        }
+        IpcMessage::GpuSetMode { width, height } => {
+            w.u16(width)?;
+            w.u16(height)?;
+            TAG_GPU_SET_MODE
+        }
+        IpcMessage::RenderSetMode { width, height } => {
+            w.u16(width)?;
+            w.u16(height)?;
+            TAG_RENDER_SET_MODE
+        }
+        IpcMessage::RenderSubscribeScreen => TAG_RENDER_SUBSCRIBE_SCREEN,
+        IpcMessage::RenderScreenInfo { width, height } => {
+            w.u16(width)?;
+            w.u16(height)?;
+            TAG_RENDER_SCREEN_INFO
+        }
+        IpcMessage::ScreenModeChanged { width, height } => {
+            w.u16(width)?;
+            w.u16(height)?;
+            TAG_SCREEN_MODE_CHANGED
+        }
+        IpcMessage::RenderCommit => TAG_RENDER_COMMIT,
+        IpcMessage::GpuFlip { page } => {
+            w.u8(page)?;
+            TAG_GPU_FLIP
+        }
+        IpcMessage::GpuFlipped { page } => {
+            w.u8(page)?;
+            TAG_GPU_FLIPPED
+        }
+        IpcMessage::RenderSubmit { ring, size, end } => {
+            w.u64(ring)?;
+            w.u32(size)?;
+            w.u32(end)?;
+            TAG_RENDER_SUBMIT
+        }
+        IpcMessage::RenderCreateSurface { width, height, owner, ref content } => {
+            w.u16(width)?;
+            w.u16(height)?;
+            w.u64(owner)?;
+            w.rect(content)?;
+            TAG_RENDER_CREATE_SURFACE
+        }
+        IpcMessage::RenderSurfaceCreated { surface, shm } => {
+            w.u32(surface)?;
+            w.u64(shm)?;
+            TAG_RENDER_SURFACE_CREATED
+        }
+        IpcMessage::RenderSetScene { ref layers } => {
+            w.layer_list(layers)?;
+            TAG_RENDER_SET_SCENE
+        }
+        IpcMessage::RenderMapSurface { surface } => {
+            w.u32(surface)?;
+            TAG_RENDER_MAP_SURFACE
+        }
+        IpcMessage::RenderSurfaceMapped { surface, shm, width, height, ref area } => {
+            w.u32(surface)?;
+            w.u64(shm)?;
+            w.u16(width)?;
+            w.u16(height)?;
+            w.rect(area)?;
+            TAG_RENDER_SURFACE_MAPPED
+        }
+        IpcMessage::RenderDamageSurface { surface, ref rects } => {
+            w.u32(surface)?;
+            w.damage_list(rects)?;
+            TAG_RENDER_DAMAGE_SURFACE
+        }
+        IpcMessage::RenderDrawLine { x0, y0, x1, y1, color } => {
+            w.u16(x0)?;
+            w.u16(y0)?;
+            w.u16(x1)?;
+            w.u16(y1)?;
+            w.rgba(color)?;
+            TAG_RENDER_DRAW_LINE
+        }
+        IpcMessage::RenderStrokeRect { x, y, w: width, h, thickness, color } => {
+            w.rect(&Rect::new(x, y, width, h))?;
+            w.u16(thickness)?;
+            w.rgba(color)?;
+            TAG_RENDER_STROKE_RECT
+        }
+        IpcMessage::RenderDrawEllipse { cx, cy, rx, ry, thickness, color } => {
+            w.u16(cx)?;
+            w.u16(cy)?;
+            w.u16(rx)?;
+            w.u16(ry)?;
+            w.u16(thickness)?;
+            w.rgba(color)?;
+            TAG_RENDER_DRAW_ELLIPSE
+        }
+        IpcMessage::RenderDrawRoundRect { x, y, w: width, h, radius, thickness, color } => {
+            w.rect(&Rect::new(x, y, width, h))?;
+            w.u16(radius)?;
+            w.u16(thickness)?;
+            w.rgba(color)?;
+            TAG_RENDER_DRAW_ROUND_RECT
+        }
+        IpcMessage::RenderFillPolygon { ref points, color } => {
+            w.point_list(points)?;
+            w.rgba(color)?;
+            TAG_RENDER_FILL_POLYGON
+        }
+        IpcMessage::RenderBlit { image, width, height, x, y, w: dst_w, h, mode } => {
+            w.u64(image)?;
+            w.u16(width)?;
+            w.u16(height)?;
+            w.rect(&Rect::new(x, y, dst_w, h))?;
+            w.blit_mode(mode)?;
+            TAG_RENDER_BLIT
+        }
+        IpcMessage::RenderMeasureText { ref text, style, max_width } => {
+            w.str(text)?;
+            w.text_style(style)?;
+            w.u16(max_width)?;
+            TAG_RENDER_MEASURE_TEXT
+        }
+        IpcMessage::RenderTextMeasured { width, height } => {
+            w.u16(width)?;
+            w.u16(height)?;
+            TAG_RENDER_TEXT_MEASURED
+        }
+        IpcMessage::WmSetTheme { text, len } => {
+            w.u64(text)?;
+            w.u32(len)?;
+            TAG_WM_SET_THEME
+        }
+        IpcMessage::WmThemeSet { ok, line } => {
+            w.bool(ok)?;
+            w.u16(line)?;
+            TAG_WM_THEME_SET
+        }
+        IpcMessage::WmSubscribeTheme => TAG_WM_SUBSCRIBE_THEME,
+        IpcMessage::ThemeChanged { text, len } => {
+            w.u64(text)?;
+            w.u32(len)?;
+            TAG_THEME_CHANGED
+        }
+        IpcMessage::MouseDown { x, y, button } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.mouse_button(button)?;
+            TAG_MOUSE_DOWN
+        }
+        IpcMessage::MouseUp { x, y, button } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.mouse_button(button)?;
+            TAG_MOUSE_UP
+        }
+        IpcMessage::RenderResizeSurface { surface, width, height, ref content } => {
+            w.u32(surface)?;
+            w.u16(width)?;
+            w.u16(height)?;
+            w.rect(content)?;
+            TAG_RENDER_RESIZE_SURFACE
+        }
+        IpcMessage::RenderSurfaceResized { surface, shm } => {
+            w.u32(surface)?;
+            w.u64(shm)?;
+            TAG_RENDER_SURFACE_RESIZED
+        }
+        IpcMessage::WmWindowResized { window_id, width, height } => {
+            w.u32(window_id)?;
+            w.u16(width)?;
+            w.u16(height)?;
+            TAG_WM_WINDOW_RESIZED
+        }
+        IpcMessage::WmSetSizeLimits { window_id, min_width, min_height, max_width, max_height } => {
+            w.u32(window_id)?;
+            w.u16(min_width)?;
+            w.u16(min_height)?;
+            w.u16(max_width)?;
+            w.u16(max_height)?;
+            TAG_WM_SET_SIZE_LIMITS
+        }
+        IpcMessage::WmSetCursor { shape } => {
+            w.cursor_shape(shape)?;
+            TAG_WM_SET_CURSOR
+        }
+        IpcMessage::WmRaiseWindow { window_id } => {
+            w.u32(window_id)?;
+            TAG_WM_RAISE_WINDOW
+        }
+        IpcMessage::WmLowerWindow { window_id } => {
+            w.u32(window_id)?;
+            TAG_WM_LOWER_WINDOW
+        }
+        IpcMessage::WmSetStackLayer { window_id, layer } => {
+            w.u32(window_id)?;
+            w.stack_layer(layer)?;
+            TAG_WM_SET_STACK_LAYER
+        }
+        IpcMessage::WmSetTransientFor { window_id, parent } => {
+            w.u32(window_id)?;
+            w.u32(parent)?;
+            TAG_WM_SET_TRANSIENT_FOR
+        }
+        IpcMessage::WmCreatePanel { x, y, w: width, h, layer } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.u16(width)?;
+            w.u16(h)?;
+            w.stack_layer(layer)?;
+            TAG_WM_CREATE_PANEL
+        }
+        IpcMessage::WmMoveWindow { window_id, x, y } => {
+            w.u32(window_id)?;
+            w.u16(x)?;
+            w.u16(y)?;
+            TAG_WM_MOVE_WINDOW
+        }
+        IpcMessage::RenderDestroySurface { surface } => {
+            w.u32(surface)?;
+            TAG_RENDER_DESTROY_SURFACE
+        }
+    };
+    let len = w.pos;
+    let payload_len = (len - HEADER_SIZE) as u16;
+    w.buf[0] = WIRE_VERSION;
+    w.buf[1] = tag;
+    w.buf[2..4].copy_from_slice(&payload_len.to_le_bytes());
+    w.buf[4..8].copy_from_slice(&correlation.request_id.to_le_bytes());
+    w.buf[8..12].copy_from_slice(&correlation.reply_to.to_le_bytes());
+    Ok(len)
+}
+
+// ==========================
+// 解码
+// ==========================
+/// 顺序读取器（只读负载部分）
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], WireError> {
+        let end = self.pos + n;
+        if end > self.buf.len() {
+            return Err(WireError::BadLength);
+        }
+        let data = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, WireError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, WireError> {
+        let mut v = [0; 4];
+        v.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(v))
+    }
+
+    fn u64(&mut self) -> Result<u64, WireError> {
+        let mut v = [0; 8];
+        v.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(v))
+    }
+
+    fn rgb(&mut self) -> Result<Rgb, WireError> {
+        let b = self.bytes(3)?;
+        Ok(Rgb { r: b[0], g: b[1], b: b[2] })
+    }
+
+    fn rgba(&mut self) -> Result<Rgba, WireError> {
+        let b = self.bytes(4)?;
+        Ok(Rgba { r: b[0], g: b[1], b: b[2], a: b[3] })
+    }
+
+    fn bool(&mut self) -> Result<bool, WireError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(WireError::BadValue),
+        }
+    }
+
+    fn dump_format(&mut self) -> Result<DumpFormat, WireError> {
+        match self.u8()? {
+            0 => Ok(DumpFormat::Ppm),
+            1 => Ok(DumpFormat::Png),
+            _ => Err(WireError::BadValue),
+        }
+    }
+
+    fn channel(&mut self) -> Result<Channel, WireError> {
+        Ok(Channel { shift: self.u8()?, size: self.u8()? })
+    }
+
+    /// 帧缓冲区格式：位数、通道要合法，一行要放得下width个像素
+    fn pixel_format(&mut self, width: u16) -> Result<PixelFormat, WireError> {
+        let format = PixelFormat {
+            bpp: self.u8()?,
+            pitch: self.u32()?,
+            red: self.channel()?,
+            green: self.channel()?,
+            blue: self.channel()?,
+        };
+        if !format.is_valid(width) {
+            return Err(WireError::BadValue);
+        }
+        Ok(format)
+    }
+
+    fn mode_list(&mut self) -> Result<ModeList, WireError> {
+        let len = self.u8()? as usize;
+        if len > MODE_LIST_CAPACITY {
+            return Err(WireError::BadValue);
+        }
+        let mut list = ModeList::new();
+        for _ in 0..len {
+            list.push(VideoMode { width: self.u16()?, height: self.u16()?, bpp: self.u8()? });
+        }
+        Ok(list)
+    }
+
+    fn rect(&mut self) -> Result<Rect, WireError> {
+        Ok(Rect::new(self.u16()?, self.u16()?, self.u16()?, self.u16()?))
+    }
+
+    fn blit_mode(&mut self) -> Result<BlitMode, WireError> {
+        match self.u8()? {
+            0 => Ok(BlitMode::Opaque),
+            1 => Ok(BlitMode::ColorKey(self.rgb()?)),
+            2 => Ok(BlitMode::Alpha),
+            _ => Err(WireError::BadValue),
+        }
+    }
+
+    fn text_style(&mut self) -> Result<TextStyle, WireError> {
+        let family = match self.u8()? {
+            0 => FontFamily::Bitmap,
+            1 => FontFamily::Sans,
+            _ => return Err(WireError::BadValue),
+        };
+        let weight = match self.u8()? {
+            0 => FontWeight::Regular,
+            1 => FontWeight::Bold,
+            _ => return Err(WireError::BadValue),
+        };
+        Ok(TextStyle { family, weight, size: self.u16()? })
+    }
+
+    fn text_align(&mut self) -> Result<TextAlign, WireError> {
+        match self.u8()? {
+            0 => Ok(TextAlign::Left),
+            1 => Ok(TextAlign::Center),
+            2 => Ok(TextAlign::Right),
+            _ => Err(WireError::BadValue),
+        }
+    }
+
+    fn mouse_button(&mut self) -> Result<MouseButton, WireError> {
+        match self.u8()? {
+            0 => Ok(MouseButton::Left),
+            1 => Ok(MouseButton::Right),
+            2 => Ok(MouseButton::Middle),
+            _ => Err(WireError::BadValue),
+        }
+    }
+
+    fn cursor_shape(&mut self) -> Result<CursorShape, WireError> {
+        match self.u8()? {
+            0 => Ok(CursorShape::Arrow),
+            1 => Ok(CursorShape::Move),
+            2 => Ok(CursorShape::ResizeNS),
+            3 => Ok(CursorShape::ResizeEW),
+            4 => Ok(CursorShape::ResizeNWSE),
+            5 => Ok(CursorShape::ResizeNESW),
+            _ => Err(WireError::BadValue),
+        }
+    }
+
+    fn stack_layer(&mut self) -> Result<StackLayer, WireError> {
+        match self.u8()? {
+            0 => Ok(StackLayer::Bottom),
+            1 => Ok(StackLayer::Normal),
+            2 => Ok(StackLayer::Top),
+            _ => Err(WireError::BadValue),
+        }
+    }
+
+    fn point_list(&mut self) -> Result<PointList, WireError> {
+        let len = self.u8()? as usize;
+        if len > POLYGON_CAPACITY {
+            return Err(WireError::BadValue);
+        }
+        let mut list = PointList::new();
+        for _ in 0..len {
+            list.push(Point { x: self.u16()?, y: self.u16()? });
+        }
+        Ok(list)
+    }
+
+    fn damage_list(&mut self) -> Result<DamageList, WireError> {
+        let len = self.u8()? as usize;
+        if len > DAMAGE_CAPACITY {
+            return Err(WireError::BadValue);
+        }
+        // 原样收下（编码的一方已经合并过），不是add会产生的列表就是畸形的
+        let mut list = DamageList::new();
+        for _ in 0..len {
+            if !list.push(self.rect()?) {
+                return Err(WireError::BadValue);
+            }
+        }
+        Ok(list)
+    }
+
+    fn layer_list(&mut self) -> Result<LayerList, WireError> {
+        let len = self.u8()? as usize;
+        if len > SCENE_CAPACITY {
+            return Err(WireError::BadValue);
+        }
+        let mut list = LayerList::new();
+        for _ in 0..len {
+            list.push(Layer { surface: self.u32()?, x: self.u16()?, y: self.u16()? });
+        }
+        Ok(list)
+    }
+
+    fn str(&mut self) -> Result<IpcStr, WireError> {
+        let len = self.u8()? as usize;
+        if len > IPC_STR_CAPACITY {
+            return Err(WireError::StringTooLong);
+        }
+        let s = core::str::from_utf8(self.bytes(len)?).map_err(|_| WireError::InvalidUtf8)?;
+        Ok(IpcStr::new(s))
+    }
+}
+
+/// 从buf解码一条完整消息（buf必须正好是一帧，多余或缺少的字节都算畸形）
+pub fn decode(buf: &[u8]) -> Result<IpcMessage, WireError> {
+    decode_frame(buf).map(|(_, msg)| msg)
+}
+
+/// 解码一帧，同时返回关联信息
+pub fn decode_frame(buf: &[u8]) -> Result<(Correlation, IpcMessage), WireError> {
+    if buf.len() < HEADER_SIZE {
+        return Err(WireError::Truncated);
+    }
+    if buf[0] != WIRE_VERSION {
+        return Err(WireError::BadVersion(buf[0]));
+    }
+    let tag = buf[1];
+    let payload_len = u16::from_le_bytes([buf[2], buf[3]]) as usize;
+    if buf.len() < HEADER_SIZE + payload_len {
+        return Err(WireError::Truncated);
+    }
+    if buf.len() > HEADER_SIZE + payload_len {
+        return Err(WireError::BadLength);
+    }
+    let correlation = Correlation {
+        request_id: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        reply_to: u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]),
+    };
+    let mut r = Reader { buf: &buf[HEADER_SIZE..], pos: 0 };
+    let msg = match tag {
+        TAG_GPU_INIT => IpcMessage::GpuInit { width: r.u16()?, height: r.u16()? },
+        TAG_GPU_GET_FB => IpcMessage::GpuGetFb,
+        TAG_GPU_FLUSH => IpcMessage::GpuFlush { rects: r.damage_list()? },
+        TAG_GPU_FB_REPLY => {
+            let (fb, width, height) = (r.u64()?, r.u16()?, r.u16()?);
+            IpcMessage::GpuFbReply { fb, width, height, format: r.pixel_format(width)?, pages: r.u8()? }
+        }
+        TAG_RENDER_DRAW_PIXEL => IpcMessage::RenderDrawPixel { x: r.u16()?, y: r.u16()?, color: r.rgb()? },
+        TAG_RENDER_DRAW_RECT => IpcMessage::RenderDrawRect {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            color: r.rgb()?,
+        },
+        TAG_WM_CREATE_WINDOW => IpcMessage::WmCreateWindow {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            title: r.str()?,
+        },
+        TAG_MOUSE_CLICK => IpcMessage::MouseClick { x: r.u16()?, y: r.u16()? },
+        TAG_WM_WINDOW_OPENED => IpcMessage::WmWindowOpened { title: r.str()?, window_id: r.u32()? },
+        TAG_WM_FOCUS_WINDOW => IpcMessage::WmFocusWindow { window_id: r.u32()? },
+        TAG_MOUSE_MOVE => IpcMessage::MouseMove { x: r.u16()?, y: r.u16()? },
+        TAG_RENDER_DRAW_TEXT => IpcMessage::RenderDrawText {
+            x: r.u16()?,
+            y: r.u16()?,
+            text: r.str()?,
+            style: r.text_style()?,
+            color: r.rgb()?,
+        },
+        TAG_WM_WINDOW_CREATED => IpcMessage::WmWindowCreated { window_id: r.u32()?, surface: r.u32()? },
+        TAG_GPU_DUMP => IpcMessage::GpuDump { format: r.dump_format()?, name: r.str()? },
+        TAG_GPU_DUMP_DONE => IpcMessage::GpuDumpDone { ok: r.bool()? },
+        TAG_GPU_LIST_MODES => IpcMessage::GpuListModes,
+        TAG_GPU_MODE_LIST => IpcMessage::GpuModeList { modes: r.mode_list()? },
+        TAG_GPU_SET_MODE => IpcMessage::GpuSetMode { width: r.u16()?, height: r.u16()? },
+        TAG_RENDER_SET_MODE => IpcMessage::RenderSetMode { width: r.u16()?, height: r.u16()? },
+        TAG_RENDER_SUBSCRIBE_SCREEN => IpcMessage::RenderSubscribeScreen,
+        TAG_RENDER_SCREEN_INFO => IpcMessage::RenderScreenInfo { width: r.u16()?, height: r.u16()? },
+        TAG_SCREEN_MODE_CHANGED => IpcMessage::ScreenModeChanged { width: r.u16()?, height: r.u16()? },
+        TAG_RENDER_COMMIT => IpcMessage::RenderCommit,
+        TAG_GPU_FLIP => IpcMessage::GpuFlip { page: r.u8()? },
+        TAG_GPU_FLIPPED => IpcMessage::GpuFlipped { page: r.u8()? },
+        TAG_RENDER_SUBMIT => IpcMessage::RenderSubmit { ring: r.u64()?, size: r.u32()?, end: r.u32()? },
+        TAG_RENDER_CREATE_SURFACE => IpcMessage::RenderCreateSurface {
+            width: r.u16()?,
+            height: r.u16()?,
+            owner: r.u64()?,
+            content: r.rect()?,
+        },
+        TAG_RENDER_SURFACE_CREATED => IpcMessage::RenderSurfaceCreated { surface: r.u32()?, shm: r.u64()? },
+        TAG_RENDER_SET_SCENE => IpcMessage::RenderSetScene { layers: r.layer_list()? },
+        TAG_RENDER_MAP_SURFACE => IpcMessage::RenderMapSurface { surface: r.u32()? },
+        TAG_RENDER_SURFACE_MAPPED => IpcMessage::RenderSurfaceMapped {
+            surface: r.u32()?,
+            shm: r.u64()?,
+            width: r.u16()?,
+            height: r.u16()?,
+            area: r.rect()?,
+        },
+        TAG_RENDER_DAMAGE_SURFACE => IpcMessage::RenderDamageSurface { surface: r.u32()?, rects: r.damage_list()? },
+        TAG_RENDER_DRAW_LINE => {
+            IpcMessage::RenderDrawLine { x0: r.u16()?, y0: r.u16()?, x1: r.u16()?, y1: r.u16()?, color: r.rgba()? }
+        }
+        TAG_RENDER_STROKE_RECT => IpcMessage::RenderStrokeRect {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            thickness: r.u16()?,
+            color: r.rgba()?,
+        },
+        TAG_RENDER_DRAW_ELLIPSE => IpcMessage::RenderDrawEllipse {
+            cx: r.u16()?,
+            cy: r.u16()?,
+            rx: r.u16()?,
+            ry: r.u16()?,
+            thickness: r.u16()?,
+            color: r.rgba()?,
+        },
+        TAG_RENDER_DRAW_ROUND_RECT => IpcMessage::RenderDrawRoundRect {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            radius: r.u16()?,
+            thickness: r.u16()?,
+            color: r.rgba()?,
+        },
+        TAG_RENDER_FILL_POLYGON => IpcMessage::RenderFillPolygon { points: r.point_list()?, color: r.rgba()? },
+        TAG_RENDER_BLIT => IpcMessage::RenderBlit {
+            image: r.u64()?,
+            width: r.u16()?,
+            height: r.u16()?,
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            mode: r.blit_mode()?,
+        },
+        TAG_RENDER_MEASURE_TEXT => IpcMessage::RenderMeasureText { text: r.str()?, style: r.text_style()?, max_width: r.u16()? },
+        TAG_RENDER_TEXT_MEASURED => IpcMessage::RenderTextMeasured { width: r.u16()?, height: r.u16()? },
+        TAG_WM_SET_THEME => IpcMessage::WmSetTheme { text: r.u64()?, len: r.u32()? },
+        TAG_WM_THEME_SET => IpcMessage::WmThemeSet { ok: r.bool()?, line: r.u16()? },
+        TAG_WM_SUBSCRIBE_THEME => IpcMessage::WmSubscribeTheme,
+        TAG_THEME_CHANGED => IpcMessage::ThemeChanged { text: r.u64()?, len: r.u32()? },
+        TAG_MOUSE_DOWN => IpcMessage::MouseDown { x: r.u16()?, y: r.u16()?, button: r.mouse_button()? },
+        TAG_MOUSE_UP => IpcMessage::MouseUp { x: r.u16()?, y: r.u16()?, button: r.mouse_button()? },
+        TAG_RENDER_RESIZE_SURFACE => IpcMessage::RenderResizeSurface {
+            surface: r.u32()?,
+            width: r.u16()?,
+            height: r.u16()?,
+            content: r.rect()?,
+        },
+        TAG_RENDER_SURFACE_RESIZED => IpcMessage::RenderSurfaceResized { surface: r.u32()?, shm: r.u64()? },
+        TAG_WM_WINDOW_RESIZED => IpcMessage::WmWindowResized { window_id: r.u32()?, width: r.u16()?, height: r.u16()? },
+        TAG_WM_SET_SIZE_LIMITS => IpcMessage::WmSetSizeLimits {
+            window_id: r.u32()?,
+            min_width: r.u16()?,
+            min_height: r.u16()?,
+            max_width: r.u16()?,
+            max_height: r.u16()?,
+        },
+        TAG_WM_SET_CURSOR => IpcMessage::WmSetCursor { shape: r.cursor_shape()? },
+        TAG_WM_RAISE_WINDOW => IpcMessage::WmRaiseWindow { window_id: r.u32()? },
+        TAG_WM_LOWER_WINDOW => IpcMessage::WmLowerWindow { window_id: r.u32()? },
+        TAG_WM_SET_STACK_LAYER => IpcMessage::WmSetStackLayer { window_id: r.u32()?, layer: r.stack_layer()? },
+        TAG_WM_SET_TRANSIENT_FOR => IpcMessage::WmSetTransientFor { window_id: r.u32()?, parent: r.u32()? },
+        TAG_WM_CREATE_PANEL => IpcMessage::WmCreatePanel {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            layer: r.stack_layer()?,
+        },
+        TAG_WM_MOVE_WINDOW => IpcMessage::WmMoveWindow { window_id: r.u32()?, x: r.u16()?, y: r.u16()? },
+        TAG_RENDER_DESTROY_SURFACE => IpcMessage::RenderDestroySurface { surface: r.u32()? },
+        other => return Err(WireError::UnknownTag(other)),
+    };
+    // 负载必须正好用完
+    if r.pos != r.buf.len() {
+        return Err(WireError::BadLength);
+    }
+    Ok((correlation, msg))
+}
+
+// ==========================
+// 绘图命令（RenderCommandBuffer里的记录）
+// ==========================
+// 记录格式：[记录总长度 u16][命令类型 u8][负载...]，负载编码规则和消息相同。
+// 没有版本号：命令缓冲区只在同一次启动的进程之间共享。
+
+/// 把一条绘图命令编码到buf，返回记录总长度
+pub fn encode_command(command: &RenderCommand, buf: &mut [u8]) -> Result<usize, WireError> {
+    let mut w = Writer { buf, pos: 3 };
+    if w.buf.len() < 3 {
+        return Err(WireError::BufferTooSmall);
+    }
+    let tag = match *command {
+        RenderCommand::Pixel { x, y, color } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.rgba(color)?;
+            CMD_PIXEL
+        }
+        RenderCommand::Rect { x, y, w: width, h, color } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.u16(width)?;
+            w.u16(h)?;
+            w.rgba(color)?;
+            CMD_RECT
+        }
+        RenderCommand::Text { x, y, ref text, style, color } => {
+            w.u16(x)?;
+            w.u16(y)?;
+            w.str(text)?;
+            w.text_style(style)?;
+            w.rgba(color)?;
+            CMD_TEXT
+        }
+        RenderCommand::Commit => CMD_COMMIT,
+        RenderCommand::Target { surface } => {
+            w.u32(surface)?;
+            CMD_TARGET
+        }
+        RenderCommand::PushClip { x, y, w: width, h } => {
+            w.rect(&Rect::new(x, y, width, h))?;
+            CMD_PUSH_CLIP
+        }
+        RenderCommand::PopClip => CMD_POP_CLIP,
+        RenderCommand::Line { x0, y0, x1, y1, color } => {
+            w.u16(x0)?;
+            w.u16(y0)?;
+            w.u16(x1)?;
+            w.u16(y1)?;
+            w.rgba(color)?;
+            CMD_LINE
+        }
+        RenderCommand::StrokeRect { x, y, w: width, h, thickness, color } => {
+            w.rect(&Rect::new(x, y, width, h))?;
+            w.u16(thickness)?;
+            w.rgba(color)?;
+            CMD_STROKE_RECT
+        }
+        RenderCommand::Ellipse { cx, cy, rx, ry, thickness, color } => {
+            w.u16(cx)?;
+            w.u16(cy)?;
+            w.u16(rx)?;
+            w.u16(ry)?;
+            w.u16(thickness)?;
+            w.rgba(color)?;
+            CMD_ELLIPSE
+        }
+        RenderCommand::RoundRect { x, y, w: width, h, radius, thickness, color } => {
+            w.rect(&Rect::new(x, y, width, h))?;
+            w.u16(radius)?;
+            w.u16(thickness)?;
+            w.rgba(color)?;
+            CMD_ROUND_RECT
+        }
+        RenderCommand::Polygon { ref points, color } => {
+            w.point_list(points)?;
+            w.rgba(color)?;
+            CMD_POLYGON
+        }
+        RenderCommand::Blit { image, width, height, x, y, w: dst_w, h, mode } => {
+            w.u64(image)?;
+            w.u16(width)?;
+            w.u16(height)?;
+            w.rect(&Rect::new(x, y, dst_w, h))?;
+            w.blit_mode(mode)?;
+            CMD_BLIT
+        }
+        RenderCommand::TextBox { x, y, w: width, h, ref text, style, align, color } => {
+            w.rect(&Rect::new(x, y, width, h))?;
+            w.str(text)?;
+            w.text_style(style)?;
+            w.text_align(align)?;
+            w.rgba(color)?;
+            CMD_TEXT_BOX
+        }
+        RenderCommand::Clear { x, y, w: width, h } => {
+            w.rect(&Rect::new(x, y, width, h))?;
+            CMD_CLEAR
+        }
+    };
+    let len = w.pos;
+    w.buf[0..2].copy_from_slice(&(len as u16).to_le_bytes());
+    w.buf[2] = tag;
+    Ok(len)
+}
+
+/// 解码一条完整的命令记录（buf必须正好是一条记录）
+pub fn decode_command(buf: &[u8]) -> Result<RenderCommand, WireError> {
+    if buf.len() < 3 {
+        return Err(WireError::Truncated);
+    }
+    if u16::from_le_bytes([buf[0], buf[1]]) as usize != buf.len() {
+        return Err(WireError::BadLength);
+    }
+    let mut r = Reader { buf: &buf[3..], pos: 0 };
+    let command = match buf[2] {
+        CMD_PIXEL => RenderCommand::Pixel { x: r.u16()?, y: r.u16()?, color: r.rgba()? },
+        CMD_RECT => RenderCommand::Rect { x: r.u16()?, y: r.u16()?, w: r.u16()?, h: r.u16()?, color: r.rgba()? },
+        CMD_TEXT => RenderCommand::Text { x: r.u16()?, y: r.u16()?, text: r.str()?, style: r.text_style()?, color: r.rgba()? },
+        CMD_COMMIT => RenderCommand::Commit,
+        CMD_TARGET => RenderCommand::Target { surface: r.u32()? },
+        CMD_PUSH_CLIP => RenderCommand::PushClip { x: r.u16()?, y: r.u16()?, w: r.u16()?, h: r.u16()? },
+        CMD_POP_CLIP => RenderCommand::PopClip,
+        CMD_LINE => RenderCommand::Line { x0: r.u16()?, y0: r.u16()?, x1: r.u16()?, y1: r.u16()?, color: r.rgba()? },
+        CMD_STROKE_RECT => RenderCommand::StrokeRect {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            thickness: r.u16()?,
+            color: r.rgba()?,
+        },
+        CMD_ELLIPSE => RenderCommand::Ellipse {
+            cx: r.u16()?,
+            cy: r.u16()?,
+            rx: r.u16()?,
+            ry: r.u16()?,
+            thickness: r.u16()?,
+            color: r.rgba()?,
+        },
+        CMD_ROUND_RECT => RenderCommand::RoundRect {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            radius: r.u16()?,
+            thickness: r.u16()?,
+            color: r.rgba()?,
+        },
+        CMD_POLYGON => RenderCommand::Polygon { points: r.point_list()?, color: r.rgba()? },
+        CMD_BLIT => RenderCommand::Blit {
+            image: r.u64()?,
+            width: r.u16()?,
+            height: r.u16()?,
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            mode: r.blit_mode()?,
+        },
+        CMD_TEXT_BOX => RenderCommand::TextBox {
+            x: r.u16()?,
+            y: r.u16()?,
+            w: r.u16()?,
+            h: r.u16()?,
+            text: r.str()?,
+            style: r.text_style()?,
+            align: r.text_align()?,
+            color: r.rgba()?,
+        },
+        CMD_CLEAR => RenderCommand::Clear { x: r.u16()?, y: r.u16()?, w: r.u16()?, h: r.u16()? },
+        other => return Err(WireError::UnknownTag(other)),
+    };
+    if r.pos != r.buf.len() {
+        return Err(WireError::BadLength);
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::MAX_COMMAND_SIZE;
+    use alloc::{vec, vec::Vec};
+
+    /// 编码再解码每条消息（帧头带上关联ID），结果必须和原来一样
+    fn assert_round_trip(messages: &[IpcMessage]) {
+        for msg in messages {
+            let mut buf = [0; MAX_MESSAGE_SIZE];
+            let correlation = Correlation { request_id: 0x0102_0304, reply_to: 0x0A0B_0C0D };
+            let len = encode_frame(msg, correlation, &mut buf).unwrap();
+            assert_eq!(buf[0], WIRE_VERSION);
+            assert_eq!(decode_frame(&buf[..len]), Ok((correlation, *msg)));
+            assert_eq!(decode(&buf[..len]), Ok(*msg));
+        }
+    }
+
+    /// 编码再解码每条绘图命令
+    fn assert_commands_round_trip(commands: &[RenderCommand]) {
+        for command in commands {
+            let mut buf = [0; MAX_COMMAND_SIZE];
+            let len = encode_command(command, &mut buf).unwrap();
+            assert_eq!(decode_command(&buf[..len]), Ok(*command));
+        }
+    }
+
+    /// 基本的绘图、窗口和鼠标消息
+    fn basic_messages() -> Vec<IpcMessage> {
+        let title = IpcStr::new("测试窗口 - RUI");
+        let style = TextStyle::new(FontFamily::Sans, 14, FontWeight::Bold);
+        let color = Rgb { r: 1, g: 2, b: 3 };
+        vec![
+            IpcMessage::GpuInit { width: 800, height: 600 },
+            IpcMessage::GpuGetFb,
+            IpcMessage::RenderDrawPixel { x: 1, y: 2, color },
+            IpcMessage::RenderDrawRect { x: 1, y: 2, w: 3, h: 4, color },
+            IpcMessage::WmCreateWindow { x: 1, y: 2, w: 300, h: 200, title },
+            IpcMessage::MouseClick { x: 10, y: 20 },
+            IpcMessage::WmWindowOpened { title, window_id: 9 },
+            IpcMessage::WmFocusWindow { window_id: 9 },
+            IpcMessage::MouseMove { x: 11, y: 21 },
+            IpcMessage::RenderDrawText { x: 1, y: 2, text: title, style, color },
+            IpcMessage::GpuFlush { rects: DamageList::new() },
+            IpcMessage::GpuFbReply { fb: 0x1234_5678_9ABC, width: 1024, height: 768, format: PixelFormat::xrgb8888(1024), pages: 1 },
+            IpcMessage::WmCreateWindow { x: 0, y: 0, w: 1, h: 1, title: IpcStr::new("") },
+        ]
+    }
+
+    #[test]
+    fn basic_round_trip() {
+        assert_round_trip(&basic_messages());
+    }
+
+    /// 带回复的请求
+    fn reply_messages() -> Vec<IpcMessage> {
+        vec![
+            IpcMessage::WmWindowCreated { window_id: 9, surface: 4 },
+            IpcMessage::WmWindowCreated { window_id: 0, surface: 0 },
+        ]
+    }
+
+    #[test]
+    fn reply_round_trip() {
+        assert_round_trip(&reply_messages());
+    }
+
+    /// 截图
+    fn dump_messages() -> Vec<IpcMessage> {
+        vec![
+            IpcMessage::GpuDump { format: DumpFormat::Png, name: IpcStr::new("out.png") },
+            IpcMessage::GpuDumpDone { ok: true },
+            IpcMessage::GpuDump { format: DumpFormat::Ppm, name: IpcStr::new("frame.ppm") },
+            IpcMessage::GpuDumpDone { ok: false },
+        ]
+    }
+
+    #[test]
+    fn dump_round_trip() {
+        assert_round_trip(&dump_messages());
+    }
+
+    /// 帧缓冲区的像素格式（15/16/24/32位，蓝色在高位，行尾有填充）
+    fn pixel_format_messages() -> Vec<IpcMessage> {
+        let mut bgr = PixelFormat::from_bpp(24, 1024 * 3 + 16).unwrap();
+        (bgr.red, bgr.blue) = (bgr.blue, bgr.red);
+        let format = PixelFormat::from_bpp(16, 2048).unwrap();
+        vec![
+            IpcMessage::GpuFbReply { fb: 0x1234_5678_9ABC, width: 1024, height: 768, format, pages: 2 },
+            IpcMessage::GpuFbReply { fb: 0xE000_0000, width: 640, height: 480, format: PixelFormat::from_bpp(15, 1280).unwrap(), pages: 1 },
+            IpcMessage::GpuFbReply { fb: 0xE000_0000, width: 1024, height: 768, format: bgr, pages: 1 },
+        ]
+    }
+
+    #[test]
+    fn pixel_format_round_trip() {
+        assert_round_trip(&pixel_format_messages());
+    }
+
+    /// 显示模式
+    fn mode_messages() -> Vec<IpcMessage> {
+        let format = PixelFormat::from_bpp(16, 2048).unwrap();
+        let mut modes = ModeList::new();
+        modes.push(VideoMode { width: 800, height: 600, bpp: 32 });
+        modes.push(VideoMode { width: 1024, height: 768, bpp: 16 });
+        vec![
+            IpcMessage::GpuListModes,
+            IpcMessage::GpuModeList { modes },
+            IpcMessage::GpuSetMode { width: 1280, height: 720 },
+            IpcMessage::GpuFbReply { fb: 0xFD00_0000, width: 1024, height: 768, format, pages: 1 },
+            IpcMessage::RenderSetMode { width: 1024, height: 768 },
+            IpcMessage::RenderSubscribeScreen,
+            IpcMessage::RenderScreenInfo { width: 800, height: 600 },
+            IpcMessage::ScreenModeChanged { width: 1024, height: 768 },
+            IpcMessage::GpuModeList { modes: ModeList::new() },
+        ]
+    }
+
+    #[test]
+    fn mode_round_trip() {
+        assert_round_trip(&mode_messages());
+    }
+
+    /// 双缓冲翻页
+    fn flip_messages() -> Vec<IpcMessage> {
+        vec![
+            IpcMessage::RenderCommit,
+            IpcMessage::GpuFlip { page: 1 },
+            IpcMessage::GpuFlipped { page: 1 },
+            IpcMessage::GpuFlip { page: 0 },
+        ]
+    }
+
+    #[test]
+    fn flip_round_trip() {
+        assert_round_trip(&flip_messages());
+    }
+
+    /// 脏区域
+    fn damage_messages() -> Vec<IpcMessage> {
+        let mut rects = DamageList::new();
+        rects.add(Rect::new(0, 0, 10, 10));
+        rects.add(Rect::new(500, 400, 20, 30));
+        vec![
+            IpcMessage::GpuFlush { rects },
+        ]
+    }
+
+    #[test]
+    fn damage_round_trip() {
+        assert_round_trip(&damage_messages());
+        // 满了之后合并过的列表也原样解码回来（不再重新合并、不改顺序）
+        let mut rects = DamageList::new();
+        for i in 0..DAMAGE_CAPACITY as u16 + 3 {
+            rects.add(Rect::new(i * 7 % 50 * 10, i * 30, 5, 5));
+        }
+        let (buf, len) = encoded(&IpcMessage::GpuFlush { rects });
+        assert_eq!(decode(&buf[..len]), Ok(IpcMessage::GpuFlush { rects }));
+    }
+
+    #[test]
+    fn bad_damage_list_rejected() {
+        // 第二个矩形碰到第一个、是空矩形：add不会留下这样的列表
+        for second in [Rect::new(10, 0, 5, 5), Rect::new(30, 30, 0, 5)] {
+            let (mut buf, len) = encoded(&IpcMessage::GpuFlush { rects: DamageList::from_rect(Rect::new(0, 0, 10, 10)) });
+            buf[HEADER_SIZE] = 2;
+            for (i, value) in [second.x, second.y, second.w, second.h].into_iter().enumerate() {
+                buf[len + i * 2..len + i * 2 + 2].copy_from_slice(&value.to_le_bytes());
+            }
+            set_payload_len(&mut buf, len + 8 - HEADER_SIZE);
+            assert_eq!(decode(&buf[..len + 8]), Err(WireError::BadValue), "{second:?}");
+        }
+    }
+
+    /// 命令缓冲区
+    fn command_buffer_messages() -> Vec<IpcMessage> {
+        vec![
+            IpcMessage::RenderSubmit { ring: 0x5000, size: 16384, end: 4096 },
+        ]
+    }
+
+    /// 命令缓冲区的绘图命令
+    fn command_buffer_commands() -> Vec<RenderCommand> {
+        let text = IpcStr::new("Labels wrap, 测试");
+        let style = TextStyle::sans(14);
+        let color = Rgba::new(10, 20, 30, 40);
+        vec![
+            RenderCommand::Pixel { x: 1, y: 2, color },
+            RenderCommand::Rect { x: 1, y: 2, w: 3, h: 4, color },
+            RenderCommand::Text { x: 1, y: 2, text, style, color },
+            RenderCommand::Commit,
+        ]
+    }
+
+    #[test]
+    fn command_buffer_round_trip() {
+        assert_round_trip(&command_buffer_messages());
+        assert_commands_round_trip(&command_buffer_commands());
+    }
+
+    /// 离屏表面和合成场景
+    fn surface_messages() -> Vec<IpcMessage> {
+        let rect = Rect::new(1, 2, 30, 40);
+        let mut layers = LayerList::new();
+        layers.push(Layer { surface: 3, x: 50, y: 60 });
+        layers.push(Layer { surface: 7, x: 100, y: 110 });
+        vec![
+            IpcMessage::RenderCreateSurface { width: 400, height: 300, owner: 6, content: rect },
+            IpcMessage::RenderSurfaceCreated { surface: 4, shm: 0x6000 },
+            IpcMessage::RenderSetScene { layers },
+            IpcMessage::RenderDestroySurface { surface: 4 },
+            IpcMessage::RenderSetScene { layers: LayerList::new() },
+        ]
+    }
+
+    /// 离屏表面和合成场景的绘图命令
+    fn surface_commands() -> Vec<RenderCommand> {
+        vec![
+            RenderCommand::Target { surface: 5 },
+        ]
+    }
+
+    #[test]
+    fn surface_round_trip() {
+        assert_round_trip(&surface_messages());
+        assert_commands_round_trip(&surface_commands());
+    }
+
+    /// 应用直接写像素的表面
+    fn shared_surface_messages() -> Vec<IpcMessage> {
+        let rect = Rect::new(1, 2, 30, 40);
+        let mut rects = DamageList::new();
+        rects.add(Rect::new(0, 0, 10, 10));
+        rects.add(Rect::new(500, 400, 20, 30));
+        vec![
+            IpcMessage::RenderMapSurface { surface: 4 },
+            IpcMessage::RenderSurfaceMapped { surface: 4, shm: 0x6000, width: 400, height: 300, area: rect },
+            IpcMessage::RenderDamageSurface { surface: 4, rects },
+        ]
+    }
+
+    #[test]
+    fn shared_surface_round_trip() {
+        assert_round_trip(&shared_surface_messages());
+    }
+
+    /// 裁剪栈的绘图命令
+    fn clip_commands() -> Vec<RenderCommand> {
+        vec![
+            RenderCommand::PushClip { x: 1, y: 2, w: 3, h: 4 },
+            RenderCommand::PopClip,
+        ]
+    }
+
+    #[test]
+    fn clip_round_trip() {
+        assert_commands_round_trip(&clip_commands());
+    }
+
+    /// 线、椭圆、圆角矩形、多边形
+    fn shape_messages() -> Vec<IpcMessage> {
+        let mut points = PointList::new();
+        for (x, y) in [(0, 0), (40, 5), (20, 30)] {
+            points.push(Point { x, y });
+        }
+        let rgba = Rgba::new(4, 5, 6, 7);
+        vec![
+            IpcMessage::RenderDrawLine { x0: 1, y0: 2, x1: 3, y1: 4, color: rgba },
+            IpcMessage::RenderStrokeRect { x: 1, y: 2, w: 3, h: 4, thickness: 5, color: rgba },
+            IpcMessage::RenderDrawEllipse { cx: 1, cy: 2, rx: 3, ry: 4, thickness: 0, color: rgba },
+            IpcMessage::RenderDrawRoundRect { x: 1, y: 2, w: 3, h: 4, radius: 5, thickness: 6, color: rgba },
+            IpcMessage::RenderFillPolygon { points, color: rgba },
+        ]
+    }
+
+    /// 线、椭圆、圆角矩形、多边形的绘图命令
+    fn shape_commands() -> Vec<RenderCommand> {
+        let color = Rgba::new(10, 20, 30, 40);
+        let mut points = PointList::new();
+        for (x, y) in [(0, 0), (40, 5), (20, 30)] {
+            points.push(Point { x, y });
+        }
+        vec![
+            RenderCommand::Line { x0: 1, y0: 2, x1: 3, y1: 4, color },
+            RenderCommand::StrokeRect { x: 1, y: 2, w: 3, h: 4, thickness: 2, color },
+            RenderCommand::Ellipse { cx: 10, cy: 20, rx: 5, ry: 6, thickness: 1, color },
+            RenderCommand::RoundRect { x: 1, y: 2, w: 30, h: 40, radius: 3, thickness: 0, color },
+            RenderCommand::Polygon { points, color },
+        ]
+    }
+
+    #[test]
+    fn shape_round_trip() {
+        assert_round_trip(&shape_messages());
+        assert_commands_round_trip(&shape_commands());
+    }
+
+    /// 位图
+    fn blit_messages() -> Vec<IpcMessage> {
+        let color = Rgb { r: 1, g: 2, b: 3 };
+        vec![
+            IpcMessage::RenderBlit { image: 0x7000, width: 32, height: 32, x: 1, y: 2, w: 64, h: 64, mode: BlitMode::ColorKey(color) },
+            IpcMessage::RenderBlit { image: 0x7000, width: 1, height: 1, x: 0, y: 0, w: 1, h: 1, mode: BlitMode::Opaque },
+        ]
+    }
+
+    /// 位图的绘图命令
+    fn blit_commands() -> Vec<RenderCommand> {
+        vec![
+            RenderCommand::Blit { image: 0x7000, width: 32, height: 32, x: 1, y: 2, w: 48, h: 48, mode: BlitMode::Alpha },
+        ]
+    }
+
+    #[test]
+    fn blit_round_trip() {
+        assert_round_trip(&blit_messages());
+        assert_commands_round_trip(&blit_commands());
+    }
+
+    /// 量文字和排版
+    fn text_layout_messages() -> Vec<IpcMessage> {
+        let title = IpcStr::new("测试窗口 - RUI");
+        let style = TextStyle::new(FontFamily::Sans, 14, FontWeight::Bold);
+        vec![
+            IpcMessage::RenderMeasureText { text: title, style, max_width: 120 },
+            IpcMessage::RenderTextMeasured { width: 100, height: 32 },
+        ]
+    }
+
+    /// 量文字和排版的绘图命令
+    fn text_layout_commands() -> Vec<RenderCommand> {
+        let text = IpcStr::new("Labels wrap, 测试");
+        let style = TextStyle::sans(14);
+        let color = Rgba::new(10, 20, 30, 40);
+        vec![
+            RenderCommand::TextBox { x: 1, y: 2, w: 200, h: 0, text, style, align: TextAlign::Center, color },
+            RenderCommand::TextBox { x: 1, y: 2, w: 200, h: 60, text, style, align: TextAlign::Right, color },
+        ]
+    }
+
+    #[test]
+    fn text_layout_round_trip() {
+        assert_round_trip(&text_layout_messages());
+        assert_commands_round_trip(&text_layout_commands());
+    }
+
+    /// 换主题
+    fn theme_messages() -> Vec<IpcMessage> {
+        vec![
+            IpcMessage::WmSetTheme { text: 0x8000, len: 1234 },
+            IpcMessage::WmThemeSet { ok: false, line: 17 },
+            IpcMessage::WmSubscribeTheme,
+            IpcMessage::ThemeChanged { text: 0x8000, len: 1234 },
+            IpcMessage::ThemeChanged { text: 0, len: 0 },
+        ]
+    }
+
+    #[test]
+    fn theme_round_trip() {
+        assert_round_trip(&theme_messages());
+    }
+
+    /// 鼠标按下、松开
+    fn drag_messages() -> Vec<IpcMessage> {
+        vec![
+            IpcMessage::MouseDown { x: 1, y: 2, button: MouseButton::Middle },
+            IpcMessage::MouseUp { x: 1, y: 2, button: MouseButton::Right },
+            IpcMessage::MouseDown { x: 0xFFFF, y: 0, button: MouseButton::Left },
+        ]
+    }
+
+    #[test]
+    fn drag_round_trip() {
+        assert_round_trip(&drag_messages());
+    }
+
+    /// 改窗口大小
+    fn resize_messages() -> Vec<IpcMessage> {
+        let rect = Rect::new(1, 2, 30, 40);
+        vec![
+            IpcMessage::RenderResizeSurface { surface: 4, width: 500, height: 350, content: rect },
+            IpcMessage::RenderSurfaceResized { surface: 4, shm: 0x9000 },
+            IpcMessage::WmWindowResized { window_id: 9, width: 496, height: 324 },
+            IpcMessage::WmSetSizeLimits { window_id: 9, min_width: 160, min_height: 200, max_width: 0, max_height: 900 },
+            IpcMessage::WmSetCursor { shape: CursorShape::ResizeNESW },
+            IpcMessage::WmSetCursor { shape: CursorShape::Arrow },
+        ]
+    }
+
+    #[test]
+    fn resize_round_trip() {
+        assert_round_trip(&resize_messages());
+    }
+
+    /// 叠放次序、面板、移动窗口
+    fn stacking_messages() -> Vec<IpcMessage> {
+        vec![
+            IpcMessage::WmRaiseWindow { window_id: 9 },
+            IpcMessage::WmLowerWindow { window_id: 9 },
+            IpcMessage::WmSetStackLayer { window_id: 9, layer: StackLayer::Top },
+            IpcMessage::WmSetTransientFor { window_id: 9, parent: 2 },
+            IpcMessage::WmCreatePanel { x: 332, y: 552, w: 136, h: 48, layer: StackLayer::Bottom },
+            IpcMessage::WmMoveWindow { window_id: 9, x: 10, y: 20 },
+            IpcMessage::WmSetStackLayer { window_id: 9, layer: StackLayer::Normal },
+        ]
+    }
+
+    /// 叠放次序、面板、移动窗口的绘图命令
+    fn stacking_commands() -> Vec<RenderCommand> {
+        vec![
+            RenderCommand::Clear { x: 1, y: 2, w: 3, h: 4 },
+        ]
+    }
+
+    #[test]
+    fn stacking_round_trip() {
+        assert_round_trip(&stacking_messages());
+        assert_commands_round_trip(&stacking_commands());
+    }
+
+    /// 每种消息至少一条（字段大多取非0的值，布局错位时能看出来）
+    fn sample_messages() -> Vec<IpcMessage> {
+        [
+            basic_messages(),
+            reply_messages(),
+            dump_messages(),
+            pixel_format_messages(),
+            mode_messages(),
+            flip_messages(),
+            damage_messages(),
+            command_buffer_messages(),
+            surface_messages(),
+            shared_surface_messages(),
+            shape_messages(),
+            blit_messages(),
+            text_layout_messages(),
+            theme_messages(),
+            drag_messages(),
+            resize_messages(),
+            stacking_messages(),
+        ]
+        .concat()
+    }
+
+    /// 每种绘图命令至少一条
+    fn sample_commands() -> Vec<RenderCommand> {
+        [
+            command_buffer_commands(),
+            surface_commands(),
+            clip_commands(),
+            shape_commands(),
+            blit_commands(),
+            text_layout_commands(),
+            stacking_commands(),
+        ]
+        .concat()
+    }
+
+    /// 编码msg，返回缓冲区和长度
+    fn encoded(msg: &IpcMessage) -> ([u8; MAX_MESSAGE_SIZE], usize) {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let len = encode(msg, &mut buf).unwrap();
+        (buf, len)
+    }
+
+    /// 改掉负载长度字段
+    fn set_payload_len(buf: &mut [u8], len: usize) {
+        buf[2..4].copy_from_slice(&(len as u16).to_le_bytes());
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = sample_messages();
+        assert_round_trip(&messages);
+        // 每个编号都测到了
+        let mut seen = [false; 256];
+        for msg in &messages {
+            seen[encoded(msg).0[1] as usize] = true;
+        }
+        for tag in 0..=TAG_RENDER_DESTROY_SURFACE {
+            assert!(seen[tag as usize], "tag {tag}");
+        }
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let commands = sample_commands();
+        assert_commands_round_trip(&commands);
+        let mut seen = [false; 256];
+        for command in &commands {
+            let mut buf = [0; MAX_COMMAND_SIZE];
+            encode_command(command, &mut buf).unwrap();
+            seen[buf[2] as usize] = true;
+        }
+        assert!(seen[..=CMD_CLEAR as usize].iter().all(|seen| *seen));
+    }
+
+    #[test]
+    fn encode_rejects_small_buffer() {
+        let msg = IpcMessage::WmCreateWindow { x: 0, y: 0, w: 1, h: 1, title: IpcStr::new("title") };
+        assert_eq!(encode(&msg, &mut [0; HEADER_SIZE + 4]), Err(WireError::BufferTooSmall));
+        assert_eq!(encode_command(&RenderCommand::Commit, &mut [0; 2]), Err(WireError::BufferTooSmall));
+    }
+
+    #[test]
+    fn truncated_frames_rejected() {
+        for msg in sample_messages() {
+            let (mut buf, len) = encoded(&msg);
+            // 少了字节：比帧头声明的短
+            assert_eq!(decode(&buf[..len - 1]), Err(WireError::Truncated), "{msg:?}");
+            assert_eq!(decode(&buf[..HEADER_SIZE - 1]), Err(WireError::Truncated));
+            // 多了字节
+            assert_eq!(decode(&buf[..len + 1]), Err(WireError::BadLength), "{msg:?}");
+            // 帧头和长度一致，但负载比消息类型要的短
+            if len > HEADER_SIZE {
+                set_payload_len(&mut buf, len - HEADER_SIZE - 1);
+                assert!(decode(&buf[..len - 1]).is_err(), "{msg:?}");
+            }
+        }
+        assert_eq!(decode(&[]), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn truncated_commands_rejected() {
+        for command in sample_commands() {
+            let mut buf = [0; MAX_COMMAND_SIZE];
+            let len = encode_command(&command, &mut buf).unwrap();
+            let expected = if len > 3 { WireError::BadLength } else { WireError::Truncated };
+            assert_eq!(decode_command(&buf[..len - 1]), Err(expected), "{command:?}");
+            if len > 3 {
+                // 记录长度也改短：负载不够
+                buf[0..2].copy_from_slice(&(len as u16 - 1).to_le_bytes());
+                assert!(decode_command(&buf[..len - 1]).is_err(), "{command:?}");
+            }
+        }
+        assert_eq!(decode_command(&[3, 0]), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn bad_version_rejected() {
+        let (mut buf, len) = encoded(&IpcMessage::GpuGetFb);
+        buf[0] = WIRE_VERSION - 1;
+        assert_eq!(decode(&buf[..len]), Err(WireError::BadVersion(WIRE_VERSION - 1)));
+        buf[0] = WIRE_VERSION + 1;
+        assert_eq!(decode(&buf[..len]), Err(WireError::BadVersion(WIRE_VERSION + 1)));
+    }
+
+    #[test]
+    fn unknown_tags_rejected() {
+        let (mut buf, len) = encoded(&IpcMessage::GpuGetFb);
+        for tag in [TAG_RENDER_DESTROY_SURFACE + 1, 0xFF] {
+            buf[1] = tag;
+            assert_eq!(decode(&buf[..len]), Err(WireError::UnknownTag(tag)));
+        }
+        let mut buf = [0; MAX_COMMAND_SIZE];
+        let len = encode_command(&RenderCommand::Commit, &mut buf).unwrap();
+        buf[2] = CMD_CLEAR + 1;
+        assert_eq!(decode_command(&buf[..len]), Err(WireError::UnknownTag(CMD_CLEAR + 1)));
+    }
+
+    #[test]
+    fn bad_enum_values_rejected() {
+        // (消息, 负载里枚举字段的偏移, 非法取值)
+        let style = TextStyle::DEFAULT;
+        let cases = [
+            (IpcMessage::MouseDown { x: 0, y: 0, button: MouseButton::Left }, 4, 3),
+            (IpcMessage::GpuDumpDone { ok: true }, 0, 2),
+            (IpcMessage::WmThemeSet { ok: true, line: 0 }, 0, 0xFF),
+            (IpcMessage::GpuDump { format: DumpFormat::Ppm, name: IpcStr::new("a") }, 0, 2),
+            (IpcMessage::WmSetCursor { shape: CursorShape::Arrow }, 0, 6),
+            (IpcMessage::WmSetStackLayer { window_id: 1, layer: StackLayer::Normal }, 4, 3),
+            (IpcMessage::RenderMeasureText { text: IpcStr::new(""), style, max_width: 0 }, 1, 2), // 字体族
+            (IpcMessage::RenderMeasureText { text: IpcStr::new(""), style, max_width: 0 }, 2, 2), // 字重
+            (IpcMessage::RenderBlit { image: 1, width: 1, height: 1, x: 0, y: 0, w: 1, h: 1, mode: BlitMode::Opaque }, 20, 3),
+        ];
+        for (msg, offset, value) in cases {
+            let (mut buf, len) = encoded(&msg);
+            buf[HEADER_SIZE + offset] = value;
+            assert_eq!(decode(&buf[..len]), Err(WireError::BadValue), "{msg:?}");
+        }
+
+        let command = RenderCommand::TextBox {
+            x: 0,
+            y: 0,
+            w: 0,
+            h: 0,
+            text: IpcStr::new(""),
+            style,
+            align: TextAlign::Left,
+            color: Rgba::new(0, 0, 0, 0),
+        };
+        let mut buf = [0; MAX_COMMAND_SIZE];
+        let len = encode_command(&command, &mut buf).unwrap();
+        buf[3 + 8 + 1 + 4] = 3; // 矩形、空字符串、文字样式之后是对齐方式
+        assert_eq!(decode_command(&buf[..len]), Err(WireError::BadValue));
+    }
+
+    #[test]
+    fn bad_pixel_format_rejected() {
+        let format = PixelFormat::from_bpp(32, 4096).unwrap();
+        let msg = IpcMessage::GpuFbReply { fb: 1, width: 1024, height: 768, format, pages: 1 };
+        // 负载里格式从第12字节开始：bpp u8, pitch u32, 红绿蓝各(偏移, 位数)
+        let corrupt = |offset: usize, bytes: &[u8]| {
+            let (mut buf, len) = encoded(&msg);
+            buf[HEADER_SIZE + offset..HEADER_SIZE + offset + bytes.len()].copy_from_slice(bytes);
+            decode(&buf[..len])
+        };
+        assert_eq!(corrupt(12, &[12]), Err(WireError::BadValue)); // 不支持的位数
+        assert_eq!(corrupt(12, &[0]), Err(WireError::BadValue));
+        assert_eq!(corrupt(13, &4095u32.to_le_bytes()), Err(WireError::BadValue)); // 一行放不下1024个像素
+        assert_eq!(corrupt(17, &[30, 8]), Err(WireError::BadValue)); // 红色通道超出像素
+        assert_eq!(corrupt(19, &[8, 0]), Err(WireError::BadValue)); // 绿色通道0位
+        // 16位色的pitch按2字节一个像素算
+        let format = PixelFormat::from_bpp(16, 2048).unwrap();
+        let (buf, len) = encoded(&IpcMessage::GpuFbReply { fb: 1, width: 1024, height: 768, format, pages: 1 });
+        assert!(decode(&buf[..len]).is_ok());
+        let (buf, len) = encoded(&IpcMessage::GpuFbReply { fb: 1, width: 1025, height: 768, format, pages: 1 });
+        assert_eq!(decode(&buf[..len]), Err(WireError::BadValue));
+    }
+
+    #[test]
+    fn invalid_utf8_rejected() {
+        let msg = IpcMessage::WmCreateWindow { x: 0, y: 0, w: 1, h: 1, title: IpcStr::new("ab") };
+        let (mut buf, len) = encoded(&msg);
+        buf[HEADER_SIZE + 9..HEADER_SIZE + 11].copy_from_slice(&[0xFF, 0xFE]);
+        assert_eq!(decode(&buf[..len]), Err(WireError::InvalidUtf8));
+
+        // 多字节字符被截断在中间
+        let msg = IpcMessage::WmFocusWindow { window_id: 0 };
+        let (mut buf, _) = encoded(&msg);
+        let text = "窗".as_bytes();
+        let mut len = HEADER_SIZE;
+        buf[len] = 2;
+        buf[len + 1..len + 3].copy_from_slice(&text[..2]);
+        len += 3;
+        buf[len..len + 4].copy_from_slice(&[0; 4]);
+        len += 4;
+        buf[1] = TAG_WM_WINDOW_OPENED;
+        set_payload_len(&mut buf, len - HEADER_SIZE);
+        assert_eq!(decode(&buf[..len]), Err(WireError::InvalidUtf8));
+    }
+
+    #[test]
+    fn oversized_lengths_rejected() {
+        // 字符串长度超过IPC_STR_CAPACITY（后面真的跟着这么多字节）
+        let (mut buf, _) = encoded(&IpcMessage::WmFocusWindow { window_id: 0 });
+        buf[1] = TAG_WM_WINDOW_OPENED;
+        let n = IPC_STR_CAPACITY + 1;
+        buf[HEADER_SIZE] = n as u8;
+        buf[HEADER_SIZE + 1..HEADER_SIZE + 1 + n].fill(b'a');
+        let len = HEADER_SIZE + 1 + n + 4;
+        set_payload_len(&mut buf, len - HEADER_SIZE);
+        assert_eq!(decode(&buf[..len]), Err(WireError::StringTooLong));
+
+        // 列表个数超过容量：(消息类型, 容量, 每项字节数)
+        let lists = [
+            (TAG_GPU_MODE_LIST, MODE_LIST_CAPACITY, 5),
+            (TAG_GPU_FLUSH, DAMAGE_CAPACITY, 8),
+            (TAG_RENDER_SET_SCENE, SCENE_CAPACITY, 8),
+        ];
+        for (tag, capacity, item) in lists {
+            let mut buf = [0; HEADER_SIZE + 1 + 33 * 8];
+            buf[0] = WIRE_VERSION;
+            buf[1] = tag;
+            buf[HEADER_SIZE] = capacity as u8 + 1;
+            let len = HEADER_SIZE + 1 + (capacity + 1) * item;
+            set_payload_len(&mut buf, len - HEADER_SIZE);
+            assert_eq!(decode(&buf[..len]), Err(WireError::BadValue), "tag {tag}");
+        }
+        let mut buf = [0; HEADER_SIZE + 1 + (POLYGON_CAPACITY + 1) * 4 + 4];
+        buf[0] = WIRE_VERSION;
+        buf[1] = TAG_RENDER_FILL_POLYGON;
+        buf[HEADER_SIZE] = POLYGON_CAPACITY as u8 + 1;
+        let len = buf.len();
+        set_payload_len(&mut buf, len - HEADER_SIZE);
+        assert_eq!(decode(&buf), Err(WireError::BadValue));
+    }
+}
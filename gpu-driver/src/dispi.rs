@@ -0,0 +1,637 @@
+// ==========================
+// Bochs/QEMU DISPI后端（stdvga、bochs-display）
+// ==========================
+// 通过索引/数据端口0x1CE/0x1CF读写VBE DISPI寄存器，运行时设置分辨率、
+// 每像素位数和虚拟高度；线性帧缓冲区地址从PCI配置空间的BAR0读取。
+// 端口访问全部经过PortIo，测试和模拟器里用FakeDispi代替硬件。
+
+use crate::{mode, port::PortIo, Framebuffer};
+use common::{kernel, ModeList, PixelFormat, VideoMode};
+
+// DISPI端口与寄存器索引
+const INDEX_PORT: u16 = 0x01CE;
+const DATA_PORT: u16 = 0x01CF;
+
+const INDEX_ID: u16 = 0x0;
+const INDEX_XRES: u16 = 0x1;
+const INDEX_YRES: u16 = 0x2;
+const INDEX_BPP: u16 = 0x3;
+const INDEX_ENABLE: u16 = 0x4;
+const INDEX_VIRT_WIDTH: u16 = 0x6;
+const INDEX_VIRT_HEIGHT: u16 = 0x7;
+const INDEX_X_OFFSET: u16 = 0x8;
+const INDEX_Y_OFFSET: u16 = 0x9;
+const INDEX_VIDEO_MEMORY_64K: u16 = 0xA;
+const REGISTER_COUNT: usize = 11;
+
+// ID寄存器的取值范围（0xB0C2起支持GETCAPS）
+const ID_MIN: u16 = 0xB0C0;
+const ID_GETCAPS: u16 = 0xB0C2;
+const ID_MAX: u16 = 0xB0C5;
+
+// ENABLE寄存器的位
+const ENABLED: u16 = 0x01;
+const GETCAPS: u16 = 0x02;
+const LFB_ENABLED: u16 = 0x40;
+
+// PCI配置空间（机制1）
+const PCI_CONFIG_ADDRESS: u16 = 0x0CF8;
+const PCI_CONFIG_DATA: u16 = 0x0CFC;
+const BOCHS_VGA_ID: u32 = 0x1111_1234; // 设备ID 0x1111，厂商ID 0x1234
+
+/// PCI里找不到设备时的线性帧缓冲区地址（Bochs的默认值）
+const DEFAULT_LFB: u64 = 0xE000_0000;
+/// 设备不报告显存大小时按16MB算
+const DEFAULT_VIDEO_MEMORY: usize = 16 << 20;
+/// 不支持GETCAPS时的最大模式
+const DEFAULT_MAX: (u16, u16, u8) = (1024, 768, 32);
+
+/// GpuListModes里列出的每像素位数（同一分辨率优先高位数）
+const LISTED_BPP: [u8; 4] = [32, 24, 16, 15];
+
+/// 设备能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caps {
+    pub max_width: u16,
+    pub max_height: u16,
+    pub max_bpp: u8,
+    pub video_memory: usize, // 显存字节数
+}
+
+/// 寄存器读回的实际模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DispiMode {
+    pub width: u16,
+    pub height: u16,
+    pub bpp: u8,
+    pub virt_width: u16,  // 每行像素数（决定每行字节数）
+    pub virt_height: u16, // 显存里可用的行数（≥ height，多出来的可以做后台缓冲）
+}
+
+impl DispiMode {
+    /// 每行字节数
+    pub fn pitch(&self) -> u32 {
+        self.virt_width as u32 * bytes_per_pixel(self.bpp) as u32
+    }
+}
+
+fn bytes_per_pixel(bpp: u8) -> usize {
+    (bpp as usize).div_ceil(8)
+}
+
+/// DISPI设备驱动
+pub struct Dispi<P: PortIo> {
+    ports: P,
+    id: u16,
+    lfb: u64,
+    caps: Caps,
+}
+
+impl<P: PortIo> Dispi<P> {
+    /// 探测设备：ID寄存器不在0xB0C0..=0xB0C5范围内说明没有DISPI，返回None
+    ///
+    /// # Safety
+    /// ports必须能安全访问0x1CE/0x1CF和PCI配置端口。
+    pub unsafe fn probe(ports: P) -> Option<Self> {
+        let mut dispi = Dispi {
+            ports,
+            id: 0,
+            lfb: DEFAULT_LFB,
+            caps: Caps {
+                max_width: DEFAULT_MAX.0,
+                max_height: DEFAULT_MAX.1,
+                max_bpp: DEFAULT_MAX.2,
+                video_memory: DEFAULT_VIDEO_MEMORY,
+            },
+        };
+        // 先写入支持的最高版本号，设备会回落到自己支持的版本
+        dispi.write(INDEX_ID, ID_MAX);
+        dispi.id = dispi.read(INDEX_ID);
+        if !(ID_MIN..=ID_MAX).contains(&dispi.id) {
+            return None;
+        }
+
+        if dispi.id >= ID_GETCAPS {
+            // GETCAPS置位时，XRES/YRES/BPP读出的是最大值
+            let enable = dispi.read(INDEX_ENABLE);
+            dispi.write(INDEX_ENABLE, enable | GETCAPS);
+            dispi.caps.max_width = dispi.read(INDEX_XRES);
+            dispi.caps.max_height = dispi.read(INDEX_YRES);
+            dispi.caps.max_bpp = dispi.read(INDEX_BPP) as u8;
+            dispi.write(INDEX_ENABLE, enable);
+        }
+        let memory_64k = dispi.read(INDEX_VIDEO_MEMORY_64K);
+        if memory_64k != 0 && memory_64k != 0xFFFF {
+            dispi.caps.video_memory = memory_64k as usize * 0x10000;
+        }
+        if let Some(lfb) = find_lfb(&mut dispi.ports) {
+            dispi.lfb = lfb;
+        }
+        Some(dispi)
+    }
+
+    unsafe fn read(&mut self, index: u16) -> u16 {
+        self.ports.write_u16(INDEX_PORT, index);
+        self.ports.read_u16(DATA_PORT)
+    }
+
+    unsafe fn write(&mut self, index: u16, value: u16) {
+        self.ports.write_u16(INDEX_PORT, index);
+        self.ports.write_u16(DATA_PORT, value);
+    }
+
+    /// 设备版本（ID寄存器的值）
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// 线性帧缓冲区的物理地址
+    pub fn lfb(&self) -> u64 {
+        self.lfb
+    }
+
+    pub fn caps(&self) -> Caps {
+        self.caps
+    }
+
+    /// 模式是否在设备能力和显存范围内
+    pub fn supports(&self, width: u16, height: u16, bpp: u8, virt_height: u16) -> bool {
+        let pitch = width as usize * bytes_per_pixel(bpp);
+        width != 0
+            && height != 0
+            && width <= self.caps.max_width
+            && height <= self.caps.max_height
+            && matches!(bpp, 15 | 16 | 24 | 32)
+            && bpp <= self.caps.max_bpp
+            && virt_height >= height
+            && pitch * virt_height as usize <= self.caps.video_memory
+    }
+
+    /// 列出支持的常见分辨率
+    pub fn modes(&self) -> ModeList {
+        let mut modes = ModeList::new();
+        for (width, height) in mode::STANDARD_RESOLUTIONS {
+            for bpp in LISTED_BPP {
+                if self.supports(width, height, bpp, height) {
+                    modes.push(VideoMode { width, height, bpp });
+                }
+            }
+        }
+        modes
+    }
+
+    /// 设置模式（显存里保留virt_height行），成功时返回寄存器读回的实际模式
+    ///
+    /// # Safety
+    /// 切换期间屏幕内容无效，调用方不能同时访问帧缓冲区。
+    pub unsafe fn set_mode(&mut self, width: u16, height: u16, bpp: u8, virt_height: u16) -> Option<DispiMode> {
+        if !self.supports(width, height, bpp, virt_height) {
+            return None;
+        }
+        // 分辨率/位数只能在关闭状态下修改
+        self.write(INDEX_ENABLE, 0);
+        self.write(INDEX_XRES, width);
+        self.write(INDEX_YRES, height);
+        self.write(INDEX_BPP, bpp as u16);
+        self.write(INDEX_VIRT_WIDTH, width);
+        self.write(INDEX_VIRT_HEIGHT, virt_height);
+        self.write(INDEX_X_OFFSET, 0);
+        self.write(INDEX_Y_OFFSET, 0);
+        self.write(INDEX_ENABLE, ENABLED | LFB_ENABLED);
+
+        // 以读回的值为准：设备可能调整了虚拟宽高，也可能拒绝了这个模式
+        let mode = self.current_mode()?;
+        let accepted = mode.width == width && mode.height == height && mode.bpp == bpp && mode.virt_height >= virt_height;
+        accepted.then_some(mode)
+    }
+
+    /// 设置显示起点（显存里第y行第x列显示在屏幕左上角），用来翻页
+    ///
+    /// # Safety
+    /// 见probe。
+    pub unsafe fn set_offset(&mut self, x: u16, y: u16) {
+        self.write(INDEX_X_OFFSET, x);
+        self.write(INDEX_Y_OFFSET, y);
+    }
+
+    /// 当前模式（设备未启用时返回None）
+    ///
+    /// # Safety
+    /// 见probe。
+    pub unsafe fn current_mode(&mut self) -> Option<DispiMode> {
+        if self.read(INDEX_ENABLE) & ENABLED == 0 {
+            return None;
+        }
+        Some(DispiMode {
+            width: self.read(INDEX_XRES),
+            height: self.read(INDEX_YRES),
+            bpp: self.read(INDEX_BPP) as u8,
+            virt_width: self.read(INDEX_VIRT_WIDTH),
+            virt_height: self.read(INDEX_VIRT_HEIGHT),
+        })
+    }
+
+    /// 关闭DISPI（回到VGA文本/传统模式）
+    ///
+    /// # Safety
+    /// 见probe。
+    pub unsafe fn disable(&mut self) {
+        self.write(INDEX_ENABLE, 0);
+    }
+}
+
+/// 在PCI总线0上找Bochs VGA设备，返回BAR0（线性帧缓冲区）的地址
+unsafe fn find_lfb(ports: &mut impl PortIo) -> Option<u64> {
+    let config = |device: u32, offset: u32| 0x8000_0000 | (device << 11) | offset;
+    for device in 0..32 {
+        ports.write_u32(PCI_CONFIG_ADDRESS, config(device, 0x00));
+        if ports.read_u32(PCI_CONFIG_DATA) != BOCHS_VGA_ID {
+            continue;
+        }
+        ports.write_u32(PCI_CONFIG_ADDRESS, config(device, 0x10));
+        let bar = ports.read_u32(PCI_CONFIG_DATA);
+        if bar & 1 != 0 {
+            return None; // I/O BAR，不是帧缓冲区
+        }
+        let mut base = (bar & 0xFFFF_FFF0) as u64;
+        if (bar >> 1) & 0b11 == 0b10 {
+            // 64位BAR：高32位在下一个BAR里
+            ports.write_u32(PCI_CONFIG_ADDRESS, config(device, 0x14));
+            base |= (ports.read_u32(PCI_CONFIG_DATA) as u64) << 32;
+        }
+        return (base != 0).then_some(base);
+    }
+    None
+}
+
+// ==========================
+// 驱动后端接口（和vesa/headless模块一致）
+// ==========================
+// 驱动里唯一的DISPI设备（init探测成功后才有值）
+static mut DEVICE: Option<Dispi<&'static mut dyn PortIo>> = None;
+
+unsafe fn device() -> Option<&'static mut Dispi<&'static mut dyn PortIo>> {
+    (*core::ptr::addr_of_mut!(DEVICE)).as_mut()
+}
+
+/// 探测设备，返回是否找到
+pub(crate) unsafe fn init(ports: &'static mut dyn PortIo) -> bool {
+    let dispi = Dispi::probe(ports);
+    let found = dispi.is_some();
+    *core::ptr::addr_of_mut!(DEVICE) = dispi;
+    found
+}
+
+pub(crate) unsafe fn modes() -> ModeList {
+    device().map_or_else(ModeList::new, |dispi| dispi.modes())
+}
+
+/// DISPI能设置任意分辨率：请求的宽高在能力范围内就原样使用（32位色），否则从常见模式里挑
+pub(crate) unsafe fn select(width: u16, height: u16) -> Option<VideoMode> {
+    let dispi = device()?;
+    if dispi.supports(width, height, 32, height) {
+        return Some(VideoMode { width, height, bpp: 32 });
+    }
+    mode::best_match(dispi.modes().as_slice(), width, height)
+}
+
+/// 显存够的话虚拟高度设为两屏（第二页做后台缓冲区，翻页不用复制），否则只用一页
+pub(crate) unsafe fn set_mode(mode: VideoMode) -> Option<Framebuffer> {
+    let dispi = device()?;
+    let double_height = mode.height.checked_mul(2).filter(|&h| dispi.supports(mode.width, mode.height, mode.bpp, h));
+    let actual = dispi.set_mode(mode.width, mode.height, mode.bpp, double_height.unwrap_or(mode.height))?;
+    let format = PixelFormat::from_bpp(actual.bpp, actual.pitch())?;
+    let pages = if actual.virt_height / actual.height >= 2 { 2 } else { 1 };
+    let virt = kernel::map_phys(dispi.lfb(), format.buffer_size(actual.height) * pages as usize);
+    if virt.is_null() {
+        return None;
+    }
+    Some(Framebuffer { phys: dispi.lfb(), virt, width: actual.width, height: actual.height, format, pages, front: 0 })
+}
+
+/// 从显存第y行开始显示
+pub(crate) unsafe fn flip(y: u16) -> bool {
+    match device() {
+        Some(dispi) => {
+            dispi.set_offset(0, y);
+            true
+        }
+        None => false,
+    }
+}
+
+// ==========================
+// 模拟寄存器（测试和宿主模拟器用）
+// ==========================
+/// 软件实现的DISPI寄存器 + PCI配置空间（设备位于总线0的2号槽，和QEMU的stdvga一样）
+///
+/// 行为按QEMU的实现简化：启用时检查模式，虚拟高度受显存限制，GETCAPS读出最大值。
+pub struct FakeDispi {
+    index: u16,
+    regs: [u16; REGISTER_COUNT],
+    lfb: u64,
+    video_memory: usize,
+    pci_address: u32,
+}
+
+/// FakeDispi支持的最大分辨率（和Bochs一致）
+const FAKE_MAX: (u16, u16, u8) = (2560, 1600, 32);
+/// FakeDispi在PCI总线上的槽位
+const FAKE_PCI_DEVICE: u32 = 2;
+
+impl FakeDispi {
+    /// lfb为BAR0报告的帧缓冲区物理地址，video_memory为显存字节数（64KB的整数倍）
+    pub const fn new(lfb: u64, video_memory: usize) -> Self {
+        let mut regs = [0; REGISTER_COUNT];
+        regs[INDEX_ID as usize] = ID_MAX;
+        regs[INDEX_VIDEO_MEMORY_64K as usize] = (video_memory / 0x10000) as u16;
+        FakeDispi { index: 0, regs, lfb, video_memory, pci_address: 0 }
+    }
+
+    pub fn lfb(&self) -> u64 {
+        self.lfb
+    }
+
+    pub fn video_memory(&self) -> usize {
+        self.video_memory
+    }
+
+    fn reg(&self, index: u16) -> u16 {
+        self.regs[index as usize]
+    }
+
+    /// 写ENABLE：从关闭到启用时检查并锁定模式，不合法则保持关闭
+    fn write_enable(&mut self, value: u16) {
+        let was_enabled = self.reg(INDEX_ENABLE) & ENABLED != 0;
+        if value & ENABLED == 0 || was_enabled {
+            self.regs[INDEX_ENABLE as usize] = value;
+            return;
+        }
+        let (width, height, bpp) = (self.reg(INDEX_XRES), self.reg(INDEX_YRES), self.reg(INDEX_BPP));
+        let valid = width != 0
+            && height != 0
+            && width <= FAKE_MAX.0
+            && height <= FAKE_MAX.1
+            && matches!(bpp, 8 | 15 | 16 | 24 | 32);
+        let virt_width = self.reg(INDEX_VIRT_WIDTH).max(width);
+        let pitch = virt_width as usize * bytes_per_pixel(bpp as u8);
+        let max_lines = (self.video_memory / pitch.max(1)).min(u16::MAX as usize) as u16;
+        if !valid || height > max_lines {
+            self.regs[INDEX_ENABLE as usize] = value & !ENABLED;
+            return;
+        }
+        let requested = self.reg(INDEX_VIRT_HEIGHT);
+        self.regs[INDEX_VIRT_WIDTH as usize] = virt_width;
+        self.regs[INDEX_VIRT_HEIGHT as usize] = requested.clamp(height, max_lines);
+        self.regs[INDEX_X_OFFSET as usize] = 0;
+        self.regs[INDEX_Y_OFFSET as usize] = 0;
+        self.regs[INDEX_ENABLE as usize] = value;
+    }
+
+    fn write_data(&mut self, value: u16) {
+        let enabled = self.reg(INDEX_ENABLE) & ENABLED != 0;
+        match self.index {
+            INDEX_ID if (ID_MIN..=ID_MAX).contains(&value) => self.regs[INDEX_ID as usize] = value,
+            // 启用状态下改分辨率/位数无效
+            INDEX_XRES | INDEX_YRES | INDEX_BPP if !enabled => self.regs[self.index as usize] = value,
+            INDEX_ENABLE => self.write_enable(value),
+            INDEX_VIRT_WIDTH | INDEX_VIRT_HEIGHT | INDEX_X_OFFSET | INDEX_Y_OFFSET => {
+                self.regs[self.index as usize] = value
+            }
+            _ => {} // 只读或不支持的寄存器
+        }
+    }
+
+    fn read_data(&self) -> u16 {
+        let caps = self.reg(INDEX_ENABLE) & GETCAPS != 0;
+        match self.index {
+            INDEX_XRES if caps => FAKE_MAX.0,
+            INDEX_YRES if caps => FAKE_MAX.1,
+            INDEX_BPP if caps => FAKE_MAX.2 as u16,
+            index if (index as usize) < REGISTER_COUNT => self.reg(index),
+            _ => 0xFFFF,
+        }
+    }
+
+    fn read_pci(&self) -> u32 {
+        let enabled = self.pci_address & 0x8000_0000 != 0;
+        let bus = (self.pci_address >> 16) & 0xFF;
+        let device = (self.pci_address >> 11) & 0x1F;
+        let function = (self.pci_address >> 8) & 0x7;
+        if !enabled || bus != 0 || device != FAKE_PCI_DEVICE || function != 0 {
+            return 0xFFFF_FFFF; // 没有设备
+        }
+        match self.pci_address & 0xFC {
+            0x00 => BOCHS_VGA_ID,
+            0x10 => (self.lfb as u32 & 0xFFFF_FFF0) | 0x8, // 32位可预取内存BAR
+            _ => 0,
+        }
+    }
+}
+
+impl PortIo for FakeDispi {
+    unsafe fn read_u16(&mut self, port: u16) -> u16 {
+        match port {
+            INDEX_PORT => self.index,
+            DATA_PORT => self.read_data(),
+            _ => 0xFFFF,
+        }
+    }
+
+    unsafe fn write_u16(&mut self, port: u16, value: u16) {
+        match port {
+            INDEX_PORT => self.index = value,
+            DATA_PORT => self.write_data(value),
+            _ => {}
+        }
+    }
+
+    unsafe fn read_u32(&mut self, port: u16) -> u32 {
+        match port {
+            PCI_CONFIG_DATA => self.read_pci(),
+            PCI_CONFIG_ADDRESS => self.pci_address,
+            _ => 0xFFFF_FFFF,
+        }
+    }
+
+    unsafe fn write_u32(&mut self, port: u16, value: u32) {
+        if port == PCI_CONFIG_ADDRESS {
+            self.pci_address = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LFB: u64 = 0xFD00_0000;
+    const MEMORY: usize = 16 << 20;
+
+    /// 包一层FakeDispi：ID寄存器只读出id（模拟老版本的设备，或者0xFFFF表示没有设备），
+    /// pci为false时PCI总线上找不到设备
+    struct Device {
+        fake: FakeDispi,
+        id: u16,
+        pci: bool,
+    }
+
+    impl PortIo for Device {
+        unsafe fn read_u16(&mut self, port: u16) -> u16 {
+            if port == DATA_PORT && self.fake.index == INDEX_ID {
+                return self.id;
+            }
+            self.fake.read_u16(port)
+        }
+
+        unsafe fn write_u16(&mut self, port: u16, value: u16) {
+            self.fake.write_u16(port, value)
+        }
+
+        unsafe fn read_u32(&mut self, port: u16) -> u32 {
+            if port == PCI_CONFIG_DATA && !self.pci {
+                return 0xFFFF_FFFF;
+            }
+            self.fake.read_u32(port)
+        }
+
+        unsafe fn write_u32(&mut self, port: u16, value: u32) {
+            self.fake.write_u32(port, value)
+        }
+    }
+
+    fn read_reg(ports: &mut impl PortIo, index: u16) -> u16 {
+        unsafe {
+            ports.write_u16(INDEX_PORT, index);
+            ports.read_u16(DATA_PORT)
+        }
+    }
+
+    fn write_reg(ports: &mut impl PortIo, index: u16, value: u16) {
+        unsafe {
+            ports.write_u16(INDEX_PORT, index);
+            ports.write_u16(DATA_PORT, value);
+        }
+    }
+
+    #[test]
+    fn probe_reads_caps_and_lfb() {
+        let mut fake = FakeDispi::new(LFB, MEMORY);
+        let dispi = unsafe { Dispi::probe(&mut fake) }.unwrap();
+        assert_eq!(dispi.id(), ID_MAX);
+        assert_eq!(dispi.lfb(), LFB);
+        assert_eq!(dispi.caps(), Caps { max_width: 2560, max_height: 1600, max_bpp: 32, video_memory: MEMORY });
+        // 查完能力要把GETCAPS位清掉，否则XRES/YRES/BPP读出的还是最大值
+        assert_eq!(read_reg(&mut fake, INDEX_ENABLE) & GETCAPS, 0);
+        assert_eq!(read_reg(&mut fake, INDEX_XRES), 0);
+    }
+
+    #[test]
+    fn probe_old_device_falls_back_to_defaults() {
+        let mut device = Device { fake: FakeDispi::new(LFB, 8 << 20), id: 0xB0C1, pci: false };
+        let dispi = unsafe { Dispi::probe(&mut device) }.unwrap();
+        assert_eq!(dispi.id(), 0xB0C1);
+        // 不支持GETCAPS：最大模式用默认值；PCI里没有设备：用默认的帧缓冲区地址
+        let caps = dispi.caps();
+        assert_eq!((caps.max_width, caps.max_height, caps.max_bpp), DEFAULT_MAX);
+        assert_eq!(caps.video_memory, 8 << 20);
+        assert_eq!(dispi.lfb(), DEFAULT_LFB);
+    }
+
+    #[test]
+    fn probe_without_dispi() {
+        let mut device = Device { fake: FakeDispi::new(LFB, MEMORY), id: 0xFFFF, pci: true };
+        assert!(unsafe { Dispi::probe(&mut device) }.is_none());
+        device.id = 0xB0BF;
+        assert!(unsafe { Dispi::probe(&mut device) }.is_none());
+    }
+
+    #[test]
+    fn set_mode_accepted() {
+        let mut fake = FakeDispi::new(LFB, MEMORY);
+        let mut dispi = unsafe { Dispi::probe(&mut fake) }.unwrap();
+        let mode = unsafe { dispi.set_mode(800, 600, 32, 1200) }.unwrap();
+        assert_eq!(mode, DispiMode { width: 800, height: 600, bpp: 32, virt_width: 800, virt_height: 1200 });
+        assert_eq!(mode.pitch(), 3200);
+        assert_eq!(unsafe { dispi.current_mode() }, Some(mode));
+
+        // 换模式（16位色一个像素2字节）
+        let mode = unsafe { dispi.set_mode(1024, 768, 16, 768) }.unwrap();
+        assert_eq!((mode.width, mode.height, mode.bpp, mode.virt_height), (1024, 768, 16, 768));
+        assert_eq!(mode.pitch(), 2048);
+    }
+
+    #[test]
+    fn set_mode_rejected() {
+        let mut fake = FakeDispi::new(LFB, 4 << 20);
+        let mut dispi = unsafe { Dispi::probe(&mut fake) }.unwrap();
+        unsafe {
+            assert_eq!(dispi.set_mode(800, 600, 8, 600), None); // 不支持的位数
+            assert_eq!(dispi.set_mode(800, 600, 12, 600), None);
+            assert_eq!(dispi.set_mode(2561, 600, 32, 600), None); // 超过最大分辨率
+            assert_eq!(dispi.set_mode(800, 1601, 16, 1601), None);
+            assert_eq!(dispi.set_mode(0, 600, 32, 600), None);
+            assert_eq!(dispi.set_mode(800, 600, 32, 599), None); // 虚拟高度比屏幕矮
+            assert_eq!(dispi.set_mode(1920, 1080, 32, 1080), None); // 显存不够
+            assert_eq!(dispi.set_mode(800, 600, 32, 1800), None);
+            // 被拒绝的模式不会写进寄存器
+            assert_eq!(dispi.current_mode(), None);
+        }
+    }
+
+    #[test]
+    fn virt_height_clamped_by_device() {
+        let mut fake = FakeDispi::new(LFB, 4 << 20);
+        write_reg(&mut fake, INDEX_XRES, 800);
+        write_reg(&mut fake, INDEX_YRES, 600);
+        write_reg(&mut fake, INDEX_BPP, 32);
+        write_reg(&mut fake, INDEX_VIRT_HEIGHT, 0xFFFF);
+        write_reg(&mut fake, INDEX_ENABLE, ENABLED);
+        // 虚拟宽度至少是屏幕宽度，虚拟高度不超过显存能放下的行数
+        assert_eq!(read_reg(&mut fake, INDEX_VIRT_WIDTH), 800);
+        assert_eq!(read_reg(&mut fake, INDEX_VIRT_HEIGHT), ((4 << 20) / 3200) as u16);
+
+        // 比屏幕矮的虚拟高度提到屏幕高度
+        write_reg(&mut fake, INDEX_ENABLE, 0);
+        write_reg(&mut fake, INDEX_VIRT_HEIGHT, 100);
+        write_reg(&mut fake, INDEX_ENABLE, ENABLED);
+        assert_eq!(read_reg(&mut fake, INDEX_VIRT_HEIGHT), 600);
+
+        // 显存连一屏都放不下时不启用
+        write_reg(&mut fake, INDEX_ENABLE, 0);
+        write_reg(&mut fake, INDEX_XRES, 2560);
+        write_reg(&mut fake, INDEX_YRES, 1600);
+        write_reg(&mut fake, INDEX_ENABLE, ENABLED);
+        assert_eq!(read_reg(&mut fake, INDEX_ENABLE) & ENABLED, 0);
+    }
+
+    #[test]
+    fn modes_fit_video_memory() {
+        let mut fake = FakeDispi::new(LFB, 1 << 20);
+        let dispi = unsafe { Dispi::probe(&mut fake) }.unwrap();
+        let mode = |width, height, bpp| VideoMode { width, height, bpp };
+        // 1MB放得下640x480的24位色，800x600只能16位色；同一分辨率按位数从高到低
+        let expected = [mode(640, 480, 24), mode(640, 480, 16), mode(640, 480, 15), mode(800, 600, 16), mode(800, 600, 15)];
+        assert_eq!(dispi.modes().as_slice(), &expected);
+    }
+
+    #[test]
+    fn set_offset_moves_display_start() {
+        let mut fake = FakeDispi::new(LFB, MEMORY);
+        {
+            let mut dispi = unsafe { Dispi::probe(&mut fake) }.unwrap();
+            unsafe {
+                dispi.set_mode(800, 600, 32, 1200).unwrap();
+                dispi.set_offset(16, 600);
+            }
+        }
+        assert_eq!(read_reg(&mut fake, INDEX_X_OFFSET), 16);
+        assert_eq!(read_reg(&mut fake, INDEX_Y_OFFSET), 600);
+
+        // 重新设置模式时显示起点回到左上角
+        let mut dispi = unsafe { Dispi::probe(&mut fake) }.unwrap();
+        unsafe { dispi.set_mode(640, 480, 32, 480) }.unwrap();
+        drop(dispi);
+        assert_eq!(read_reg(&mut fake, INDEX_Y_OFFSET), 0);
+    }
+}
@@ -0,0 +1,475 @@
+// ==========================
+// 帧缓冲区导出：PPM/PNG编码
+// ==========================
+// 编码器是流式的（不需要堆内存），输出写到任意Sink里：
+// 驱动里是FileSink（通过内核写文件），宿主测试里可以直接传闭包收集字节。
+// 文件只写在DUMP_DIR里，请求方只能给文件名。
+
+use common::{kernel, DumpFormat, PixelFormat, IPC_STR_CAPACITY};
+
+/// 编码输出目标，写失败返回false
+pub trait Sink {
+    fn write(&mut self, data: &[u8]) -> bool;
+}
+
+impl<F: FnMut(&[u8]) -> bool> Sink for F {
+    fn write(&mut self, data: &[u8]) -> bool {
+        self(data)
+    }
+}
+
+/// 导出的文件放在哪个目录（相对GPU驱动的工作目录）
+pub const DUMP_DIR: &str = "dumps/";
+
+/// 一行最多先攒多少个像素再写出（避免逐字节调用Sink）
+const ROW_CHUNK: usize = 256;
+
+/// 十进制数字转字符串（PPM头用）
+fn decimal(mut n: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut pos = buf.len();
+    loop {
+        pos -= 1;
+        buf[pos] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return &buf[pos..];
+        }
+    }
+}
+
+/// 帧缓冲区是否装得下width×height个format格式的像素
+fn fits(fb: &[u8], format: &PixelFormat, width: u16, height: u16) -> bool {
+    format.is_valid(width) && fb.len() >= format.buffer_size(height)
+}
+
+/// 把一行像素按format解码成RGB字节，交给emit（每次最多ROW_CHUNK个像素）
+fn for_each_rgb_chunk(row: &[u8], format: &PixelFormat, width: u16, mut emit: impl FnMut(&[u8]) -> bool) -> bool {
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let row = &row[..width as usize * bytes_per_pixel]; // 去掉行尾填充
+    let mut rgb = [0u8; ROW_CHUNK * 3];
+    for chunk in row.chunks(ROW_CHUNK * bytes_per_pixel) {
+        let count = chunk.len() / bytes_per_pixel;
+        for (i, src) in chunk.chunks_exact(bytes_per_pixel).enumerate() {
+            let mut value = [0u8; 4];
+            value[..bytes_per_pixel].copy_from_slice(src);
+            let color = format.decode(u32::from_le_bytes(value));
+            rgb[i * 3..i * 3 + 3].copy_from_slice(&[color.r, color.g, color.b]);
+        }
+        if !emit(&rgb[..count * 3]) {
+            return false;
+        }
+    }
+    true
+}
+
+// ==========================
+// PPM（P6）
+// ==========================
+/// 编码为二进制PPM：fb为format格式的帧缓冲区字节，格式不对时返回false
+pub fn write_ppm(fb: &[u8], format: &PixelFormat, width: u16, height: u16, sink: &mut impl Sink) -> bool {
+    if !fits(fb, format, width, height) {
+        return false;
+    }
+    let mut num = [0u8; 10];
+    let header_ok = sink.write(b"P6\n")
+        && sink.write(decimal(width as u32, &mut num))
+        && sink.write(b" ")
+        && sink.write(decimal(height as u32, &mut num))
+        && sink.write(b"\n255\n");
+    if !header_ok {
+        return false;
+    }
+    fb.chunks(format.pitch.max(1) as usize)
+        .take(height as usize)
+        .all(|row| for_each_rgb_chunk(row, format, width, |rgb| sink.write(rgb)))
+}
+
+// ==========================
+// PNG（RGB 8位，zlib用不压缩的stored块）
+// ==========================
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// deflate stored块的最大长度
+const STORED_BLOCK_MAX: usize = 65535;
+/// PNG块的最大长度（2^31-1）
+const PNG_CHUNK_MAX: u32 = 0x7FFF_FFFF;
+
+/// CRC32查表（多项式0xEDB88320，编译期生成）
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// 写PNG数据块，同时计算CRC
+struct ChunkWriter<'a, S: Sink> {
+    sink: &'a mut S,
+    crc: u32,
+}
+
+impl<'a, S: Sink> ChunkWriter<'a, S> {
+    /// 写块头（长度+类型），类型也计入CRC
+    fn begin(sink: &'a mut S, len: u32, kind: &[u8; 4]) -> Option<Self> {
+        if !sink.write(&len.to_be_bytes()) {
+            return None;
+        }
+        let mut chunk = ChunkWriter { sink, crc: 0xFFFF_FFFF };
+        chunk.data(kind).then_some(chunk)
+    }
+
+    fn data(&mut self, data: &[u8]) -> bool {
+        for &byte in data {
+            self.crc = CRC_TABLE[((self.crc ^ byte as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+        self.sink.write(data)
+    }
+
+    fn end(self) -> bool {
+        self.sink.write(&(self.crc ^ 0xFFFF_FFFF).to_be_bytes())
+    }
+}
+
+/// zlib流（stored块）：负责切块、写块头、计算Adler-32
+struct StoredStream {
+    remaining: usize,       // 整个流还剩多少原始字节
+    block_remaining: usize, // 当前块还剩多少字节
+    adler_a: u32,
+    adler_b: u32,
+}
+
+impl StoredStream {
+    fn feed<S: Sink>(&mut self, chunk: &mut ChunkWriter<S>, mut data: &[u8]) -> bool {
+        while !data.is_empty() {
+            if self.block_remaining == 0 {
+                // 新块：BFINAL位 + 长度 + 长度取反
+                let len = self.remaining.min(STORED_BLOCK_MAX);
+                let last = (len == self.remaining) as u8;
+                let len16 = len as u16;
+                let header = [last, len16 as u8, (len16 >> 8) as u8, !len16 as u8, (!len16 >> 8) as u8];
+                if !chunk.data(&header) {
+                    return false;
+                }
+                self.block_remaining = len;
+            }
+            let n = data.len().min(self.block_remaining);
+            for &byte in &data[..n] {
+                self.adler_a = (self.adler_a + byte as u32) % 65521;
+                self.adler_b = (self.adler_b + self.adler_a) % 65521;
+            }
+            if !chunk.data(&data[..n]) {
+                return false;
+            }
+            self.block_remaining -= n;
+            self.remaining -= n;
+            data = &data[n..];
+        }
+        true
+    }
+
+    fn adler32(&self) -> u32 {
+        (self.adler_b << 16) | self.adler_a
+    }
+}
+
+/// IDAT块的长度：zlib头 + stored块（每行前面一个滤波类型字节0，每块5字节块头）+ Adler-32；
+/// 超过PNG块的长度上限时返回None
+fn idat_len(width: u16, height: u16) -> Option<u32> {
+    let raw_len = height as u64 * (1 + width as u64 * 3);
+    let blocks = raw_len.div_ceil(STORED_BLOCK_MAX as u64).max(1);
+    let len = 2 + blocks * 5 + raw_len + 4;
+    (len <= PNG_CHUNK_MAX as u64).then_some(len as u32)
+}
+
+/// 编码为PNG：fb为format格式的帧缓冲区字节，格式不对或图片大得一个IDAT块放不下时返回false
+pub fn write_png(fb: &[u8], format: &PixelFormat, width: u16, height: u16, sink: &mut impl Sink) -> bool {
+    let Some(idat_len) = idat_len(width, height) else {
+        return false;
+    };
+    if !fits(fb, format, width, height) || !sink.write(&PNG_SIGNATURE) {
+        return false;
+    }
+
+    // IHDR：宽、高、位深8、颜色类型2（RGB）、压缩/滤波/隔行都为0
+    let mut ihdr = [0u8; 13];
+    ihdr[0..4].copy_from_slice(&(width as u32).to_be_bytes());
+    ihdr[4..8].copy_from_slice(&(height as u32).to_be_bytes());
+    ihdr[8] = 8;
+    ihdr[9] = 2;
+    let Some(mut chunk) = ChunkWriter::begin(sink, ihdr.len() as u32, b"IHDR") else {
+        return false;
+    };
+    if !(chunk.data(&ihdr) && chunk.end()) {
+        return false;
+    }
+
+    // IDAT：zlib头 + stored块 + Adler-32
+    let raw_len = height as usize * (1 + width as usize * 3);
+    let Some(mut chunk) = ChunkWriter::begin(sink, idat_len, b"IDAT") else {
+        return false;
+    };
+    if !chunk.data(&[0x78, 0x01]) {
+        return false;
+    }
+    let mut stream = StoredStream { remaining: raw_len, block_remaining: 0, adler_a: 1, adler_b: 0 };
+    if raw_len == 0 && !chunk.data(&[1, 0, 0, 0xFF, 0xFF]) {
+        return false; // 空图片也要有一个结束块
+    }
+    for row in fb.chunks(format.pitch.max(1) as usize).take(height as usize) {
+        let row_ok = stream.feed(&mut chunk, &[0])
+            && for_each_rgb_chunk(row, format, width, |rgb| stream.feed(&mut chunk, rgb));
+        if !row_ok {
+            return false;
+        }
+    }
+    if !(chunk.data(&stream.adler32().to_be_bytes()) && chunk.end()) {
+        return false;
+    }
+
+    // IEND
+    match ChunkWriter::begin(sink, 0, b"IEND") {
+        Some(chunk) => chunk.end(),
+        None => false,
+    }
+}
+
+// ==========================
+// 写文件
+// ==========================
+/// 导出的文件名能不能用：只能有字母、数字、'.'、'-'、'_'，不能以'.'开头（不能带目录，也不能是..）
+pub fn valid_dump_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= IPC_STR_CAPACITY
+        && !name.starts_with('.')
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b))
+}
+
+/// 通过内核写文件的Sink（攒满缓冲区再写，第一次写会清空文件）
+struct FileSink {
+    path: [u8; DUMP_DIR.len() + IPC_STR_CAPACITY + 1], // DUMP_DIR + 文件名，以0结尾
+    buf: [u8; 4096],
+    len: usize,
+    started: bool,
+}
+
+impl FileSink {
+    /// 写DUMP_DIR里的name（name已经检查过）
+    fn new(name: &str) -> Self {
+        let mut sink = FileSink { path: [0; DUMP_DIR.len() + IPC_STR_CAPACITY + 1], buf: [0; 4096], len: 0, started: false };
+        sink.path[..DUMP_DIR.len()].copy_from_slice(DUMP_DIR.as_bytes());
+        sink.path[DUMP_DIR.len()..][..name.len()].copy_from_slice(name.as_bytes());
+        sink
+    }
+
+    fn flush(&mut self) -> bool {
+        let ok = unsafe {
+            kernel::kernel_write_file(self.path.as_ptr(), self.buf.as_ptr(), self.len, self.started)
+        };
+        self.started = true;
+        self.len = 0;
+        ok
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&mut self, mut data: &[u8]) -> bool {
+        while !data.is_empty() {
+            if self.len == self.buf.len() && !self.flush() {
+                return false;
+            }
+            let n = data.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+        }
+        true
+    }
+}
+
+/// 把帧缓冲区按指定格式写到DUMP_DIR里的name文件（文件名不合规矩时返回false）
+pub fn dump_to_file(fb: &[u8], pixel_format: &PixelFormat, width: u16, height: u16, format: DumpFormat, name: &str) -> bool {
+    if !valid_dump_name(name) {
+        return false;
+    }
+    let mut sink = FileSink::new(name);
+    let ok = match format {
+        DumpFormat::Ppm => write_ppm(fb, pixel_format, width, height, &mut sink),
+        DumpFormat::Png => write_png(fb, pixel_format, width, height, &mut sink),
+    };
+    // 最后一段（也保证空文件会被创建/清空）
+    ok && sink.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// 逐位算的CRC32（和查表的结果对照）
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = 0xFFFF_FFFFu32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    fn be_u32(data: &[u8]) -> u32 {
+        u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+    }
+
+    /// 编码成PNG，检查每个块的CRC，返回(类型, 数据)列表
+    fn png_chunks(fb: &[u8], format: &PixelFormat, width: u16, height: u16) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut out = Vec::new();
+        assert!(write_png(fb, format, width, height, &mut |data: &[u8]| {
+            out.extend_from_slice(data);
+            true
+        }));
+        assert_eq!(out[..8], PNG_SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &out[8..];
+        while !rest.is_empty() {
+            let len = be_u32(rest) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            assert_eq!(be_u32(&rest[8 + len..]), crc32(&rest[4..8 + len]), "{:?}", core::str::from_utf8(kind));
+            chunks.push(([kind[0], kind[1], kind[2], kind[3]], data.to_vec()));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    /// 解开stored块的zlib流，检查块头和Adler-32
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(zlib[..2], [0x78, 0x01]);
+        let mut raw = Vec::new();
+        let mut pos = 2;
+        loop {
+            let last = zlib[pos];
+            let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]);
+            assert_eq!(u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]), !len);
+            raw.extend_from_slice(&zlib[pos + 5..pos + 5 + len as usize]);
+            pos += 5 + len as usize;
+            if last == 1 {
+                break;
+            }
+            assert_eq!(last, 0);
+        }
+        let (a, b) = raw.iter().fold((1u32, 0u32), |(a, b), byte| {
+            let a = (a + *byte as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(be_u32(&zlib[pos..]), (b << 16) | a);
+        assert_eq!(pos + 4, zlib.len());
+        raw
+    }
+
+    #[test]
+    fn crc_table_matches_bitwise() {
+        for data in [&b""[..], b"IEND", b"123456789"] {
+            let mut chunk = ChunkWriter { sink: &mut |_: &[u8]| true, crc: 0xFFFF_FFFF };
+            assert!(chunk.data(data));
+            assert_eq!(chunk.crc ^ 0xFFFF_FFFF, crc32(data));
+        }
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn png_layout() {
+        // 2×2的XRGB8888，每行后面有4字节填充
+        let mut format = PixelFormat::xrgb8888(2);
+        format.pitch = 12;
+        let pixels: [u32; 6] = [0x00FF_0000, 0x0000_FF00, 0xDEAD, 0x0000_00FF, 0x00FF_FFFF, 0xBEEF];
+        let fb: Vec<u8> = pixels.iter().flat_map(|p| p.to_le_bytes()).collect();
+        let chunks = png_chunks(&fb, &format, 2, 2);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        // 每行：滤波类型0 + RGB
+        let raw = inflate_stored(&chunks[1].1);
+        assert_eq!(raw, [0, 255, 0, 0, 0, 255, 0, 0, 0, 0, 255, 255, 255, 255]);
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn png_splits_stored_blocks() {
+        // 每行1 + 300×3字节，100行超过一个stored块（65535字节）
+        let (width, height) = (300u16, 100u16);
+        let format = PixelFormat::xrgb8888(width);
+        let fb: Vec<u8> = (0..width as u32 * height as u32).flat_map(|i| (i * 0x010203).to_le_bytes()).collect();
+        let chunks = png_chunks(&fb, &format, width, height);
+        let raw = inflate_stored(&chunks[1].1);
+        assert_eq!(raw.len(), height as usize * (1 + width as usize * 3));
+        let row = &raw[(1 + width as usize * 3) * 7..][..4];
+        let pixel = (7 * width as u32).wrapping_mul(0x010203);
+        assert_eq!(row, [0, (pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+    }
+
+    #[test]
+    fn png_empty_image() {
+        let format = PixelFormat::xrgb8888(0);
+        let chunks = png_chunks(&[], &format, 0, 0);
+        assert!(inflate_stored(&chunks[1].1).is_empty());
+    }
+
+    #[test]
+    fn png_rejects_oversized_idat() {
+        // 2×2：zlib头2 + 块头5 + 2×(1 + 2×3) + Adler-32 4
+        assert_eq!(idat_len(2, 2), Some(2 + 5 + 14 + 4));
+        assert_eq!(idat_len(0, 0), Some(2 + 5 + 4));
+        assert_eq!(idat_len(1920, 1080), Some(2 + 5 * 95 + 1080 * 5761 + 4));
+        // 65535×65535的原始数据将近13GB，长度不能截成u32写进块头；PNG块最长2^31-1字节
+        assert_eq!(idat_len(u16::MAX, u16::MAX), None);
+        assert_eq!(idat_len(u16::MAX, 10922), None);
+        assert_eq!(idat_len(u16::MAX, 10921), Some(2_147_297_952));
+        let format = PixelFormat::xrgb8888(u16::MAX);
+        let mut written = false;
+        assert!(!write_png(&[], &format, u16::MAX, u16::MAX, &mut |_: &[u8]| {
+            written = true;
+            true
+        }));
+        assert!(!written);
+    }
+
+    #[test]
+    fn ppm_layout() {
+        let format = PixelFormat::xrgb8888(2);
+        let fb: Vec<u8> = [0x0012_3456u32, 0x00AB_CDEF].iter().flat_map(|p| p.to_le_bytes()).collect();
+        let mut out = Vec::new();
+        assert!(write_ppm(&fb, &format, 2, 1, &mut |data: &[u8]| {
+            out.extend_from_slice(data);
+            true
+        }));
+        assert_eq!(out, b"P6\n2 1\n255\n\x12\x34\x56\xAB\xCD\xEF");
+    }
+
+    #[test]
+    fn rejects_short_framebuffer() {
+        let format = PixelFormat::xrgb8888(4);
+        let mut sink = |_: &[u8]| true;
+        assert!(!write_png(&[0; 15], &format, 4, 1, &mut sink));
+        assert!(!write_ppm(&[0; 15], &format, 4, 1, &mut sink));
+    }
+
+    #[test]
+    fn dump_names() {
+        for name in ["snap.png", "frame-01_a.ppm", "x"] {
+            assert!(valid_dump_name(name), "{name}");
+        }
+        for name in ["", ".", "..", ".hidden", "../x.png", "/tmp/x.png", "a/b", "a\\b", "a b", "名字.png"] {
+            assert!(!valid_dump_name(name), "{name}");
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// ==========================
+// 无头后端：内存里的帧缓冲区
+// ==========================
+// 不接触显卡，向内核申请一块共享内存当帧缓冲区，分辨率任意。
+// 和virtio-gpu一样需要显式刷新：渲染服务写的是帧缓冲区，GpuFlush时才把脏区域复制到
+// "屏幕"（驱动私有的扫描缓冲区）上，GpuDump导出的是屏幕内容。
+// 配合GpuDump导出图片，用于在宿主模拟器里做截图对比测试。
+
+use crate::{mode, Framebuffer};
+use common::{kernel, ModeList, PixelFormat, Rect, ShmHandle, VideoMode};
+
+/// 已分配的共享内存（切换到更小的模式时复用，不够大才重新分配）
+///
+/// 前size字节是帧缓冲区，紧跟着同样大小的扫描缓冲区。
+struct Buffer {
+    phys: ShmHandle,
+    virt: *mut u8,
+    size: usize,
+}
+
+static mut BUFFER: Buffer = Buffer { phys: 0, virt: core::ptr::null_mut(), size: 0 };
+
+/// 扫描缓冲区（屏幕上显示的内容）
+unsafe fn scanout_ptr() -> *mut u8 {
+    let buffer = &*core::ptr::addr_of!(BUFFER);
+    buffer.virt.add(buffer.size)
+}
+
+/// 支持的模式：常见分辨率，32位色（实际任意宽高都能设置）
+pub fn modes() -> ModeList {
+    let mut modes = ModeList::new();
+    for (width, height) in mode::STANDARD_RESOLUTIONS {
+        modes.push(VideoMode { width, height, bpp: 32 });
+    }
+    modes
+}
+
+/// 切换模式：按宽高准备32位XRGB帧缓冲区（内容清零），bpp总是32
+pub unsafe fn set_mode(mode: VideoMode) -> Option<Framebuffer> {
+    let (width, height) = (mode.width, mode.height);
+    let format = PixelFormat::xrgb8888(width);
+    let size = format.buffer_size(height);
+    if size == 0 {
+        return None;
+    }
+
+    let buffer = &mut *core::ptr::addr_of_mut!(BUFFER);
+    if size > buffer.size {
+        let phys = kernel::shm_alloc(size * 2);
+        if phys == 0 {
+            return None; // 内存不足
+        }
+        let virt = kernel::map_phys(phys, size * 2);
+        if virt.is_null() {
+            return None;
+        }
+        // 旧的缓冲区：内核没有释放接口，进程退出时才回收
+        *buffer = Buffer { phys, virt, size };
+    }
+    core::ptr::write_bytes(buffer.virt, 0, buffer.size * 2);
+    Some(Framebuffer { phys: buffer.phys, virt: buffer.virt, width, height, format, pages: 1, front: 0 })
+}
+
+/// 把帧缓冲区里的这些区域复制到屏幕上（空列表表示整个屏幕）
+pub unsafe fn flush(fb: &Framebuffer, rects: &[Rect]) {
+    let scanout = scanout_ptr();
+    let full = [Rect::new(0, 0, fb.width, fb.height)];
+    let rects = if rects.is_empty() { &full[..] } else { rects };
+    let bytes_per_pixel = fb.format.bytes_per_pixel();
+    for rect in rects.iter().filter_map(|r| r.clip(fb.width, fb.height)) {
+        for y in rect.y..rect.y + rect.h {
+            let offset = fb.format.offset(rect.x, y);
+            core::ptr::copy_nonoverlapping(fb.virt.add(offset), scanout.add(offset), rect.w as usize * bytes_per_pixel);
+        }
+    }
+}
+
+/// 屏幕上显示的内容（布局同帧缓冲区）
+pub unsafe fn scanout(fb: &Framebuffer) -> &[u8] {
+    core::slice::from_raw_parts(scanout_ptr(), fb.format.buffer_size(fb.height))
+}
@@ -0,0 +1,270 @@
+#![no_std]
+
+mod headless;
+mod mode;
+mod vesa;
+pub mod dispi;
+pub mod dump;
+pub mod port;
+
+// 引入共享类型和内核API
+use common::{ipc::{self, Endpoint, Incoming}, IpcMessage, kernel, ModeList, Pid, PixelFormat, Rect, ShmHandle, VideoMode};
+use core::ffi::CStr;
+
+// ==========================
+// 显卡状态与后端
+// ==========================
+/// 帧缓冲区（物理地址发给渲染服务，虚拟地址驱动自己用）
+///
+/// 显存里连续放着pages页，每页format.buffer_size(height)字节；front是正在显示的页。
+pub(crate) struct Framebuffer {
+    pub phys: ShmHandle,
+    pub virt: *mut u8,
+    pub width: u16,
+    pub height: u16,
+    pub format: PixelFormat,
+    pub pages: u8,
+    pub front: u8,
+}
+
+impl Framebuffer {
+    /// 回复给渲染服务的帧缓冲区信息
+    fn reply(&self) -> IpcMessage {
+        IpcMessage::GpuFbReply { fb: self.phys, width: self.width, height: self.height, format: self.format, pages: self.pages }
+    }
+
+    /// 当前的显示模式
+    fn mode(&self) -> VideoMode {
+        VideoMode { width: self.width, height: self.height, bpp: self.format.bpp }
+    }
+
+    /// 正在显示的那一页的原始字节（布局见format）
+    unsafe fn bytes(&self) -> &[u8] {
+        let page_size = self.format.buffer_size(self.height);
+        core::slice::from_raw_parts(self.virt.add(self.front as usize * page_size), page_size)
+    }
+}
+
+/// 帧缓冲区从哪来
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Vesa,     // 引导程序设置好的VESA线性帧缓冲区
+    Dispi,    // Bochs/QEMU的DISPI接口（可以运行时切换模式）
+    Headless, // 内存里的帧缓冲区（无显示输出）
+}
+
+impl Backend {
+    unsafe fn modes(self) -> ModeList {
+        match self {
+            Backend::Vesa => vesa::modes(),
+            Backend::Dispi => dispi::modes(),
+            Backend::Headless => headless::modes(),
+        }
+    }
+
+    /// 为请求的宽高选模式：0x0表示沿用当前模式（还没有就用默认模式）
+    unsafe fn select(self, width: u16, height: u16, current: Option<VideoMode>) -> Option<VideoMode> {
+        if width == 0 || height == 0 {
+            if current.is_some() {
+                return current;
+            }
+            return match self {
+                Backend::Vesa => self.modes().as_slice().first().copied(),
+                Backend::Dispi => dispi::select(mode::DEFAULT_MODE.width, mode::DEFAULT_MODE.height),
+                Backend::Headless => Some(mode::DEFAULT_MODE),
+            };
+        }
+        match self {
+            Backend::Vesa => mode::best_match(self.modes().as_slice(), width, height),
+            Backend::Dispi => dispi::select(width, height),
+            // 内存帧缓冲区任意宽高都行
+            Backend::Headless => Some(VideoMode { width, height, bpp: 32 }),
+        }
+    }
+
+    unsafe fn set_mode(self, mode: VideoMode) -> Option<Framebuffer> {
+        match self {
+            Backend::Vesa => vesa::set_mode(mode),
+            Backend::Dispi => dispi::set_mode(mode),
+            Backend::Headless => headless::set_mode(mode),
+        }
+    }
+
+    /// 把帧缓冲区的这些区域刷新到屏幕上（空列表表示整个屏幕）
+    unsafe fn flush(self, fb: &Framebuffer, rects: &[Rect]) {
+        match self {
+            Backend::Vesa => vesa::flush_screen(rects),
+            Backend::Dispi => {} // 显卡直接扫描线性帧缓冲区，不需要刷新
+            Backend::Headless => headless::flush(fb, rects),
+        }
+    }
+
+    /// 屏幕上正在显示的内容（GpuDump导出的就是这个）
+    unsafe fn screen(self, fb: &Framebuffer) -> &[u8] {
+        match self {
+            Backend::Headless => headless::scanout(fb),
+            Backend::Vesa | Backend::Dispi => fb.bytes(),
+        }
+    }
+
+    /// 显示第page页，返回是否成功（只有多页的帧缓冲区能翻页）
+    unsafe fn flip(self, fb: &Framebuffer, page: u8) -> bool {
+        match self {
+            Backend::Dispi => dispi::flip(page as u16 * fb.height),
+            Backend::Vesa | Backend::Headless => false,
+        }
+    }
+}
+
+/// GPU驱动全局状态
+struct GpuState {
+    backend: Backend,
+    fb: Option<Framebuffer>, // 初始化后才有值
+    owner: Pid,              // 发GpuInit的进程（渲染服务），只有它能切换模式
+}
+
+impl GpuState {
+    /// 切换到最接近宽高的模式（已经是这个模式就不动），返回是否成功
+    unsafe fn change_mode(&mut self, width: u16, height: u16) -> bool {
+        let current = self.fb.as_ref().map(Framebuffer::mode);
+        let Some(mode) = self.backend.select(width, height, current) else {
+            return false;
+        };
+        if Some(mode) == current {
+            return true;
+        }
+        match self.backend.set_mode(mode) {
+            Some(fb) => {
+                self.fb = Some(fb);
+                true
+            }
+            None => false, // 切换失败：保留原来的帧缓冲区
+        }
+    }
+
+    fn fb_reply(&self) -> IpcMessage {
+        self.fb.as_ref().map_or(NO_FB_REPLY, Framebuffer::reply)
+    }
+}
+
+// 全局状态（单例，驱动唯一实例）
+static mut GPU_STATE: GpuState = GpuState { backend: Backend::Vesa, fb: None, owner: 0 };
+
+/// 未初始化或初始化失败时的回复
+const NO_FB_REPLY: IpcMessage = IpcMessage::GpuFbReply { fb: 0, width: 0, height: 0, format: PixelFormat::xrgb8888(0), pages: 0 };
+
+// ==========================
+// IPC消息处理：响应渲染服务
+// ==========================
+/// 处理来自渲染服务的消息（请求类消息回复给发送者）
+unsafe fn handle_ipc_message(incoming: Incoming) {
+    let state = &mut *core::ptr::addr_of_mut!(GPU_STATE);
+    match incoming.msg {
+        // 渲染服务请求初始化显卡：选最接近的模式，回复实际选中的模式
+        IpcMessage::GpuInit { width, height } => {
+            state.owner = incoming.sender;
+            let reply = if state.change_mode(width, height) { state.fb_reply() } else { NO_FB_REPLY };
+            ipc::reply(&incoming, &reply);
+        }
+
+        // 渲染服务请求获取帧缓冲区信息
+        IpcMessage::GpuGetFb => {
+            ipc::reply(&incoming, &state.fb_reply());
+        }
+
+        // 查询支持的显示模式
+        IpcMessage::GpuListModes => {
+            ipc::reply(&incoming, &IpcMessage::GpuModeList { modes: state.backend.modes() });
+        }
+
+        // 渲染服务运行时切换模式（帧缓冲区换了只有它知道，所以不接受别的进程的请求）
+        IpcMessage::GpuSetMode { width, height } => {
+            let ok = state.owner != 0 && incoming.sender == state.owner && state.change_mode(width, height);
+            ipc::reply(&incoming, &if ok { state.fb_reply() } else { NO_FB_REPLY });
+        }
+
+        // 渲染服务请求刷新屏幕上的脏区域
+        IpcMessage::GpuFlush { rects } => {
+            if let Some(fb) = &state.fb {
+                state.backend.flush(fb, rects.as_slice());
+            }
+        }
+
+        // 渲染服务画完了后台页，请求翻页：回复当前显示的页（翻页失败时不变）
+        IpcMessage::GpuFlip { page } => {
+            let backend = state.backend;
+            let front = match &mut state.fb {
+                Some(fb) if page < fb.pages && backend.flip(fb, page) => {
+                    fb.front = page;
+                    page
+                }
+                Some(fb) => fb.front,
+                None => 0,
+            };
+            ipc::reply(&incoming, &IpcMessage::GpuFlipped { page: front });
+        }
+
+        // 把当前画面导出为图片文件（只有无头后端可以，免得任意进程往任意路径写文件）
+        IpcMessage::GpuDump { format, name } => {
+            let ok = match &state.fb {
+                Some(fb) if state.backend == Backend::Headless => {
+                    let screen = state.backend.screen(fb);
+                    dump::dump_to_file(screen, &fb.format, fb.width, fb.height, format, name.as_str())
+                }
+                _ => false,
+            };
+            ipc::reply(&incoming, &IpcMessage::GpuDumpDone { ok });
+        }
+
+        // 忽略其他类型消息
+        _ => {}
+    }
+}
+
+// ==========================
+// 驱动入口与主循环
+// ==========================
+/// 注册服务并处理消息（不返回）
+unsafe fn serve(backend: Backend) -> ! {
+    (*core::ptr::addr_of_mut!(GPU_STATE)).backend = backend;
+
+    // 1. 注册驱动到内核（让其他服务能通过"gpu-driver"名称找到本服务）
+    let driver_name = CStr::from_bytes_with_nul(b"gpu-driver\0").unwrap();
+    let _ = kernel::kernel_register_service(driver_name.as_ptr() as *const u8); // 忽略注册结果（简化）
+
+    // 2. 主循环：等待并处理IPC消息
+    let mut endpoint = Endpoint::new();
+    loop {
+        let incoming = endpoint.recv(); // 阻塞等待消息
+        handle_ipc_message(incoming);   // 处理消息
+        kernel::idle(); // 停机等待下一条消息（降低CPU占用）
+    }
+}
+
+/// 驱动主函数（裸机入口main.rs和宿主模拟器都从这里启动）：
+/// 裸机上先探测Bochs/QEMU的DISPI，没有再用VESA；宿主上没有端口可探测，直接用VESA
+pub fn run() -> ! {
+    #[cfg(target_os = "none")]
+    unsafe {
+        static mut HARDWARE_PORTS: port::HardwarePorts = port::HardwarePorts;
+        if dispi::init(&mut *core::ptr::addr_of_mut!(HARDWARE_PORTS)) {
+            serve(Backend::Dispi)
+        }
+    }
+    unsafe { serve(Backend::Vesa) }
+}
+
+/// 驱动主函数：通过给定的端口访问DISPI（模拟器里传FakeDispi）；探测失败时退回VESA
+pub fn run_dispi(ports: &'static mut dyn port::PortIo) -> ! {
+    unsafe {
+        if dispi::init(ports) {
+            serve(Backend::Dispi)
+        }
+        serve(Backend::Vesa)
+    }
+}
+
+/// 驱动主函数：无头后端（帧缓冲区在内存里，分辨率由GpuInit决定）
+pub fn run_headless() -> ! {
+    unsafe { serve(Backend::Headless) }
+}
@@ -0,0 +1,79 @@
+// ==========================
+// 模式选择：从支持的模式里挑最接近请求的一个
+// ==========================
+
+use common::VideoMode;
+
+/// 请求的宽高为0且还没有当前模式时使用的默认模式
+pub const DEFAULT_MODE: VideoMode = VideoMode { width: 800, height: 600, bpp: 32 };
+
+/// 能任意设置分辨率的后端在GpuListModes里列出的常见分辨率
+pub const STANDARD_RESOLUTIONS: [(u16, u16); 7] = [
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 720),
+    (1280, 1024),
+    (1600, 900),
+    (1920, 1080),
+];
+
+/// 挑选规则：
+/// 1. 宽高完全一致的优先；
+/// 2. 否则取能装下请求（宽、高都不小于）的模式里面积最小的；
+/// 3. 都装不下就取面积最大的。
+///
+/// 同样大小的模式里优先每像素位数高的。modes为空时返回None。
+pub fn best_match(modes: &[VideoMode], width: u16, height: u16) -> Option<VideoMode> {
+    let area = |m: &VideoMode| m.width as u32 * m.height as u32;
+    let exact = modes.iter().filter(|m| m.width == width && m.height == height);
+    if let Some(mode) = exact.max_by_key(|m| m.bpp) {
+        return Some(*mode);
+    }
+    let fitting = modes.iter().filter(|m| m.width >= width && m.height >= height);
+    if let Some(mode) = fitting.min_by_key(|m| (area(m), u8::MAX - m.bpp)) {
+        return Some(*mode);
+    }
+    modes.iter().max_by_key(|m| (area(m), m.bpp)).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn mode(width: u16, height: u16, bpp: u8) -> VideoMode {
+        VideoMode { width, height, bpp }
+    }
+
+    const MODES: [VideoMode; 5] =
+        [mode(640, 480, 32), mode(800, 600, 16), mode(800, 600, 32), mode(1024, 768, 32), mode(1280, 1024, 24)];
+
+    #[test]
+    fn prefers_exact_size() {
+        assert_eq!(best_match(&MODES, 1024, 768), Some(mode(1024, 768, 32)));
+        assert_eq!(best_match(&MODES, 640, 480), Some(mode(640, 480, 32)));
+    }
+
+    #[test]
+    fn picks_smallest_mode_that_fits() {
+        assert_eq!(best_match(&MODES, 900, 700), Some(mode(1024, 768, 32)));
+        assert_eq!(best_match(&MODES, 1, 1), Some(mode(640, 480, 32)));
+        // 宽够了高不够的不算装得下
+        assert_eq!(best_match(&MODES, 700, 800), Some(mode(1280, 1024, 24)));
+        // 都装不下就取最大的
+        assert_eq!(best_match(&MODES, 1920, 1080), Some(mode(1280, 1024, 24)));
+    }
+
+    #[test]
+    fn prefers_higher_bpp_among_equal_sizes() {
+        assert_eq!(best_match(&MODES, 800, 600), Some(mode(800, 600, 32)));
+        assert_eq!(best_match(&MODES, 700, 500), Some(mode(800, 600, 32)));
+        let large = [mode(1600, 1200, 16), mode(1600, 1200, 24)];
+        assert_eq!(best_match(&large, 1920, 1080), Some(mode(1600, 1200, 24)));
+    }
+
+    #[test]
+    fn no_modes_no_match() {
+        assert_eq!(best_match(&[], 800, 600), None);
+    }
+}
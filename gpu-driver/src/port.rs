@@ -0,0 +1,62 @@
+// ==========================
+// 端口I/O抽象
+// ==========================
+// DISPI驱动只通过这个trait访问I/O端口：裸机上是真的in/out指令，
+// 宿主模拟器和测试里换成模拟的寄存器（见dispi::FakeDispi）。
+
+/// x86 I/O端口读写
+pub trait PortIo {
+    /// # Safety
+    /// 读端口可能有副作用，调用方要确认端口属于要操作的设备。
+    unsafe fn read_u16(&mut self, port: u16) -> u16;
+    /// # Safety
+    /// 同read_u16。
+    unsafe fn write_u16(&mut self, port: u16, value: u16);
+    /// # Safety
+    /// 同read_u16。
+    unsafe fn read_u32(&mut self, port: u16) -> u32;
+    /// # Safety
+    /// 同read_u16。
+    unsafe fn write_u32(&mut self, port: u16, value: u32);
+}
+
+impl<T: PortIo + ?Sized> PortIo for &mut T {
+    unsafe fn read_u16(&mut self, port: u16) -> u16 {
+        (**self).read_u16(port)
+    }
+
+    unsafe fn write_u16(&mut self, port: u16, value: u16) {
+        (**self).write_u16(port, value)
+    }
+
+    unsafe fn read_u32(&mut self, port: u16) -> u32 {
+        (**self).read_u32(port)
+    }
+
+    unsafe fn write_u32(&mut self, port: u16, value: u32) {
+        (**self).write_u32(port, value)
+    }
+}
+
+/// 真实硬件端口（in/out指令，只在裸机上可用）
+#[cfg(target_os = "none")]
+pub struct HardwarePorts;
+
+#[cfg(target_os = "none")]
+impl PortIo for HardwarePorts {
+    unsafe fn read_u16(&mut self, port: u16) -> u16 {
+        x86_64::instructions::port::Port::<u16>::new(port).read()
+    }
+
+    unsafe fn write_u16(&mut self, port: u16, value: u16) {
+        x86_64::instructions::port::Port::<u16>::new(port).write(value)
+    }
+
+    unsafe fn read_u32(&mut self, port: u16) -> u32 {
+        x86_64::instructions::port::Port::<u32>::new(port).read()
+    }
+
+    unsafe fn write_u32(&mut self, port: u16, value: u32) {
+        x86_64::instructions::port::Port::<u32>::new(port).write(value)
+    }
+}
@@ -0,0 +1,110 @@
+// ==========================
+// VESA后端：使用引导程序设置好的线性帧缓冲区
+// ==========================
+// VBE切换模式要调实模式BIOS，进入长模式后做不到，所以这里只有引导程序选好的那一个模式。
+
+use crate::Framebuffer;
+use common::{kernel, ModeList, PixelFormat, Rect, VideoMode};
+
+/// VESA VBE模式信息（从内核获取，布局和内核的结构体一致）
+///
+/// 内核只给出分辨率、每像素位数和帧缓冲区地址，没有每行字节数和通道位置，
+/// 所以按位数取常见的RGB布局，并假设行尾没有填充。
+#[derive(Debug, Clone)]
+#[repr(C)]
+struct VesaInfo {
+    width: u16,
+    height: u16,
+    bpp: u8,
+    fb_phys: u64,
+}
+
+impl VesaInfo {
+    /// 由VBE信息得到像素格式；不支持的模式（比如8bpp调色板）返回None
+    fn pixel_format(&self) -> Option<PixelFormat> {
+        let pitch = self.width as u32 * (self.bpp as u32).div_ceil(8);
+        let format = PixelFormat::from_bpp(self.bpp, pitch)?;
+        format.is_valid(self.width).then_some(format)
+    }
+}
+
+// ==========================
+// 与内核交互：获取VESA信息
+// ==========================
+/// 从内核获取VESA VBE模式信息（引导程序已在实模式下初始化VESA）
+unsafe fn get_vesa_info_from_kernel() -> Option<VesaInfo> {
+    // 内核提供的接口：返回VESA信息结构体指针
+    extern "C" {
+        fn kernel_get_vesa_info() -> *const VesaInfo;
+    }
+    let vesa_ptr = kernel_get_vesa_info();
+    if vesa_ptr.is_null() {
+        return None; // 内核未提供VESA信息（如显卡不支持）
+    }
+    Some((*vesa_ptr).clone()) // 复制内核提供的VESA信息
+}
+
+// ==========================
+// 帧缓冲区映射：物理→虚拟
+// ==========================
+/// 将显卡帧缓冲区物理地址映射到用户态虚拟地址
+unsafe fn map_framebuffer(phys_addr: u64, size: usize) -> *mut u8 {
+    // 调用内核的内存映射API：phys_addr → 虚拟地址
+    kernel::map_phys(phys_addr, size)
+}
+
+/// 支持的模式：只有引导程序设置的当前模式
+pub unsafe fn modes() -> ModeList {
+    let mut modes = ModeList::new();
+    if let Some(vesa) = get_vesa_info_from_kernel() {
+        if vesa.pixel_format().is_some() {
+            modes.push(VideoMode { width: vesa.width, height: vesa.height, bpp: vesa.bpp });
+        }
+    }
+    modes
+}
+
+/// 初始化：映射引导程序设置的模式（mode只会是modes()里的那一个）
+pub unsafe fn set_mode(_mode: VideoMode) -> Option<Framebuffer> {
+    // 1. 从内核获取VESA信息
+    let vesa = get_vesa_info_from_kernel()?;
+    // 2. 确定像素格式，帧缓冲区大小按每行字节数算
+    let format = vesa.pixel_format()?;
+    let fb_size = format.buffer_size(vesa.height);
+    // 3. 映射帧缓冲区到用户态
+    let virt = map_framebuffer(vesa.fb_phys, fb_size);
+    if virt.is_null() {
+        return None;
+    }
+    Some(Framebuffer { phys: vesa.fb_phys, virt, width: vesa.width, height: vesa.height, format, pages: 1, front: 0 })
+}
+
+// ==========================
+// 硬件刷新：通知显卡更新屏幕
+// ==========================
+/// 触发屏幕刷新（部分VESA显卡需要显式调用）；rects为需要刷新的区域，空表示整个屏幕
+pub unsafe fn flush_screen(_rects: &[Rect]) {
+    // 对于VESA VBE，多数情况下写入帧缓冲区后自动刷新
+    // 如需显式刷新，可通过out指令操作显卡寄存器（示例）：
+    // x86_64::instructions::port::outw(0x3D4, 0x0A); // 具体端口因显卡而异
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vesa(bpp: u8) -> VesaInfo {
+        VesaInfo { width: 640, height: 480, bpp, fb_phys: 0xE000_0000 }
+    }
+
+    #[test]
+    fn derives_format_from_bpp() {
+        for (bpp, pitch, green_size) in [(15, 1280, 5), (16, 1280, 6), (24, 1920, 8), (32, 2560, 8)] {
+            let format = vesa(bpp).pixel_format().unwrap();
+            assert_eq!((format.bpp, format.pitch, format.green.size), (bpp, pitch, green_size));
+            assert_eq!(format.buffer_size(480), pitch as usize * 480);
+        }
+        // 8bpp是调色板模式，不支持
+        assert!(vesa(8).pixel_format().is_none());
+    }
+}
@@ -0,0 +1,65 @@
+#!/usr/bin/env python3
+# ==========================
+# 从GNU Unifont生成CJK点阵字体
+# ==========================
+# 用法：unifont-subset.py unifont-15.1.05.hex [--all] > cjk-ui-16.bdf
+#   unifont的.hex每行是"码位:点阵"，32个十六进制数是8×16的字形，64个是16×16的。
+#   默认取GB2312里的全部汉字，加上CJK标点和全角字符；--all取整个CJK统一汉字区（含扩展A）。
+# 生成的BDF和cjk-ui-16.bdf格式一样（16像素，基线在顶上14像素处），直接覆盖它就行。
+# Unifont的授权是OFL-1.1或GPL-2.0+（带字体例外），生成的文件跟着原字体的授权。
+
+import sys
+
+ASCENT, DESCENT = 14, 2
+
+
+def wanted(codepoint, everything):
+    if 0x3000 <= codepoint <= 0x303F or 0xFF00 <= codepoint <= 0xFFEF:
+        return True  # CJK标点、全角字符
+    if not (0x3400 <= codepoint <= 0x4DBF or 0x4E00 <= codepoint <= 0x9FFF):
+        return False
+    if everything:
+        return True
+    try:
+        chr(codepoint).encode("gb2312")
+        return True
+    except UnicodeEncodeError:
+        return False
+
+
+def main():
+    args = [a for a in sys.argv[1:] if not a.startswith("--")]
+    everything = "--all" in sys.argv[1:]
+    if len(args) != 1:
+        sys.exit("用法：unifont-subset.py unifont.hex [--all] > cjk-ui-16.bdf")
+    glyphs = []
+    with open(args[0], encoding="ascii") as hex_file:
+        for line in hex_file:
+            code, _, bits = line.strip().partition(":")
+            if not bits or len(bits) not in (32, 64):
+                continue
+            codepoint = int(code, 16)
+            if wanted(codepoint, everything):
+                glyphs.append((codepoint, bits))
+    out = sys.stdout
+    out.write("STARTFONT 2.1\n")
+    out.write("COMMENT CJK subset of GNU Unifont (OFL-1.1 or GPL-2.0+ with font embedding exception)\n")
+    out.write("FONT -gnu-unifont-medium-r-normal--16-160-75-75-c-160-iso10646-1\n")
+    out.write("SIZE 16 75 75\n")
+    out.write("FONTBOUNDINGBOX 16 16 0 -%d\n" % DESCENT)
+    out.write("STARTPROPERTIES 2\nFONT_ASCENT %d\nFONT_DESCENT %d\nENDPROPERTIES\n" % (ASCENT, DESCENT))
+    out.write("CHARS %d\n" % len(glyphs))
+    for codepoint, bits in glyphs:
+        width = len(bits) // 4  # 每行 width/4 个十六进制数，一共16行
+        digits = width // 4
+        out.write("STARTCHAR uni%04X\nENCODING %d\n" % (codepoint, codepoint))
+        out.write("SWIDTH %d 0\nDWIDTH %d 0\n" % (width * 1000 // 16, width))
+        out.write("BBX %d 16 0 -%d\nBITMAP\n" % (width, DESCENT))
+        for row in range(16):
+            out.write(bits[row * digits:(row + 1) * digits] + "\n")
+        out.write("ENDCHAR\n")
+    out.write("ENDFONT\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,115 @@
+// ==========================
+// 裁剪栈
+// ==========================
+// 每个命令缓冲区各有一个裁剪栈（PushClip/PopClip），坐标相对当前绘图目标，切换目标时清空。
+// 栈顶是所有压入矩形的交集；画的时候还要和表面本身允许的区域（见Compositor::canvas）取交集。
+
+use common::Rect;
+
+/// 最多嵌套多少层（更深的PushClip不再缩小裁剪区，但照样计数，保证和PopClip配对）
+const MAX_DEPTH: usize = 16;
+
+pub(crate) struct ClipStack {
+    rects: [Rect; MAX_DEPTH], // rects[i]：压入i+1层后的裁剪区（已和外层取交集，空矩形表示什么都画不了）
+    depth: usize,
+}
+
+impl ClipStack {
+    pub(crate) const fn new() -> Self {
+        ClipStack { rects: [Rect::new(0, 0, 0, 0); MAX_DEPTH], depth: 0 }
+    }
+
+    pub(crate) fn push(&mut self, rect: Rect) {
+        let clip = match self.top() {
+            Some(top) => rect.intersect(&top).unwrap_or(Rect::new(top.x, top.y, 0, 0)),
+            None => rect,
+        };
+        if self.depth < MAX_DEPTH {
+            self.rects[self.depth] = clip;
+        }
+        self.depth = self.depth.saturating_add(1);
+    }
+
+    /// 多余的PopClip忽略
+    pub(crate) fn pop(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.depth = 0;
+    }
+
+    /// 当前裁剪区；没压入过矩形时返回None（不限制）
+    pub(crate) fn top(&self) -> Option<Rect> {
+        match self.depth {
+            0 => None,
+            depth => Some(self.rects[depth.min(MAX_DEPTH) - 1]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_clips_intersect() {
+        let mut stack = ClipStack::new();
+        assert_eq!(stack.top(), None);
+        stack.push(Rect::new(0, 0, 100, 100));
+        stack.push(Rect::new(50, 20, 100, 30));
+        assert_eq!(stack.top(), Some(Rect::new(50, 20, 50, 30)));
+        stack.push(Rect::new(60, 0, 10, 200));
+        assert_eq!(stack.top(), Some(Rect::new(60, 20, 10, 30)));
+        stack.pop();
+        assert_eq!(stack.top(), Some(Rect::new(50, 20, 50, 30)));
+        stack.pop();
+        assert_eq!(stack.top(), Some(Rect::new(0, 0, 100, 100)));
+    }
+
+    #[test]
+    fn disjoint_clip_draws_nothing() {
+        let mut stack = ClipStack::new();
+        stack.push(Rect::new(0, 0, 10, 10));
+        stack.push(Rect::new(20, 20, 10, 10));
+        assert!(stack.top().unwrap().is_empty());
+        // 空裁剪区里再压入什么都还是空的
+        stack.push(Rect::new(0, 0, 100, 100));
+        assert!(stack.top().unwrap().is_empty());
+        stack.pop();
+        stack.pop();
+        assert_eq!(stack.top(), Some(Rect::new(0, 0, 10, 10)));
+    }
+
+    #[test]
+    fn extra_pops_are_ignored() {
+        let mut stack = ClipStack::new();
+        stack.pop();
+        assert_eq!(stack.top(), None);
+        stack.push(Rect::new(5, 5, 10, 10));
+        stack.pop();
+        stack.pop();
+        assert_eq!(stack.top(), None);
+        // 之后的PushClip照常从头算
+        stack.push(Rect::new(1, 2, 3, 4));
+        assert_eq!(stack.top(), Some(Rect::new(1, 2, 3, 4)));
+    }
+
+    #[test]
+    fn pushes_beyond_max_depth_stay_paired() {
+        let mut stack = ClipStack::new();
+        for i in 0..MAX_DEPTH as u16 + 4 {
+            stack.push(Rect::new(i, 0, 100, 100));
+        }
+        let deepest = Rect::new(MAX_DEPTH as u16 - 1, 0, 100 - MAX_DEPTH as u16 + 1, 100);
+        assert_eq!(stack.top(), Some(deepest));
+        for _ in 0..4 {
+            stack.pop();
+        }
+        assert_eq!(stack.top(), Some(deepest));
+        stack.pop();
+        assert_eq!(stack.top(), Some(Rect::new(MAX_DEPTH as u16 - 2, 0, 100 - MAX_DEPTH as u16 + 2, 100)));
+        stack.clear();
+        assert_eq!(stack.top(), None);
+    }
+}
@@ -0,0 +1,314 @@
+// ==========================
+// 离屏表面与合成
+// ==========================
+// 每个窗口有自己的表面（32位预乘alpha的0xAARRGGBB，放在共享内存里），0号表面是铺满屏幕的桌面背景（不透明）。
+// 绘图命令只画在表面上；呈现前按场景（背景在最下，其余层从下到上）
+// 把脏区域重新合成到后台缓冲区（source-over混合），所以窗口互相遮挡、移动时不需要客户端重画。
+// 窗口表面的共享内存也可以交给所属的应用直接写像素，写完用RenderDamageSurface告诉渲染服务改了哪里。
+// 所属的应用用的坐标都相对内容区左上角（标题栏下面），创建者（WM）和背景用表面坐标。
+// 背景表面的创建者是桌面进程（注册为"desktop"服务的进程），只有它能画背景。
+
+use common::{blend, kernel::SharedMemory, Layer, LayerList, Pid, Pixel, PixelFormat, Rect, Rgb, ShmHandle, SurfaceId};
+use core::ptr;
+
+/// 桌面背景表面（只有桌面进程能画）
+pub(crate) const ROOT: SurfaceId = 0;
+/// 最多同时存在多少个窗口表面
+const MAX_SURFACES: usize = 32;
+/// 合成时一次处理一行里的多少个像素（中间结果放在栈上）
+const COMPOSE_CHUNK: usize = 512;
+/// 背景表面清屏用的颜色
+const OPAQUE_BLACK: Pixel = 0xFF00_0000;
+
+/// 一块可以画的表面（每像素一个预乘的0xAARRGGBB，每行width个）
+#[derive(Clone, Copy)]
+pub(crate) struct Canvas {
+    pub pixels: *mut Pixel,
+    pub width: u16,
+    pub clip: Rect, // 只能画在这里面（表面坐标，总在表面以内）
+    pub origin_x: u16, // 发送者坐标的(0, 0)在表面上的位置
+    pub origin_y: u16,
+}
+
+impl Canvas {
+    /// 发送者坐标 → 表面坐标
+    pub fn to_surface(self, x: u16, y: u16) -> (u16, u16) {
+        (x.saturating_add(self.origin_x), y.saturating_add(self.origin_y))
+    }
+
+    /// 把预乘像素src混合到表面坐标(x, y)处（裁剪区外的忽略）
+    pub unsafe fn blend_pixel(&self, x: u16, y: u16, src: Pixel) {
+        if !self.clip.contains(x, y) {
+            return;
+        }
+        let dst = self.pixels.add(y as usize * self.width as usize + x as usize);
+        *dst = blend(*dst, src);
+    }
+}
+
+/// 离屏表面
+struct Surface {
+    id: SurfaceId,
+    creator: Pid,     // 创建者（通常是WM，用来画边框）
+    owner: Pid,       // 窗口所属的应用（可以映射像素直接写）
+    content: Rect,    // 所属的应用能画的区域（内容区，不含标题栏）
+    memory: SharedMemory, // 像素（背景表面换分辨率时够用就复用）
+    width: u16,
+    height: u16,
+}
+
+impl Surface {
+    /// 分配width×height的表面（初始为全透明），内存不足时返回None
+    unsafe fn alloc(id: SurfaceId, width: u16, height: u16, creator: Pid, owner: Pid, content: Rect) -> Option<Surface> {
+        let memory = SharedMemory::alloc(PixelFormat::xrgb8888(width).buffer_size(height))?;
+        ptr::write_bytes(memory.as_ptr(), 0, memory.size());
+        Some(Surface { id, creator, owner, content, memory, width, height })
+    }
+
+    fn pixels(&self) -> *mut Pixel {
+        self.memory.as_ptr() as *mut Pixel
+    }
+
+    /// 放在(x, y)处时，把落在屏幕上span（一行）里的部分混合到row里（row[0]对应span.x）；
+    /// opaque为true时直接复制（背景表面）
+    unsafe fn blend_into(&self, x: u16, y: u16, span: Rect, row: &mut [Pixel], opaque: bool) {
+        let Some(area) = self.placed(x, y).intersect(&span) else {
+            return;
+        };
+        let start = (area.y - y) as usize * self.width as usize + (area.x - x) as usize;
+        let src = core::slice::from_raw_parts(self.pixels().add(start), area.w as usize);
+        let dst = &mut row[(area.x - span.x) as usize..][..area.w as usize];
+        if opaque {
+            dst.copy_from_slice(src);
+            return;
+        }
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d = blend(*d, *s);
+        }
+    }
+
+    /// sender能不能访问（背景只有创建者能画，窗口表面只有创建者和所属应用能画）
+    fn allows(&self, sender: Pid) -> bool {
+        sender == self.creator || (self.id != ROOT && sender == self.owner)
+    }
+
+    /// sender能画的区域（表面坐标，左上角也是sender坐标的原点）：
+    /// 背景和创建者是整个表面，所属的应用只有内容区
+    fn area_for(&self, sender: Pid) -> Rect {
+        let whole = Rect::new(0, 0, self.width, self.height);
+        if self.id == ROOT || sender == self.creator {
+            return whole;
+        }
+        self.content.intersect(&whole).unwrap_or(Rect::new(self.content.x.min(self.width), self.content.y.min(self.height), 0, 0))
+    }
+
+    /// 放在(x, y)时在屏幕上占的区域
+    fn placed(&self, x: u16, y: u16) -> Rect {
+        Rect::new(x, y, self.width, self.height)
+    }
+}
+
+/// 所有表面和当前场景
+pub(crate) struct Compositor {
+    root: Option<Surface>,
+    root_creator: Pid, // 能画背景的进程（0：还不知道，只有渲染服务自己能画）
+    surfaces: [Option<Surface>; MAX_SURFACES],
+    next_id: SurfaceId,
+    scene: LayerList,
+}
+
+impl Compositor {
+    pub(crate) const fn new() -> Self {
+        Compositor { root: None, root_creator: 0, surfaces: [const { None }; MAX_SURFACES], next_id: 1, scene: LayerList::new() }
+    }
+
+    /// 屏幕大小变了：背景表面换成新大小并清成黑色
+    pub(crate) unsafe fn resize_root(&mut self, width: u16, height: u16) -> bool {
+        let size = PixelFormat::xrgb8888(width).buffer_size(height);
+        match &mut self.root {
+            Some(root) if root.memory.size() >= size => {
+                root.width = width;
+                root.height = height;
+                root.content = Rect::new(0, 0, width, height);
+            }
+            // 旧的内存：内核没有释放接口，进程退出时才回收
+            _ => self.root = Surface::alloc(ROOT, width, height, self.root_creator, 0, Rect::new(0, 0, width, height)),
+        }
+        let Some(root) = &self.root else {
+            return false;
+        };
+        core::slice::from_raw_parts_mut(root.pixels(), width as usize * height as usize).fill(OPAQUE_BLACK);
+        true
+    }
+
+    /// 从现在起只有pid能画背景
+    pub(crate) fn set_root_creator(&mut self, pid: Pid) {
+        self.root_creator = pid;
+        if let Some(root) = &mut self.root {
+            root.creator = pid;
+        }
+    }
+
+    /// 新建窗口表面，返回编号和共享内存句柄；表满或内存不足时返回None
+    pub(crate) unsafe fn create(
+        &mut self,
+        width: u16,
+        height: u16,
+        creator: Pid,
+        owner: Pid,
+        content: Rect,
+    ) -> Option<(SurfaceId, ShmHandle)> {
+        let slot = self.surfaces.iter().position(Option::is_none)?;
+        let surface = Surface::alloc(self.next_id, width, height, creator, owner, content)?;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let result = (surface.id, surface.memory.handle());
+        self.surfaces[slot] = Some(surface);
+        Some(result)
+    }
+
+    /// 窗口表面改成width×height（只有创建者能改），清成全透明，返回共享内存句柄；
+    /// 原来的内存够用就接着用，不够时按1.5倍多分配一些，拖边框时不用每一步都重新分配；
+    /// 内存不足或无权修改时返回None，表面保持原样
+    pub(crate) unsafe fn resize(
+        &mut self,
+        id: SurfaceId,
+        sender: Pid,
+        width: u16,
+        height: u16,
+        content: Rect,
+    ) -> Option<ShmHandle> {
+        let surface = self.surfaces.iter_mut().flatten().find(|surface| surface.id == id && surface.creator == sender)?;
+        let size = PixelFormat::xrgb8888(width).buffer_size(height);
+        if surface.memory.size() < size {
+            // 旧的内存：内核没有释放接口，进程退出时才回收
+            let memory = SharedMemory::alloc(size.max(surface.memory.size() / 2 * 3))?;
+            surface.memory = memory;
+        }
+        ptr::write_bytes(surface.memory.as_ptr(), 0, surface.memory.size());
+        surface.width = width;
+        surface.height = height;
+        surface.content = content;
+        Some(surface.memory.handle())
+    }
+
+    /// 销毁窗口表面（只有创建者能销毁），空出表里的位置；返回它在屏幕上占过的区域（不在场景里时为None）。
+    /// 共享内存不再使用（内核没有释放接口，进程退出时才回收；也不给别的表面复用，原来映射过的应用还能写）
+    pub(crate) fn destroy(&mut self, id: SurfaceId, sender: Pid) -> Option<Rect> {
+        let slot = self.surfaces.iter().position(|slot| slot.as_ref().is_some_and(|surface| surface.id == id && surface.creator == sender))?;
+        let placed = self.placed(id);
+        self.surfaces[slot] = None;
+        let mut scene = LayerList::new();
+        for layer in self.scene.as_slice().iter().filter(|layer| layer.surface != id) {
+            scene.push(*layer);
+        }
+        self.scene = scene;
+        placed
+    }
+
+    fn find(&self, id: SurfaceId) -> Option<&Surface> {
+        if id == ROOT {
+            return self.root.as_ref();
+        }
+        self.surfaces.iter().flatten().find(|surface| surface.id == id)
+    }
+
+    /// sender能画的表面；背景只有桌面进程能画，窗口表面只有创建者和所属应用能画（应用只能画内容区）
+    pub(crate) fn canvas(&self, id: SurfaceId, sender: Pid) -> Option<Canvas> {
+        let surface = self.find(id).filter(|surface| surface.allows(sender))?;
+        let area = surface.area_for(sender);
+        Some(Canvas {
+            pixels: surface.pixels(),
+            width: surface.width,
+            clip: area,
+            origin_x: area.x,
+            origin_y: area.y,
+        })
+    }
+
+    /// 把窗口表面的共享内存交给sender直接写像素：返回(句柄, 宽, 高, sender能写的区域)；背景表面不外借
+    pub(crate) fn share(&self, id: SurfaceId, sender: Pid) -> Option<(ShmHandle, u16, u16, Rect)> {
+        let surface = self.find(id).filter(|surface| id != ROOT && surface.allows(sender))?;
+        Some((surface.memory.handle(), surface.width, surface.height, surface.area_for(sender)))
+    }
+
+    /// 表面上的rect对应屏幕上的哪块区域；不在场景里的表面返回None
+    pub(crate) fn to_screen(&self, id: SurfaceId, rect: Rect) -> Option<Rect> {
+        if id == ROOT {
+            return Some(rect);
+        }
+        let layer = self.scene.as_slice().iter().find(|layer| layer.surface == id)?;
+        Some(rect.offset(layer.x, layer.y))
+    }
+
+    /// 表面在屏幕上占的区域；不在场景里的表面返回None
+    pub(crate) fn placed(&self, id: SurfaceId) -> Option<Rect> {
+        let surface = self.find(id)?;
+        self.to_screen(id, Rect::new(0, 0, surface.width, surface.height))
+    }
+
+    /// 换成新的场景（不存在的表面和背景表面跳过），变化了的区域交给damage
+    pub(crate) fn set_scene(&mut self, layers: &LayerList, mut damage: impl FnMut(Rect)) {
+        let mut scene = LayerList::new();
+        for layer in layers.as_slice() {
+            let duplicate = scene.as_slice().iter().any(|l| l.surface == layer.surface);
+            if layer.surface != ROOT && !duplicate && self.find(layer.surface).is_some() {
+                scene.push(*layer);
+            }
+        }
+        let old = self.scene.as_slice();
+        let new = scene.as_slice();
+        let position = |list: &[Layer], id: SurfaceId| list.iter().position(|l| l.surface == id);
+        // 位置或上下次序变了的层，旧位置和新位置都要重新合成
+        for (index, layer) in old.iter().enumerate() {
+            let Some(surface) = self.find(layer.surface) else { continue };
+            match position(new, layer.surface) {
+                Some(i) if i == index && new[i] == *layer => {}
+                Some(i) => {
+                    damage(surface.placed(layer.x, layer.y));
+                    damage(surface.placed(new[i].x, new[i].y));
+                }
+                None => damage(surface.placed(layer.x, layer.y)),
+            }
+        }
+        for layer in new.iter().filter(|l| position(old, l.surface).is_none()) {
+            if let Some(surface) = self.find(layer.surface) {
+                damage(surface.placed(layer.x, layer.y));
+            }
+        }
+        self.scene = scene;
+    }
+
+    /// 把屏幕上rect区域按场景合成到后台缓冲区dst（整个屏幕大小，像素格式是format）：
+    /// 背景不透明，上面各层按预乘alpha依次混合
+    pub(crate) unsafe fn compose(&mut self, rect: Rect, dst: *mut u8, format: &PixelFormat) {
+        let direct = format.same_pixels(&PixelFormat::xrgb8888(0));
+        let bpp = format.bytes_per_pixel();
+        let mut row = [0 as Pixel; COMPOSE_CHUNK];
+        for y in rect.y..rect.y + rect.h {
+            let mut x = rect.x;
+            while (x as u32) < rect.right() {
+                let span = Rect::new(x, y, (rect.right() - x as u32).min(COMPOSE_CHUNK as u32) as u16, 1);
+                let row = &mut row[..span.w as usize];
+                match &self.root {
+                    Some(root) => root.blend_into(0, 0, span, row, true),
+                    None => row.fill(OPAQUE_BLACK),
+                }
+                for layer in self.scene.as_slice() {
+                    if let Some(surface) = self.find(layer.surface) {
+                        surface.blend_into(layer.x, layer.y, span, row, false);
+                    }
+                }
+                let to = dst.add(format.offset(span.x, y));
+                if direct {
+                    ptr::copy_nonoverlapping(row.as_ptr() as *const u8, to, row.len() * 4);
+                } else {
+                    for (i, pixel) in row.iter().enumerate() {
+                        let color = Rgb { r: (pixel >> 16) as u8, g: (pixel >> 8) as u8, b: *pixel as u8 };
+                        format.store(to.add(i * bpp), format.encode(color));
+                    }
+                }
+                x += span.w;
+            }
+        }
+    }
+}
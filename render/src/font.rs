@@ -0,0 +1,449 @@
+// ==========================
+// 点阵字体
+// ==========================
+// 启动时加载FONT_FILES里的字体文件（按文件头识别格式）：
+//   PSF2：Linux控制台字体，所有字形一样大，可以带Unicode映射表
+//   BDF：X11的文本点阵字体，每个字形有自己的大小、位置和步进
+// 找字形时按加载顺序找第一个有这个字的字体；都没有时画一个方框（宽字符占两格），接着画后面的字。
+// 一行的高度取所有字体里最高的；PSF2的字形贴着行框顶上画，BDF的按基线（行框顶 + FONT_ASCENT）对齐。
+// 换字体只要替换fonts/目录里的文件；CJK字体用fonts/unifont-subset.py从GNU Unifont生成。
+
+use common::kernel::SharedMemory;
+
+/// 启动时加载的字体（靠前的优先）
+pub(crate) const FONT_FILES: [&[u8]; 2] = [
+    include_bytes!("../fonts/latin-8x16.psf"), // font8x8的拉丁、希腊、制表符等，放在8×16的格子里
+    include_bytes!("../fonts/cjk-ui-16.bdf"),  // CJK汉字、标点和全角字符（16×16，unifont-subset.py生成）
+];
+
+/// 最多加载几个字体
+const MAX_FONTS: usize = 4;
+/// 字形最宽多少像素（每行用一个u32）
+const MAX_GLYPH_WIDTH: u16 = 32;
+/// 还没加载字体时的行高
+const DEFAULT_LINE_HEIGHT: u16 = 16;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 1;
+const PSF2_SEPARATOR: u8 = 0xFF; // 一个字形的Unicode列表结束
+const PSF2_SEQUENCE: u8 = 0xFE; // 后面是组合字符序列（不支持，跳过）
+
+/// 一个字形
+pub(crate) struct Glyph<'a> {
+    pub advance: u16, // 画完后笔位置右移多少
+    pub left: i16,    // 点阵左边相对笔位置
+    pub top: i16,     // 点阵上边相对行框顶
+    pub width: u16,
+    pub height: u16,
+    bits: Bits<'a>,
+}
+
+/// 字形的点阵
+enum Bits<'a> {
+    Packed { data: &'a [u8], stride: usize }, // 每行stride字节，高位在左（PSF2）
+    Hex(&'a [u8]),                            // BITMAP之后的十六进制文本，每行一个（BDF）
+    Frame,                                    // 缺字时的方框
+}
+
+impl Glyph<'_> {
+    /// 逐行取点阵：每行一个u32，最高位是最左边的像素
+    pub fn rows(&self) -> impl Iterator<Item = u32> + '_ {
+        let mut lines = match self.bits {
+            Bits::Hex(text) => Some(text.split(|b| *b == b'\n')),
+            _ => None,
+        };
+        (0..self.height as usize).map(move |row| match self.bits {
+            Bits::Packed { data, stride } => {
+                let bytes = &data[row * stride..][..stride];
+                bytes.iter().take(4).enumerate().fold(0, |v, (i, b)| v | (*b as u32) << (24 - 8 * i))
+            }
+            Bits::Hex(_) => hex_row(lines.as_mut().and_then(|lines| lines.next()).unwrap_or(&[])),
+            Bits::Frame => {
+                let full = !0u32 << (32 - self.width as u32);
+                if row == 0 || row + 1 == self.height as usize {
+                    full
+                } else {
+                    (full & !(full << 1)) | 0x8000_0000
+                }
+            }
+        })
+    }
+}
+
+/// BDF的一行点阵：十六进制，第一位的最高位是最左边的像素（最多8位，忽略空白）
+fn hex_row(line: &[u8]) -> u32 {
+    let (value, digits) = line
+        .iter()
+        .filter_map(|c| (*c as char).to_digit(16))
+        .take(8)
+        .fold((0u32, 0u32), |(v, n), d| ((v << 4) | d, n + 1));
+    if digits == 0 { 0 } else { value << (32 - 4 * digits) }
+}
+
+/// 码位 → 字形的索引项（按码位排序）
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct IndexEntry {
+    codepoint: u32,
+    glyph: u32, // PSF2：第几个字形；BDF：STARTCHAR那一行在文件里的偏移
+}
+
+enum Format {
+    Psf2 { glyphs: usize, charsize: usize, width: u16, height: u16 },
+    Bdf { ascent: i16 },
+}
+
+struct Font {
+    data: &'static [u8],
+    format: Format,
+    index: &'static [IndexEntry],
+    line_height: u16,
+}
+
+/// 按行遍历文本（返回每行的偏移和去掉换行符的内容）
+fn lines(data: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut offset = 0;
+    data.split(|b| *b == b'\n').map(move |line| {
+        let start = offset;
+        offset += line.len() + 1;
+        (start, line.strip_suffix(b"\r").unwrap_or(line))
+    })
+}
+
+/// BDF一行里关键字后面的第n个整数
+fn field(line: &[u8], n: usize) -> Option<i32> {
+    let text = core::str::from_utf8(line).ok()?;
+    text.split_ascii_whitespace().nth(n + 1)?.parse().ok()
+}
+
+/// 在共享内存里放一张count项的索引（内核没有释放接口，字体一直用到进程退出）
+fn alloc_index(count: usize) -> Option<&'static mut [IndexEntry]> {
+    let memory = SharedMemory::alloc(count * core::mem::size_of::<IndexEntry>())?;
+    Some(unsafe { core::slice::from_raw_parts_mut(memory.as_ptr() as *mut IndexEntry, count) })
+}
+
+fn le_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl Font {
+    fn parse(data: &'static [u8]) -> Option<Font> {
+        if data.starts_with(&PSF2_MAGIC) {
+            Font::parse_psf2(data)
+        } else if data.starts_with(b"STARTFONT") {
+            Font::parse_bdf(data)
+        } else {
+            None
+        }
+    }
+
+    fn parse_psf2(data: &'static [u8]) -> Option<Font> {
+        let header_size = le_u32(data, 8)? as usize;
+        let flags = le_u32(data, 12)?;
+        let count = le_u32(data, 16)? as usize;
+        let charsize = le_u32(data, 20)? as usize;
+        let height = le_u32(data, 24)?;
+        let width = le_u32(data, 28)?;
+        let stride = (width as usize).div_ceil(8);
+        if count == 0 || width == 0 || width > MAX_GLYPH_WIDTH as u32 || height == 0 || charsize < stride * height as usize {
+            return None;
+        }
+        let table = header_size.checked_add(count.checked_mul(charsize)?)?;
+        if table > data.len() {
+            return None;
+        }
+        // 映射表：每个字形依次是它对应的UTF-8字符，以0xFF结束（0xFE之后是组合序列）
+        let mapping = |add: &mut dyn FnMut(u32, u32)| {
+            if flags & PSF2_HAS_UNICODE_TABLE == 0 {
+                (0..count as u32).for_each(|i| add(i, i));
+                return;
+            }
+            for (glyph, entry) in data[table..].split(|b| *b == PSF2_SEPARATOR).take(count).enumerate() {
+                let singles = entry.split(|b| *b == PSF2_SEQUENCE).next().unwrap_or(&[]);
+                for c in core::str::from_utf8(singles).unwrap_or("").chars() {
+                    add(c as u32, glyph as u32);
+                }
+            }
+        };
+        let mut total = 0;
+        mapping(&mut |_, _| total += 1);
+        let index = alloc_index(total)?;
+        let mut filled = 0;
+        mapping(&mut |codepoint, glyph| {
+            index[filled] = IndexEntry { codepoint, glyph };
+            filled += 1;
+        });
+        index.sort_unstable_by_key(|entry| entry.codepoint);
+        let index: &'static [IndexEntry] = index;
+        let format = Format::Psf2 { glyphs: header_size, charsize, width: width as u16, height: height as u16 };
+        Some(Font { data, format, index, line_height: height as u16 })
+    }
+
+    fn parse_bdf(data: &'static [u8]) -> Option<Font> {
+        let (mut ascent, mut descent, mut bounds) = (None, None, None);
+        let mut total = 0;
+        for (_, line) in lines(data) {
+            match line.split(|b| *b == b' ').next()? {
+                b"FONT_ASCENT" => ascent = field(line, 0),
+                b"FONT_DESCENT" => descent = field(line, 0),
+                b"FONTBOUNDINGBOX" => bounds = Some((field(line, 1)?, field(line, 3)?)),
+                b"ENCODING" if field(line, 0).is_some_and(|c| c >= 0) => total += 1,
+                _ => {}
+            }
+        }
+        // 没有FONT_ASCENT/FONT_DESCENT时按字体的外框算
+        let (height, bottom) = bounds.unwrap_or((0, 0));
+        let ascent = ascent.unwrap_or(height + bottom);
+        let descent = descent.unwrap_or(-bottom);
+        if total == 0 || ascent + descent <= 0 {
+            return None;
+        }
+        let index = alloc_index(total)?;
+        let mut filled = 0;
+        let mut start = 0;
+        for (offset, line) in lines(data) {
+            if line.starts_with(b"STARTCHAR") {
+                start = offset;
+            } else if line.starts_with(b"ENCODING") {
+                if let Some(codepoint) = field(line, 0).filter(|c| *c >= 0) {
+                    if filled < total {
+                        index[filled] = IndexEntry { codepoint: codepoint as u32, glyph: start as u32 };
+                        filled += 1;
+                    }
+                }
+            }
+        }
+        let index: &'static mut [IndexEntry] = &mut index[..filled];
+        index.sort_unstable_by_key(|entry| entry.codepoint);
+        let format = Format::Bdf { ascent: ascent as i16 };
+        Some(Font { data, format, index, line_height: (ascent + descent) as u16 })
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph<'static>> {
+        let found = self.index.binary_search_by_key(&(c as u32), |entry| entry.codepoint).ok()?;
+        let glyph = self.index[found].glyph as usize;
+        match self.format {
+            Format::Psf2 { glyphs, charsize, width, height } => {
+                let data = &self.data[glyphs + glyph * charsize..][..charsize];
+                let bits = Bits::Packed { data, stride: (width as usize).div_ceil(8) };
+                Some(Glyph { advance: width, left: 0, top: 0, width, height, bits })
+            }
+            Format::Bdf { ascent } => self.bdf_glyph(glyph, ascent),
+        }
+    }
+
+    /// 从STARTCHAR那一行开始读一个BDF字形
+    fn bdf_glyph(&self, start: usize, ascent: i16) -> Option<Glyph<'static>> {
+        let data = self.data;
+        let (mut advance, mut bbx) = (None, None);
+        for (offset, line) in lines(&data[start..]) {
+            if line.starts_with(b"DWIDTH") {
+                advance = field(line, 0);
+            } else if line.starts_with(b"BBX") {
+                bbx = Some((field(line, 0)?, field(line, 1)?, field(line, 2)?, field(line, 3)?));
+            } else if line.starts_with(b"BITMAP") {
+                let (width, height, left, bottom) = bbx?;
+                if !(0..=MAX_GLYPH_WIDTH as i32).contains(&width) || height < 0 {
+                    return None;
+                }
+                let bitmap = start + offset + line.len() + 1;
+                let bits = Bits::Hex(data.get(bitmap..)?);
+                return Some(Glyph {
+                    advance: advance.unwrap_or(width).max(0) as u16,
+                    left: left as i16,
+                    top: ascent - (bottom + height) as i16,
+                    width: width as u16,
+                    height: height as u16,
+                    bits,
+                });
+            } else if line.starts_with(b"ENDCHAR") {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+/// 按顺序查找的一组字体
+pub(crate) struct FontSet {
+    fonts: [Option<Font>; MAX_FONTS],
+    line_height: u16,
+}
+
+impl FontSet {
+    pub(crate) const fn new() -> Self {
+        FontSet { fonts: [const { None }; MAX_FONTS], line_height: 0 }
+    }
+
+    /// 加载一个字体文件（格式不认识、文件损坏或已经满了时返回false）
+    pub(crate) fn load(&mut self, data: &'static [u8]) -> bool {
+        let Some(slot) = self.fonts.iter_mut().find(|slot| slot.is_none()) else {
+            return false;
+        };
+        let Some(font) = Font::parse(data) else {
+            return false;
+        };
+        self.line_height = self.line_height.max(font.line_height);
+        *slot = Some(font);
+        true
+    }
+
+    /// 一行文字的高度
+    pub(crate) fn line_height(&self) -> u16 {
+        if self.line_height == 0 { DEFAULT_LINE_HEIGHT } else { self.line_height }
+    }
+
+    /// c的字形；哪个字体都没有时返回方框（控制字符返回None，不占位置）
+    pub(crate) fn glyph(&self, c: char) -> Option<Glyph<'static>> {
+        if c.is_control() {
+            return None;
+        }
+        if let Some(glyph) = self.fonts.iter().flatten().find_map(|font| font.glyph(c)) {
+            return Some(glyph);
+        }
+        let width = if is_wide(c) { 16 } else { 8 };
+        let height = self.line_height().saturating_sub(4);
+        Some(Glyph { advance: width, left: 1, top: 2, width: width - 2, height, bits: Bits::Frame })
+    }
+}
+
+/// 东亚文字里占两格的字符（缺字方框按这个决定宽度）
+pub(crate) fn is_wide(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6 | 0x20000..=0x3FFFD)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// 8×2的PSF2字体：第i个字形两行是[i, 0x80 | i]；table为None时没有Unicode映射表
+    fn psf2(count: u32, table: Option<&[u8]>) -> Vec<u8> {
+        let flags = if table.is_some() { PSF2_HAS_UNICODE_TABLE } else { 0 };
+        let mut data = PSF2_MAGIC.to_vec();
+        for field in [0, 32, flags, count, 2, 2, 8] {
+            data.extend(u32::to_le_bytes(field));
+        }
+        for i in 0..count as u8 {
+            data.extend([i, 0x80 | i]);
+        }
+        data.extend(table.unwrap_or(&[]));
+        data
+    }
+
+    fn leak(data: Vec<u8>) -> &'static [u8] {
+        data.leak()
+    }
+
+    fn rows(glyph: &Glyph) -> Vec<u32> {
+        glyph.rows().collect()
+    }
+
+    const BDF: &str = "STARTFONT 2.1\n\
+        FONTBOUNDINGBOX 16 16 0 -2\n\
+        FONT_ASCENT 14\n\
+        FONT_DESCENT 2\n\
+        CHARS 2\n\
+        STARTCHAR uni6C49\n\
+        ENCODING 27721\n\
+        DWIDTH 16 0\n\
+        BBX 16 2 0 3\n\
+        BITMAP\n\
+        FFFF\n\
+        8001\n\
+        ENDCHAR\n\
+        STARTCHAR broken\n\
+        ENCODING 66\n\
+        DWIDTH 8 0\n\
+        ENDCHAR\n\
+        ENDFONT\n";
+
+    #[test]
+    fn psf2_unicode_table() {
+        // 字形0是A，字形1是B和ß，字形2是x（0xFE后面的组合序列忽略）
+        let table = [&b"A\xFFB"[..], "ß".as_bytes(), b"\xFFx\xFEe", "\u{301}".as_bytes(), b"\xFF"].concat();
+        let font = Font::parse(leak(psf2(3, Some(&table)))).unwrap();
+        assert_eq!(font.line_height, 2);
+        let glyph = font.glyph('ß').unwrap();
+        assert_eq!((glyph.advance, glyph.width, glyph.height), (8, 8, 2));
+        assert_eq!(rows(&glyph), [0x0100_0000, 0x8100_0000]);
+        assert_eq!(rows(&font.glyph('A').unwrap()), [0, 0x8000_0000]);
+        assert_eq!(rows(&font.glyph('x').unwrap()), [0x0200_0000, 0x8200_0000]);
+        assert!(font.glyph('e').is_none() && font.glyph('C').is_none());
+    }
+
+    #[test]
+    fn psf2_without_table_maps_glyph_numbers() {
+        let font = Font::parse(leak(psf2(2, None))).unwrap();
+        assert_eq!(rows(&font.glyph('\u{1}').unwrap()), [0x0100_0000, 0x8100_0000]);
+        assert!(font.glyph('\u{2}').is_none());
+    }
+
+    #[test]
+    fn psf2_rejects_bad_headers() {
+        let valid = psf2(2, None);
+        // 文件头不完整、字形数据不够
+        assert!(Font::parse(leak(valid[..20].to_vec())).is_none());
+        assert!(Font::parse(leak(valid[..valid.len() - 1].to_vec())).is_none());
+        // 魔数不对
+        let mut bad_magic = valid.clone();
+        bad_magic[3] ^= 1;
+        assert!(Font::parse(leak(bad_magic)).is_none());
+        // 字形个数为0或大得放不下（乘法溢出也不能越界）
+        for count in [0, 3, u32::MAX] {
+            let mut data = valid.clone();
+            data[16..20].copy_from_slice(&count.to_le_bytes());
+            assert!(Font::parse(leak(data)).is_none(), "{count}");
+        }
+        // 太宽、字形比点阵小
+        let mut wide = valid.clone();
+        wide[28..32].copy_from_slice(&33u32.to_le_bytes());
+        assert!(Font::parse(leak(wide)).is_none());
+        let mut small = valid;
+        small[20..24].copy_from_slice(&1u32.to_le_bytes());
+        assert!(Font::parse(leak(small)).is_none());
+    }
+
+    #[test]
+    fn bdf_glyph_metrics() {
+        let font = Font::parse(BDF.as_bytes()).unwrap();
+        assert_eq!(font.line_height, 16);
+        let glyph = font.glyph('汉').unwrap();
+        // 点阵底边在基线上方3像素：top = 14 - (3 + 2)
+        assert_eq!((glyph.advance, glyph.left, glyph.top, glyph.width, glyph.height), (16, 0, 9, 16, 2));
+        assert_eq!(rows(&glyph), [0xFFFF_0000, 0x8001_0000]);
+        // 没有BITMAP的字形当作没有
+        assert!(font.glyph('B').is_none());
+    }
+
+    #[test]
+    fn bdf_rejects_broken_files() {
+        assert!(Font::parse(b"STARTFONT 2.1\nFONT_ASCENT 14\nFONT_DESCENT 2\nENDFONT\n").is_none());
+        assert!(Font::parse(b"STARTFONT 2.1\nFONT_ASCENT 0\nFONT_DESCENT 0\nENCODING 65\n").is_none());
+        assert!(Font::parse(&BDF.as_bytes()[1..]).is_none());
+    }
+
+    #[test]
+    fn font_set_falls_back() {
+        let mut fonts = FontSet::new();
+        assert_eq!(fonts.line_height(), DEFAULT_LINE_HEIGHT);
+        assert!(!fonts.load(b"not a font"));
+        assert!(fonts.load(leak(psf2(1, Some(&[b'A', PSF2_SEPARATOR])))));
+        assert!(fonts.load(BDF.as_bytes()));
+        assert_eq!(fonts.line_height(), 16);
+        // 按加载顺序找，都没有时画方框（宽字符占两格）
+        assert_eq!(fonts.glyph('A').unwrap().height, 2);
+        assert_eq!(fonts.glyph('汉').unwrap().advance, 16);
+        let frame = fonts.glyph('Z').unwrap();
+        assert_eq!((frame.advance, frame.width, frame.height), (8, 6, 12));
+        assert_eq!(rows(&frame)[..2], [0xFC00_0000, 0x8400_0000]);
+        assert_eq!(fonts.glyph('字').unwrap().advance, 16);
+        assert!(fonts.glyph('\n').is_none());
+    }
+}
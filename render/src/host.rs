@@ -0,0 +1,45 @@
+// ==========================
+// 宿主上的单元测试用的内核接口
+// ==========================
+// 字体索引、字形缓存都放在共享内存里；测试时没有内核，用堆内存代替：句柄就是内存的地址，
+// 记下是哪个进程分配的（run_as切换），客户端的命令缓冲区按它检查。
+
+extern crate std;
+
+use common::Pid;
+use core::cell::{Cell, RefCell};
+use std::{
+    alloc::{alloc_zeroed, Layout},
+    vec::Vec,
+};
+
+std::thread_local! {
+    static PID: Cell<Pid> = const { Cell::new(1) };
+    static SHM: RefCell<Vec<(u64, usize, Pid)>> = const { RefCell::new(Vec::new()) }; // 地址、大小、分配者
+}
+
+/// 之后本线程当作pid这个进程分配共享内存
+pub(crate) fn run_as(pid: Pid) {
+    PID.with(|current| current.set(pid));
+}
+
+#[no_mangle]
+extern "C" fn shm_alloc(size: usize) -> u64 {
+    let handle = match Layout::from_size_align(size, 4096) {
+        Ok(layout) if size > 0 => unsafe { alloc_zeroed(layout) as u64 },
+        _ => return 0,
+    };
+    SHM.with(|shm| shm.borrow_mut().push((handle, size, PID.with(Cell::get))));
+    handle
+}
+
+#[no_mangle]
+extern "C" fn shm_owner(handle: u64, size: usize) -> Pid {
+    let inside = |&&(base, len, _): &&(u64, usize, Pid)| handle >= base && handle.saturating_add(size as u64) <= base + len as u64;
+    SHM.with(|shm| shm.borrow().iter().find(inside).map_or(0, |&(_, _, owner)| owner))
+}
+
+#[no_mangle]
+extern "C" fn map_phys(phys_addr: u64, _size: usize) -> *mut u8 {
+    phys_addr as *mut u8
+}
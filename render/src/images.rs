@@ -0,0 +1,133 @@
+// ==========================
+// 位图（Blit）
+// ==========================
+// 客户端把解码好的位图放在共享内存里（common::image::Image），blit命令只带句柄和大小。
+// 第一次用到时映射，之后复用映射（和命令缓冲区一样按客户端记）。
+// 缩放取最近的像素：目标上每个像素对应位图里的(dx×width/w, dy×height/h)。
+
+use crate::compositor::Canvas;
+use common::{blend, image::BlitMode, kernel::SharedMemory, unpremultiply, Pid, Pixel, Rect, Rgba, ShmHandle};
+
+/// 最多同时记住多少张位图（满了按轮转替换最早的）
+const MAX_IMAGES: usize = 32;
+
+/// 映射好的位图
+#[derive(Clone, Copy)]
+pub(crate) struct Source {
+    pixels: *const Pixel,
+    width: u16,
+    height: u16,
+}
+
+struct Slot {
+    owner: Pid,
+    memory: SharedMemory,
+    source: Source,
+}
+
+pub(crate) struct ImageTable {
+    slots: [Option<Slot>; MAX_IMAGES],
+    next_victim: usize,
+}
+
+impl ImageTable {
+    pub(crate) const fn new() -> Self {
+        ImageTable { slots: [const { None }; MAX_IMAGES], next_victim: 0 }
+    }
+
+    /// 找到（或映射）owner的位图；内存不是owner分配的、句柄无效或大小为0时返回None
+    pub(crate) unsafe fn get(&mut self, owner: Pid, image: ShmHandle, width: u16, height: u16) -> Option<Source> {
+        let matches = |slot: &&Slot| {
+            slot.owner == owner && slot.memory.handle() == image && slot.source.width == width && slot.source.height == height
+        };
+        if let Some(slot) = self.slots.iter().flatten().find(matches) {
+            return Some(slot.source);
+        }
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let memory = SharedMemory::map_from(owner, image, width as usize * height as usize * 4)?;
+        let source = Source { pixels: memory.as_ptr() as *const Pixel, width, height };
+        let index = match self.slots.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => {
+                let index = self.next_victim;
+                self.next_victim = (index + 1) % MAX_IMAGES;
+                index
+            }
+        };
+        // 被替换的旧映射：内核没有取消映射的接口，进程退出时才回收
+        self.slots[index] = Some(Slot { owner, memory, source });
+        Some(source)
+    }
+}
+
+/// 把位图缩放画到发送者坐标的(x, y, w, h)，返回画过的区域
+///
+/// 位图是预乘的：Opaque和ColorKey先把半透明的像素还原成原来的颜色再覆盖（直接把alpha改成255会变暗），
+/// ColorKey也按还原后的颜色比较。
+pub(crate) unsafe fn draw_blit(canvas: &Canvas, source: Source, x: u16, y: u16, w: u16, h: u16, mode: BlitMode) -> Option<Rect> {
+    let (x, y) = canvas.to_surface(x, y);
+    let area = Rect::new(x, y, w, h).intersect(&canvas.clip)?;
+    let (src_w, src_h) = (source.width as u32, source.height as u32);
+    let key = match mode {
+        BlitMode::ColorKey(key) => Some(Rgba::from(key).to_premultiplied()), // 不透明的key颜色
+        _ => None,
+    };
+    for dy in area.y..area.y + area.h {
+        let sy = (dy - y) as u32 * src_h / h as u32;
+        let row = source.pixels.add((sy * src_w) as usize);
+        for dx in area.x..area.x + area.w {
+            let sx = (dx - x) as u32 * src_w / w as u32;
+            let src = *row.add(sx as usize);
+            let dst = canvas.pixels.add(dy as usize * canvas.width as usize + dx as usize);
+            *dst = match mode {
+                BlitMode::Opaque => unpremultiply(src),
+                BlitMode::ColorKey(_) => match unpremultiply(src) {
+                    opaque if Some(opaque) == key => continue,
+                    opaque => opaque,
+                },
+                BlitMode::Alpha => blend(*dst, src),
+            };
+        }
+    }
+    Some(area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::Rgb;
+
+    const GREEN: Pixel = 0xFF00_FF00;
+    /// 半透明的红、不透明的蓝、全透明
+    const PIXELS: [Pixel; 3] = [0x8080_0000, 0xFF00_00FF, 0x0000_0000];
+
+    /// 把PIXELS（3×1）缩放画到绿色的6×2表面上，返回表面的像素
+    fn blit(mode: BlitMode) -> [Pixel; 12] {
+        let mut pixels = [GREEN; 12];
+        let canvas = Canvas { pixels: pixels.as_mut_ptr(), width: 6, clip: Rect::new(0, 0, 6, 2), origin_x: 0, origin_y: 0 };
+        let source = Source { pixels: PIXELS.as_ptr(), width: 3, height: 1 };
+        assert_eq!(unsafe { draw_blit(&canvas, source, 0, 0, 6, 2, mode) }, Some(Rect::new(0, 0, 6, 2)));
+        pixels
+    }
+
+    #[test]
+    fn opaque_keeps_translucent_colors() {
+        // 半透明的红还原成红色（不是暗红），全透明的是黑色
+        let row = [0xFFFF_0000, 0xFFFF_0000, 0xFF00_00FF, 0xFF00_00FF, 0xFF00_0000, 0xFF00_0000];
+        assert_eq!(blit(BlitMode::Opaque), [row, row].concat()[..]);
+    }
+
+    #[test]
+    fn color_key_compares_restored_colors() {
+        let row = [GREEN, GREEN, 0xFF00_00FF, 0xFF00_00FF, 0xFF00_0000, 0xFF00_0000];
+        assert_eq!(blit(BlitMode::ColorKey(Rgb { r: 255, g: 0, b: 0 })), [row, row].concat()[..]);
+    }
+
+    #[test]
+    fn alpha_blends_over_surface() {
+        let row = [0xFF80_7F00, 0xFF80_7F00, 0xFF00_00FF, 0xFF00_00FF, GREEN, GREEN];
+        assert_eq!(blit(BlitMode::Alpha), [row, row].concat()[..]);
+    }
+}
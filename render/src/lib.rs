@@ -0,0 +1,545 @@
+#![no_std]
+
+mod clip;
+mod compositor;
+mod font;
+mod images;
+mod rings;
+mod shapes;
+mod text;
+mod truetype;
+#[cfg(test)]
+mod host;
+
+use common::{command::RenderCommand, ipc::{self, Endpoint, Incoming}, DamageList, IpcMessage, Rect, Rgba, kernel, Pid, PixelFormat, ShmHandle, SurfaceId, TextStyle};
+use compositor::{Canvas, Compositor, ROOT};
+use rings::DrawContext;
+use core::{
+    ffi::CStr,
+    ptr,
+};
+
+/// 最多记录多少个屏幕变化的订阅者
+const MAX_SCREEN_SUBSCRIBERS: usize = 16;
+
+// 渲染服务状态：帧缓冲区、后台缓冲区、分辨率、像素格式、GPU驱动PID
+//
+// 绘图请求画在各自的表面上（见compositor），收到RenderCommit时把画过的区域
+// 合成到后台缓冲区再呈现到屏幕上：
+// 帧缓冲区有两页时后台缓冲区就是不显示的那一页，呈现时让GPU驱动翻页；
+// 只有一页时后台缓冲区是一块共享内存，呈现时复制到帧缓冲区。
+// 两种方式都只处理上次呈现以来变化过的区域（damage）。
+struct RenderState {
+    fb: *mut u8,          // 帧缓冲区虚拟地址（映射自GPU驱动，包含所有页）
+    back: *mut u8,        // 后台缓冲区（所有绘图都写这里）
+    pages: u8,            // 帧缓冲区的页数（≥2时翻页）
+    back_page: u8,        // 翻页模式下后台缓冲区是第几页
+    shadow: *mut u8,      // 单页模式下的后台缓冲区（切换到更小的模式时复用）
+    shadow_size: usize,   // shadow的字节数
+    damage: DamageList,   // 上次呈现以来变化过的屏幕区域
+    compositor: Compositor, // 所有表面和场景
+    width: u16,           // 屏幕宽度（像素）
+    height: u16,          // 屏幕高度（像素）
+    format: PixelFormat,  // 帧缓冲区像素格式（每像素位数、通道位置、每行字节数）
+    gpu_pid: Pid,         // GPU驱动的PID
+    desktop_pid: Pid,     // 桌面进程的PID（有人画背景时查，只有它能画背景）
+    desktop_asked: Pid,   // 上次为谁查过桌面进程（同一个进程再画背景时不再查）
+    wm_pid: Pid,          // 窗口管理器的PID（收到场景时查，只接受它的场景）
+    wm_asked: Pid,        // 上次为谁查过窗口管理器
+    initialized: bool,    // 是否初始化完成
+    endpoint: Endpoint,   // 本服务唯一的收消息端点（等GPU回复时暂存其他消息）
+    subscribers: [Pid; MAX_SCREEN_SUBSCRIBERS], // 订阅了屏幕变化的进程（0为空位）
+}
+
+impl RenderState {
+    const fn new() -> Self {
+        RenderState {
+            fb: ptr::null_mut(),
+            back: ptr::null_mut(),
+            pages: 0,
+            back_page: 0,
+            shadow: ptr::null_mut(),
+            shadow_size: 0,
+            damage: DamageList::new(),
+            compositor: Compositor::new(),
+            width: 0,
+            height: 0,
+            format: PixelFormat::xrgb8888(0),
+            gpu_pid: 0,
+            desktop_pid: 0,
+            desktop_asked: 0,
+            wm_pid: 0,
+            wm_asked: 0,
+            initialized: false,
+            endpoint: Endpoint::new(),
+            subscribers: [0; MAX_SCREEN_SUBSCRIBERS],
+        }
+    }
+
+    /// 映射GPU驱动给的帧缓冲区并准备后台缓冲区（初始化和模式切换时调用）
+    ///
+    /// 驱动刚设置好模式时显示的总是第0页。
+    unsafe fn attach(&mut self, fb: ShmHandle, width: u16, height: u16, format: PixelFormat, pages: u8) -> bool {
+        if fb == 0 || width == 0 || height == 0 || !format.is_valid(width) {
+            return false; // 帧缓冲区无效或像素格式不支持
+        }
+        // 帧缓冲区以物理地址的形式传过来，映射到本服务的地址空间（按每行字节数算大小）
+        let page_size = format.buffer_size(height);
+        let pages = pages.max(1);
+        let mapped = kernel::map_phys(fb, page_size * pages as usize);
+        if mapped.is_null() {
+            return false;
+        }
+        if pages >= 2 {
+            self.back_page = 1;
+            self.back = mapped.add(page_size);
+        } else {
+            if page_size > self.shadow_size {
+                let shadow = kernel::shm_alloc(page_size);
+                let virt = if shadow == 0 { ptr::null_mut() } else { kernel::map_phys(shadow, page_size) };
+                if virt.is_null() {
+                    return false; // 内存不足
+                }
+                // 旧的缓冲区：内核没有释放接口，进程退出时才回收
+                self.shadow = virt;
+                self.shadow_size = page_size;
+            }
+            self.back = self.shadow;
+        }
+        // 背景表面跟着屏幕大小走（清成黑色），下次呈现时整屏重新合成
+        if !self.compositor.resize_root(width, height) {
+            return false;
+        }
+        self.fb = mapped;
+        self.pages = pages;
+        self.width = width;
+        self.height = height;
+        self.format = format;
+        self.damage = DamageList::from_rect(Rect::new(0, 0, width, height));
+        self.initialized = true;
+        true
+    }
+
+    /// 记录变化过的屏幕区域（裁剪到屏幕内）
+    fn damage(&mut self, rect: Rect) {
+        if let Some(rect) = rect.clip(self.width, self.height) {
+            self.damage.add(rect);
+        }
+    }
+
+    /// 把src里rect区域的像素复制到dst（两者布局都是self.format）
+    unsafe fn copy_rect(&self, src: *const u8, dst: *mut u8, rect: Rect) {
+        let row_bytes = rect.w as usize * self.format.bytes_per_pixel();
+        for y in rect.y..rect.y + rect.h {
+            let offset = self.format.offset(rect.x, y);
+            ptr::copy_nonoverlapping(src.add(offset), dst.add(offset), row_bytes);
+        }
+    }
+
+    /// 第page页的起始地址
+    unsafe fn page(&self, page: u8) -> *mut u8 {
+        self.fb.add(page as usize * self.format.buffer_size(self.height))
+    }
+
+    /// sender要画背景：查出桌面进程（注册为"desktop"的服务），从此只有它能画背景
+    ///
+    /// 查服务要等对方注册（可能阻塞好几秒），所以还没注册时只在换了一个进程来画时再查：
+    /// 桌面进程先注册再画，轮到它画时一定查得到。
+    fn find_desktop(&mut self, sender: Pid) {
+        if self.desktop_pid == 0 && sender != self.desktop_asked {
+            self.desktop_asked = sender;
+            self.desktop_pid = unsafe { kernel::get_service_pid(c"desktop".as_ptr() as *const u8) };
+            self.compositor.set_root_creator(self.desktop_pid);
+        }
+    }
+
+    /// sender是不是窗口管理器（注册为"wm"的服务）；查到之后记住，还没注册时换了发送者再查
+    fn is_wm(&mut self, sender: Pid) -> bool {
+        if self.wm_pid == 0 && sender != self.wm_asked {
+            self.wm_asked = sender;
+            self.wm_pid = unsafe { kernel::get_service_pid(c"wm".as_ptr() as *const u8) };
+        }
+        sender != 0 && sender == self.wm_pid
+    }
+
+    /// 在target表面上画一笔，只画在sender能画的区域和clip的交集里：
+    /// draw返回表面上画过的区域，换算成屏幕上的脏区域（sender无权画这个表面时什么都不做）
+    fn draw_on(&mut self, target: SurfaceId, sender: Pid, clip: Option<Rect>, draw: impl FnOnce(&Canvas) -> Option<Rect>) {
+        if !self.initialized {
+            return;
+        }
+        if target == ROOT && sender != 0 {
+            self.find_desktop(sender);
+        }
+        let Some(mut canvas) = self.compositor.canvas(target, sender) else {
+            return;
+        };
+        if let Some(clip) = clip {
+            let clip = clip.offset(canvas.origin_x, canvas.origin_y);
+            canvas.clip = canvas.clip.intersect(&clip).unwrap_or(Rect::new(0, 0, 0, 0));
+        }
+        if let Some(rect) = draw(&canvas).and_then(|rect| self.compositor.to_screen(target, rect)) {
+            self.damage(rect);
+        }
+    }
+
+    /// 客户端直接改了表面上的rects（sender坐标，空表示它能写的整个区域），换算成屏幕上的脏区域
+    fn damage_surface(&mut self, surface: SurfaceId, sender: Pid, rects: &DamageList) {
+        let Some(canvas) = self.compositor.canvas(surface, sender) else {
+            return;
+        };
+        let mut changed = DamageList::new();
+        for rect in rects.as_slice() {
+            if let Some(rect) = rect.offset(canvas.origin_x, canvas.origin_y).intersect(&canvas.clip) {
+                changed.add(rect);
+            }
+        }
+        if rects.is_empty() {
+            changed.add(canvas.clip);
+        }
+        for rect in changed.as_slice() {
+            if let Some(screen) = self.compositor.to_screen(surface, *rect) {
+                self.damage(screen);
+            }
+        }
+    }
+
+    /// 把变化过的区域合成到后台缓冲区并呈现到屏幕上（没有变化就什么都不做）
+    unsafe fn commit(&mut self) {
+        if !self.initialized || self.damage.is_empty() {
+            return;
+        }
+        let damage = self.damage;
+        for rect in damage.as_slice() {
+            self.compositor.compose(*rect, self.back, &self.format);
+        }
+        if self.pages < 2 {
+            for rect in damage.as_slice() {
+                self.copy_rect(self.back, self.fb, *rect);
+            }
+            kernel::send(self.gpu_pid, &IpcMessage::GpuFlush { rects: damage });
+            self.damage.clear();
+            return;
+        }
+        // 翻页：等驱动确认后，换一页当后台缓冲区。新的后台页比刚显示出去的画面
+        // 只旧在这一帧画过的区域上，把这些区域复制过去就和屏幕一致了
+        let flip = IpcMessage::GpuFlip { page: self.back_page };
+        let front = match self.endpoint.call(self.gpu_pid, &flip) {
+            Ok(IpcMessage::GpuFlipped { page }) if page < self.pages => page,
+            _ => return, // 驱动没有回复：保留后台缓冲区和脏区域，下次再试
+        };
+        self.damage.clear();
+        if front != self.back_page {
+            // 驱动没翻过去：直接复制到正在显示的页
+            for rect in damage.as_slice() {
+                self.copy_rect(self.back, self.page(front), *rect);
+            }
+            return;
+        }
+        let shown = self.back;
+        self.back_page = (front + 1) % self.pages;
+        self.back = self.page(self.back_page);
+        for rect in damage.as_slice() {
+            self.copy_rect(shown, self.back, *rect);
+        }
+    }
+
+    /// 记录订阅者（重复订阅只记一次，满了忽略）
+    fn subscribe(&mut self, pid: Pid) {
+        if self.subscribers.contains(&pid) {
+            return;
+        }
+        if let Some(slot) = self.subscribers.iter_mut().find(|slot| **slot == 0) {
+            *slot = pid;
+        }
+    }
+
+    /// 请GPU驱动切换到最接近width×height的模式，返回是否成功
+    ///
+    /// 模式真的变了才换用新的帧缓冲区（背景清成黑色），呈现后通知订阅者重新布局。
+    unsafe fn set_mode(&mut self, width: u16, height: u16) -> bool {
+        if !self.initialized {
+            return false;
+        }
+        let reply = self.endpoint.call(self.gpu_pid, &IpcMessage::GpuSetMode { width, height });
+        let Ok(IpcMessage::GpuFbReply { fb, width, height, format, pages }) = reply else {
+            return false; // GPU驱动没有回复或回复不对
+        };
+        if fb == 0 {
+            return false; // 驱动切换失败，还是原来的模式
+        }
+        if (width, height, format, pages.max(1)) == (self.width, self.height, self.format, self.pages) {
+            return true;
+        }
+        if !self.attach(fb, width, height, format, pages) {
+            return false;
+        }
+        self.commit();
+        for pid in self.subscribers.iter().filter(|pid| **pid != 0) {
+            kernel::send(*pid, &IpcMessage::ScreenModeChanged { width, height });
+        }
+        true
+    }
+
+    /// 初始化：连接GPU驱动并获取帧缓冲区
+    fn init(&mut self) -> bool {
+        unsafe {
+            // 1. 获取GPU驱动的PID
+            let gpu_name = CStr::from_bytes_with_nul(b"gpu-driver\0").expect("Invalid CStr");
+            self.gpu_pid = kernel::get_service_pid(gpu_name.as_ptr() as *const u8);
+            if self.gpu_pid == 0 {
+                return false; // 未找到GPU驱动
+            }
+
+            // 2. 向GPU驱动发送初始化请求（0x0：由驱动沿用当前模式或选默认模式），等待对应的回复
+            let init_msg = IpcMessage::GpuInit { width: 0, height: 0 };
+            let reply = match self.endpoint.call(self.gpu_pid, &init_msg) {
+                Ok(reply) => reply,
+                Err(_) => return false, // GPU驱动没有回复
+            };
+
+            // 3. 从回复中获取帧缓冲区信息
+            if let IpcMessage::GpuFbReply { fb, width, height, format, pages } = reply {
+                self.attach(fb, width, height, format, pages)
+            } else {
+                false // 未收到预期回复
+            }
+        }
+    }
+}
+
+// 全局渲染状态
+static mut RENDER_STATE: RenderState = RenderState::new();
+// 客户端提交过的命令缓冲区
+static mut RINGS: rings::RingTable = rings::RingTable::new();
+// 客户端blit过的位图
+static mut IMAGES: images::ImageTable = images::ImageTable::new();
+// 启动时加载的字体和栅格化过的字形
+static mut FONTS: text::Fonts = text::Fonts::new();
+
+// 下面的绘图函数收到的坐标是发送者坐标（应用相对窗口内容区），返回表面上画过的区域
+
+/// 安全绘制像素（半透明的颜色和原来的像素混合），返回画过的区域
+unsafe fn draw_pixel(canvas: &Canvas, x: u16, y: u16, color: Rgba) -> Option<Rect> {
+    let (x, y) = canvas.to_surface(x, y);
+    canvas.blend_pixel(x, y, color.to_premultiplied());
+    Rect::new(x, y, 1, 1).intersect(&canvas.clip)
+}
+
+/// 绘制填充矩形
+unsafe fn draw_rect(canvas: &Canvas, x: u16, y: u16, w: u16, h: u16, color: Rgba) -> Option<Rect> {
+    // 换算到表面坐标并裁剪（只画在裁剪区内）
+    let (x, y) = canvas.to_surface(x, y);
+    let rect = Rect::new(x, y, w, h).intersect(&canvas.clip)?;
+
+    // 颜色只转换一次，再逐像素绘制矩形
+    let value = color.to_premultiplied();
+    for dy in 0..rect.h {
+        for dx in 0..rect.w {
+            canvas.blend_pixel(rect.x + dx, rect.y + dy, value);
+        }
+    }
+    Some(rect)
+}
+
+/// 把矩形清成全透明（直接替换，不混合）
+unsafe fn clear_rect(canvas: &Canvas, x: u16, y: u16, w: u16, h: u16) -> Option<Rect> {
+    let (x, y) = canvas.to_surface(x, y);
+    let rect = Rect::new(x, y, w, h).intersect(&canvas.clip)?;
+    for row in rect.y..rect.y + rect.h {
+        let start = canvas.pixels.add(row as usize * canvas.width as usize + rect.x as usize);
+        core::slice::from_raw_parts_mut(start, rect.w as usize).fill(0);
+    }
+    Some(rect)
+}
+
+/// 绘制文字（字体和排版见text模块）
+unsafe fn draw_text(canvas: &Canvas, x: u16, y: u16, text: &str, style: TextStyle, color: Rgba) -> Option<Rect> {
+    text::draw_text(&mut *core::ptr::addr_of_mut!(FONTS), canvas, x, y, text, style, color)
+}
+
+/// 执行命令缓冲区里的一条命令（context是这个缓冲区当前的绘图目标和裁剪栈）
+unsafe fn execute(command: RenderCommand, context: &mut DrawContext, sender: Pid) {
+    let state = &mut *core::ptr::addr_of_mut!(RENDER_STATE);
+    let (target, clip) = (context.target, context.clips.top());
+    context.open = command != RenderCommand::Commit;
+    match command {
+        RenderCommand::Pixel { x, y, color } => state.draw_on(target, sender, clip, |c| draw_pixel(c, x, y, color)),
+        RenderCommand::Rect { x, y, w, h, color } => state.draw_on(target, sender, clip, |c| draw_rect(c, x, y, w, h, color)),
+        RenderCommand::Text { x, y, text, style, color } => {
+            state.draw_on(target, sender, clip, |c| draw_text(c, x, y, text.as_str(), style, color))
+        }
+        RenderCommand::Commit => state.commit(),
+        // 权限在画的时候检查（表面可能之后才创建）；裁剪栈是相对旧目标的，清空
+        RenderCommand::Target { surface } => {
+            context.target = surface;
+            context.clips.clear();
+        }
+        RenderCommand::PushClip { x, y, w, h } => context.clips.push(Rect::new(x, y, w, h)),
+        RenderCommand::PopClip => context.clips.pop(),
+        RenderCommand::Line { x0, y0, x1, y1, color } => {
+            state.draw_on(target, sender, clip, |c| shapes::draw_line(c, x0, y0, x1, y1, color))
+        }
+        RenderCommand::StrokeRect { x, y, w, h, thickness, color } => {
+            state.draw_on(target, sender, clip, |c| shapes::draw_stroke_rect(c, x, y, w, h, thickness, color))
+        }
+        RenderCommand::Ellipse { cx, cy, rx, ry, thickness, color } => {
+            state.draw_on(target, sender, clip, |c| shapes::draw_ellipse(c, cx, cy, rx, ry, thickness, color))
+        }
+        RenderCommand::RoundRect { x, y, w, h, radius, thickness, color } => {
+            state.draw_on(target, sender, clip, |c| shapes::draw_round_rect(c, x, y, w, h, radius, thickness, color))
+        }
+        RenderCommand::Polygon { points, color } => {
+            state.draw_on(target, sender, clip, |c| shapes::fill_polygon(c, &points, color))
+        }
+        RenderCommand::Blit { image, width, height, x, y, w, h, mode } => {
+            if let Some(source) = (*core::ptr::addr_of_mut!(IMAGES)).get(sender, image, width, height) {
+                state.draw_on(target, sender, clip, |c| images::draw_blit(c, source, x, y, w, h, mode));
+            }
+        }
+        RenderCommand::TextBox { x, y, w, h, text, style, align, color } => state.draw_on(target, sender, clip, |c| {
+            text::draw_text_box(&mut *core::ptr::addr_of_mut!(FONTS), c, x, y, w, h, text.as_str(), style, align, color)
+        }),
+        RenderCommand::Clear { x, y, w, h } => state.draw_on(target, sender, clip, |c| clear_rect(c, x, y, w, h)),
+    }
+}
+
+/// 处理IPC消息（来自窗口管理器或应用）
+unsafe fn handle_ipc_message(incoming: Incoming) {
+    let state = &mut *core::ptr::addr_of_mut!(RENDER_STATE);
+    let sender = incoming.sender;
+    match incoming.msg {
+        // 单条绘图请求画在桌面背景上
+        IpcMessage::RenderDrawPixel { x, y, color } => {
+            state.draw_on(ROOT, sender, None, |c| draw_pixel(c, x, y, color.into()));
+        }
+        IpcMessage::RenderDrawRect { x, y, w, h, color } => {
+            state.draw_on(ROOT, sender, None, |c| draw_rect(c, x, y, w, h, color.into()));
+        }
+        IpcMessage::RenderDrawText { x, y, text, style, color } => {
+            state.draw_on(ROOT, sender, None, |c| draw_text(c, x, y, text.as_str(), style, color.into()));
+        }
+        IpcMessage::RenderDrawLine { x0, y0, x1, y1, color } => {
+            state.draw_on(ROOT, sender, None, |c| shapes::draw_line(c, x0, y0, x1, y1, color));
+        }
+        IpcMessage::RenderStrokeRect { x, y, w, h, thickness, color } => {
+            state.draw_on(ROOT, sender, None, |c| shapes::draw_stroke_rect(c, x, y, w, h, thickness, color));
+        }
+        IpcMessage::RenderDrawEllipse { cx, cy, rx, ry, thickness, color } => {
+            state.draw_on(ROOT, sender, None, |c| shapes::draw_ellipse(c, cx, cy, rx, ry, thickness, color));
+        }
+        IpcMessage::RenderDrawRoundRect { x, y, w, h, radius, thickness, color } => {
+            state.draw_on(ROOT, sender, None, |c| shapes::draw_round_rect(c, x, y, w, h, radius, thickness, color));
+        }
+        IpcMessage::RenderFillPolygon { points, color } => {
+            state.draw_on(ROOT, sender, None, |c| shapes::fill_polygon(c, &points, color));
+        }
+        IpcMessage::RenderBlit { image, width, height, x, y, w, h, mode } => {
+            if let Some(source) = (*core::ptr::addr_of_mut!(IMAGES)).get(sender, image, width, height) {
+                state.draw_on(ROOT, sender, None, |c| images::draw_blit(c, source, x, y, w, h, mode));
+            }
+        }
+        // 一帧画完：合成并呈现
+        IpcMessage::RenderCommit => {
+            state.commit();
+        }
+        // 客户端的命令缓冲区里有新命令：执行到end为止，读到Commit时呈现
+        IpcMessage::RenderSubmit { ring, size, end } => {
+            if let Some((ring, context)) = (*core::ptr::addr_of_mut!(RINGS)).get(sender, ring, size) {
+                ring.drain(end, |command| execute(command, context, sender));
+            }
+        }
+        // 新建窗口表面（通常由WM代应用创建，owner是窗口所属的应用）
+        IpcMessage::RenderCreateSurface { width, height, owner, content } => {
+            let (surface, shm) = state.compositor.create(width, height, sender, owner, content).unwrap_or((0, 0));
+            ipc::reply(&incoming, &IpcMessage::RenderSurfaceCreated { surface, shm });
+        }
+        // WM改了窗口大小：表面换成新大小（编号不变），在场景里时旧的和新的范围都要重新合成
+        IpcMessage::RenderResizeSurface { surface, width, height, content } => {
+            let before = state.compositor.placed(surface);
+            let shm = state.compositor.resize(surface, sender, width, height, content).unwrap_or(0);
+            let after = state.compositor.placed(surface);
+            for rect in before.into_iter().chain(after) {
+                state.damage(rect);
+            }
+            ipc::reply(&incoming, &IpcMessage::RenderSurfaceResized { surface, shm });
+        }
+        // WM关掉了窗口：销毁表面，还在场景里时重新合成它盖住的区域
+        IpcMessage::RenderDestroySurface { surface } => {
+            if let Some(rect) = state.compositor.destroy(surface, sender) {
+                state.damage(rect);
+                state.commit();
+            }
+        }
+        // 应用要直接写窗口表面的像素：把共享内存交给它（只给窗口所属的应用和WM）
+        IpcMessage::RenderMapSurface { surface } => {
+            let (shm, width, height, area) = state.compositor.share(surface, sender).unwrap_or((0, 0, 0, Rect::new(0, 0, 0, 0)));
+            ipc::reply(&incoming, &IpcMessage::RenderSurfaceMapped { surface, shm, width, height, area });
+        }
+        // 应用直接写完了表面像素：合成改过的区域并呈现
+        IpcMessage::RenderDamageSurface { surface, rects } => {
+            state.damage_surface(surface, sender, &rects);
+            state.commit();
+        }
+        // 量文字（排版和画的时候一样）
+        IpcMessage::RenderMeasureText { text, style, max_width } => {
+            let (width, height) = (*core::ptr::addr_of_mut!(FONTS)).measure(text.as_str(), style, max_width);
+            ipc::reply(&incoming, &IpcMessage::RenderTextMeasured { width, height });
+        }
+        // WM更新了窗口的位置和叠放次序：重新合成变化的区域并马上呈现（别的进程发来的忽略）
+        IpcMessage::RenderSetScene { layers } => {
+            if !state.is_wm(sender) {
+                return;
+            }
+            let RenderState { compositor, damage, width, height, .. } = &mut *state;
+            compositor.set_scene(&layers, |rect| {
+                if let Some(rect) = rect.clip(*width, *height) {
+                    damage.add(rect);
+                }
+            });
+            state.commit();
+        }
+        // 查询屏幕大小，同时订阅之后的分辨率变化
+        IpcMessage::RenderSubscribeScreen => {
+            state.subscribe(sender);
+            ipc::reply(&incoming, &IpcMessage::RenderScreenInfo { width: state.width, height: state.height });
+        }
+        // 切换显示模式：回复实际的分辨率（失败时为0x0）
+        IpcMessage::RenderSetMode { width, height } => {
+            let (width, height) = if state.set_mode(width, height) { (state.width, state.height) } else { (0, 0) };
+            ipc::reply(&incoming, &IpcMessage::RenderScreenInfo { width, height });
+        }
+        _ => {} // 忽略其他类型消息
+    }
+}
+
+/// 渲染服务主函数（裸机入口main.rs和宿主模拟器都从这里启动）
+pub fn run() -> ! {
+    unsafe {
+        // 1. 注册服务到内核（让其他服务可通过"render"名称找到）
+        let service_name = CStr::from_bytes_with_nul(b"render\0").expect("Invalid CStr");
+        extern "C" {
+            fn kernel_register_service(name: *const u8) -> bool;
+        }
+        let _ = kernel_register_service(service_name.as_ptr() as *const u8);
+
+        // 2. 加载字体（认不出的文件跳过），再初始化渲染服务（连接GPU驱动；期间收到的绘图请求会暂存，初始化后再处理）
+        (*core::ptr::addr_of_mut!(FONTS)).load();
+        if !(&mut *core::ptr::addr_of_mut!(RENDER_STATE)).init() {
+            // 初始化失败：进入死循环（内核可检测并重启服务）
+            loop { kernel::idle(); }
+        }
+
+        // 3. 测试：绘制初始化成功提示（左上角白色文字）
+        let state = &mut *core::ptr::addr_of_mut!(RENDER_STATE);
+        state.draw_on(ROOT, 0, None, |c| draw_text(c, 10, 10, "RUI Render Service Ready", TextStyle::DEFAULT, Rgba::new(255, 255, 255, 255)));
+        state.commit();
+
+        // 4. 消息循环：处理绘图请求
+        loop {
+            let incoming = (*core::ptr::addr_of_mut!(RENDER_STATE)).endpoint.recv();
+            handle_ipc_message(incoming);
+            kernel::idle();
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// ==========================
+// 客户端的命令缓冲区
+// ==========================
+// 每个客户端的RenderCommandBuffer是一块共享内存，第一次收到RenderSubmit时映射（只映射发送方自己
+// 分配的共享内存），之后复用映射。
+// 每个缓冲区各自记着绘图状态（Target设置的目标、裁剪栈），一批命令可以分几次提交。
+// 映射了的缓冲区一直留着（内核没有取消映射的接口，也不通知进程退出）。表满了之后，
+// 新缓冲区顶替最久没提交过、上一批已经读到Commit的那个：它的读写位置在共享内存里，
+// 主人再提交时重新映射，从目标0、空裁剪栈开始（所以每批命令都应该先Target）。
+// 一批还没结束的缓冲区不顶替；全都没结束时新缓冲区的命令丢掉，客户端那边push会超时返回false。
+
+use crate::clip::ClipStack;
+use common::{command::CommandRing, kernel::SharedMemory, Pid, ShmHandle, SurfaceId};
+
+/// 最多同时记住多少个命令缓冲区（满了顶替最久没用、已经Commit的）
+const MAX_RINGS: usize = 16;
+
+/// 一个命令缓冲区的绘图状态
+pub(crate) struct DrawContext {
+    pub target: SurfaceId, // 当前绘图目标
+    pub clips: ClipStack,  // 相对target的裁剪栈
+    pub open: bool,        // 上一批命令还没读到Commit（这时不能顶替这个缓冲区）
+}
+
+struct Slot {
+    owner: Pid,
+    shm: ShmHandle,
+    size: u32,
+    ring: CommandRing,
+    context: DrawContext,
+    used: u64, // 最后一次提交的序号
+}
+
+pub(crate) struct RingTable {
+    slots: [Option<Slot>; MAX_RINGS],
+    clock: u64, // 提交计数（挑最久没用的缓冲区）
+}
+
+impl RingTable {
+    pub(crate) const fn new() -> Self {
+        RingTable { slots: [const { None }; MAX_RINGS], clock: 0 }
+    }
+
+    /// 找到（或映射）owner提交的命令缓冲区及其绘图状态；内存不是owner分配的、无效或没有能顶替的缓冲区时返回None
+    pub(crate) unsafe fn get(&mut self, owner: Pid, shm: ShmHandle, size: u32) -> Option<(&CommandRing, &mut DrawContext)> {
+        self.clock += 1;
+        let matches = |slot: &Option<Slot>| {
+            matches!(slot, Some(s) if s.owner == owner && s.shm == shm && s.size == size)
+        };
+        if let Some(index) = self.slots.iter().position(matches) {
+            return self.slots[index].as_mut().map(|slot| {
+                slot.used = self.clock;
+                (&slot.ring, &mut slot.context)
+            });
+        }
+        let index = self.free_slot()?;
+        let memory = SharedMemory::map_from(owner, shm, size as usize)?;
+        let ring = CommandRing::open(memory.as_ptr(), size as usize)?;
+        let context = DrawContext { target: 0, clips: ClipStack::new(), open: false };
+        self.slots[index] = Some(Slot { owner, shm, size, ring, context, used: self.clock });
+        self.slots[index].as_mut().map(|slot| (&slot.ring, &mut slot.context))
+    }
+
+    /// 空位；没有空位时是最久没用、已经Commit的缓冲区
+    fn free_slot(&self) -> Option<usize> {
+        if let Some(index) = self.slots.iter().position(Option::is_none) {
+            return Some(index);
+        }
+        let idle = self.slots.iter().enumerate().filter_map(|(index, slot)| Some((index, slot.as_ref()?)));
+        idle.filter(|(_, slot)| !slot.context.open).min_by_key(|(_, slot)| slot.used).map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host;
+
+    const SIZE: usize = CommandRing::shm_size(256);
+
+    /// 客户端owner新建的命令缓冲区（共享内存句柄）
+    fn ring(owner: Pid) -> ShmHandle {
+        host::run_as(owner);
+        let memory = SharedMemory::alloc(SIZE).unwrap();
+        unsafe { CommandRing::create(memory.as_ptr(), 256) };
+        memory.handle()
+    }
+
+    fn get(table: &mut RingTable, owner: Pid, shm: ShmHandle) -> Option<&mut DrawContext> {
+        unsafe { table.get(owner, shm, SIZE as u32) }.map(|(_, context)| context)
+    }
+
+    #[test]
+    fn keeps_context_per_ring() {
+        let mut table = RingTable::new();
+        let (a, b) = (ring(1), ring(1));
+        get(&mut table, 1, a).unwrap().target = 5;
+        assert_eq!(get(&mut table, 1, b).unwrap().target, 0);
+        assert_eq!(get(&mut table, 1, a).unwrap().target, 5);
+        assert!(get(&mut table, 1, 0).is_none());
+    }
+
+    #[test]
+    fn refuses_memory_of_other_processes() {
+        let mut table = RingTable::new();
+        let shm = ring(1);
+        // 别的进程拿到了句柄也不能让渲染服务映射它；不是shm_alloc分配的地址更不行
+        assert!(get(&mut table, 2, shm).is_none());
+        assert!(get(&mut table, 2, 0xB_8000).is_none());
+        assert!(unsafe { table.get(1, shm, SIZE as u32 * 2) }.is_none());
+        assert!(get(&mut table, 1, shm).is_some());
+    }
+
+    #[test]
+    fn replaces_least_recently_used_committed_ring() {
+        let mut table = RingTable::new();
+        let rings: [ShmHandle; MAX_RINGS] = core::array::from_fn(|index| ring(index as Pid + 1));
+        for (pid, shm) in rings.iter().enumerate() {
+            get(&mut table, pid as Pid + 1, *shm).unwrap().target = 7;
+        }
+        // 第一个最久没用，但再提交一次就轮到第二个
+        get(&mut table, 1, rings[0]).unwrap();
+        let newcomer = ring(100);
+        assert_eq!(get(&mut table, 100, newcomer).unwrap().target, 0);
+        assert_eq!(get(&mut table, 1, rings[0]).unwrap().target, 7);
+        // 被顶替的缓冲区再提交时重新映射，从目标0开始
+        assert_eq!(get(&mut table, 2, rings[1]).unwrap().target, 0);
+    }
+
+    #[test]
+    fn keeps_open_batches() {
+        let mut table = RingTable::new();
+        let rings: [ShmHandle; MAX_RINGS] = core::array::from_fn(|index| ring(index as Pid + 1));
+        for (pid, shm) in rings.iter().enumerate() {
+            get(&mut table, pid as Pid + 1, *shm).unwrap().open = true;
+        }
+        assert!(get(&mut table, 100, ring(100)).is_none());
+        // 有一个读到了Commit就能顶替它
+        get(&mut table, 5, rings[4]).unwrap().open = false;
+        get(&mut table, 100, ring(100)).unwrap().open = true;
+        assert!(get(&mut table, 5, rings[4]).is_none());
+    }
+}
@@ -0,0 +1,365 @@
+// ==========================
+// 矢量图形：线段、矩形边框、椭圆、圆角矩形、多边形
+// ==========================
+// 和lib.rs里的绘图函数一样，收到的是发送者坐标，返回表面上画过的区域（已和裁剪区取交集）。
+// 图形可能伸到表面外面（比如圆心靠近左边的圆），内部统一换成i32的表面坐标，逐行求出要画的区间再裁剪。
+// 每个像素最多混合一次，半透明的颜色不会在线段交叉、边框拐角处叠出更深的颜色。
+
+use crate::compositor::Canvas;
+use common::{Pixel, Point, PointList, Rect, Rgba, POLYGON_CAPACITY};
+
+/// 发送者坐标 → 表面坐标（i32，不会溢出）
+fn to_surface(canvas: &Canvas, x: u16, y: u16) -> (i32, i32) {
+    (x as i32 + canvas.origin_x as i32, y as i32 + canvas.origin_y as i32)
+}
+
+/// 表面上[x0, x1)×[y0, y1)和裁剪区的交集
+fn bounds(canvas: &Canvas, x0: i32, y0: i32, x1: i32, y1: i32) -> Option<Rect> {
+    let clamp = |v: i32| v.clamp(0, u16::MAX as i32);
+    let (x0, y0, x1, y1) = (clamp(x0), clamp(y0), clamp(x1), clamp(y1));
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Rect::new(x0 as u16, y0 as u16, (x1 - x0) as u16, (y1 - y0) as u16).intersect(&canvas.clip)
+}
+
+/// 裁剪区里的行范围[top, bottom)
+fn clip_rows(canvas: &Canvas) -> (i32, i32) {
+    (canvas.clip.y as i32, canvas.clip.bottom() as i32)
+}
+
+/// 在第y行混合[x0, x1)（裁剪区外的部分忽略）
+unsafe fn span(canvas: &Canvas, y: i32, x0: i32, x1: i32, value: Pixel) {
+    let (top, bottom) = clip_rows(canvas);
+    if y < top || y >= bottom {
+        return;
+    }
+    for x in x0.max(canvas.clip.x as i32)..x1.min(canvas.clip.right() as i32) {
+        canvas.blend_pixel(x as u16, y as u16, value);
+    }
+}
+
+/// 在第y行混合outer去掉hole剩下的部分（都是左闭右开）
+unsafe fn span_around(canvas: &Canvas, y: i32, outer: (i32, i32), hole: Option<(i32, i32)>, value: Pixel) {
+    match hole {
+        Some((left, right)) if left < right => {
+            span(canvas, y, outer.0, left.min(outer.1), value);
+            span(canvas, y, right.max(outer.0), outer.1, value);
+        }
+        _ => span(canvas, y, outer.0, outer.1, value),
+    }
+}
+
+/// 半轴为(a, b)的椭圆在离中心d行处向左右各延伸几个像素（不含中心那一列）
+fn half_width(a: u16, b: u16, d: i32) -> i32 {
+    let d = d.unsigned_abs() as u64;
+    let (a, b) = (a as u64, b as u64);
+    if d > b {
+        return -1;
+    }
+    if b == 0 {
+        return a as i32;
+    }
+    // x²/a² + d²/b² ≤ 1  →  x ≤ √(a²(b² - d²)) / b
+    ((a * a * (b * b - d * d)).isqrt() / b) as i32
+}
+
+/// Bresenham线段（含两个端点）
+pub(crate) unsafe fn draw_line(canvas: &Canvas, x0: u16, y0: u16, x1: u16, y1: u16, color: Rgba) -> Option<Rect> {
+    let value = color.to_premultiplied();
+    let (mut x, mut y) = to_surface(canvas, x0, y0);
+    let (x1, y1) = to_surface(canvas, x1, y1);
+    let area = bounds(canvas, x.min(x1), y.min(y1), x.max(x1) + 1, y.max(y1) + 1)?;
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (step_x, step_y) = (if x < x1 { 1 } else { -1 }, if y < y1 { 1 } else { -1 });
+    let mut error = dx + dy;
+    loop {
+        span(canvas, y, x, x + 1, value);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    Some(area)
+}
+
+/// 矩形边框：四条边都画在矩形里面，线宽为0或超过一半时等于填充
+pub(crate) unsafe fn draw_stroke_rect(
+    canvas: &Canvas,
+    x: u16,
+    y: u16,
+    w: u16,
+    h: u16,
+    thickness: u16,
+    color: Rgba,
+) -> Option<Rect> {
+    let value = color.to_premultiplied();
+    let (left, top) = to_surface(canvas, x, y);
+    let (right, bottom) = (left + w as i32, top + h as i32);
+    let area = bounds(canvas, left, top, right, bottom)?;
+    let t = thickness as i32;
+    let hole = (t > 0 && left + t < right - t && top + t < bottom - t).then_some((left + t, right - t));
+    let (first, last) = clip_rows(canvas);
+    for row in top.max(first)..bottom.min(last) {
+        let inside = row >= top + t && row < bottom - t;
+        span_around(canvas, row, (left, right), hole.filter(|_| inside), value);
+    }
+    Some(area)
+}
+
+/// 椭圆：(cx, cy)是圆心，覆盖cx-rx..=cx+rx、cy-ry..=cy+ry；thickness为0时填充，否则画线宽thickness的环
+pub(crate) unsafe fn draw_ellipse(
+    canvas: &Canvas,
+    cx: u16,
+    cy: u16,
+    rx: u16,
+    ry: u16,
+    thickness: u16,
+    color: Rgba,
+) -> Option<Rect> {
+    let value = color.to_premultiplied();
+    let (cx, cy) = to_surface(canvas, cx, cy);
+    let area = bounds(canvas, cx - rx as i32, cy - ry as i32, cx + rx as i32 + 1, cy + ry as i32 + 1)?;
+    // 环的内圈（线宽超过半轴时整个填满）
+    let inner = (thickness > 0 && thickness <= rx.min(ry)).then(|| (rx - thickness, ry - thickness));
+    let (first, last) = clip_rows(canvas);
+    for row in (cy - ry as i32).max(first)..(cy + ry as i32 + 1).min(last) {
+        let d = row - cy;
+        let outer = half_width(rx, ry, d);
+        let hole = match inner {
+            Some((ix, iy)) if d.unsigned_abs() <= iy as u32 => {
+                let h = half_width(ix, iy, d);
+                Some((cx - h, cx + h + 1))
+            }
+            // 填充，或者这一行在内圈上下：整行都画
+            _ => None,
+        };
+        span_around(canvas, row, (cx - outer, cx + outer + 1), hole, value);
+    }
+    Some(area)
+}
+
+/// 圆角矩形第row行（从0数）两边各缩进多少：四个角是半径radius-1、圆心离边radius-1的四分之一圆
+fn corner_inset(h: i32, radius: u16, row: i32) -> i32 {
+    let r = radius as i32;
+    let d = if row < r {
+        r - 1 - row
+    } else if row >= h - r {
+        row - (h - r)
+    } else {
+        return 0;
+    };
+    let corner = radius.saturating_sub(1);
+    corner as i32 - half_width(corner, corner, d)
+}
+
+/// 圆角矩形：半径不超过宽高的一半；thickness为0时填充，否则边框画在里面（内圈的圆角半径相应减小）
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn draw_round_rect(
+    canvas: &Canvas,
+    x: u16,
+    y: u16,
+    w: u16,
+    h: u16,
+    radius: u16,
+    thickness: u16,
+    color: Rgba,
+) -> Option<Rect> {
+    let value = color.to_premultiplied();
+    let (left, top) = to_surface(canvas, x, y);
+    let (right, bottom) = (left + w as i32, top + h as i32);
+    let area = bounds(canvas, left, top, right, bottom)?;
+    let radius = radius.min(w / 2).min(h / 2);
+    let t = thickness as i32;
+    // 内圈（线宽为0或超过一半时没有）
+    let inner = (thickness > 0 && 2 * t < w as i32 && 2 * t < h as i32)
+        .then(|| (w as i32 - 2 * t, h as i32 - 2 * t, radius.saturating_sub(thickness)));
+    let (first, last) = clip_rows(canvas);
+    for row in top.max(first)..bottom.min(last) {
+        let i = row - top;
+        let inset = corner_inset(h as i32, radius, i);
+        let hole = match inner {
+            Some((inner_w, inner_h, inner_radius)) if i >= t && i < t + inner_h => {
+                let inset = corner_inset(inner_h, inner_radius, i - t);
+                Some((left + t + inset, left + t + inner_w - inset))
+            }
+            _ => None,
+        };
+        span_around(canvas, row, (left + inset, right - inset), hole, value);
+    }
+    Some(area)
+}
+
+/// 填充多边形（顶点在像素的左上角，按奇偶规则填充像素中心落在里面的像素；
+/// 比如(0,0)-(10,0)-(10,10)-(0,10)正好是10×10个像素）
+pub(crate) unsafe fn fill_polygon(canvas: &Canvas, points: &PointList, color: Rgba) -> Option<Rect> {
+    let value = color.to_premultiplied();
+    let mut vertices = [(0i32, 0i32); POLYGON_CAPACITY];
+    let points = points.as_slice();
+    if points.len() < 3 {
+        return None;
+    }
+    for (vertex, Point { x, y }) in vertices.iter_mut().zip(points) {
+        *vertex = to_surface(canvas, *x, *y);
+    }
+    let vertices = &vertices[..points.len()];
+    let min_x = vertices.iter().map(|v| v.0).min()?;
+    let max_x = vertices.iter().map(|v| v.0).max()?;
+    let min_y = vertices.iter().map(|v| v.1).min()?;
+    let max_y = vertices.iter().map(|v| v.1).max()?;
+    let area = bounds(canvas, min_x, min_y, max_x, max_y)?;
+    let (first, last) = clip_rows(canvas);
+    for row in min_y.max(first)..max_y.min(last) {
+        // 这一行像素中心的y（乘2避免小数）和各条边的交点（x也乘2）
+        let center = 2 * row + 1;
+        let mut crossings = [0i32; POLYGON_CAPACITY];
+        let mut count = 0;
+        for (i, &(x0, y0)) in vertices.iter().enumerate() {
+            let (x1, y1) = vertices[(i + 1) % vertices.len()];
+            if (2 * y0 <= center) == (2 * y1 <= center) {
+                continue; // 水平边或这一行不经过这条边
+            }
+            crossings[count] = 2 * x0 + (center - 2 * y0) * (x1 - x0) / (y1 - y0);
+            count += 1;
+        }
+        let crossings = &mut crossings[..count];
+        crossings.sort_unstable();
+        // 像素中心2x+1落在[a, b)里的像素是[a/2, b/2)
+        for [a, b] in crossings.as_chunks::<2>().0 {
+            span(canvas, row, a.div_euclid(2), b.div_euclid(2), value);
+        }
+    }
+    Some(area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+    const RED: Rgba = Rgba::new(255, 0, 0, 255);
+
+    /// 在16×16的空表面上画一笔，返回画过的区域和每个像素有没有被画到（[y][x]）
+    fn paint(draw: impl FnOnce(&Canvas) -> Option<Rect>) -> (Option<Rect>, [[bool; SIZE]; SIZE]) {
+        let mut pixels = [0 as Pixel; SIZE * SIZE];
+        let canvas = Canvas {
+            pixels: pixels.as_mut_ptr(),
+            width: SIZE as u16,
+            clip: Rect::new(0, 0, SIZE as u16, SIZE as u16),
+            origin_x: 0,
+            origin_y: 0,
+        };
+        let area = draw(&canvas);
+        let mut painted = [[false; SIZE]; SIZE];
+        for (y, row) in painted.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = pixels[y * SIZE + x] != 0;
+            }
+        }
+        (area, painted)
+    }
+
+    fn count(painted: &[[bool; SIZE]; SIZE]) -> usize {
+        painted.iter().flatten().filter(|p| **p).count()
+    }
+
+    /// 左右翻转、上下翻转之后都不变（图形关于第center_x列、第center_y行对称）
+    fn assert_symmetric(painted: &[[bool; SIZE]; SIZE], center_x: usize, center_y: usize) {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (mx, my) = ((2 * center_x).wrapping_sub(x), (2 * center_y).wrapping_sub(y));
+                let mirrored = |x: usize, y: usize| x < SIZE && y < SIZE && painted[y][x];
+                assert_eq!(painted[y][x], mirrored(mx, y), "({x}, {y})左右不对称");
+                assert_eq!(painted[y][x], mirrored(x, my), "({x}, {y})上下不对称");
+            }
+        }
+    }
+
+    #[test]
+    fn line_covers_both_endpoints() {
+        // 平缓的线每列一个像素，陡的线每行一个像素
+        for (x0, y0, x1, y1) in [(1, 2, 12, 6), (12, 6, 1, 2), (3, 14, 7, 1), (5, 5, 5, 5), (0, 9, 15, 9)] {
+            let (area, painted) = unsafe { paint(|canvas| draw_line(canvas, x0, y0, x1, y1, RED)) };
+            assert!(painted[y0 as usize][x0 as usize] && painted[y1 as usize][x1 as usize], "{x0},{y0}→{x1},{y1}");
+            let (dx, dy) = (x0.abs_diff(x1), y0.abs_diff(y1));
+            assert_eq!(count(&painted), dx.max(dy) as usize + 1, "{x0},{y0}→{x1},{y1}");
+            let expected = Rect::new(x0.min(x1), y0.min(y1), dx + 1, dy + 1);
+            assert_eq!(area, Some(expected));
+        }
+    }
+
+    #[test]
+    fn ellipse_is_symmetric() {
+        let (area, filled) = unsafe { paint(|canvas| draw_ellipse(canvas, 7, 7, 6, 3, 0, RED)) };
+        assert_eq!(area, Some(Rect::new(1, 4, 13, 7)));
+        assert_symmetric(&filled, 7, 7);
+        // 四个顶点在，再往外就没有了
+        assert!(filled[7][1] && filled[7][13] && filled[4][7] && filled[10][7]);
+        assert!(!filled[7][0] && !filled[7][14] && !filled[3][7] && !filled[11][7]);
+        assert!(!filled[4][1] && !filled[10][13]);
+
+        let (_, ring) = unsafe { paint(|canvas| draw_ellipse(canvas, 7, 7, 5, 5, 1, RED)) };
+        assert_symmetric(&ring, 7, 7);
+        assert!(ring[7][2] && ring[2][7] && !ring[7][7] && !ring[7][3]);
+    }
+
+    #[test]
+    fn round_rect_corners_are_cut() {
+        let (area, filled) = unsafe { paint(|canvas| draw_round_rect(canvas, 1, 2, 13, 9, 3, 0, RED)) };
+        assert_eq!(area, Some(Rect::new(1, 2, 13, 9)));
+        assert_symmetric(&filled, 7, 6);
+        // 角上的像素挖掉了，边的中间和里面都画了
+        assert!(!filled[2][1] && !filled[2][13] && !filled[10][1] && !filled[10][13]);
+        assert!(filled[2][7] && filled[6][1] && filled[6][13] && filled[10][7] && filled[6][7]);
+        // 半径0就是普通矩形
+        let (_, square) = unsafe { paint(|canvas| draw_round_rect(canvas, 1, 2, 13, 9, 0, 0, RED)) };
+        assert_eq!(count(&square), 13 * 9);
+        assert!(count(&filled) < 13 * 9);
+
+        // 边框：线宽2，中间空着，整圈都比填充的少
+        let (_, outline) = unsafe { paint(|canvas| draw_round_rect(canvas, 1, 2, 13, 9, 3, 2, RED)) };
+        assert_symmetric(&outline, 7, 6);
+        assert!(outline[2][7] && outline[3][7] && !outline[4][7] && !outline[6][7]);
+        assert!(outline[6][1] && outline[6][2] && !outline[6][3]);
+        assert!(!outline[2][1]);
+    }
+
+    #[test]
+    fn polygon_fills_even_odd() {
+        let polygon = |points: &[(u16, u16)]| {
+            let mut list = PointList::new();
+            for &(x, y) in points {
+                list.push(Point { x, y });
+            }
+            list
+        };
+        // 顶点在像素左上角：10×10的正方形正好100个像素
+        let square = polygon(&[(2, 3), (12, 3), (12, 13), (2, 13)]);
+        let (area, painted) = unsafe { paint(|canvas| fill_polygon(canvas, &square, RED)) };
+        assert_eq!(area, Some(Rect::new(2, 3, 10, 10)));
+        assert_eq!(count(&painted), 100);
+        assert!(painted[3][2] && painted[12][11] && !painted[13][12] && !painted[3][12]);
+
+        // 外框里套一个反向的内框（中间用来回两条重合的边连起来）：奇偶规则下内框是空的
+        let framed = polygon(&[(0, 0), (12, 0), (12, 12), (0, 12), (0, 0), (4, 4), (4, 8), (8, 8), (8, 4), (4, 4)]);
+        let (_, painted) = unsafe { paint(|canvas| fill_polygon(canvas, &framed, RED)) };
+        assert_eq!(count(&painted), 144 - 16);
+        assert!(painted[2][2] && painted[10][10] && !painted[5][5] && !painted[7][7] && painted[4][3]);
+
+        // 自相交的"8"字（两个三角形对顶）：两边都填，交点外面是空的
+        let bowtie = polygon(&[(0, 0), (12, 12), (12, 0), (0, 12)]);
+        let (_, painted) = unsafe { paint(|canvas| fill_polygon(canvas, &bowtie, RED)) };
+        assert!(painted[6][1] && painted[6][10] && !painted[1][6] && !painted[10][6]);
+        // 少于3个点什么都不画
+        let (area, painted) = unsafe { paint(|canvas| fill_polygon(canvas, &polygon(&[(0, 0), (5, 5)]), RED)) };
+        assert_eq!((area, count(&painted)), (None, 0));
+    }
+}
@@ -0,0 +1,335 @@
+// ==========================
+// 文字
+// ==========================
+// TextStyle决定用哪种字体：
+//   Bitmap：font.rs的点阵字体（PSF2/BDF），size是16的整数倍时按倍数放大，粗体是错开1像素画两遍
+//   Sans：truetype.rs栅格化的TrueType字体，任意像素大小、抗锯齿，粗体有单独的字体文件
+// TrueType字体里没有的字（比如汉字）用点阵字体补上，在行里竖直居中。
+// (x, y)是行框的左上角；排版（layout）和绘制共用同一套字形位置，所以量出来的宽度就是画出来的宽度。
+// 一行的宽度是各个字形的步进之和（没有字距调整），折行时逐字累加。
+// 折行优先断在空格处（空格留在上一行末尾不画）和宽字符（汉字、假名、谚文）前后，一个词一行都放不下时硬断。
+
+use crate::compositor::Canvas;
+use crate::font::{self, is_wide, FontSet};
+use crate::truetype::{self, Faces, GlyphCache};
+use common::{fade, FontFamily, FontWeight, Pixel, Rect, Rgba, TextAlign, TextStyle};
+
+/// 排好位置的一个字形（位置相对行框左上角）
+enum Placed<'a> {
+    Bitmap { x: i32, y: i32, glyph: font::Glyph<'a>, scale: i32, bold: bool },
+    Coverage { x: i32, y: i32, width: u16, height: u16, coverage: &'a [u8] },
+}
+
+/// 渲染服务的所有字体
+pub(crate) struct Fonts {
+    bitmap: FontSet,
+    faces: Faces,
+    regular: Option<u8>, // Sans常规体和粗体在faces里的编号
+    bold: Option<u8>,
+    cache: GlyphCache,
+}
+
+impl Fonts {
+    pub(crate) const fn new() -> Self {
+        Fonts { bitmap: FontSet::new(), faces: Faces::new(), regular: None, bold: None, cache: GlyphCache::new() }
+    }
+
+    /// 加载内置的字体文件（认不出的跳过；没有粗体时用常规体）
+    pub(crate) fn load(&mut self) {
+        for file in font::FONT_FILES {
+            self.bitmap.load(file);
+        }
+        let [regular, bold] = truetype::FACE_FILES;
+        self.regular = self.faces.load(regular);
+        self.bold = self.faces.load(bold).or(self.regular);
+    }
+
+    /// 排版一行文字：按顺序把每个字形交给place，返回(宽, 行高)
+    fn layout(&mut self, text: &str, style: TextStyle, mut place: impl FnMut(Placed)) -> (i32, i32) {
+        let face = match (style.family, style.weight) {
+            (FontFamily::Bitmap, _) => None,
+            (FontFamily::Sans, FontWeight::Regular) => self.regular,
+            (FontFamily::Sans, FontWeight::Bold) => self.bold,
+        };
+        let size = style.size.clamp(1, truetype::MAX_SIZE);
+        let Some((index, face)) = face.and_then(|index| Some((index, self.faces.get(index)?))) else {
+            // 点阵字体（没有TrueType字体时Sans也用它）
+            let line_height = self.bitmap.line_height() as i32;
+            let scale = (size as i32 / line_height).max(1);
+            let bold = style.weight == FontWeight::Bold;
+            let mut pen = 0;
+            for c in text.chars() {
+                let Some(glyph) = self.bitmap.glyph(c) else {
+                    continue; // 控制字符不占位置
+                };
+                let (x, y) = (pen + glyph.left as i32 * scale, glyph.top as i32 * scale);
+                pen += (glyph.advance as i32 + bold as i32) * scale;
+                place(Placed::Bitmap { x, y, glyph, scale, bold });
+            }
+            return (pen, line_height * scale);
+        };
+        let metrics = truetype::metrics(face, size);
+        let line_height = metrics.line_height as i32;
+        // 补字用的点阵字形在行里竖直居中
+        let fallback_y = (line_height - self.bitmap.line_height() as i32) / 2;
+        let mut pen = 0;
+        for c in text.chars() {
+            if c.is_control() {
+                continue;
+            }
+            match face.glyph_index(c) {
+                Some(id) => {
+                    let Some((glyph, coverage)) = self.cache.get(index, face, size, id) else {
+                        continue;
+                    };
+                    let (x, y) = (pen + glyph.left as i32, metrics.ascent + glyph.top as i32);
+                    pen += glyph.advance as i32;
+                    place(Placed::Coverage { x, y, width: glyph.width, height: glyph.height, coverage });
+                }
+                None => {
+                    let Some(glyph) = self.bitmap.glyph(c) else {
+                        continue;
+                    };
+                    let (x, y) = (pen + glyph.left as i32, fallback_y + glyph.top as i32);
+                    pen += glyph.advance as i32;
+                    place(Placed::Bitmap { x, y, glyph, scale: 1, bold: false });
+                }
+            }
+        }
+        (pen, line_height)
+    }
+
+    /// 一行文字的(宽, 行高)
+    fn measure_line(&mut self, text: &str, style: TextStyle) -> (i32, i32) {
+        self.layout(text, style, |_| {})
+    }
+
+    /// 从text开头取一行，返回(这一行, 剩下的文字, 这一行的宽)；
+    /// 遇到\n或超过max_width时断开，max_width为0表示只在\n处断开。
+    /// 没有下一行时剩下的是None（以\n结尾的文字最后还有一个空行，剩下的是Some("")）
+    fn next_line<'t>(&mut self, text: &'t str, style: TextStyle, max_width: i32) -> (&'t str, Option<&'t str>, i32) {
+        let mut width = 0;
+        let mut wrap = None; // 最后一个可以断开的地方：(行尾, 下一行开头, 行宽)
+        let mut previous_wide = false;
+        for (i, c) in text.char_indices() {
+            if c == '\n' {
+                return (&text[..i], Some(&text[i + 1..]), width);
+            }
+            let end = i + c.len_utf8();
+            if i > 0 && (previous_wide || is_wide(c)) {
+                wrap = Some((i, i, width));
+            }
+            let advance = self.measure_line(&text[i..end], style).0;
+            if max_width > 0 && i > 0 && width + advance > max_width && c != ' ' {
+                let (line_end, next, line_width) = wrap.unwrap_or((i, i, width));
+                return (&text[..line_end], Some(text[next..].trim_start_matches(' ')), line_width);
+            }
+            if c == ' ' && !text[..i].ends_with(' ') {
+                wrap = Some((i, end, width));
+            }
+            width += advance;
+            previous_wide = is_wide(c);
+        }
+        (text, None, width)
+    }
+
+    /// 文字在max_width宽的框里折行后的(最宽一行的宽, 总高度)，max_width为0时不折行
+    pub(crate) fn measure(&mut self, text: &str, style: TextStyle, max_width: u16) -> (u16, u16) {
+        let line_height = self.measure_line("", style).1;
+        let (mut width, mut lines, mut rest) = (0, 0, Some(text));
+        while let Some(text) = rest {
+            let (_, next, line_width) = self.next_line(text, style, max_width as i32);
+            width = width.max(line_width);
+            lines += 1;
+            rest = next;
+        }
+        (width.min(u16::MAX as i32) as u16, (lines * line_height).min(u16::MAX as i32) as u16)
+    }
+}
+
+/// 在表面坐标(x, y)混合一个像素（超出u16范围或裁剪区的忽略）
+unsafe fn plot(canvas: &Canvas, x: i32, y: i32, value: Pixel) {
+    if (0..=u16::MAX as i32).contains(&x) && (0..=u16::MAX as i32).contains(&y) {
+        canvas.blend_pixel(x as u16, y as u16, value);
+    }
+}
+
+/// 画过的范围（表面坐标，左上右下）
+type Ink = (i32, i32, i32, i32);
+
+/// 在表面坐标(x, y)画一行文字，把画到的范围（行框和伸出行框的笔画）并进ink
+#[allow(clippy::too_many_arguments)]
+unsafe fn draw_run(fonts: &mut Fonts, canvas: &Canvas, x: i32, y: i32, text: &str, style: TextStyle, value: Pixel, ink: &mut Ink) {
+    let mut extend = |left: i32, top: i32, right: i32, bottom: i32| {
+        *ink = (ink.0.min(left), ink.1.min(top), ink.2.max(right), ink.3.max(bottom));
+    };
+    let (width, height) = fonts.layout(text, style, |placed| match placed {
+        Placed::Bitmap { x: gx, y: gy, glyph, scale, bold } => {
+            let (left, top) = (x + gx, y + gy);
+            let columns = (glyph.width as i32 + bold as i32).min(32);
+            extend(left, top, left + columns * scale, top + glyph.height as i32 * scale);
+            for (row, bits) in glyph.rows().enumerate() {
+                // 粗体：每一行和自己右移一位叠在一起
+                let bits = if bold { bits | bits >> 1 } else { bits };
+                for col in 0..columns {
+                    if bits & (0x8000_0000 >> col) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            plot(canvas, left + col * scale + dx, top + row as i32 * scale + dy, value);
+                        }
+                    }
+                }
+            }
+        }
+        Placed::Coverage { x: gx, y: gy, width, height, coverage } => {
+            let (left, top) = (x + gx, y + gy);
+            extend(left, top, left + width as i32, top + height as i32);
+            for (i, c) in coverage.iter().enumerate() {
+                if *c != 0 {
+                    let (col, row) = ((i % width as usize) as i32, (i / width as usize) as i32);
+                    plot(canvas, left + col, top + row, fade(value, *c));
+                }
+            }
+        }
+    });
+    extend(x, y, x + width, y + height);
+}
+
+/// 画过的范围和裁剪区的交集
+fn ink_rect(canvas: &Canvas, ink: Ink) -> Option<Rect> {
+    let (left, top) = (ink.0.max(0), ink.1.max(0));
+    let (right, bottom) = (ink.2.min(u16::MAX as i32), ink.3.min(u16::MAX as i32));
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Rect::new(left as u16, top as u16, (right - left) as u16, (bottom - top) as u16).intersect(&canvas.clip)
+}
+
+/// 画一行文字（不折行），返回画过的区域
+pub(crate) unsafe fn draw_text(
+    fonts: &mut Fonts,
+    canvas: &Canvas,
+    x: u16,
+    y: u16,
+    text: &str,
+    style: TextStyle,
+    color: Rgba,
+) -> Option<Rect> {
+    let (x, y) = canvas.to_surface(x, y);
+    let (x, y) = (x as i32, y as i32);
+    let mut ink = (x, y, x, y);
+    draw_run(fonts, canvas, x, y, text, style, color.to_premultiplied(), &mut ink);
+    ink_rect(canvas, ink)
+}
+
+/// 在(x, y, w, h)里画一段文字：按w折行，每行按align对齐；w为0时只在\n处换行，x就是对齐的位置
+/// （居中时是中线，右对齐时是右边）。h不为0时整段文字在框里竖直居中，否则从y开始往下排。返回画过的区域
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn draw_text_box(
+    fonts: &mut Fonts,
+    canvas: &Canvas,
+    x: u16,
+    y: u16,
+    w: u16,
+    h: u16,
+    text: &str,
+    style: TextStyle,
+    align: TextAlign,
+    color: Rgba,
+) -> Option<Rect> {
+    let (x, y) = canvas.to_surface(x, y);
+    let (x, mut y) = (x as i32, y as i32);
+    if h > 0 {
+        let (_, height) = fonts.measure(text, style, w);
+        y += (h as i32 - height as i32) / 2;
+    }
+    let value = color.to_premultiplied();
+    let line_height = fonts.measure_line("", style).1;
+    let mut ink = (x, y, x, y);
+    let mut rest = Some(text);
+    while let Some(text) = rest {
+        let (line, next, width) = fonts.next_line(text, style, w as i32);
+        let left = match align {
+            TextAlign::Left => x,
+            TextAlign::Center => x + (w as i32 - width) / 2,
+            TextAlign::Right => x + w as i32 - width,
+        };
+        draw_run(fonts, canvas, left, y, line, style, value, &mut ink);
+        y += line_height;
+        rest = next;
+    }
+    ink_rect(canvas, ink)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    const STYLE: TextStyle = TextStyle::DEFAULT; // 点阵字体：拉丁字母8像素宽，汉字16像素宽，行高16
+
+    fn fonts() -> Fonts {
+        let mut fonts = Fonts::new();
+        fonts.load();
+        fonts
+    }
+
+    /// 按max_width折成的各行
+    fn lines(fonts: &mut Fonts, text: &'static str, max_width: i32) -> Vec<(&'static str, i32)> {
+        let (mut lines, mut rest) = (Vec::new(), Some(text));
+        while let Some(text) = rest {
+            let (line, next, width) = fonts.next_line(text, STYLE, max_width);
+            lines.push((line, width));
+            rest = next;
+        }
+        lines
+    }
+
+    #[test]
+    fn breaks_at_spaces() {
+        let mut fonts = fonts();
+        assert_eq!(lines(&mut fonts, "hello world", 80), [("hello", 40), ("world", 40)]);
+        // 多个空格都留在上一行末尾
+        assert_eq!(lines(&mut fonts, "ab   cd", 40), [("ab", 16), ("cd", 16)]);
+        assert_eq!(lines(&mut fonts, "hello world", 0), [("hello world", 88)]);
+    }
+
+    #[test]
+    fn breaks_next_to_wide_characters() {
+        let mut fonts = fonts();
+        assert_eq!(lines(&mut fonts, "测试窗口", 40), [("测试", 32), ("窗口", 32)]);
+        assert_eq!(lines(&mut fonts, "ab测试", 40), [("ab测", 32), ("试", 16)]);
+    }
+
+    #[test]
+    fn hard_breaks_long_words() {
+        let mut fonts = fonts();
+        assert_eq!(lines(&mut fonts, "abcdefghijkl", 40), [("abcde", 40), ("fghij", 40), ("kl", 16)]);
+        // 一行至少一个字
+        assert_eq!(lines(&mut fonts, "测试", 8), [("测", 16), ("试", 16)]);
+    }
+
+    #[test]
+    fn breaks_at_newlines() {
+        let mut fonts = fonts();
+        assert_eq!(lines(&mut fonts, "ab\ncd", 0), [("ab", 16), ("cd", 16)]);
+        assert_eq!(lines(&mut fonts, "ab\n", 0), [("ab", 16), ("", 0)]);
+        assert_eq!(lines(&mut fonts, "\n\n", 80), [("", 0), ("", 0), ("", 0)]);
+    }
+
+    #[test]
+    fn measure_counts_lines() {
+        let mut fonts = fonts();
+        assert_eq!(fonts.measure("", STYLE, 0), (0, 16));
+        assert_eq!(fonts.measure("hello world", STYLE, 80), (40, 32));
+        assert_eq!(fonts.measure("abc", STYLE, 0), (24, 16));
+        // 结尾的\n后面还有一个空行
+        assert_eq!(fonts.measure("abc\n", STYLE, 0), (24, 32));
+        assert_eq!(fonts.measure("abc\n\n", STYLE, 0), (24, 48));
+        assert_eq!(fonts.measure("测试窗口\n", STYLE, 40), (32, 48));
+    }
+}
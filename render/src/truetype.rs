@@ -0,0 +1,523 @@
+// ==========================
+// TrueType/OpenType字体
+// ==========================
+// ttf-parser负责解析字体文件、取字形轮廓（no_std，不分配内存），这里把轮廓栅格化成每像素一个字节的覆盖率。
+// 栅格化用面积累加：每条边把它在每个像素里扫过的带符号面积加进累加缓冲区，再整块前缀求和，
+// 绝对值就是像素被覆盖的比例（抗锯齿）。曲线先按弯曲程度切成若干段直线。
+// 画过的字形放在GlyphCache里，按(字体, 大小, 字形)查找；缓存放满时整个清空重新来。
+
+use common::kernel::SharedMemory;
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+/// 启动时加载的TrueType字体（text.rs按这个顺序选常规体/粗体）
+pub(crate) const FACE_FILES: [&[u8]; 2] = [
+    include_bytes!("../fonts/DejaVuSans-Latin1.ttf"),      // DejaVu Sans（Latin-1子集）
+    include_bytes!("../fonts/DejaVuSans-Bold-Latin1.ttf"), // DejaVu Sans Bold（Latin-1子集）
+];
+
+/// 最多加载几个字体
+pub(crate) const MAX_FACES: usize = 4;
+/// 最大字号（像素），更大的按这个画
+pub(crate) const MAX_SIZE: u16 = 128;
+/// 缓存最多记住多少个字形
+const CACHE_SLOTS: usize = 1024;
+/// 缓存里覆盖率数据的总字节数
+const CACHE_BYTES: usize = 256 * 1024;
+/// 栅格化缓冲区最多放多少个像素（最大字号时字形最多约两个em见方）
+const RASTER_PIXELS: usize = (2 * MAX_SIZE as usize) * (2 * MAX_SIZE as usize);
+
+// core里的f32没有floor/ceil/round（no_std），字形坐标都不大，换成整数算
+fn floor(v: f32) -> i32 {
+    let i = v as i32;
+    if (i as f32) > v { i - 1 } else { i }
+}
+
+fn ceil(v: f32) -> i32 {
+    let i = v as i32;
+    if (i as f32) < v { i + 1 } else { i }
+}
+
+fn round(v: f32) -> i32 {
+    floor(v + 0.5)
+}
+
+/// 一个字号下的行距
+#[derive(Clone, Copy)]
+pub(crate) struct Metrics {
+    pub ascent: i32,      // 行框顶到基线
+    pub line_height: u16, // 行高（上伸 + 下伸 + 行间距）
+}
+
+/// 已加载的字体
+pub(crate) struct Faces {
+    faces: [Option<Face<'static>>; MAX_FACES],
+}
+
+impl Faces {
+    pub(crate) const fn new() -> Self {
+        Faces { faces: [const { None }; MAX_FACES] }
+    }
+
+    /// 加载一个字体文件，返回它的编号（解析失败或已满时返回None）
+    pub(crate) fn load(&mut self, data: &'static [u8]) -> Option<u8> {
+        let index = self.faces.iter().position(Option::is_none)?;
+        self.faces[index] = Some(Face::parse(data, 0).ok()?);
+        Some(index as u8)
+    }
+
+    pub(crate) fn get(&self, index: u8) -> Option<&Face<'static>> {
+        self.faces.get(index as usize)?.as_ref()
+    }
+}
+
+/// 字号size（em的像素数）下的行距
+pub(crate) fn metrics(face: &Face, size: u16) -> Metrics {
+    let scale = size as f32 / face.units_per_em() as f32;
+    let ascent = round(face.ascender() as f32 * scale);
+    let descent = round(-(face.descender() as f32) * scale);
+    let gap = round(face.line_gap() as f32 * scale);
+    Metrics { ascent, line_height: (ascent + descent + gap).clamp(1, u16::MAX as i32) as u16 }
+}
+
+/// 栅格化好的字形（位置相对笔位置和基线，向下为正）
+#[derive(Clone, Copy)]
+pub(crate) struct Rendered {
+    pub left: i16,
+    pub top: i16,
+    pub width: u16,
+    pub height: u16,
+    pub advance: u16,
+    offset: u32, // 覆盖率在缓存数据区里的位置（width×height个字节，逐行）
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    key: u64, // 0表示空
+    glyph: Rendered,
+}
+
+/// 字形缓存：开放寻址的散列表 + 一块连续的覆盖率数据区
+pub(crate) struct GlyphCache {
+    slots: [Slot; CACHE_SLOTS],
+    used: usize,
+    data: Option<SharedMemory>,
+    data_used: usize,
+    raster: Option<SharedMemory>, // 栅格化用的累加缓冲区（RASTER_PIXELS个f32）
+}
+
+impl GlyphCache {
+    pub(crate) const fn new() -> Self {
+        const EMPTY: Slot = Slot { key: 0, glyph: Rendered { left: 0, top: 0, width: 0, height: 0, advance: 0, offset: 0 } };
+        GlyphCache { slots: [EMPTY; CACHE_SLOTS], used: 0, data: None, data_used: 0, raster: None }
+    }
+
+    /// face里字形glyph在字号size下的样子和覆盖率（第一次用到时栅格化；size不在1..=MAX_SIZE里时返回None）
+    pub(crate) fn get(&mut self, face_index: u8, face: &Face, size: u16, glyph: GlyphId) -> Option<(Rendered, &[u8])> {
+        if !(1..=MAX_SIZE).contains(&size) {
+            return None;
+        }
+        if self.data.is_none() {
+            self.data = Some(SharedMemory::alloc(CACHE_BYTES)?);
+            self.raster = Some(SharedMemory::alloc(RASTER_PIXELS * core::mem::size_of::<f32>())?);
+        }
+        let key = (1 << 48) | (face_index as u64) << 32 | (size as u64) << 16 | glyph.0 as u64;
+        let rendered = match self.find(key) {
+            Ok(index) => self.slots[index].glyph,
+            Err(_) => self.insert(key, face, size, glyph)?,
+        };
+        let data = self.data.as_ref()?;
+        let len = rendered.width as usize * rendered.height as usize;
+        let coverage = unsafe { core::slice::from_raw_parts(data.as_ptr().add(rendered.offset as usize), len) };
+        Some((rendered, coverage))
+    }
+
+    /// 找key所在的槽（Ok），或者它应该放进去的空槽（Err）
+    fn find(&self, key: u64) -> Result<usize, usize> {
+        let start = (key.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) as usize % CACHE_SLOTS;
+        for i in 0..CACHE_SLOTS {
+            let index = (start + i) % CACHE_SLOTS;
+            match self.slots[index].key {
+                k if k == key => return Ok(index),
+                0 => return Err(index),
+                _ => {}
+            }
+        }
+        Err(start) // 不会发生：表最多放3/4满
+    }
+
+    fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| slot.key = 0);
+        self.used = 0;
+        self.data_used = 0;
+    }
+
+    fn insert(&mut self, key: u64, face: &Face, size: u16, glyph: GlyphId) -> Option<Rendered> {
+        let scale = size as f32 / face.units_per_em() as f32;
+        let advance = round(face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale).max(0) as u16;
+        // 像素边界（y向下）；空格之类没有轮廓的字形只有步进
+        let (left, top, width, height) = match face.glyph_bounding_box(glyph) {
+            Some(bbox) => {
+                let left = floor(bbox.x_min as f32 * scale);
+                let top = floor(-(bbox.y_max as f32) * scale);
+                let right = ceil(bbox.x_max as f32 * scale);
+                let bottom = ceil(-(bbox.y_min as f32) * scale);
+                (left, top, (right - left).max(0) as usize, (bottom - top).max(0) as usize)
+            }
+            None => (0, 0, 0, 0),
+        };
+        let len = width * height;
+        // 累加时会多写一个像素（最后一行的右边），缓冲区留两个
+        if len + 2 > RASTER_PIXELS || len > CACHE_BYTES {
+            return None;
+        }
+        if self.used + 1 > CACHE_SLOTS * 3 / 4 || self.data_used + len > CACHE_BYTES {
+            self.clear();
+        }
+        let offset = self.data_used;
+        if len > 0 {
+            let acc = unsafe { core::slice::from_raw_parts_mut(self.raster.as_ref()?.as_ptr() as *mut f32, len + 2) };
+            acc.fill(0.0);
+            let mut raster = Raster { acc, width, height };
+            let mut outline = Outline {
+                raster: &mut raster,
+                scale,
+                dx: -left as f32,
+                dy: -top as f32,
+                start: (0.0, 0.0),
+                last: (0.0, 0.0),
+            };
+            face.outline_glyph(glyph, &mut outline);
+            outline.finish_contour();
+            let out = unsafe { core::slice::from_raw_parts_mut(self.data.as_ref()?.as_ptr().add(offset), len) };
+            raster.coverage(out);
+        }
+        let rendered = Rendered {
+            left: left as i16,
+            top: top as i16,
+            width: width as u16,
+            height: height as u16,
+            advance,
+            offset: offset as u32,
+        };
+        let index = self.find(key).err()?;
+        self.slots[index] = Slot { key, glyph: rendered };
+        self.used += 1;
+        self.data_used += len;
+        Some(rendered)
+    }
+}
+
+/// 面积累加缓冲区（width×height，逐行）
+struct Raster<'a> {
+    acc: &'a mut [f32],
+    width: usize,
+    height: usize,
+}
+
+impl Raster<'_> {
+    /// 把一条边(x0, y0)→(x1, y1)扫过的带符号面积加进缓冲区（像素坐标，y向下）
+    fn line(&mut self, (x0, y0): (f32, f32), (x1, y1): (f32, f32)) {
+        if y0 == y1 {
+            return; // 水平边不影响覆盖率
+        }
+        let (dir, (x0, y0), (x1, y1)) = if y0 < y1 { (1.0, (x0, y0), (x1, y1)) } else { (-1.0, (x1, y1), (x0, y0)) };
+        let dxdy = (x1 - x0) / (y1 - y0);
+        let mut x = x0;
+        if y0 < 0.0 {
+            x -= y0 * dxdy;
+        }
+        let width = self.width as i32;
+        for row in floor(y0).max(0)..ceil(y1).min(self.height as i32) {
+            let line_start = row * width;
+            // 这条边在这一行里的纵向长度，以及它离开这一行时的x
+            let dy = ((row + 1) as f32).min(y1) - (row as f32).max(y0);
+            let x_next = x + dxdy * dy;
+            let d = dy * dir;
+            let (left, right) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let left_floor = floor(left);
+            let right_ceil = ceil(right);
+            let first = line_start + left_floor;
+            if first < 0 {
+                x = x_next;
+                continue;
+            }
+            let first = first as usize;
+            if right_ceil <= left_floor + 1 {
+                // 只经过一个像素：按边在像素里的平均位置分给它和右边那个
+                let middle = 0.5 * (x + x_next) - left_floor as f32;
+                self.add(first, d - d * middle);
+                self.add(first + 1, d * middle);
+            } else {
+                // 经过好几个像素：两头按三角形面积，中间每个像素一样多
+                let s = 1.0 / (right - left);
+                let left_frac = left - left_floor as f32;
+                let a0 = 0.5 * s * (1.0 - left_frac) * (1.0 - left_frac);
+                let right_frac = right - right_ceil as f32 + 1.0;
+                let am = 0.5 * s * right_frac * right_frac;
+                self.add(first, d * a0);
+                let span = (right_ceil - left_floor) as usize;
+                if span == 2 {
+                    self.add(first + 1, d * (1.0 - a0 - am));
+                } else {
+                    let a1 = s * (1.5 - left_frac);
+                    self.add(first + 1, d * (a1 - a0));
+                    for i in 2..span - 1 {
+                        self.add(first + i, d * s);
+                    }
+                    let a2 = a1 + (span - 3) as f32 * s;
+                    self.add(first + span - 1, d * (1.0 - a2 - am));
+                }
+                self.add(first + span, d * am);
+            }
+            x = x_next;
+        }
+    }
+
+    fn add(&mut self, index: usize, value: f32) {
+        if let Some(cell) = self.acc.get_mut(index) {
+            *cell += value;
+        }
+    }
+
+    /// 前缀求和得到每个像素的覆盖率（0..=255）
+    fn coverage(&self, out: &mut [u8]) {
+        let mut sum = 0.0;
+        for (cell, value) in self.acc.iter().zip(out.iter_mut()) {
+            sum += *cell;
+            let covered = if sum < 0.0 { -sum } else { sum };
+            *value = round(covered.min(1.0) * 255.0) as u8;
+        }
+    }
+}
+
+/// 把ttf-parser给的轮廓（字体单位，y向上）换成像素坐标交给Raster
+struct Outline<'r, 'a> {
+    raster: &'r mut Raster<'a>,
+    scale: f32,
+    dx: f32,
+    dy: f32,
+    start: (f32, f32),
+    last: (f32, f32),
+}
+
+impl Outline<'_, '_> {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (x * self.scale + self.dx, -y * self.scale + self.dy)
+    }
+
+    /// 曲线要切成几段：按二阶差分的大小（像素），越弯段数越多
+    fn segments(deviation: (f32, f32)) -> u32 {
+        let square = deviation.0 * deviation.0 + deviation.1 * deviation.1;
+        if square < 0.333 {
+            return 1;
+        }
+        1 + ((3.0 * square) as u32).isqrt().isqrt()
+    }
+
+    /// 把当前轮廓连回起点
+    fn finish_contour(&mut self) {
+        if self.last != self.start {
+            self.raster.line(self.last, self.start);
+        }
+        self.last = self.start;
+    }
+}
+
+impl OutlineBuilder for Outline<'_, '_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.finish_contour();
+        self.start = self.point(x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.raster.line(self.last, p);
+        self.last = p;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (self.last, self.point(x1, y1), self.point(x, y));
+        let n = Self::segments((p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1));
+        let mut previous = p0;
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            let u = 1.0 - t;
+            let p = (u * u * p0.0 + 2.0 * u * t * p1.0 + t * t * p2.0, u * u * p0.1 + 2.0 * u * t * p1.1 + t * t * p2.1);
+            self.raster.line(previous, p);
+            previous = p;
+        }
+        self.last = p2;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p0, p1, p2, p3) = (self.last, self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        let d0 = (p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1);
+        let d1 = (p1.0 - 2.0 * p2.0 + p3.0, p1.1 - 2.0 * p2.1 + p3.1);
+        let n = Self::segments(d0).max(Self::segments(d1));
+        let mut previous = p0;
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            let p = (a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0, a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1);
+            self.raster.line(previous, p);
+            previous = p;
+        }
+        self.last = p3;
+    }
+
+    fn close(&mut self) {
+        self.finish_contour();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// 把多边形（像素坐标）栅格化成width×height的覆盖率
+    fn rasterize(width: usize, height: usize, points: &[(f32, f32)]) -> Vec<u8> {
+        let mut acc = std::vec![0.0; width * height + 2];
+        let mut raster = Raster { acc: &mut acc, width, height };
+        for (i, &p) in points.iter().enumerate() {
+            raster.line(p, points[(i + 1) % points.len()]);
+        }
+        let mut out = std::vec![0; width * height];
+        raster.coverage(&mut out);
+        out
+    }
+
+    fn total(coverage: &[u8]) -> f32 {
+        coverage.iter().map(|&c| c as f32 / 255.0).sum()
+    }
+
+    fn face() -> Face<'static> {
+        Face::parse(FACE_FILES[0], 0).unwrap()
+    }
+
+    #[test]
+    fn covers_whole_pixels() {
+        let square = rasterize(4, 4, &[(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0)]);
+        #[rustfmt::skip]
+        assert_eq!(square, [
+            0, 0, 0, 0,
+            0, 255, 255, 0,
+            0, 255, 255, 0,
+            0, 0, 0, 0,
+        ]);
+        // 反方向绕也一样
+        let reversed = rasterize(4, 4, &[(1.0, 3.0), (3.0, 3.0), (3.0, 1.0), (1.0, 1.0)]);
+        assert_eq!(reversed, square);
+    }
+
+    #[test]
+    fn covers_partial_pixels() {
+        // 错开半个像素的2×1矩形：两头的像素各盖一半
+        let coverage = rasterize(4, 1, &[(0.5, 0.0), (2.5, 0.0), (2.5, 1.0), (0.5, 1.0)]);
+        assert_eq!(coverage, [128, 255, 128, 0]);
+        // 对角线切开的正方形：对角线上的像素各盖一半
+        let coverage = rasterize(2, 2, &[(0.0, 0.0), (2.0, 0.0), (0.0, 2.0)]);
+        assert_eq!(coverage, [255, 128, 128, 0]);
+    }
+
+    #[test]
+    fn coverage_sums_to_area() {
+        let shapes: [&[(f32, f32)]; 3] = [
+            &[(0.3, 0.2), (7.6, 1.1), (2.2, 6.9)],            // 三角形：面积23.6
+            &[(4.0, 0.5), (7.5, 4.0), (4.0, 7.5), (0.5, 4.0)], // 斜放的正方形：面积24.5
+            // 中间挖空（里面的轮廓反方向绕）：面积48
+            &[(0.0, 0.0), (8.0, 0.0), (8.0, 8.0), (0.0, 8.0), (0.0, 0.0), (2.0, 2.0), (2.0, 6.0), (6.0, 6.0), (6.0, 2.0), (2.0, 2.0)],
+        ];
+        for (shape, area) in shapes.into_iter().zip([23.6, 24.5, 48.0]) {
+            let sum = total(&rasterize(8, 8, shape));
+            assert!((sum - area).abs() < 0.1, "{sum} != {area}");
+        }
+    }
+
+    #[test]
+    fn clips_to_raster() {
+        // 超出上下边界的部分不算，也不会写到缓冲区外面
+        let coverage = rasterize(4, 4, &[(1.0, -2.0), (3.0, -1.0), (3.0, 6.0), (1.0, 6.0)]);
+        assert_eq!(coverage.iter().filter(|&&c| c == 255).count(), 8);
+        assert_eq!(total(&coverage), 8.0);
+    }
+
+    #[test]
+    fn caches_glyphs() {
+        let face = face();
+        let glyph = face.glyph_index('A').unwrap();
+        let mut cache = GlyphCache::new();
+        let (first, coverage) = cache.get(0, &face, 16, glyph).unwrap();
+        assert!(first.width > 0 && first.height > 0 && first.advance > 0);
+        assert_eq!(coverage.len(), first.width as usize * first.height as usize);
+        assert!(coverage.iter().any(|&c| c == 255));
+        let (used, data_used) = (cache.used, cache.data_used);
+        // 第二次直接用缓存
+        let (second, _) = cache.get(0, &face, 16, glyph).unwrap();
+        assert_eq!((second.offset, second.width), (first.offset, first.width));
+        assert_eq!((cache.used, cache.data_used), (used, data_used));
+        // 字号不同是另一个字形
+        let (bigger, _) = cache.get(0, &face, 32, glyph).unwrap();
+        assert!(bigger.width > first.width);
+        assert_eq!(cache.used, used + 1);
+    }
+
+    #[test]
+    fn clears_when_slots_run_out() {
+        let face = face();
+        let mut cache = GlyphCache::new();
+        let glyphs = face.number_of_glyphs();
+        let mut inserted = 0;
+        'fill: for size in 1..=MAX_SIZE {
+            for id in 0..glyphs {
+                let used = cache.used;
+                cache.get(0, &face, size, GlyphId(id)).unwrap();
+                assert!(cache.used <= CACHE_SLOTS * 3 / 4);
+                if cache.used < used {
+                    break 'fill;
+                }
+                inserted += 1;
+            }
+        }
+        // 放满3/4之后下一个字形清空缓存，只剩它自己
+        assert_eq!(inserted, CACHE_SLOTS * 3 / 4);
+        assert_eq!(cache.used, 1);
+    }
+
+    #[test]
+    fn clears_when_data_runs_out() {
+        let face = face();
+        let mut cache = GlyphCache::new();
+        let glyph = face.glyph_index('W').unwrap();
+        let mut cleared = false;
+        for face_index in 0..=u8::MAX {
+            let data_used = cache.data_used;
+            let (rendered, _) = cache.get(face_index, &face, MAX_SIZE, glyph).unwrap();
+            assert!(cache.data_used <= CACHE_BYTES);
+            assert_eq!(rendered.offset as usize + rendered.width as usize * rendered.height as usize, cache.data_used);
+            if cache.data_used < data_used {
+                cleared = true;
+                break;
+            }
+        }
+        assert!(cleared);
+        assert_eq!(cache.used, 1);
+    }
+
+    #[test]
+    fn rejects_out_of_range_sizes() {
+        let face = face();
+        let glyph = face.glyph_index('W').unwrap();
+        let mut cache = GlyphCache::new();
+        assert!(cache.get(0, &face, 0, glyph).is_none());
+        assert!(cache.get(0, &face, MAX_SIZE + 1, glyph).is_none());
+        assert!(cache.get(0, &face, u16::MAX, glyph).is_none());
+        assert_eq!(cache.used, 0);
+        let (rendered, _) = cache.get(0, &face, MAX_SIZE, glyph).unwrap();
+        assert!((rendered.width as usize + 2) * (rendered.height as usize) < RASTER_PIXELS);
+    }
+}
@@ -1,15 +1,24 @@
 #![no_std]
 
-use common::{WindowId, IpcMessage, kernel, Pid};
-use core::ffi::CStr;
+use common::{ipc::{Endpoint, Incoming, IpcError}, kernel::SharedMemory, theme::WidgetTheme, wire::WireError, DamageList, WindowId, IpcMessage, IpcStr, kernel, Pid, Pixel, PixelFormat, Rect, SurfaceId};
 
-pub use common::Rgb;  // 保留这行
+pub use common::{Rgb, Rgba};  // 保留这行
+pub use common::{FontFamily, FontWeight, TextAlign, TextStyle};
+pub use common::theme::Theme;
+pub use common::StackLayer;
 pub use common;  // 重新导出common模块
+pub use common::command::RenderCommandBuffer;
+pub use common::image::{BlitMode, Image};
 
 // 控件基类（所有控件继承此特性）
+// draw把绘图命令录进cmds，画在当前绘图目标上（窗口里的控件用Window::draw，坐标相对内容区左上角），
+// 调用方画完一批后再submit；
+// 控件只画在自己的范围内（push_clip/pop_clip），容器控件画子控件前先压入自己的范围
+// 主题换了（ThemeChanged）时调用set_theme，之后重画；控件默认用默认主题
 pub trait Widget {
-    fn draw(&self, window_id: WindowId, render_pid: Pid);
+    fn draw(&self, cmds: &mut RenderCommandBuffer);
     fn on_click(&mut self, x: u16, y: u16);
+    fn set_theme(&mut self, _theme: &Theme) {}
 }
 
 // 按钮控件
@@ -18,61 +27,372 @@ pub struct Button {
     y: u16,
     width: u16,
     height: u16,
-    _text: &'static str,
+    text: IpcStr,
+    style: Option<TextStyle>, // 标签的字体（None：用主题里的按钮字体）
+    theme: WidgetTheme,
     color: Rgb,
     callback: fn(),  // 点击回调函数
 }
 
 impl Button {
-    pub fn new(x: u16, y: u16, width: u16, height: u16, text: &'static str, color: Rgb, callback: fn()) -> Self {
-        Button { x, y, width, height, _text: text, color, callback }
+    pub fn new(x: u16, y: u16, width: u16, height: u16, text: &str, color: Rgb, callback: fn()) -> Self {
+        let theme = Theme::default().widget;
+        Button { x, y, width, height, text: IpcStr::new(text), style: None, theme, color, callback }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    // 改宽度（比如按measure_text量出的标签宽度调整）
+    pub fn set_width(&mut self, width: u16) {
+        self.width = width;
+    }
+
+    // 换标签字体（之后不再跟着主题变）
+    pub fn set_text_style(&mut self, style: TextStyle) {
+        self.style = Some(style);
+    }
+
+    // 移动按钮（比如任务栏按钮排队时重新摆放）
+    pub fn set_position(&mut self, x: u16, y: u16) {
+        self.x = x;
+        self.y = y;
+    }
+
+    // 检查坐标是否落在按钮范围内
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
     }
 }
 
 impl Widget for Button {
-    fn draw(&self, _window_id: WindowId, render_pid: Pid) {
-        // 绘制按钮背景（调用渲染服务）
-        let msg = IpcMessage::RenderDrawRect {
-            x: self.x,
-            y: self.y,
-            w: self.width,
-            h: self.height,
-            color: self.color,
-        };
-        unsafe { kernel::ipc_send(render_pid, &msg); }
+    fn draw(&self, cmds: &mut RenderCommandBuffer) {
+        // 只画在按钮范围内（外层还有窗口或父控件的裁剪区）
+        cmds.push_clip(self.x, self.y, self.width, self.height);
+        // 绘制按钮背景
+        cmds.rect(self.x, self.y, self.width, self.height, self.color);
+        // 标签在按钮里居中（太长时折行）；深色背景用白字，浅色背景用黑字
+        let brightness = (self.color.r as u32 * 299 + self.color.g as u32 * 587 + self.color.b as u32 * 114) / 1000;
+        let label = if brightness < 140 { self.theme.button_light_text } else { self.theme.button_dark_text };
+        let style = self.style.unwrap_or(self.theme.button_font);
+        cmds.text_box(self.x, self.y, self.width, self.height, self.text.as_str(), style, TextAlign::Center, label);
+        cmds.pop_clip();
     }
 
     fn on_click(&mut self, x: u16, y: u16) {
         // 检查点击是否在按钮范围内
-        if x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height {
+        if self.contains(x, y) {
             (self.callback)();  // 触发回调
         }
     }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.widget;
+    }
+}
+
+// 文字标签：超过width的部分折行，每行按align对齐（width为0时不折行）
+// 高度由文字决定（可以用measure_text先量），所以不裁剪，外层控件负责。
+// 文字最多IPC_STR_CAPACITY字节（UTF-8），更长的不收（不截断）
+pub struct Label {
+    x: u16,
+    y: u16,
+    width: u16,
+    text: IpcStr,
+    style: TextStyle,
+    align: TextAlign,
+    color: Rgba,
+}
+
+impl Label {
+    // 文字太长时返回None
+    pub fn new(x: u16, y: u16, width: u16, text: &str, style: TextStyle, color: impl Into<Rgba>) -> Option<Self> {
+        let text = IpcStr::try_new(text)?;
+        Some(Label { x, y, width, text, style, align: TextAlign::Left, color: color.into() })
+    }
+
+    // 文字太长时返回false，标签保持原来的文字
+    pub fn set_text(&mut self, text: &str) -> bool {
+        let Some(text) = IpcStr::try_new(text) else {
+            return false;
+        };
+        self.text = text;
+        true
+    }
+
+    // 改折行宽度（比如窗口大小变了重新布局）
+    pub fn set_width(&mut self, width: u16) {
+        self.width = width;
+    }
+
+    pub fn set_align(&mut self, align: TextAlign) {
+        self.align = align;
+    }
+
+    pub fn set_color(&mut self, color: impl Into<Rgba>) {
+        self.color = color.into();
+    }
+}
+
+impl Widget for Label {
+    fn draw(&self, cmds: &mut RenderCommandBuffer) {
+        // 文字不超过IpcStr的容量，text_box不会拒绝
+        cmds.text_box(self.x, self.y, self.width, 0, self.text.as_str(), self.style, self.align, self.color);
+    }
+
+    fn on_click(&mut self, _x: u16, _y: u16) {}
+}
+
+// 图标控件（桌面Dock等处使用）：有位图时缩放到图标大小，没有时画占位方块
+pub struct Icon {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub name: &'static str,
+    pub image: Option<Image>, // 图标的位图（比如Image::decode解码的文件）
+    placeholder: Rgb, // 没有位图时的占位颜色（来自主题）
+    callback: fn(),  // 点击回调函数（比如启动应用）
+}
+
+impl Icon {
+    pub fn new(x: u16, y: u16, width: u16, height: u16, name: &'static str, callback: fn()) -> Self {
+        let placeholder = Theme::default().widget.icon_placeholder;
+        Icon { x, y, width, height, name, image: None, placeholder, callback }
+    }
+}
+
+impl Widget for Icon {
+    fn draw(&self, cmds: &mut RenderCommandBuffer) {
+        // 只画在图标范围内；位图按alpha混合（可以有透明的圆角），没有位图时用浅色方块占位
+        cmds.push_clip(self.x, self.y, self.width, self.height);
+        match &self.image {
+            Some(image) => cmds.blit(image, self.x, self.y, self.width, self.height, BlitMode::Alpha),
+            None => cmds.rect(self.x, self.y, self.width, self.height, self.placeholder),
+        };
+        cmds.pop_clip();
+    }
+
+    fn on_click(&mut self, x: u16, y: u16) {
+        if x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height {
+            (self.callback)();
+        }
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.placeholder = theme.widget.icon_placeholder;
+    }
 }
 
 // 窗口结构体（供应用创建窗口）
 pub struct Window {
     id: WindowId,
-    _wm_pid: Pid,  // 窗口管理器PID
+    surface: SurfaceId, // 窗口的离屏表面（Window::draw会先切过去）
+    wm_pid: Pid,  // 窗口管理器PID
 }
 
 impl Window {
-    // 创建新窗口（向WM发送请求）
-    pub fn new(title: &'static str, x: u16, y: u16, width: u16, height: u16) -> Self {
-        unsafe {
-            // 获取窗口管理器PID
-            let wm_name = CStr::from_bytes_with_nul(b"wm\0").unwrap();
-            let wm_pid = kernel::get_service_pid(wm_name.as_ptr() as *const u8);
-            // 发送创建窗口消息
-            let msg = IpcMessage::WmCreateWindow { x, y, w: width, h: height, title };
-            kernel::ipc_send(wm_pid, &msg);
-
-            // 简化：假设窗口ID为1（实际应从WM的回复中获取）
-            Window { id: 1, _wm_pid: wm_pid }
+    // 创建新窗口（向WM发送请求，等待WM回复真实的窗口ID）
+    // endpoint是应用收消息用的端点：等回复期间到达的其他消息会暂存在里面
+    pub fn new(endpoint: &mut Endpoint, title: &str, x: u16, y: u16, width: u16, height: u16) -> Result<Self, IpcError> {
+        // 发送创建窗口消息
+        let msg = IpcMessage::WmCreateWindow { x, y, w: width, h: height, title: IpcStr::new(title) };
+        Self::create(endpoint, &msg)
+    }
+
+    // 创建面板（没有标题栏和边框，不获得焦点，不能拖动；整个表面都是内容区），放在layer层
+    pub fn new_panel(endpoint: &mut Endpoint, x: u16, y: u16, width: u16, height: u16, layer: StackLayer) -> Result<Self, IpcError> {
+        Self::create(endpoint, &IpcMessage::WmCreatePanel { x, y, w: width, h: height, layer })
+    }
+
+    // 把创建请求发给WM，等它回复窗口ID和表面
+    fn create(endpoint: &mut Endpoint, msg: &IpcMessage) -> Result<Self, IpcError> {
+        // 获取窗口管理器PID
+        let wm_pid = unsafe { kernel::get_service_pid(c"wm".as_ptr() as *const u8) };
+        match endpoint.call(wm_pid, msg)? {
+            IpcMessage::WmWindowCreated { window_id, surface } => Ok(Window { id: window_id, surface, wm_pid }),
+            _ => Err(IpcError::UnexpectedReply),
         }
     }
 
     pub fn id(&self) -> WindowId {
         self.id
     }
-}
\ No newline at end of file
+
+    // 窗口的表面（应用画的时候坐标相对内容区左上角）
+    pub fn surface(&self) -> SurfaceId {
+        self.surface
+    }
+
+    // 限制内容区的大小（用户拖边框、最大化时WM不会超出），max为0表示不限制
+    pub fn set_size_limits(&self, min_width: u16, min_height: u16, max_width: u16, max_height: u16) {
+        let msg = IpcMessage::WmSetSizeLimits { window_id: self.id, min_width, min_height, max_width, max_height };
+        kernel::send(self.wm_pid, &msg);
+    }
+
+    // 提到它那一层的最上面、降到最下面
+    pub fn raise(&self) {
+        kernel::send(self.wm_pid, &IpcMessage::WmRaiseWindow { window_id: self.id });
+    }
+
+    pub fn lower(&self) {
+        kernel::send(self.wm_pid, &IpcMessage::WmLowerWindow { window_id: self.id });
+    }
+
+    // 换到layer层（总在普通窗口上面或下面）
+    pub fn set_stack_layer(&self, layer: StackLayer) {
+        kernel::send(self.wm_pid, &IpcMessage::WmSetStackLayer { window_id: self.id, layer });
+    }
+
+    // 设成parent的子窗口（对话框等）：总叠在parent上面，None表示不再是子窗口
+    pub fn set_transient_for(&self, parent: Option<&Window>) {
+        let parent = parent.map_or(0, |parent| parent.id);
+        kernel::send(self.wm_pid, &IpcMessage::WmSetTransientFor { window_id: self.id, parent });
+    }
+
+    // 挪到屏幕上的(x, y)（窗口左上角）
+    pub fn move_to(&self, x: u16, y: u16) {
+        kernel::send(self.wm_pid, &IpcMessage::WmMoveWindow { window_id: self.id, x, y });
+    }
+
+    // msg是这个窗口的WmWindowResized时返回新的内容区(宽, 高)，别的消息返回None；
+    // 调用方按新大小重新布局再重画（WM已经用背景色清掉了内容区），之前的SurfaceBuffer作废，要重新buffer
+    pub fn resized(&self, msg: &IpcMessage) -> Option<(u16, u16)> {
+        match *msg {
+            IpcMessage::WmWindowResized { window_id, width, height } if window_id == self.id => Some((width, height)),
+            _ => None,
+        }
+    }
+
+    // 在窗口里画控件：切到窗口的表面，坐标相对内容区左上角（标题栏下面），
+    // WM移动窗口时不用重画
+    pub fn draw(&self, widget: &dyn Widget, cmds: &mut RenderCommandBuffer) {
+        cmds.target(self.surface);
+        widget.draw(cmds);
+    }
+
+    // 映射窗口的内容区，直接写像素
+    pub fn buffer(&self, endpoint: &mut Endpoint) -> Result<SurfaceBuffer, IpcError> {
+        SurfaceBuffer::map(endpoint, self.surface)
+    }
+}
+
+// 可以直接写像素的窗口内容区（图片查看器、编辑器这类画面用）
+// 和渲染服务共享同一块内存：像素是预乘alpha的0xAARRGGBB（见Rgba::to_premultiplied），坐标相对内容区左上角；
+// 写完调用commit告诉渲染服务改了哪些区域，渲染服务合成后呈现
+pub struct SurfaceBuffer {
+    surface: SurfaceId,
+    memory: SharedMemory,
+    stride: usize, // 表面每行的像素数
+    area: Rect,    // 能写的区域在表面上的位置（内容区）
+    render_pid: Pid,
+}
+
+impl SurfaceBuffer {
+    // 向渲染服务要表面的共享内存并映射（只有窗口所属的应用能映射，否则返回Refused）
+    pub fn map(endpoint: &mut Endpoint, surface: SurfaceId) -> Result<Self, IpcError> {
+        let render_pid = unsafe { kernel::get_service_pid(c"render".as_ptr() as *const u8) };
+        match endpoint.call(render_pid, &IpcMessage::RenderMapSurface { surface })? {
+            IpcMessage::RenderSurfaceMapped { shm, width, height, area, .. } => {
+                if area.right() > width as u32 || area.bottom() > height as u32 {
+                    return Err(IpcError::UnexpectedReply);
+                }
+                let size = PixelFormat::xrgb8888(width).buffer_size(height);
+                let memory = SharedMemory::map_from(render_pid, shm, size).ok_or(IpcError::Refused)?;
+                Ok(SurfaceBuffer { surface, memory, stride: width as usize, area, render_pid })
+            }
+            _ => Err(IpcError::UnexpectedReply),
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.area.w
+    }
+
+    pub fn height(&self) -> u16 {
+        self.area.h
+    }
+
+    // 第y行的像素（y超出范围时返回空切片）
+    pub fn row(&mut self, y: u16) -> &mut [Pixel] {
+        if y >= self.area.h {
+            return &mut [];
+        }
+        let start = (self.area.y + y) as usize * self.stride + self.area.x as usize;
+        unsafe { core::slice::from_raw_parts_mut((self.memory.as_ptr() as *mut Pixel).add(start), self.area.w as usize) }
+    }
+
+    // 写一个像素（直接替换，不和原来的混合；超出范围的忽略）
+    pub fn put_pixel(&mut self, x: u16, y: u16, color: impl Into<Rgba>) {
+        if let Some(pixel) = self.row(y).get_mut(x as usize) {
+            *pixel = color.into().to_premultiplied();
+        }
+    }
+
+    // 填充矩形（直接替换；裁剪到内容区内）
+    pub fn fill_rect(&mut self, x: u16, y: u16, w: u16, h: u16, color: impl Into<Rgba>) {
+        let Some(rect) = Rect::new(x, y, w, h).clip(self.area.w, self.area.h) else {
+            return;
+        };
+        let pixel = color.into().to_premultiplied();
+        for y in rect.y..rect.y + rect.h {
+            self.row(y)[rect.x as usize..rect.right() as usize].fill(pixel);
+        }
+    }
+
+    // 把改过的区域交给渲染服务合成并呈现（rects为空表示整个内容区；太多时合并成几块）
+    pub fn commit(&self, rects: &[Rect]) {
+        let mut damage = DamageList::new();
+        for rect in rects {
+            damage.add(*rect);
+        }
+        kernel::send(self.render_pid, &IpcMessage::RenderDamageSurface { surface: self.surface, rects: damage });
+    }
+}
+
+// 量文字画出来的(宽, 高)：按max_width折行（0表示不折行），和text_box/Label排出来的完全一样
+// 用来摆放控件（比如按标题宽度决定按钮多宽）；等渲染服务回复期间的其他消息暂存在endpoint里
+// text最多IPC_STR_CAPACITY字节，更长时返回Encode(BadLength)（量截断了的文字会得到错的大小）
+pub fn measure_text(endpoint: &mut Endpoint, text: &str, style: TextStyle, max_width: u16) -> Result<(u16, u16), IpcError> {
+    let text = IpcStr::try_new(text).ok_or(IpcError::Encode(WireError::BadLength))?;
+    let render_pid = unsafe { kernel::get_service_pid(c"render".as_ptr() as *const u8) };
+    match endpoint.call(render_pid, &IpcMessage::RenderMeasureText { text, style, max_width })? {
+        IpcMessage::RenderTextMeasured { width, height } => Ok((width, height)),
+        _ => Err(IpcError::UnexpectedReply),
+    }
+}
+
+// 向WM订阅主题变化，返回当前主题（WM没有回复或主题读不了时用默认主题）；
+// 之后WM换主题时会发来ThemeChanged，交给theme_changed处理
+pub fn subscribe_theme(endpoint: &mut Endpoint) -> Theme {
+    let wm_pid = unsafe { kernel::get_service_pid(c"wm".as_ptr() as *const u8) };
+    match endpoint.call(wm_pid, &IpcMessage::WmSubscribeTheme) {
+        Ok(msg) => read_theme(wm_pid, &msg).unwrap_or_default(),
+        Err(_) => Theme::default(),
+    }
+}
+
+// 收到的是ThemeChanged时返回新主题（调用方把它交给控件的set_theme再重画），别的消息返回None
+pub fn theme_changed(incoming: &Incoming) -> Option<Theme> {
+    read_theme(incoming.sender, &incoming.msg)
+}
+
+// sender发来的ThemeChanged里的主题（主题文本在sender分配的共享内存里）
+fn read_theme(sender: Pid, msg: &IpcMessage) -> Option<Theme> {
+    match *msg {
+        // WM解析过这段文本，读不了说明共享内存出了问题（或者不是WM发来的），这时继续用原来的主题
+        IpcMessage::ThemeChanged { text, len } => Theme::from_shared(sender, text, len).ok(),
+        _ => None,
+    }
+}
+
+// 把已经画好的内容呈现到屏幕上（渲染服务画在后台缓冲区里，不提交就看不到）
+// 用RenderDrawRect等单条消息画图时调用；用RenderCommandBuffer时submit就会呈现
+pub fn present(render_pid: Pid) {
+    kernel::send(render_pid, &IpcMessage::RenderCommit);
+}
@@ -0,0 +1,392 @@
+// ==========================
+// 宿主模拟内核：用std实现common::kernel的FFI接口
+// ==========================
+// 每个服务/应用是一个线程；服务名称表、消息队列（按字节拷贝的
+// 编码后消息）、"物理内存"都放在全局的Kernel里。
+
+use common::{wire, IpcMessage, Pid};
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    ffi::CStr,
+    fs::OpenOptions,
+    io::Write,
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// 查找服务时最多等待多久（服务线程启动有先后，给注册留点时间）
+const SERVICE_LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 模拟显卡帧缓冲区的"物理地址"（和Bochs/QEMU的线性帧缓冲区一致）
+const FB_PHYS: u64 = 0xE000_0000;
+/// shm_alloc分配的"物理地址"从这里开始（不和帧缓冲区重叠）
+const SHM_BASE: u64 = 0x1_0000_0000;
+/// shm_alloc按页对齐
+const PAGE_SIZE: u64 = 4096;
+
+/// VESA VBE模式信息（必须和内核、gpu-driver里的VesaInfo布局一致）
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct VesaInfo {
+    pub width: u16,
+    pub height: u16,
+    pub bpp: u8,
+    pub fb_phys: u64,
+}
+
+impl VesaInfo {
+    /// 模拟一个VBE模式：bpp为15/16/24/32（通道按RGB顺序，行尾没有填充）
+    pub fn mode(width: u16, height: u16, bpp: u8) -> Result<Self, String> {
+        if !matches!(bpp, 15 | 16 | 24 | 32) {
+            return Err(format!("不支持{bpp}bpp（可选15/16/24/32）"));
+        }
+        Ok(VesaInfo { width, height, bpp, fb_phys: FB_PHYS })
+    }
+
+    /// 每行字节数
+    fn pitch(&self) -> usize {
+        self.width as usize * (self.bpp as usize).div_ceil(8)
+    }
+}
+
+/// 信封：发送者PID + 编码后的消息字节
+struct Envelope {
+    sender: Pid,
+    bytes: Vec<u8>,
+}
+
+/// 每个进程的消息队列
+struct Mailbox {
+    queue: Mutex<VecDeque<Envelope>>,
+    ready: Condvar,
+}
+
+/// 一段模拟的"物理内存"（泄漏的堆内存，地址在整个进程生命周期内有效）
+struct PhysRegion {
+    base: u64,
+    ptr: usize,
+    len: usize,
+}
+
+/// shm_alloc分配出去的一块共享内存（shm_owner按它回答是谁分配的）
+struct ShmBlock {
+    base: u64,
+    len: usize,
+    owner: Pid,
+}
+
+/// 模拟内核全局状态
+pub struct Kernel {
+    next_pid: Mutex<Pid>,
+    services: Mutex<HashMap<String, Pid>>, // 服务名称 → PID
+    registered: Condvar,                   // 有新服务注册时唤醒查找者
+    mailboxes: Mutex<HashMap<Pid, Arc<Mailbox>>>,
+    phys: Mutex<Vec<PhysRegion>>,
+    next_shm: Mutex<u64>, // 下一块共享内存的"物理地址"
+    shm_blocks: Mutex<Vec<ShmBlock>>, // 分配出去的共享内存和分配者
+    apps: Mutex<HashMap<String, fn() -> !>>, // spawn_process可启动的应用
+    vesa: VesaInfo,
+    boot_time: Instant,
+}
+
+thread_local! {
+    // 当前线程对应的进程PID（0表示还没接入内核）
+    static CURRENT_PID: Cell<Pid> = const { Cell::new(0) };
+}
+
+static KERNEL: OnceLock<Kernel> = OnceLock::new();
+
+/// 启动模拟内核（只能调用一次），按VBE模式分配帧缓冲区
+pub fn boot(vesa: VesaInfo) -> &'static Kernel {
+    let kernel = Kernel {
+        next_pid: Mutex::new(1), // 从1开始（0表示服务不存在）
+        services: Mutex::new(HashMap::new()),
+        registered: Condvar::new(),
+        mailboxes: Mutex::new(HashMap::new()),
+        phys: Mutex::new(Vec::new()),
+        next_shm: Mutex::new(SHM_BASE),
+        shm_blocks: Mutex::new(Vec::new()),
+        apps: Mutex::new(HashMap::new()),
+        vesa,
+        boot_time: Instant::now(),
+    };
+    if KERNEL.set(kernel).is_err() {
+        panic!("模拟内核已经启动过了");
+    }
+    let kernel = get();
+    // 预先分配帧缓冲区（gpu-driver之后通过map_phys映射它）
+    kernel.map_phys(FB_PHYS, kernel.framebuffer_len());
+    kernel
+}
+
+/// 获取模拟内核（boot之前调用会panic）
+pub fn get() -> &'static Kernel {
+    KERNEL.get().expect("模拟内核还没有启动")
+}
+
+impl Kernel {
+    /// 分配一个新PID并建立消息队列
+    fn new_process(&self) -> Pid {
+        let pid = {
+            let mut next = self.next_pid.lock().unwrap();
+            let pid = *next;
+            *next += 1;
+            pid
+        };
+        let mailbox = Arc::new(Mailbox { queue: Mutex::new(VecDeque::new()), ready: Condvar::new() });
+        self.mailboxes.lock().unwrap().insert(pid, mailbox);
+        pid
+    }
+
+    /// 在新线程里启动一个服务/应用，返回它的PID
+    pub fn spawn(&self, name: &str, entry: fn() -> !) -> Pid {
+        let pid = self.new_process();
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                CURRENT_PID.with(|current| current.set(pid));
+                entry()
+            })
+            .expect("无法创建服务线程");
+        pid
+    }
+
+    /// 让当前线程（比如模拟器的控制台）也成为一个进程，可以收发消息
+    pub fn attach_current_thread(&self) -> Pid {
+        let pid = self.new_process();
+        CURRENT_PID.with(|current| current.set(pid));
+        pid
+    }
+
+    /// 登记可以被spawn_process按名称启动的应用
+    pub fn register_app(&self, name: &str, entry: fn() -> !) {
+        self.apps.lock().unwrap().insert(name.to_string(), entry);
+    }
+
+    /// 按名称启动已登记的应用
+    pub fn spawn_app(&self, name: &str) -> Option<Pid> {
+        let entry = *self.apps.lock().unwrap().get(name)?;
+        Some(self.spawn(name, entry))
+    }
+
+    /// 注册服务名称（同名服务已被其他进程注册时失败）
+    pub fn register_service(&self, name: &str, pid: Pid) -> bool {
+        let mut services = self.services.lock().unwrap();
+        match services.get(name) {
+            Some(&owner) if owner != pid => false,
+            _ => {
+                services.insert(name.to_string(), pid);
+                self.registered.notify_all();
+                true
+            }
+        }
+    }
+
+    /// 查找服务PID，最多等待timeout让服务完成注册；找不到返回None
+    pub fn wait_for_service(&self, name: &str, timeout: Duration) -> Option<Pid> {
+        let deadline = Instant::now() + timeout;
+        let mut services = self.services.lock().unwrap();
+        loop {
+            if let Some(&pid) = services.get(name) {
+                return Some(pid);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            services = self.registered.wait_timeout(services, deadline - now).unwrap().0;
+        }
+    }
+
+    /// 投递消息字节到目标进程的队列（目标不存在时丢弃，和内核行为一致）
+    pub fn send_bytes(&self, sender: Pid, target: Pid, bytes: &[u8]) -> bool {
+        let mailbox = match self.mailboxes.lock().unwrap().get(&target) {
+            Some(mailbox) => mailbox.clone(),
+            None => return false,
+        };
+        mailbox.queue.lock().unwrap().push_back(Envelope { sender, bytes: bytes.to_vec() });
+        mailbox.ready.notify_one();
+        true
+    }
+
+    /// 编码并投递一条消息（模拟器自己注入事件时用）
+    pub fn send(&self, sender: Pid, target: Pid, msg: &IpcMessage) -> bool {
+        let mut buf = [0u8; wire::MAX_MESSAGE_SIZE];
+        match wire::encode(msg, &mut buf) {
+            Ok(len) => self.send_bytes(sender, target, &buf[..len]),
+            Err(_) => false,
+        }
+    }
+
+    /// 接收发给pid的下一条消息字节，最多等待timeout（None表示一直等）；超时返回None
+    pub fn recv_bytes(&self, pid: Pid, timeout: Option<Duration>) -> Option<(Pid, Vec<u8>)> {
+        let mailbox = self.mailboxes.lock().unwrap().get(&pid).cloned().expect("进程没有消息队列");
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut queue = mailbox.queue.lock().unwrap();
+        loop {
+            if let Some(envelope) = queue.pop_front() {
+                return Some((envelope.sender, envelope.bytes));
+            }
+            queue = match deadline {
+                None => mailbox.ready.wait(queue).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    mailbox.ready.wait_timeout(queue, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+
+    /// 启动以来的毫秒数
+    pub fn uptime_ms(&self) -> u64 {
+        self.boot_time.elapsed().as_millis() as u64
+    }
+
+    /// 把"物理地址"映射成可访问的内存：已有区域直接返回，否则分配一段清零的内存
+    pub fn map_phys(&self, phys_addr: u64, size: usize) -> *mut u8 {
+        let mut regions = self.phys.lock().unwrap();
+        for region in regions.iter() {
+            if phys_addr >= region.base && phys_addr + size as u64 <= region.base + region.len as u64 {
+                return (region.ptr + (phys_addr - region.base) as usize) as *mut u8;
+            }
+        }
+        let memory: &'static mut [u8] = Box::leak(vec![0u8; size].into_boxed_slice());
+        let ptr = memory.as_mut_ptr();
+        regions.push(PhysRegion { base: phys_addr, ptr: ptr as usize, len: size });
+        ptr
+    }
+
+    /// 给owner分配一段清零的共享内存，返回它的"物理地址"（之后用map_phys映射）
+    pub fn shm_alloc(&self, owner: Pid, size: usize) -> u64 {
+        let base = {
+            let mut next = self.next_shm.lock().unwrap();
+            let base = *next;
+            *next += (size as u64).div_ceil(PAGE_SIZE).max(1) * PAGE_SIZE;
+            base
+        };
+        self.map_phys(base, size);
+        self.shm_blocks.lock().unwrap().push(ShmBlock { base, len: size, owner });
+        base
+    }
+
+    /// [handle, handle+size)落在哪个进程分配的共享内存里（0：不是shm_alloc分配的）
+    pub fn shm_owner(&self, handle: u64, size: usize) -> Pid {
+        let Some(end) = handle.checked_add(size as u64) else {
+            return 0;
+        };
+        let blocks = self.shm_blocks.lock().unwrap();
+        let block = blocks.iter().find(|block| handle >= block.base && end <= block.base + block.len as u64);
+        block.map_or(0, |block| block.owner)
+    }
+
+    /// 模拟显卡的VESA信息
+    pub fn vesa_info(&self) -> &VesaInfo {
+        &self.vesa
+    }
+
+    fn framebuffer_len(&self) -> usize {
+        self.vesa.pitch() * self.vesa.height as usize
+    }
+}
+
+/// 当前线程的PID
+fn current_pid() -> Pid {
+    CURRENT_PID.with(|current| current.get())
+}
+
+/// 把C字符串转成&str（非法UTF-8按空字符串处理）
+unsafe fn c_str<'a>(name: *const u8) -> &'a str {
+    if name.is_null() {
+        return "";
+    }
+    CStr::from_ptr(name as *const core::ffi::c_char).to_str().unwrap_or("")
+}
+
+// ==========================
+// 导出给服务链接的内核ABI（签名和common::kernel里的extern块一致）
+// ==========================
+#[no_mangle]
+pub unsafe extern "C" fn ipc_send_bytes(pid: Pid, buf: *const u8, len: usize) {
+    // 内核不解析消息，只按字节拷贝
+    get().send_bytes(current_pid(), pid, std::slice::from_raw_parts(buf, len));
+}
+
+/// 把收到的消息写给调用方，返回长度（超时写0）
+unsafe fn deliver(received: Option<(Pid, Vec<u8>)>, sender_pid: *mut Pid, buf: *mut u8, cap: usize) -> usize {
+    let Some((sender, bytes)) = received else {
+        return 0;
+    };
+    // 接收缓冲区放不下时截断（接收方解码会发现并丢弃）
+    let len = bytes.len().min(cap);
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf, len);
+    *sender_pid = sender;
+    len
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipc_recv_bytes(sender_pid: *mut Pid, buf: *mut u8, cap: usize) -> usize {
+    deliver(get().recv_bytes(current_pid(), None), sender_pid, buf, cap)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipc_recv_bytes_timeout(sender_pid: *mut Pid, buf: *mut u8, cap: usize, timeout_ms: u64) -> usize {
+    let received = get().recv_bytes(current_pid(), Some(Duration::from_millis(timeout_ms)));
+    deliver(received, sender_pid, buf, cap)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kernel_uptime_ms() -> u64 {
+    get().uptime_ms()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn get_service_pid(name: *const u8) -> Pid {
+    get().wait_for_service(c_str(name), SERVICE_LOOKUP_TIMEOUT).unwrap_or(0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn map_phys(phys_addr: u64, size: usize) -> *mut u8 {
+    get().map_phys(phys_addr, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn shm_alloc(size: usize) -> u64 {
+    get().shm_alloc(current_pid(), size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn shm_owner(handle: u64, size: usize) -> Pid {
+    get().shm_owner(handle, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kernel_write_file(path: *const u8, data: *const u8, len: usize, append: bool) -> bool {
+    let bytes = if len == 0 { &[][..] } else { std::slice::from_raw_parts(data, len) };
+    let file = OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(c_str(path));
+    file.and_then(|mut file| file.write_all(bytes)).is_ok()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kernel_register_service(name: *const u8) -> bool {
+    get().register_service(c_str(name), current_pid())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn spawn_process(name: *const u8) -> Pid {
+    get().spawn_app(c_str(name)).unwrap_or(0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kernel_get_vesa_info() -> *const VesaInfo {
+    get().vesa_info()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn debug_print(s: *const u8) {
+    eprint!("{}", c_str(s));
+}
@@ -0,0 +1,311 @@
+// ==========================
+// RUI宿主模拟器
+// ==========================
+// 在普通Linux上按 gpu-driver → render → wm → desktop 的顺序启动整套服务，
+// 控制台从标准输入读命令，模拟鼠标事件、截图、启动应用。
+//
+// 用法：rui-sim [--size 宽x高] [--bpp 15|16|24|32] [--dispi | --headless] [--app 应用名]...
+//   --bpp         模拟VESA显卡的每像素位数
+//   --dispi       GPU驱动通过模拟的Bochs DISPI寄存器设置模式（分辨率同--size）
+//   --headless    GPU驱动用无头后端（内存帧缓冲区，分辨率同--size）
+// 控制台命令：
+//   move X Y      鼠标移动
+//   click X Y     鼠标点击
+//   down X Y / up X Y  左键按下、松开
+//   drag X0 Y0 X1 Y1   按下左键从起点拖到终点（拖标题栏移动窗口，拖边框改大小）
+//   raise ID / lower ID  把窗口提到最上面、降到最下面（窗口ID从1开始按创建顺序编号）
+//   snap [文件]   让GPU驱动把当前屏幕保存为图片（.png或.ppm，默认rui-sim.ppm；只有--headless能用）
+//   modes         列出GPU驱动支持的显示模式
+//   mode WxH      运行时切换分辨率（驱动选最接近的模式）
+//   spawn 应用名  启动应用（比如test-window）
+//   quit          退出
+
+mod kernel;
+
+use common::{ipc::Endpoint, kernel::SharedMemory, DumpFormat, IpcMessage, IpcStr, MouseButton, Pid};
+use gpu_driver::{dispi::FakeDispi, dump::DUMP_DIR};
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::Path,
+    process,
+    time::Duration,
+};
+
+/// 等待核心服务完成注册的时间
+const BOOT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 模拟的DISPI显卡：帧缓冲区物理地址（QEMU stdvga的BAR0）和显存大小
+const DISPI_LFB: u64 = 0xFD00_0000;
+const DISPI_VIDEO_MEMORY: usize = 16 << 20;
+/// drag命令把鼠标分几步从起点移到终点
+const DRAG_STEPS: i32 = 8;
+/// 发完鼠标事件后等WM回指针形状的时间（毫秒）
+const CURSOR_WAIT_MS: u64 = 50;
+
+/// GPU驱动用哪个后端
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GpuBackend {
+    Vesa,
+    Dispi,
+    Headless,
+}
+
+/// 命令行参数
+struct Options {
+    width: u16,
+    height: u16,
+    bpp: u8,
+    backend: GpuBackend,
+    apps: Vec<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options { width: 800, height: 600, bpp: 32, backend: GpuBackend::Vesa, apps: Vec::new() };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let size = args.next().ok_or("--size 需要参数，比如 1024x768")?;
+                let (w, h) = size.split_once('x').ok_or("分辨率格式应为 宽x高")?;
+                options.width = w.parse().map_err(|_| "宽度不是数字")?;
+                options.height = h.parse().map_err(|_| "高度不是数字")?;
+            }
+            "--bpp" => options.bpp = args.next().and_then(|v| v.parse().ok()).ok_or("--bpp 需要数字参数")?,
+            "--dispi" => options.backend = GpuBackend::Dispi,
+            "--headless" => options.backend = GpuBackend::Headless,
+            "--app" => options.apps.push(args.next().ok_or("--app 需要应用名")?),
+            other => return Err(format!("未知参数：{other}")),
+        }
+    }
+    Ok(options)
+}
+
+/// 启动一个服务并等它注册好名称（后面的服务启动时会查找它）
+fn start_service(kernel: &kernel::Kernel, name: &str, entry: fn() -> !) -> Pid {
+    kernel.spawn(name, entry);
+    match kernel.wait_for_service(name, BOOT_TIMEOUT) {
+        Some(pid) => pid,
+        None => {
+            eprintln!("rui-sim: 服务 {name} 没有在规定时间内注册");
+            process::exit(1);
+        }
+    }
+}
+
+/// --dispi时的GPU驱动入口：在驱动线程里创建模拟寄存器
+fn run_dispi_driver() -> ! {
+    let ports = Box::leak(Box::new(FakeDispi::new(DISPI_LFB, DISPI_VIDEO_MEMORY)));
+    gpu_driver::run_dispi(ports)
+}
+
+/// 请渲染服务切换到最接近width×height的模式，返回实际的分辨率
+fn set_mode(endpoint: &mut Endpoint, render_pid: Pid, width: u16, height: u16) -> Result<(u16, u16), String> {
+    match endpoint.call(render_pid, &IpcMessage::RenderSetMode { width, height }) {
+        Ok(IpcMessage::RenderScreenInfo { width: 0, .. }) => Err("GPU驱动无法切换到该模式".to_string()),
+        Ok(IpcMessage::RenderScreenInfo { width, height }) => Ok((width, height)),
+        Ok(other) => Err(format!("意外的回复：{other:?}")),
+        Err(err) => Err(format!("{err:?}")),
+    }
+}
+
+/// 请GPU驱动把当前画面导出到文件（格式按扩展名决定，只有无头模式能用）：
+/// 驱动只往DUMP_DIR里写，写完再复制到path
+fn snap(endpoint: &mut Endpoint, gpu_pid: Pid, path: &str) -> Result<(), String> {
+    let (format, name) = if path.ends_with(".png") { (DumpFormat::Png, "snap.png") } else { (DumpFormat::Ppm, "snap.ppm") };
+    fs::create_dir_all(DUMP_DIR).map_err(|err| err.to_string())?;
+    let request = IpcMessage::GpuDump { format, name: IpcStr::new(name) };
+    // 大分辨率下编码要花点时间，超时放宽一些
+    match endpoint.call_timeout(gpu_pid, &request, 10_000) {
+        Ok(IpcMessage::GpuDumpDone { ok: true }) => {
+            let dumped = format!("{DUMP_DIR}{name}");
+            if Path::new(&dumped) != Path::new(path) {
+                fs::copy(&dumped, path).map_err(|err| err.to_string())?;
+                fs::remove_file(&dumped).map_err(|err| err.to_string())?;
+            }
+            Ok(())
+        }
+        Ok(IpcMessage::GpuDumpDone { ok: false }) => Err("GPU驱动写文件失败（只有--headless能导出）".to_string()),
+        Ok(other) => Err(format!("意外的回复：{other:?}")),
+        Err(err) => Err(format!("{err:?}")),
+    }
+}
+
+/// 把主题文件放进共享内存交给WM（WM解析成功后广播ThemeChanged）
+fn set_theme(endpoint: &mut Endpoint, wm_pid: Pid, path: &str) -> Result<(), String> {
+    let text = fs::read(path).map_err(|err| err.to_string())?;
+    let memory = SharedMemory::alloc(text.len()).ok_or("主题文件是空的")?;
+    unsafe { std::ptr::copy_nonoverlapping(text.as_ptr(), memory.as_ptr(), text.len()) };
+    let request = IpcMessage::WmSetTheme { text: memory.handle(), len: text.len() as u32 };
+    match endpoint.call(wm_pid, &request) {
+        Ok(IpcMessage::WmThemeSet { ok: true, .. }) => Ok(()),
+        Ok(IpcMessage::WmThemeSet { ok: false, line: 0 }) => Err("WM读不了主题文本（不是UTF-8？）".to_string()),
+        Ok(IpcMessage::WmThemeSet { ok: false, line }) => Err(format!("第{line}行有错")),
+        Ok(other) => Err(format!("意外的回复：{other:?}")),
+        Err(err) => Err(format!("{err:?}")),
+    }
+}
+
+/// 控制台充当鼠标驱动：打印WM发来的指针形状变化（没有画指针）
+fn report_cursor(endpoint: &mut Endpoint) {
+    while let Some(incoming) = endpoint.recv_timeout(CURSOR_WAIT_MS) {
+        if let IpcMessage::WmSetCursor { shape } = incoming.msg {
+            eprintln!("指针：{shape:?}");
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("rui-sim: {err}");
+            process::exit(2);
+        }
+    };
+
+    // 1. 启动模拟内核，登记可按名称启动的应用
+    let vesa = match kernel::VesaInfo::mode(options.width, options.height, options.bpp) {
+        Ok(vesa) => vesa,
+        Err(err) => {
+            eprintln!("rui-sim: {err}");
+            process::exit(2);
+        }
+    };
+    let kernel = kernel::boot(vesa);
+    kernel.register_app("test-window", test_window::run);
+
+    // 2. 按依赖顺序启动服务：GPU驱动 → 渲染 → 窗口管理器 → 桌面
+    let gpu_entry: fn() -> ! = match options.backend {
+        GpuBackend::Vesa => gpu_driver::run,
+        GpuBackend::Dispi => {
+            // 整块显存预先分配好，驱动和渲染服务按不同大小映射时拿到的是同一段内存
+            kernel.map_phys(DISPI_LFB, DISPI_VIDEO_MEMORY);
+            run_dispi_driver
+        }
+        GpuBackend::Headless => gpu_driver::run_headless,
+    };
+    let gpu_pid = start_service(kernel, "gpu-driver", gpu_entry);
+    let render_pid = start_service(kernel, "render", render::run);
+    // 控制台当前线程充当鼠标驱动和调试工具
+    let console_pid = kernel.attach_current_thread();
+    let mut endpoint = Endpoint::new();
+    if options.backend != GpuBackend::Vesa {
+        // DISPI/无头后端默认800x600，在WM和桌面查询屏幕大小之前切到--size
+        if let Err(err) = set_mode(&mut endpoint, render_pid, options.width, options.height) {
+            eprintln!("rui-sim: 设置分辨率失败：{err}");
+        }
+    }
+    let wm_pid = start_service(kernel, "wm", wm::run);
+    let desktop_pid = kernel.spawn("desktop", desktop::run);
+    for app in &options.apps {
+        if kernel.spawn_app(app).is_none() {
+            eprintln!("rui-sim: 未知应用 {app}");
+        }
+    }
+
+    // 3. 控制台：把鼠标事件发给WM和桌面，其他命令发给对应的服务
+    let backend = match options.backend {
+        GpuBackend::Vesa => "VESA",
+        GpuBackend::Dispi => "DISPI",
+        GpuBackend::Headless => "无头模式",
+    };
+    eprintln!("rui-sim: {}x{} {}bpp（{backend}）已启动，输入 help 查看命令", options.width, options.height, options.bpp);
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let point = || -> Option<(u16, u16)> { Some((words.get(1)?.parse().ok()?, words.get(2)?.parse().ok()?)) };
+        match words.first().copied() {
+            Some("move") | Some("click") | Some("down") | Some("up") => {
+                let Some((x, y)) = point() else {
+                    eprintln!("用法：{} X Y", words[0]);
+                    continue;
+                };
+                let msg = match words[0] {
+                    "move" => IpcMessage::MouseMove { x, y },
+                    "click" => IpcMessage::MouseClick { x, y },
+                    "down" => IpcMessage::MouseDown { x, y, button: MouseButton::Left },
+                    _ => IpcMessage::MouseUp { x, y, button: MouseButton::Left },
+                };
+                for target in [wm_pid, desktop_pid] {
+                    kernel.send(console_pid, target, &msg);
+                }
+                report_cursor(&mut endpoint);
+            }
+            // 拖动：按下、分几步移动、松开
+            Some("drag") => {
+                let coords: Option<Vec<u16>> = words[1..].iter().map(|word| word.parse().ok()).collect();
+                let Some(&[x0, y0, x1, y1]) = coords.as_deref() else {
+                    eprintln!("用法：drag X0 Y0 X1 Y1");
+                    continue;
+                };
+                let lerp = |a: u16, b: u16, i: i32| (a as i32 + (b as i32 - a as i32) * i / DRAG_STEPS) as u16;
+                let mut events = vec![IpcMessage::MouseDown { x: x0, y: y0, button: MouseButton::Left }];
+                events.extend((1..=DRAG_STEPS).map(|i| IpcMessage::MouseMove { x: lerp(x0, x1, i), y: lerp(y0, y1, i) }));
+                events.push(IpcMessage::MouseUp { x: x1, y: y1, button: MouseButton::Left });
+                for msg in &events {
+                    for target in [wm_pid, desktop_pid] {
+                        kernel.send(console_pid, target, msg);
+                    }
+                }
+                report_cursor(&mut endpoint);
+            }
+            Some("raise") | Some("lower") => {
+                let Some(window_id) = words.get(1).and_then(|id| id.parse().ok()) else {
+                    eprintln!("用法：{} 窗口ID", words[0]);
+                    continue;
+                };
+                let msg = match words[0] {
+                    "raise" => IpcMessage::WmRaiseWindow { window_id },
+                    _ => IpcMessage::WmLowerWindow { window_id },
+                };
+                kernel.send(console_pid, wm_pid, &msg);
+            }
+            Some("snap") => {
+                let path = words.get(1).copied().unwrap_or("rui-sim.ppm");
+                match snap(&mut endpoint, gpu_pid, path) {
+                    Ok(()) => eprintln!("已保存 {path}"),
+                    Err(err) => eprintln!("保存 {path} 失败：{err}"),
+                }
+            }
+            Some("modes") => match endpoint.call(gpu_pid, &IpcMessage::GpuListModes) {
+                Ok(IpcMessage::GpuModeList { modes }) => {
+                    for mode in modes.as_slice() {
+                        eprintln!("{}x{} {}bpp", mode.width, mode.height, mode.bpp);
+                    }
+                }
+                other => eprintln!("查询失败：{other:?}"),
+            },
+            Some("mode") => {
+                let size = words.get(1).and_then(|s| s.split_once('x'));
+                let Some((Ok(w), Ok(h))) = size.map(|(w, h)| (w.parse(), h.parse())) else {
+                    eprintln!("用法：mode 宽x高");
+                    continue;
+                };
+                match set_mode(&mut endpoint, render_pid, w, h) {
+                    Ok((w, h)) => eprintln!("已切换到 {w}x{h}"),
+                    Err(err) => eprintln!("切换失败：{err}"),
+                }
+            }
+            Some("spawn") => match words.get(1).and_then(|name| kernel.spawn_app(name)) {
+                Some(pid) => eprintln!("已启动，PID {pid}"),
+                None => eprintln!("未知应用"),
+            },
+            Some("theme") => {
+                let Some(path) = words.get(1) else {
+                    eprintln!("用法：theme 文件");
+                    continue;
+                };
+                match set_theme(&mut endpoint, wm_pid, path) {
+                    Ok(()) => eprintln!("已换成 {path}"),
+                    Err(err) => eprintln!("换主题失败：{err}"),
+                }
+            }
+            Some("quit") => break,
+            Some("help") => eprintln!("命令：move X Y | click X Y | down X Y | up X Y | drag X0 Y0 X1 Y1 | raise ID | lower ID | snap [文件] | modes | mode 宽x高 | spawn 应用名 | theme 文件 | quit"),
+            Some(other) => eprintln!("未知命令：{other}"),
+            None => {}
+        }
+    }
+    // 服务线程都是死循环，直接结束进程
+    process::exit(0);
+}
@@ -0,0 +1,51 @@
+// ==========================
+// 宿主上的单元测试用的内核接口
+// ==========================
+// 测试时没有内核：共享内存用堆内存代替（句柄就是内存的地址），发给渲染服务的消息直接丢掉，
+// 也没有消息可收（等回复的调用马上超时），时钟每读一次走1毫秒（等回复、等命令缓冲区的循环不会卡住）。
+
+extern crate std;
+
+use common::Pid;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::alloc::{alloc_zeroed, Layout};
+
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+#[no_mangle]
+extern "C" fn shm_alloc(size: usize) -> u64 {
+    match Layout::from_size_align(size, 4096) {
+        Ok(layout) if size > 0 => unsafe { alloc_zeroed(layout) as u64 },
+        _ => 0,
+    }
+}
+
+// 测试里WM只映射自己分配的内存，没有别的进程分配的共享内存
+#[no_mangle]
+extern "C" fn shm_owner(_handle: u64, _size: usize) -> Pid {
+    0
+}
+
+#[no_mangle]
+extern "C" fn map_phys(phys_addr: u64, _size: usize) -> *mut u8 {
+    phys_addr as *mut u8
+}
+
+#[no_mangle]
+extern "C" fn ipc_send_bytes(_pid: Pid, _buf: *const u8, _len: usize) {}
+
+#[no_mangle]
+extern "C" fn kernel_uptime_ms() -> u64 {
+    NOW.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+#[no_mangle]
+extern "C" fn ipc_recv_bytes(_sender_pid: *mut Pid, _buf: *mut u8, _cap: usize) -> usize {
+    panic!("没有消息可收，ipc_recv_bytes会一直等下去")
+}
+
+#[no_mangle]
+extern "C" fn ipc_recv_bytes_timeout(_sender_pid: *mut Pid, _buf: *mut u8, _cap: usize, timeout_ms: u64) -> usize {
+    NOW.fetch_add(timeout_ms, Ordering::Relaxed); // 等到超时
+    0
+}
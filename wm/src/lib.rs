@@ -0,0 +1,1239 @@
+#![no_std]
+extern crate alloc;
+use alloc::vec::Vec;
+use common::{
+    command::RenderCommandBuffer, ipc::{self, Endpoint}, CursorShape, IpcMessage, IpcStr, Layer, LayerList, MouseButton, Rect, Rgb, kernel::{self, SharedMemory}, Pid, ShmHandle, StackLayer, SurfaceId, TextAlign, WindowId,
+    // 引入渲染服务的消息类型（复用common中定义的）
+    theme::{Theme, ThemeError, WindowTheme},
+};
+use core::{
+    ffi::CStr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+#[cfg(test)]
+mod host;
+
+/// 拖动窗口时至少留在屏幕里的宽度（窗口可以伸出屏幕右边和下边，但要留一段标题栏能抓回来）
+const MIN_VISIBLE: u16 = 48;
+/// 边框上离窗口的角多近算抓住了角（两个方向一起改大小）
+const RESIZE_CORNER: u16 = 16;
+
+// ==========================
+// 数据结构：窗口元数据与状态
+// ==========================
+/// 窗口状态（正常/最小化；关闭的窗口直接从列表里删掉）
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WindowState {
+    Normal,
+    Minimized,
+}
+
+/// 标题栏右边的按钮（从右往左：关闭、最大化、最小化）
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TitleButton {
+    Close,
+    Maximize,
+    Minimize,
+}
+
+impl TitleButton {
+    const ALL: [TitleButton; 3] = [TitleButton::Close, TitleButton::Maximize, TitleButton::Minimize];
+
+    /// (默认颜色, 鼠标悬停时的颜色)
+    fn colors(self, theme: &WindowTheme) -> (Rgb, Rgb) {
+        match self {
+            TitleButton::Close => (theme.close_button, theme.close_button_hover),
+            TitleButton::Maximize => (theme.maximize_button, theme.maximize_button_hover),
+            TitleButton::Minimize => (theme.minimize_button, theme.minimize_button_hover),
+        }
+    }
+}
+
+/// 鼠标按在边框的哪一边（改大小用）：horizontal为-1是左边、1是右边、0表示不改宽度，vertical同理（上、下）
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Edge {
+    horizontal: i8,
+    vertical: i8,
+}
+
+impl Edge {
+    /// 这一边对应的指针形状
+    fn cursor(self) -> CursorShape {
+        match (self.horizontal, self.vertical) {
+            (0, _) => CursorShape::ResizeNS,
+            (_, 0) => CursorShape::ResizeEW,
+            (h, v) if h == v => CursorShape::ResizeNWSE,
+            _ => CursorShape::ResizeNESW,
+        }
+    }
+}
+
+/// 拖着边往外（delta为正是往右、往下）拉：side是Edge的一个方向，返回新的(起点, 长度)；
+/// 拖左边、上边时对面的边不动，长度限制在[min, max]里，起点不会小于0
+fn resize_span(start: u16, size: u16, side: i8, delta: i32, min: u16, max: u16) -> (u16, u16) {
+    let (min, max) = (min as i32, max as i32);
+    match side {
+        1 => (start, (size as i32 + delta).clamp(min, max) as u16),
+        -1 => {
+            let end = start as i32 + size as i32;
+            let size = (size as i32 - delta).clamp(min, max).min(end);
+            ((end - size) as u16, size as u16)
+        }
+        _ => (start, size),
+    }
+}
+
+/// 鼠标拖动的状态（左键在标题栏或边框上按下时开始，松开时结束）
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Drag {
+    Idle,
+    /// 移动窗口：grab是按下时鼠标相对窗口左上角的位置，窗口跟着鼠标走时保持不变
+    Move { window_id: WindowId, grab_x: u16, grab_y: u16 },
+    /// 改大小：start是按下时窗口的位置和大小，(grab_x, grab_y)是按下时鼠标在屏幕上的位置
+    Resize { window_id: WindowId, edge: Edge, start: Rect, grab_x: u16, grab_y: u16 },
+}
+
+/// 窗口元数据（所有需要的信息都存在这里）
+#[derive(Debug, Clone, Copy)]  // 添加 Clone 和 Copy
+struct Window {
+    id: WindowId,          // 唯一ID
+    owner_pid: Pid,        // 所属应用的PID（大小变了通知它）
+    x: u16,                // 左上角X坐标
+    y: u16,                // 左上角Y坐标
+    width: u16,            // 宽度
+    height: u16,           // 高度
+    title: IpcStr,          // 窗口标题
+    titlebar_height: u16,  // 创建时主题里的标题栏高度和边框宽度（内容区已经告诉了渲染服务，换主题也不变）
+    border_width: u16,
+    state: WindowState,    // 窗口状态
+    is_focused: bool,      // 是否获得焦点（影响标题栏和边框颜色）
+    hover: Option<TitleButton>, // 鼠标停在哪个标题栏按钮上
+    surface: SurfaceId,    // 渲染服务里的离屏表面（0：没创建成功，不显示）
+    min_width: u16,        // 应用限制的内容区大小（WmSetSizeLimits），max为0表示不限制
+    min_height: u16,
+    max_width: u16,
+    max_height: u16,
+    restore: Option<Rect>, // 最大化之前的位置和大小（没有最大化时为None）
+    layer: StackLayer,     // 叠放的层（子窗口跟着父窗口的层）
+    parent: WindowId,      // 父窗口（WmSetTransientFor），0表示没有
+    panel: bool,           // 面板：没有标题栏和边框，WM不画它，也不给它焦点
+}
+
+impl Window {
+    /// 创建新窗口（自动生成唯一ID；表面由create_surface另外创建）
+    fn new(owner_pid: Pid, x: u16, y: u16, width: u16, height: u16, title: IpcStr, theme: &WindowTheme) -> Self {
+        static NEXT_WINDOW_ID: AtomicU32 = AtomicU32::new(1); // 从1开始（0为无效ID）
+        let id = NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed) as WindowId;
+        Window {
+            id,
+            owner_pid,
+            x,
+            y,
+            width,
+            height,
+            title,
+            titlebar_height: theme.titlebar_height,
+            border_width: theme.border_width,
+            state: WindowState::Normal,
+            is_focused: false,
+            hover: None,
+            surface: 0,
+            min_width: 0,
+            min_height: 0,
+            max_width: 0,
+            max_height: 0,
+            restore: None,
+            layer: StackLayer::Normal,
+            parent: 0,
+            panel: false,
+        }
+    }
+
+    /// 窗口在屏幕上的位置和大小
+    fn geometry(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// 应用能画的内容区：标题栏下面、边框里面
+    fn content(&self) -> Rect {
+        Rect::new(
+            self.border_width,
+            self.titlebar_height,
+            self.width.saturating_sub(self.border_width.saturating_mul(2)),
+            self.height.saturating_sub(self.titlebar_height.saturating_add(self.border_width)),
+        )
+    }
+
+    /// 整个窗口（含标题栏和边框）的(最小宽, 最小高, 最大宽, 最大高)：应用的限制加上标题栏和边框，
+    /// 再至少放得下标题栏按钮
+    fn size_limits(&self, theme: &WindowTheme) -> (u16, u16, u16, u16) {
+        let (frame_w, frame_h) = (self.border_width.saturating_mul(2), self.titlebar_height.saturating_add(self.border_width));
+        let buttons = (TitleButton::ALL.len() as u16)
+            .saturating_mul(theme.button_size.saturating_add(theme.button_margin))
+            .saturating_add(theme.button_margin);
+        let min_w = self.min_width.saturating_add(frame_w).max(frame_w.saturating_add(buttons));
+        let min_h = self.min_height.saturating_add(frame_h);
+        let max = |limit: u16, frame: u16, min: u16| if limit == 0 { u16::MAX } else { limit.saturating_add(frame).max(min) };
+        (min_w, min_h, max(self.max_width, frame_w, min_w), max(self.max_height, frame_h, min_h))
+    }
+
+    /// 屏幕上的点(x, y)是否落在可以拖动改大小的边框上，在哪一边（离角RESIZE_CORNER以内算角）；
+    /// 边框宽度是0时也留1像素能抓
+    fn edge_at(&self, x: u16, y: u16) -> Option<Edge> {
+        if self.panel {
+            return None;
+        }
+        let (x, y) = (x.checked_sub(self.x)?, y.checked_sub(self.y)?);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let border = self.border_width.max(1);
+        let side = |pos: u16, size: u16, reach: u16| {
+            if pos < reach {
+                -1
+            } else if pos >= size.saturating_sub(reach) {
+                1
+            } else {
+                0
+            }
+        };
+        let (horizontal, vertical) = (side(x, self.width, border), side(y, self.height, border));
+        match (horizontal, vertical) {
+            (0, 0) => None,
+            // 在上下边框上离左右两角不远，或者在左右边框上离上下两角不远
+            (0, vertical) => Some(Edge { horizontal: side(x, self.width, RESIZE_CORNER), vertical }),
+            (horizontal, 0) => Some(Edge { horizontal, vertical: side(y, self.height, RESIZE_CORNER) }),
+            (horizontal, vertical) => Some(Edge { horizontal, vertical }),
+        }
+    }
+
+    /// 绘制整个窗口（画在窗口自己的表面上，坐标相对窗口左上角）
+    fn draw(&self, cmds: &mut RenderCommandBuffer, theme: &WindowTheme) {
+        if self.surface == 0 || self.panel {
+            return;
+        }
+
+        // 1. 绘制窗口背景
+        cmds.target(self.surface);
+        cmds.rect(0, 0, self.width, self.height, theme.background);
+
+        // 2. 绘制边框、标题栏和按钮
+        self.draw_frame(cmds, theme);
+    }
+
+    /// 只绘制边框、标题栏和按钮（焦点、悬停变化时用，不碰应用画的内容）
+    fn draw_frame(&self, cmds: &mut RenderCommandBuffer, theme: &WindowTheme) {
+        if self.surface == 0 || self.panel {
+            return;
+        }
+        let (title_color, text_color, border_color) = if self.is_focused {
+            (theme.focused_titlebar, theme.focused_title_text, theme.focused_border)
+        } else {
+            (theme.unfocused_titlebar, theme.unfocused_title_text, theme.unfocused_border)
+        };
+        cmds.target(self.surface);
+        cmds.rect(0, 0, self.width, self.titlebar_height, title_color);
+        cmds.stroke_rect(0, 0, self.width, self.height, self.border_width, border_color);
+
+        // 标题文字（竖直居中；太长的部分在按钮前面裁掉）
+        let title_x = self.border_width.saturating_add(theme.title_offset);
+        let buttons_x = self.button_rect(TitleButton::Minimize, theme).x;
+        cmds.push_clip(title_x, 0, buttons_x.saturating_sub(title_x.saturating_add(theme.button_margin)), self.titlebar_height);
+        let title = self.title.as_str();
+        cmds.text_box(title_x, 0, 0, self.titlebar_height, title, theme.title_font, TextAlign::Left, text_color);
+        cmds.pop_clip();
+
+        for button in TitleButton::ALL {
+            self.draw_button(cmds, button, theme);
+        }
+    }
+
+    /// 画一个标题栏按钮：圆角方块加上符号
+    fn draw_button(&self, cmds: &mut RenderCommandBuffer, button: TitleButton, theme: &WindowTheme) {
+        let Rect { x, y, w, h } = self.button_rect(button, theme);
+        if w < 4 || h < 4 {
+            return;
+        }
+        let (normal, hover) = button.colors(theme);
+        let color = if self.hover == Some(button) { hover } else { normal };
+        cmds.round_rect(x, y, w, h, 3, 0, color);
+        // 符号占按钮中间的一半
+        let inset = w / 4;
+        let (left, top, right, bottom) = (x + inset, y + inset, x + w - 1 - inset, y + h - 1 - inset);
+        let symbol = theme.button_symbol;
+        match button {
+            TitleButton::Close => {
+                cmds.line(left, top, right, bottom, symbol);
+                cmds.line(left, bottom, right, top, symbol);
+            }
+            TitleButton::Maximize => {
+                cmds.stroke_rect(left, top, right - left + 1, bottom - top + 1, 1, symbol);
+            }
+            TitleButton::Minimize => {
+                cmds.rect(left, bottom - 1, right - left + 1, 2, symbol);
+            }
+        }
+    }
+
+    /// 按钮在窗口里的位置：靠右排，在标题栏里竖直居中（比标题栏高时缩到标题栏里）
+    fn button_rect(&self, button: TitleButton, theme: &WindowTheme) -> Rect {
+        let index = TitleButton::ALL.iter().position(|b| *b == button).unwrap_or(0) as u16;
+        let size = theme.button_size.min(self.titlebar_height);
+        let right = self.width.saturating_sub(self.border_width);
+        let x = right.saturating_sub((index + 1).saturating_mul(size.saturating_add(theme.button_margin)));
+        Rect::new(x, (self.titlebar_height - size) / 2, size, size)
+    }
+
+    /// 屏幕上的点(x, y)落在哪个标题栏按钮上
+    fn button_at(&self, x: u16, y: u16, theme: &WindowTheme) -> Option<TitleButton> {
+        if self.panel {
+            return None;
+        }
+        let (x, y) = (x.checked_sub(self.x)?, y.checked_sub(self.y)?);
+        TitleButton::ALL.into_iter().find(|button| self.button_rect(*button, theme).contains(x, y))
+    }
+
+    /// 屏幕上的点(x, y)是否在窗口里
+    fn contains(&self, x: u16, y: u16) -> bool {
+        Rect::new(self.x, self.y, self.width, self.height).contains(x, y)
+    }
+}
+
+/// 请渲染服务为窗口创建表面（owner是窗口所属的应用，它只能画窗口的内容区），失败返回0
+fn create_surface(endpoint: &mut Endpoint, render_pid: Pid, window: &Window, owner: Pid) -> SurfaceId {
+    let (width, height, content) = (window.width, window.height, window.content());
+    match endpoint.call(render_pid, &IpcMessage::RenderCreateSurface { width, height, owner, content }) {
+        Ok(IpcMessage::RenderSurfaceCreated { surface, .. }) => surface,
+        _ => 0,
+    }
+}
+
+/// 读WmSetTheme的主题文本：先复制到WM自己分配的共享内存（发送方之后再改也影响不到解析结果，
+/// 订阅者也只认WM分配的句柄）再解析，返回主题和副本的句柄（text为0是默认主题，句柄也是0）
+fn load_theme(owner: Pid, text: ShmHandle, len: u32) -> Result<(Theme, ShmHandle), ThemeError> {
+    if text == 0 {
+        return Ok((Theme::default(), 0));
+    }
+    let source = SharedMemory::map_from(owner, text, len as usize).ok_or(ThemeError::UNREADABLE)?;
+    let copy = SharedMemory::alloc(len as usize).ok_or(ThemeError::UNREADABLE)?;
+    unsafe { core::ptr::copy_nonoverlapping(source.as_ptr(), copy.as_ptr(), len as usize) };
+    Ok((Theme::from_memory(&copy)?, copy.handle()))
+}
+
+// ==========================
+// 窗口管理器状态管理
+// ==========================
+/// 窗口管理器全局状态
+struct WmState {
+    windows: Vec<Window>,       // 所有窗口列表（动态数组，安全管理）
+    cmds: RenderCommandBuffer,  // 发给渲染服务的绘图命令
+    render_pid: Pid,            // 渲染服务PID（更新场景用）
+    focused_window_id: WindowId, // 当前焦点窗口ID
+    screen_width: u16,          // 屏幕大小（从渲染服务查询，分辨率变化时更新）
+    screen_height: u16,
+    theme: Theme,               // 当前主题
+    theme_text: (ShmHandle, u32), // 当前主题文本的副本（WM分配的共享内存）和长度（0：默认主题），转发给订阅者
+    theme_subscribers: Vec<Pid>, // 订阅了主题变化的进程
+    drag: Drag,                 // 鼠标拖动的状态
+    pointer_pid: Pid,           // 发鼠标事件的进程（鼠标驱动），指针形状告诉它
+    cursor: CursorShape,        // 上次告诉鼠标驱动的指针形状
+}
+
+impl WmState {
+    fn new(cmds: RenderCommandBuffer, render_pid: Pid, screen_width: u16, screen_height: u16) -> Self {
+        WmState {
+            windows: Vec::new(),
+            cmds,
+            render_pid,
+            focused_window_id: 0, // 初始无焦点
+            screen_width,
+            screen_height,
+            theme: Theme::default(),
+            theme_text: (0, 0),
+            theme_subscribers: Vec::new(),
+            drag: Drag::Idle,
+            pointer_pid: 0,
+            cursor: CursorShape::Arrow,
+        }
+    }
+
+    /// 按当前主题创建窗口和它的表面（owner是窗口所属的应用）
+    fn create_window(&self, endpoint: &mut Endpoint, owner: Pid, geometry: Rect, title: IpcStr) -> Window {
+        let Rect { x, y, w, h } = geometry;
+        let mut window = Window::new(owner, x, y, w, h, title, &self.theme.window);
+        window.surface = create_surface(endpoint, self.render_pid, &window, owner);
+        window
+    }
+
+    /// 按当前主题创建面板和它的表面：面板整个都是内容区，放在layer层
+    fn create_panel(&self, endpoint: &mut Endpoint, owner: Pid, geometry: Rect, layer: StackLayer) -> Window {
+        let Rect { x, y, w, h } = geometry;
+        let mut window = Window::new(owner, x, y, w, h, IpcStr::new(""), &self.theme.window);
+        (window.titlebar_height, window.border_width) = (0, 0);
+        (window.panel, window.layer) = (true, layer);
+        window.surface = create_surface(endpoint, self.render_pid, &window, owner);
+        window
+    }
+
+    /// 把窗口挪回屏幕内（比屏幕大的窗口靠左上角放，保证标题栏可见；屏幕大小未知时不动）
+    fn fit_to_screen(&self, window: &mut Window) {
+        if self.screen_width == 0 || self.screen_height == 0 {
+            return;
+        }
+        window.x = window.x.min(self.screen_width.saturating_sub(window.width));
+        window.y = window.y.min(self.screen_height.saturating_sub(window.height));
+    }
+
+    /// 屏幕分辨率变化：把窗口挪回屏幕内（窗口内容在各自的表面上，不用重画）
+    fn handle_screen_resize(&mut self, width: u16, height: u16) {
+        self.screen_width = width;
+        self.screen_height = height;
+        let mut windows = core::mem::take(&mut self.windows);
+        for window in &mut windows {
+            self.fit_to_screen(window);
+        }
+        self.windows = windows;
+        self.update_scene();
+    }
+
+    /// 添加新窗口：放在它那一层的最上面，画好边框，再放进场景（面板由应用自己画，也不获得焦点）
+    fn add_window(&mut self, mut window: Window) {
+        self.fit_to_screen(&mut window);
+        let old = self.focused_window_id;
+        self.windows.push(window);
+        self.restack();
+        if window.panel {
+            self.update_scene();
+            return;
+        }
+        // 新窗口默认获得焦点，原来的焦点窗口标题栏变灰
+        self.focused_window_id = window.id;
+        self.update_focus();
+        if let Some(old) = self.window_index(old) {
+            self.windows[old].draw_frame(&mut self.cmds, &self.theme.window);
+        }
+        if let Some(index) = self.window_index(window.id) {
+            self.windows[index].draw(&mut self.cmds, &self.theme.window);
+        }
+        // 先让渲染服务执行完这些命令，更新场景时一起呈现
+        self.cmds.flush();
+        self.update_scene();
+    }
+
+    /// 把窗口位置和叠放次序（windows里靠后的在上面）告诉渲染服务，渲染服务重新合成变化的区域
+    fn update_scene(&self) {
+        let mut layers = LayerList::new();
+        let visible = self.windows.iter().filter(|w| w.state == WindowState::Normal && w.surface != 0);
+        for window in visible {
+            layers.push(Layer { surface: window.surface, x: window.x, y: window.y });
+        }
+        kernel::send(self.render_pid, &IpcMessage::RenderSetScene { layers });
+    }
+
+    /// 更新窗口焦点状态（只让焦点窗口的is_focused为true）
+    fn update_focus(&mut self) {
+        for window in &mut self.windows {
+            window.is_focused = window.id == self.focused_window_id;
+        }
+    }
+
+    /// 窗口在Z轴顺序里的位置
+    fn window_index(&self, window_id: WindowId) -> Option<usize> {
+        self.windows.iter().position(|window| window.id == window_id)
+    }
+
+    /// 切换焦点：只重绘标题栏颜色变了的两个窗口，再把焦点窗口提到最上面
+    fn set_focus(&mut self, window_id: WindowId) {
+        if window_id != self.focused_window_id {
+            let old = self.window_index(self.focused_window_id);
+            let new = self.window_index(window_id);
+            self.focused_window_id = window_id;
+            self.update_focus();
+            for index in old.into_iter().chain(new) {
+                self.windows[index].draw_frame(&mut self.cmds, &self.theme.window);
+            }
+            self.cmds.submit();
+        }
+        self.raise(window_id);
+    }
+
+    /// 整理叠放次序：先按层（Bottom、Normal、Top）排，同一层里保持原来的先后；
+    /// 子窗口跟着父窗口的层，紧跟在父窗口后面（叠在它上面）
+    fn restack(&mut self) {
+        let windows = core::mem::take(&mut self.windows);
+        let is_root = |window: &Window| window.parent == 0 || !windows.iter().any(|w| w.id == window.parent);
+        let mut order = Vec::with_capacity(windows.len());
+        for layer in [StackLayer::Bottom, StackLayer::Normal, StackLayer::Top] {
+            for window in windows.iter().filter(|w| w.layer == layer && is_root(w)) {
+                push_family(&windows, window, &mut order);
+            }
+        }
+        self.windows = order;
+    }
+
+    /// 按change调整windows的先后，再整理叠放次序；次序真的变了才换场景
+    fn reorder(&mut self, change: impl FnOnce(&mut Vec<Window>)) {
+        let before: Vec<WindowId> = self.windows.iter().map(|window| window.id).collect();
+        change(&mut self.windows);
+        self.restack();
+        if self.windows.iter().map(|window| window.id).ne(before) {
+            self.update_scene();
+        }
+    }
+
+    /// 把窗口提到它那一层的最上面：祖先窗口也一起提上去，子窗口还叠在它上面
+    fn raise(&mut self, window_id: WindowId) {
+        let mut chain = Vec::new(); // 窗口、父窗口、祖父窗口……
+        let mut id = window_id;
+        while let Some(index) = self.window_index(id) {
+            chain.push(id);
+            id = self.windows[index].parent;
+        }
+        self.reorder(|windows| {
+            for id in chain.iter().rev() {
+                if let Some(index) = windows.iter().position(|window| window.id == *id) {
+                    let window = windows.remove(index);
+                    windows.push(window);
+                }
+            }
+        });
+    }
+
+    /// 把窗口降到它那一层的最下面（子窗口降到父窗口的其他子窗口下面）；焦点窗口降下去后焦点给最上面的窗口
+    fn lower(&mut self, window_id: WindowId) {
+        self.reorder(|windows| {
+            if let Some(index) = windows.iter().position(|window| window.id == window_id) {
+                let window = windows.remove(index);
+                windows.insert(0, window);
+            }
+        });
+        if window_id == self.focused_window_id {
+            self.focus_topmost();
+        }
+    }
+
+    /// window是不是ancestor自己或者它的子孙
+    fn descends_from(&self, window_id: WindowId, ancestor: WindowId) -> bool {
+        let mut id = window_id;
+        while let Some(index) = self.window_index(id) {
+            if id == ancestor {
+                return true;
+            }
+            id = self.windows[index].parent;
+        }
+        false
+    }
+
+    /// 应用把窗口换到layer层（只接受窗口所属的应用）
+    fn set_stack_layer(&mut self, sender: Pid, window_id: WindowId, layer: StackLayer) {
+        let Some(index) = self.window_index(window_id).filter(|index| self.windows[*index].owner_pid == sender) else {
+            return;
+        };
+        self.windows[index].layer = layer;
+        self.reorder(|_| {});
+    }
+
+    /// 应用把窗口设成parent的子窗口（只接受窗口所属的应用；parent是窗口自己或它的子孙时忽略，免得绕成圈）
+    fn set_transient_for(&mut self, sender: Pid, window_id: WindowId, parent: WindowId) {
+        let Some(index) = self.window_index(window_id).filter(|index| self.windows[*index].owner_pid == sender) else {
+            return;
+        };
+        if parent != 0 && (self.window_index(parent).is_none() || self.descends_from(parent, window_id)) {
+            return;
+        }
+        self.windows[index].parent = parent;
+        self.reorder(|_| {});
+    }
+
+    /// 应用挪动自己的窗口（只接受窗口所属的应用）：不超出屏幕
+    fn move_window(&mut self, sender: Pid, window_id: WindowId, x: u16, y: u16) {
+        let Some(index) = self.window_index(window_id).filter(|index| self.windows[*index].owner_pid == sender) else {
+            return;
+        };
+        let mut window = self.windows[index];
+        (window.x, window.y) = (x, y);
+        self.fit_to_screen(&mut window);
+        if (window.x, window.y) != (self.windows[index].x, self.windows[index].y) {
+            self.windows[index] = window;
+            self.update_scene();
+        }
+    }
+
+    /// 屏幕上的点(x, y)处最上面的可见窗口
+    fn window_at(&self, x: u16, y: u16) -> Option<usize> {
+        // 从顶层窗口开始检查（逆序遍历，靠后的在上面）
+        self.windows.iter().rposition(|window| window.state == WindowState::Normal && window.contains(x, y))
+    }
+
+    /// 关掉窗口：从列表里删掉，让渲染服务销毁它的表面（表面数量有限），焦点给最上面的窗口；
+    /// 它的子窗口留下来，变成普通的顶级窗口
+    fn close(&mut self, index: usize) {
+        let window = self.windows.remove(index);
+        for child in self.windows.iter_mut().filter(|child| child.parent == window.id) {
+            child.parent = 0;
+        }
+        if window.surface != 0 {
+            kernel::send(self.render_pid, &IpcMessage::RenderDestroySurface { surface: window.surface });
+        }
+        self.restack();
+        self.update_scene();
+        self.focus_topmost();
+    }
+
+    /// 焦点给最上面的可见窗口（焦点窗口被关闭、最小化或降下去后用；面板不算）
+    fn focus_topmost(&mut self) {
+        let topmost = self.windows.iter().rev().find(|window| window.state == WindowState::Normal && !window.panel);
+        self.set_focus(topmost.map_or(0, |window| window.id));
+    }
+
+    /// 处理鼠标点击事件：点到标题栏按钮执行按钮的操作，点到窗口其他地方获取焦点（面板上的点击不管）
+    fn handle_mouse_click(&mut self, endpoint: &mut Endpoint, x: u16, y: u16) {
+        let Some(index) = self.window_at(x, y).filter(|index| !self.windows[*index].panel) else {
+            return;
+        };
+        let window = &mut self.windows[index];
+        match window.button_at(x, y, &self.theme.window) {
+            Some(TitleButton::Close) => self.close(index),
+            Some(TitleButton::Minimize) => {
+                window.state = WindowState::Minimized;
+                window.hover = None;
+                self.update_scene();
+                self.focus_topmost();
+            }
+            Some(TitleButton::Maximize) => {
+                // 先改大小再获取焦点（获取焦点会提升窗口，index就不对了）
+                let window_id = window.id;
+                self.toggle_maximize(endpoint, index);
+                self.set_focus(window_id);
+            }
+            None => {
+                let window_id = window.id;
+                self.set_focus(window_id); // 重绘以更新标题栏颜色，提到最上面
+            }
+        }
+    }
+
+    /// 处理鼠标按下：左键按在边框上时开始改大小，按在标题栏上（按钮以外）时开始拖动窗口，两种都会获取焦点
+    fn handle_mouse_down(&mut self, x: u16, y: u16, button: MouseButton) {
+        if button != MouseButton::Left {
+            return;
+        }
+        let Some(index) = self.window_at(x, y).filter(|index| !self.windows[*index].panel) else {
+            return;
+        };
+        let window = &self.windows[index];
+        let window_id = window.id;
+        if let Some(edge) = window.edge_at(x, y) {
+            let start = window.geometry();
+            self.set_focus(window_id);
+            self.drag = Drag::Resize { window_id, edge, start, grab_x: x, grab_y: y };
+        } else if y < window.y.saturating_add(window.titlebar_height) && window.button_at(x, y, &self.theme.window).is_none() {
+            let (grab_x, grab_y) = (x - window.x, y - window.y);
+            self.set_focus(window_id);
+            self.drag = Drag::Move { window_id, grab_x, grab_y };
+        } else {
+            return;
+        }
+        self.update_cursor(x, y);
+    }
+
+    /// 处理鼠标松开：结束拖动
+    fn handle_mouse_up(&mut self, x: u16, y: u16, button: MouseButton) {
+        if button == MouseButton::Left {
+            self.drag = Drag::Idle;
+            self.update_cursor(x, y);
+        }
+    }
+
+    /// 指针形状：拖动中按拖的是什么，否则看指针是不是停在最上面那个窗口的边框上
+    fn update_cursor(&mut self, x: u16, y: u16) {
+        let shape = match self.drag {
+            Drag::Move { .. } => CursorShape::Move,
+            Drag::Resize { edge, .. } => edge.cursor(),
+            Drag::Idle => {
+                let edge = self.window_at(x, y).and_then(|index| self.windows[index].edge_at(x, y));
+                edge.map_or(CursorShape::Arrow, Edge::cursor)
+            }
+        };
+        if shape != self.cursor && self.pointer_pid != 0 {
+            self.cursor = shape;
+            kernel::send(self.pointer_pid, &IpcMessage::WmSetCursor { shape });
+        }
+    }
+
+    /// 把窗口左上角挪到(x, y)附近：标题栏至少留MIN_VISIBLE宽在屏幕里（屏幕大小未知时不限制）
+    fn keep_on_screen(&self, window: &Window, x: u16, y: u16) -> (u16, u16) {
+        if self.screen_width == 0 || self.screen_height == 0 {
+            return (x, y);
+        }
+        let max_x = self.screen_width.saturating_sub(MIN_VISIBLE.min(window.width));
+        let max_y = self.screen_height.saturating_sub(window.titlebar_height);
+        (x.min(max_x), y.min(max_y))
+    }
+
+    /// 拖动中窗口跟着鼠标走：只换场景，渲染服务重新合成窗口的旧位置和新位置
+    fn drag_to(&mut self, window_id: WindowId, x: u16, y: u16) {
+        let Some(index) = self.window_index(window_id) else {
+            self.drag = Drag::Idle; // 拖着的窗口没了
+            return;
+        };
+        let (x, y) = self.keep_on_screen(&self.windows[index], x, y);
+        let window = &mut self.windows[index];
+        if (window.x, window.y) != (x, y) {
+            (window.x, window.y) = (x, y);
+            self.update_scene();
+        }
+    }
+
+    /// 改大小：把窗口改成geometry（屏幕坐标），渲染服务换好表面后重画整个窗口、换场景，再通知应用；
+    /// 大小没变时只是移动。渲染服务改不了（内存不足）时窗口保持原样
+    fn resize_window(&mut self, endpoint: &mut Endpoint, index: usize, geometry: Rect) {
+        let mut window = self.windows[index];
+        if (window.width, window.height) == (geometry.w, geometry.h) {
+            if (window.x, window.y) != (geometry.x, geometry.y) {
+                (self.windows[index].x, self.windows[index].y) = (geometry.x, geometry.y);
+                self.update_scene();
+            }
+            return;
+        }
+        (window.x, window.y, window.width, window.height) = (geometry.x, geometry.y, geometry.w, geometry.h);
+        let content = window.content();
+        let request = IpcMessage::RenderResizeSurface { surface: window.surface, width: geometry.w, height: geometry.h, content };
+        match endpoint.call(self.render_pid, &request) {
+            Ok(IpcMessage::RenderSurfaceResized { shm, .. }) if shm != 0 => {}
+            _ => return,
+        }
+        self.windows[index] = window;
+        window.draw(&mut self.cmds, &self.theme.window);
+        self.cmds.flush();
+        self.update_scene();
+        let resized = IpcMessage::WmWindowResized { window_id: window.id, width: content.w, height: content.h };
+        kernel::send(window.owner_pid, &resized);
+    }
+
+    /// 拖动边框中：从按下时的大小起，按鼠标走过的距离改拖着的那一边（或两边），不超出窗口的大小限制
+    fn resize_to(&mut self, endpoint: &mut Endpoint, window_id: WindowId, edge: Edge, start: Rect, dx: i32, dy: i32) {
+        let Some(index) = self.window_index(window_id) else {
+            self.drag = Drag::Idle; // 拖着的窗口没了
+            return;
+        };
+        let (min_w, min_h, max_w, max_h) = self.windows[index].size_limits(&self.theme.window);
+        let (x, w) = resize_span(start.x, start.w, edge.horizontal, dx, min_w, max_w);
+        let (y, h) = resize_span(start.y, start.h, edge.vertical, dy, min_h, max_h);
+        self.windows[index].restore = None; // 拖过边框就不算最大化了
+        self.resize_window(endpoint, index, Rect::new(x, y, w, h));
+    }
+
+    /// 最大化按钮：铺满屏幕（不超过窗口的最大大小），已经最大化时恢复原来的位置和大小
+    fn toggle_maximize(&mut self, endpoint: &mut Endpoint, index: usize) {
+        if let Some(restore) = self.windows[index].restore.take() {
+            self.resize_window(endpoint, index, restore);
+            return;
+        }
+        if self.screen_width == 0 || self.screen_height == 0 {
+            return; // 屏幕大小未知
+        }
+        let window = self.windows[index];
+        let (_, _, max_w, max_h) = window.size_limits(&self.theme.window);
+        let geometry = Rect::new(0, 0, self.screen_width.min(max_w), self.screen_height.min(max_h));
+        self.windows[index].restore = Some(window.geometry());
+        self.resize_window(endpoint, index, geometry);
+    }
+
+    /// 应用限制窗口内容区的大小（只接受窗口所属的应用）：现在的大小超出限制时马上改
+    #[allow(clippy::too_many_arguments)]
+    fn set_size_limits(
+        &mut self,
+        endpoint: &mut Endpoint,
+        sender: Pid,
+        window_id: WindowId,
+        min_width: u16,
+        min_height: u16,
+        max_width: u16,
+        max_height: u16,
+    ) {
+        let Some(index) = self.window_index(window_id).filter(|index| self.windows[*index].owner_pid == sender) else {
+            return;
+        };
+        let window = &mut self.windows[index];
+        (window.min_width, window.min_height, window.max_width, window.max_height) = (min_width, min_height, max_width, max_height);
+        let (min_w, min_h, max_w, max_h) = window.size_limits(&self.theme.window);
+        let geometry = Rect::new(window.x, window.y, window.width.clamp(min_w, max_w), window.height.clamp(min_h, max_h));
+        self.resize_window(endpoint, index, geometry);
+    }
+
+    /// 处理鼠标移动：拖动中移动窗口或改大小，否则在鼠标进出标题栏按钮时重画按钮所在窗口的标题栏；
+    /// 指针进出边框时换指针形状
+    fn handle_mouse_move(&mut self, endpoint: &mut Endpoint, x: u16, y: u16) {
+        match self.drag {
+            Drag::Move { window_id, grab_x, grab_y } => {
+                // 鼠标比抓住的位置更靠左上时窗口贴着屏幕边（坐标没有负数）
+                self.drag_to(window_id, x.saturating_sub(grab_x), y.saturating_sub(grab_y));
+                return;
+            }
+            Drag::Resize { window_id, edge, start, grab_x, grab_y } => {
+                let (dx, dy) = (x as i32 - grab_x as i32, y as i32 - grab_y as i32);
+                self.resize_to(endpoint, window_id, edge, start, dx, dy);
+                return;
+            }
+            Drag::Idle => self.update_cursor(x, y),
+        }
+        let theme = &self.theme.window;
+        let hovered = self.window_at(x, y).and_then(|index| Some((index, self.windows[index].button_at(x, y, theme)?)));
+        let mut changed = false;
+        for (index, window) in self.windows.iter_mut().enumerate() {
+            let hover = hovered.filter(|(hovered, _)| *hovered == index).map(|(_, button)| button);
+            if window.hover != hover {
+                window.hover = hover;
+                window.draw_frame(&mut self.cmds, theme);
+                changed = true;
+            }
+        }
+        if changed {
+            self.cmds.submit();
+        }
+    }
+
+    /// 任务栏请求激活窗口：最小化的窗口先恢复显示，再获得焦点
+    fn activate(&mut self, window_id: WindowId) {
+        let Some(index) = self.window_index(window_id) else {
+            return;
+        };
+        if self.windows[index].state == WindowState::Minimized {
+            self.windows[index].state = WindowState::Normal;
+            self.update_scene();
+        }
+        if self.windows[index].state == WindowState::Normal {
+            self.set_focus(window_id);
+        }
+    }
+
+    /// 换主题：重画所有窗口，再通知订阅者（应用收到后重画自己的内容）
+    fn set_theme(&mut self, theme: Theme, text: ShmHandle, len: u32) {
+        self.theme = theme;
+        self.theme_text = (text, len);
+        for window in &self.windows {
+            window.draw(&mut self.cmds, &self.theme.window);
+        }
+        self.cmds.submit();
+        for pid in &self.theme_subscribers {
+            kernel::send(*pid, &IpcMessage::ThemeChanged { text, len });
+        }
+    }
+
+    /// 记录主题订阅者（重复订阅只记一次）
+    fn subscribe_theme(&mut self, pid: Pid) {
+        if !self.theme_subscribers.contains(&pid) {
+            self.theme_subscribers.push(pid);
+        }
+    }
+}
+
+/// 把window和它的子孙按windows里原来的先后放进order（父窗口在前，也就是在下面）
+fn push_family(windows: &[Window], window: &Window, order: &mut Vec<Window>) {
+    order.push(*window);
+    for child in windows.iter().filter(|w| w.parent == window.id) {
+        push_family(windows, child, order);
+    }
+}
+
+// ==========================
+// 入口与消息循环
+// ==========================
+/// 窗口管理器主函数（裸机入口main.rs和宿主模拟器都从这里启动）
+pub fn run() -> ! {
+    unsafe {
+        // 0. 注册服务到内核（rui_lib通过"wm"名称找到窗口管理器）
+        let wm_name = CStr::from_bytes_with_nul(b"wm\0").unwrap();
+        let _ = kernel::kernel_register_service(wm_name.as_ptr() as *const u8);
+
+        // 1. 初始化：获取渲染服务PID
+        let render_name = CStr::from_bytes_with_nul(b"render\0").unwrap();
+        let render_pid = kernel::get_service_pid(render_name.as_ptr() as *const u8);
+
+        // 2. 查询屏幕大小并订阅分辨率变化，初始化窗口管理器状态
+        let mut endpoint = Endpoint::new();
+        let (screen_width, screen_height) = match endpoint.call(render_pid, &IpcMessage::RenderSubscribeScreen) {
+            Ok(IpcMessage::RenderScreenInfo { width, height }) => (width, height),
+            _ => (0, 0), // 渲染服务没回复：先不限制窗口位置，等ScreenModeChanged
+        };
+        let Some(cmds) = RenderCommandBuffer::new(render_pid) else {
+            loop { kernel::idle(); } // 分配不到命令缓冲区就画不了窗口
+        };
+        let mut wm_state = WmState::new(cmds, render_pid, screen_width, screen_height);
+
+        // 3. 测试：添加一个默认窗口（模拟应用请求）
+        let test_window = wm_state.create_window(
+            &mut endpoint,
+            100, // 假设应用PID为100
+            Rect::new(50, 50, 400, 300),
+            IpcStr::new("测试窗口 - RUI Demo"),
+        );
+        wm_state.add_window(test_window);
+
+        // 4. 消息循环：处理应用请求和输入事件
+        loop {
+            let incoming = endpoint.recv();
+            match incoming.msg {
+                // 处理应用的"创建窗口"请求，回复新窗口ID和窗口的表面（应用往上画内容）
+                IpcMessage::WmCreateWindow { x, y, w, h, title } => {
+                    let new_window = wm_state.create_window(&mut endpoint, incoming.sender, Rect::new(x, y, w, h), title);
+                    let (window_id, surface) = (new_window.id, new_window.surface);
+                    wm_state.add_window(new_window);
+                    ipc::reply(&incoming, &IpcMessage::WmWindowCreated { window_id, surface });
+                }
+                // 创建面板（桌面的Dock等），回复和创建窗口一样
+                IpcMessage::WmCreatePanel { x, y, w, h, layer } => {
+                    let panel = wm_state.create_panel(&mut endpoint, incoming.sender, Rect::new(x, y, w, h), layer);
+                    let (window_id, surface) = (panel.id, panel.surface);
+                    wm_state.add_window(panel);
+                    ipc::reply(&incoming, &IpcMessage::WmWindowCreated { window_id, surface });
+                }
+                // 处理鼠标驱动的"点击事件"（假设鼠标驱动发送此消息）
+                IpcMessage::MouseClick { x, y } => {
+                    wm_state.handle_mouse_click(&mut endpoint, x, y);
+                }
+                // 鼠标按下、松开：开始、结束拖动（指针形状发回给鼠标驱动）
+                IpcMessage::MouseDown { x, y, button } => {
+                    wm_state.pointer_pid = incoming.sender;
+                    wm_state.handle_mouse_down(x, y, button);
+                }
+                IpcMessage::MouseUp { x, y, button } => {
+                    wm_state.pointer_pid = incoming.sender;
+                    wm_state.handle_mouse_up(x, y, button);
+                }
+                // 鼠标移动：拖动窗口、改大小，或者更新标题栏按钮的悬停状态和指针形状
+                IpcMessage::MouseMove { x, y } => {
+                    wm_state.pointer_pid = incoming.sender;
+                    wm_state.handle_mouse_move(&mut endpoint, x, y);
+                }
+                // 应用限制自己窗口的大小
+                IpcMessage::WmSetSizeLimits { window_id, min_width, min_height, max_width, max_height } => {
+                    wm_state.set_size_limits(&mut endpoint, incoming.sender, window_id, min_width, min_height, max_width, max_height);
+                }
+                // 叠放次序：提升、降低任何窗口；换层、设父窗口、挪动只能是窗口所属的应用
+                IpcMessage::WmRaiseWindow { window_id } => {
+                    wm_state.raise(window_id);
+                }
+                IpcMessage::WmLowerWindow { window_id } => {
+                    wm_state.lower(window_id);
+                }
+                IpcMessage::WmSetStackLayer { window_id, layer } => {
+                    wm_state.set_stack_layer(incoming.sender, window_id, layer);
+                }
+                IpcMessage::WmSetTransientFor { window_id, parent } => {
+                    wm_state.set_transient_for(incoming.sender, window_id, parent);
+                }
+                IpcMessage::WmMoveWindow { window_id, x, y } => {
+                    wm_state.move_window(incoming.sender, window_id, x, y);
+                }
+                // 桌面任务栏点了窗口按钮：把焦点给这个窗口（最小化的先恢复）
+                IpcMessage::WmFocusWindow { window_id } => {
+                    wm_state.activate(window_id);
+                }
+                // 换主题：解析成功才换，回复解析结果
+                IpcMessage::WmSetTheme { text, len } => {
+                    let (ok, line) = match load_theme(incoming.sender, text, len) {
+                        Ok((theme, text)) => {
+                            wm_state.set_theme(theme, text, len);
+                            (true, 0)
+                        }
+                        Err(err) => (false, err.line),
+                    };
+                    ipc::reply(&incoming, &IpcMessage::WmThemeSet { ok, line });
+                }
+                // 订阅主题变化：先回复当前主题
+                IpcMessage::WmSubscribeTheme => {
+                    wm_state.subscribe_theme(incoming.sender);
+                    let (text, len) = wm_state.theme_text;
+                    ipc::reply(&incoming, &IpcMessage::ThemeChanged { text, len });
+                }
+                // 渲染服务通知：屏幕分辨率变了
+                IpcMessage::ScreenModeChanged { width, height } => {
+                    wm_state.handle_screen_resize(width, height);
+                }
+                _ => {} // 忽略其他消息
+            }
+            kernel::idle();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(x: u16, y: u16, width: u16, height: u16, border_width: u16) -> Window {
+        let mut window = Window::new(1, x, y, width, height, IpcStr::new("test"), &Theme::default().window);
+        window.border_width = border_width;
+        window
+    }
+
+    fn edge(horizontal: i8, vertical: i8) -> Option<Edge> {
+        Some(Edge { horizontal, vertical })
+    }
+
+    /// 按windows的先后放好的WM（渲染服务不存在，消息都丢掉）
+    fn wm(windows: &[Window]) -> WmState {
+        let mut state = WmState::new(RenderCommandBuffer::new(0).unwrap(), 0, 800, 600);
+        state.windows = windows.to_vec();
+        state
+    }
+
+    fn ids(state: &WmState) -> Vec<WindowId> {
+        state.windows.iter().map(|window| window.id).collect()
+    }
+
+    fn child_of(parent: &Window) -> Window {
+        let mut child = window(0, 0, 100, 100, 4);
+        child.parent = parent.id;
+        child
+    }
+
+    #[test]
+    fn resize_right_and_bottom() {
+        assert_eq!(resize_span(100, 200, 1, 50, 100, 400), (100, 250));
+        assert_eq!(resize_span(100, 200, 1, -150, 100, 400), (100, 100));
+        assert_eq!(resize_span(100, 200, 1, 300, 100, 400), (100, 400));
+        // 不改这个方向
+        assert_eq!(resize_span(100, 200, 0, 300, 100, 400), (100, 200));
+    }
+
+    #[test]
+    fn resize_left_and_top_keep_far_edge() {
+        // 往外拉：起点跟着动，对面的边（300）不动
+        assert_eq!(resize_span(100, 200, -1, -50, 100, 400), (50, 250));
+        // 往里推到最小值
+        assert_eq!(resize_span(100, 200, -1, 150, 100, 400), (200, 100));
+        // 往外拉到最大值
+        assert_eq!(resize_span(100, 200, -1, -100, 100, 250), (50, 250));
+        // 起点不会小于0
+        assert_eq!(resize_span(100, 200, -1, -500, 100, 1000), (0, 300));
+        // 最小值比对面的边还大时贴着0
+        assert_eq!(resize_span(10, 20, -1, 5, 100, 400), (0, 30));
+    }
+
+    #[test]
+    fn edges_and_corners() {
+        // (10, 10)起200×100的窗口，边框4像素
+        let window = window(10, 10, 200, 100, 4);
+        assert_eq!(window.edge_at(11, 60), edge(-1, 0));
+        assert_eq!(window.edge_at(209, 60), edge(1, 0));
+        assert_eq!(window.edge_at(100, 10), edge(0, -1));
+        assert_eq!(window.edge_at(100, 109), edge(0, 1));
+        // 边框交叉处
+        assert_eq!(window.edge_at(10, 10), edge(-1, -1));
+        assert_eq!(window.edge_at(209, 109), edge(1, 1));
+        // 左边框上离角RESIZE_CORNER以内
+        assert_eq!(window.edge_at(11, 10 + RESIZE_CORNER - 1), edge(-1, -1));
+        assert_eq!(window.edge_at(11, 10 + RESIZE_CORNER), edge(-1, 0));
+        assert_eq!(window.edge_at(11, 110 - RESIZE_CORNER), edge(-1, 1));
+        assert_eq!(window.edge_at(11, 110 - RESIZE_CORNER - 1), edge(-1, 0));
+        // 上边框上离角RESIZE_CORNER以内
+        assert_eq!(window.edge_at(210 - RESIZE_CORNER, 12), edge(1, -1));
+        assert_eq!(window.edge_at(210 - RESIZE_CORNER - 1, 12), edge(0, -1));
+        assert_eq!(window.edge_at(10 + RESIZE_CORNER - 1, 109), edge(-1, 1));
+    }
+
+    #[test]
+    fn no_edge_inside_or_outside() {
+        let window = window(10, 10, 200, 100, 4);
+        assert_eq!(window.edge_at(14, 60), None);
+        assert_eq!(window.edge_at(100, 50), None);
+        assert_eq!(window.edge_at(9, 60), None);
+        assert_eq!(window.edge_at(210, 60), None);
+        // 没有边框时边上留1像素
+        let window = self::window(10, 10, 200, 100, 0);
+        assert_eq!(window.edge_at(10, 60), edge(-1, 0));
+        assert_eq!(window.edge_at(11, 60), None);
+        // 面板不能拖动改大小
+        let mut panel = self::window(10, 10, 200, 100, 4);
+        panel.panel = true;
+        assert_eq!(panel.edge_at(10, 10), None);
+    }
+
+    #[test]
+    fn restack_by_layer() {
+        let mut windows = [(); 5].map(|_| window(0, 0, 100, 100, 4));
+        windows[0].layer = StackLayer::Top;
+        windows[2].layer = StackLayer::Bottom;
+        windows[4].layer = StackLayer::Top;
+        let [a, b, c, d, e] = windows.map(|window| window.id);
+        let mut state = wm(&windows);
+        state.restack();
+        // 同一层里保持原来的先后
+        assert_eq!(ids(&state), [c, b, d, a, e]);
+    }
+
+    #[test]
+    fn children_follow_parent_layer() {
+        let mut parent = window(0, 0, 100, 100, 4);
+        parent.layer = StackLayer::Top;
+        let child = child_of(&parent);
+        let grandchild = child_of(&child);
+        let other = window(0, 0, 100, 100, 4);
+        let mut state = wm(&[grandchild, child, parent, other]);
+        state.restack();
+        assert_eq!(ids(&state), [other.id, parent.id, child.id, grandchild.id]);
+    }
+
+    #[test]
+    fn raise_keeps_children_above() {
+        let parent = window(0, 0, 100, 100, 4);
+        let (first, second) = (child_of(&parent), child_of(&parent));
+        let [a, b] = [(); 2].map(|_| window(0, 0, 100, 100, 4));
+        let mut state = wm(&[parent, first, second, a, b]);
+        state.raise(parent.id);
+        assert_eq!(ids(&state), [a.id, b.id, parent.id, first.id, second.id]);
+        state.raise(a.id);
+        assert_eq!(ids(&state), [b.id, parent.id, first.id, second.id, a.id]);
+        // 提子窗口时父窗口一起上来，它在兄弟窗口上面
+        state.raise(first.id);
+        assert_eq!(ids(&state), [b.id, a.id, parent.id, second.id, first.id]);
+    }
+
+    #[test]
+    fn lower_keeps_children_above() {
+        let parent = window(0, 0, 100, 100, 4);
+        let (first, second) = (child_of(&parent), child_of(&parent));
+        let other = window(0, 0, 100, 100, 4);
+        let mut state = wm(&[other, parent, first, second]);
+        state.lower(parent.id);
+        assert_eq!(ids(&state), [parent.id, first.id, second.id, other.id]);
+        // 子窗口只降到兄弟窗口下面，还在父窗口上面
+        state.lower(second.id);
+        assert_eq!(ids(&state), [parent.id, second.id, first.id, other.id]);
+    }
+
+    #[test]
+    fn lower_stays_in_layer() {
+        let mut top = window(0, 0, 100, 100, 4);
+        top.layer = StackLayer::Top;
+        let normal = window(0, 0, 100, 100, 4);
+        let mut state = wm(&[normal, top]);
+        state.lower(top.id);
+        assert_eq!(ids(&state), [normal.id, top.id]);
+    }
+
+    #[test]
+    fn rejects_transient_cycles() {
+        let parent = window(0, 0, 100, 100, 4);
+        let child = child_of(&parent);
+        let grandchild = child_of(&child);
+        let mut state = wm(&[parent, child, grandchild]);
+        let parent_of = |state: &WmState, id| state.windows[state.window_index(id).unwrap()].parent;
+        // 自己、子窗口、孙窗口都不能当父窗口
+        for new_parent in [parent.id, child.id, grandchild.id] {
+            state.set_transient_for(1, parent.id, new_parent);
+            assert_eq!(parent_of(&state, parent.id), 0);
+        }
+        state.set_transient_for(1, child.id, grandchild.id);
+        assert_eq!(parent_of(&state, child.id), parent.id);
+        // 不存在的窗口也不行
+        state.set_transient_for(1, parent.id, WindowId::MAX);
+        assert_eq!(parent_of(&state, parent.id), 0);
+        // 换到别的父窗口可以，0表示解开
+        state.set_transient_for(1, grandchild.id, parent.id);
+        assert_eq!(parent_of(&state, grandchild.id), parent.id);
+        state.set_transient_for(1, child.id, 0);
+        assert_eq!(parent_of(&state, child.id), 0);
+    }
+
+    #[test]
+    fn transient_for_checks_owner() {
+        let parent = window(0, 0, 100, 100, 4);
+        let other = window(0, 0, 100, 100, 4);
+        let mut state = wm(&[parent, other]);
+        state.set_transient_for(2, other.id, parent.id);
+        assert_eq!(state.windows[1].parent, 0);
+    }
+
+    /// 在第index个窗口的标题栏上按下左键（左边四分之一处，避开按钮和边框），返回按在哪里
+    fn grab_title(state: &mut WmState, index: usize) -> (u16, u16) {
+        let window = state.windows[index];
+        let (x, y) = (window.x + window.width / 4, window.y + window.titlebar_height / 2);
+        state.handle_mouse_down(x, y, MouseButton::Left);
+        (x, y)
+    }
+
+    fn position(state: &WmState, id: WindowId) -> (u16, u16) {
+        let window = &state.windows[state.window_index(id).unwrap()];
+        (window.x, window.y)
+    }
+
+    #[test]
+    fn title_bar_drag_moves_window() {
+        let (back, front) = (window(100, 100, 200, 150, 4), window(400, 300, 200, 150, 4));
+        let mut state = wm(&[back, front]);
+        let mut endpoint = Endpoint::new();
+        let (x, y) = grab_title(&mut state, 0);
+        assert_eq!(state.drag, Drag::Move { window_id: back.id, grab_x: 50, grab_y: y - 100 });
+        assert_eq!(state.focused_window_id, back.id);
+        // 窗口跟着鼠标走，抓住的位置不变
+        state.handle_mouse_move(&mut endpoint, x + 150, y + 60);
+        assert_eq!(position(&state, back.id), (250, 160));
+        state.handle_mouse_move(&mut endpoint, x - 20, y + 5);
+        assert_eq!(position(&state, back.id), (80, 105));
+        // 鼠标比抓住的位置还靠左上：窗口贴着屏幕边
+        state.handle_mouse_move(&mut endpoint, 10, 0);
+        assert_eq!(position(&state, back.id), (0, 0));
+        // 松开之后不再跟着走
+        state.handle_mouse_up(10, 0, MouseButton::Left);
+        assert_eq!(state.drag, Drag::Idle);
+        state.handle_mouse_move(&mut endpoint, 300, 300);
+        assert_eq!(position(&state, back.id), (0, 0));
+        assert_eq!(position(&state, front.id), (400, 300));
+    }
+
+    #[test]
+    fn only_title_bar_starts_move() {
+        let target = window(100, 100, 200, 150, 4);
+        let mut state = wm(&[target]);
+        let title_y = 100 + target.titlebar_height / 2;
+        // 右键、内容区、窗口外面、标题栏按钮上都不开始拖动
+        state.handle_mouse_down(140, title_y, MouseButton::Right);
+        state.handle_mouse_down(140, 200, MouseButton::Left);
+        state.handle_mouse_down(50, 50, MouseButton::Left);
+        let close = target.button_rect(TitleButton::Close, &state.theme.window);
+        state.handle_mouse_down(100 + close.x + 1, 100 + close.y + 1, MouseButton::Left);
+        assert_eq!(state.drag, Drag::Idle);
+        // 面板也不能拖
+        state.windows[0].panel = true;
+        grab_title(&mut state, 0);
+        assert_eq!(state.drag, Drag::Idle);
+    }
+
+    #[test]
+    fn drag_keeps_title_bar_on_screen() {
+        let (wide, narrow) = (window(100, 100, 200, 150, 4), window(100, 300, MIN_VISIBLE - 8, 100, 4));
+        let mut state = wm(&[wide]);
+        let mut endpoint = Endpoint::new();
+        // 抓着标题栏最左边拖到右下角：标题栏还留MIN_VISIBLE宽、整个标题栏高在屏幕里
+        state.handle_mouse_down(108, 100 + wide.titlebar_height / 2, MouseButton::Left);
+        state.handle_mouse_move(&mut endpoint, 799, 599);
+        assert_eq!(position(&state, wide.id), (800 - MIN_VISIBLE, 600 - wide.titlebar_height));
+        state.handle_mouse_up(799, 599, MouseButton::Left);
+        assert_eq!(state.drag, Drag::Idle);
+        // 比MIN_VISIBLE窄的窗口整个留在屏幕里（标题栏被按钮占满了，直接算）
+        assert_eq!(state.keep_on_screen(&narrow, 799, 599), (800 - narrow.width, 600 - narrow.titlebar_height));
+        assert_eq!(state.keep_on_screen(&narrow, 20, 30), (20, 30));
+        // 屏幕大小未知时不限制
+        state.screen_width = 0;
+        assert_eq!(state.keep_on_screen(&wide, 2000, 2000), (2000, 2000));
+    }
+
+    #[test]
+    fn drag_ends_when_window_closes() {
+        let (dragged, other) = (window(100, 100, 200, 150, 4), window(400, 300, 200, 150, 4));
+        let mut state = wm(&[other, dragged]);
+        let mut endpoint = Endpoint::new();
+        grab_title(&mut state, 1);
+        assert!(matches!(state.drag, Drag::Move { .. }));
+        state.close(1);
+        state.handle_mouse_move(&mut endpoint, 500, 400);
+        assert_eq!(state.drag, Drag::Idle);
+        assert_eq!(position(&state, other.id), (400, 300));
+    }
+}
+